@@ -0,0 +1,36 @@
+/* C API for the dkdc-md-cli MotherDuck client. */
+#ifndef DKDC_MD_CLI_H
+#define DKDC_MD_CLI_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MdClient MdClient;
+
+/* Create a client. Pass NULL to resolve the token from env vars
+ * (motherduck_token, MOTHERDUCK_TOKEN, motherduck_api_key, MOTHERDUCK_API_KEY).
+ * Returns NULL on failure; see md_last_error(). */
+MdClient *md_client_new(const char *token);
+
+/* Free a client. NULL is a no-op. */
+void md_client_free(MdClient *client);
+
+/* Call an API endpoint, e.g. ("GET", "/v1/users/svc_x/tokens", NULL).
+ * GET/DELETE take no body; POST/PUT/PATCH require a JSON body.
+ * Returns the JSON response (free with md_string_free), or NULL on failure. */
+char *md_client_call(const MdClient *client, const char *method, const char *path,
+                     const char *body_json);
+
+/* Free a string returned by md_client_call. NULL is a no-op. */
+void md_string_free(char *s);
+
+/* Message for the most recent failure on this thread, or NULL.
+ * Valid until the next md_* call on the same thread. */
+const char *md_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DKDC_MD_CLI_H */
@@ -0,0 +1,220 @@
+//! C ABI over [`dkdc_md_cli::MotherduckClient`]. See `include/dkdc_md_cli.h`.
+//!
+//! Every function that can fail returns `NULL` and records a message retrievable
+//! with `md_last_error()` on the same thread.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+use anyhow::{Context, Result};
+use dkdc_md_cli::MotherduckClient;
+use dkdc_md_cli::auth;
+use serde_json::Value;
+
+/// Opaque client handle handed out to C callers.
+pub struct MdClient(MotherduckClient);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(e: &anyhow::Error) {
+    // Interior NULs cannot cross the C boundary; replace them rather than lose the message.
+    let msg = format!("{e:#}").replace('\0', " ");
+    let msg = CString::new(msg).expect("NUL bytes were replaced");
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(msg));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = None);
+}
+
+/// Borrow a C string argument, treating `NULL` as absent.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a valid NUL-terminated string that outlives the returned reference.
+unsafe fn opt_str<'a>(s: *const c_char, what: &str) -> Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: upheld by the caller.
+    let s = unsafe { CStr::from_ptr(s) };
+    s.to_str()
+        .map(Some)
+        .with_context(|| format!("{what} is not valid UTF-8"))
+}
+
+/// Borrow a required C string argument.
+///
+/// # Safety
+///
+/// Same as [`opt_str`].
+unsafe fn req_str<'a>(s: *const c_char, what: &str) -> Result<&'a str> {
+    // SAFETY: upheld by the caller.
+    unsafe { opt_str(s, what) }?.with_context(|| format!("{what} must not be NULL"))
+}
+
+fn new_client(token: Option<&str>) -> Result<MdClient> {
+    let token = auth::resolve_token_or(token)?;
+    Ok(MdClient(MotherduckClient::new(&token)?))
+}
+
+fn call(client: &MdClient, method: &str, path: &str, body: Option<&str>) -> Result<CString> {
+    let body: Option<Value> = body
+        .map(serde_json::from_str)
+        .transpose()
+        .context("request body is not valid JSON")?;
+    let result = client.0.call(method, path, body.as_ref())?;
+    CString::new(result.to_string()).context("response contained a NUL byte")
+}
+
+/// Create a client. Pass `NULL` to resolve the token from the same env vars as the CLI.
+/// Returns `NULL` on failure. Free with `md_client_free`.
+///
+/// # Safety
+///
+/// `token` must be `NULL` or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn md_client_new(token: *const c_char) -> *mut MdClient {
+    clear_last_error();
+    // SAFETY: upheld by the caller.
+    match unsafe { opt_str(token, "token") }.and_then(new_client) {
+        Ok(client) => Box::into_raw(Box::new(client)),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a client created by `md_client_new`. Passing `NULL` is a no-op.
+///
+/// # Safety
+///
+/// `client` must be `NULL` or a pointer returned by `md_client_new` that has not been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn md_client_free(client: *mut MdClient) {
+    if !client.is_null() {
+        // SAFETY: upheld by the caller.
+        drop(unsafe { Box::from_raw(client) });
+    }
+}
+
+/// Call an API endpoint, e.g. `md_client_call(c, "GET", "/v1/users/svc_x/tokens", NULL)`.
+/// Returns the JSON response as a string (free with `md_string_free`), or `NULL` on failure.
+///
+/// # Safety
+///
+/// `client` must be a live pointer from `md_client_new`. `method` and `path` must be valid
+/// NUL-terminated strings; `body_json` must be `NULL` or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn md_client_call(
+    client: *const MdClient,
+    method: *const c_char,
+    path: *const c_char,
+    body_json: *const c_char,
+) -> *mut c_char {
+    clear_last_error();
+    // SAFETY: upheld by the caller.
+    let result = unsafe { client.as_ref() }
+        .context("client must not be NULL")
+        .and_then(|client| {
+            // SAFETY: upheld by the caller.
+            let (method, path, body) = unsafe {
+                (
+                    req_str(method, "method")?,
+                    req_str(path, "path")?,
+                    opt_str(body_json, "body")?,
+                )
+            };
+            call(client, method, path, body)
+        });
+    match result {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_last_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a string returned by `md_client_call`. Passing `NULL` is a no-op.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a pointer returned by `md_client_call` that has not been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn md_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: upheld by the caller.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Message for the most recent failure on this thread, or `NULL` if the last call succeeded.
+/// The pointer stays valid until the next `md_*` call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn md_last_error() -> *const c_char {
+    LAST_ERROR.with(|slot| slot.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let p = md_last_error();
+        assert!(!p.is_null());
+        unsafe { CStr::from_ptr(p) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn client_new_rejects_empty_token() {
+        let token = CString::new("  ").unwrap();
+        let client = unsafe { md_client_new(token.as_ptr()) };
+        assert!(client.is_null());
+        assert!(last_error().contains("must not be empty"));
+    }
+
+    #[test]
+    fn client_new_and_free() {
+        let token = CString::new("tok").unwrap();
+        let client = unsafe { md_client_new(token.as_ptr()) };
+        assert!(!client.is_null());
+        assert!(md_last_error().is_null());
+        unsafe { md_client_free(client) };
+    }
+
+    #[test]
+    fn call_rejects_null_client() {
+        let method = CString::new("GET").unwrap();
+        let path = CString::new("/v1/active_accounts").unwrap();
+        let out =
+            unsafe { md_client_call(ptr::null(), method.as_ptr(), path.as_ptr(), ptr::null()) };
+        assert!(out.is_null());
+        assert!(last_error().contains("client must not be NULL"));
+    }
+
+    #[test]
+    fn call_rejects_invalid_body() {
+        let token = CString::new("tok").unwrap();
+        let client = unsafe { md_client_new(token.as_ptr()) };
+        let method = CString::new("POST").unwrap();
+        let path = CString::new("/v1/users").unwrap();
+        let body = CString::new("{not json").unwrap();
+        let out = unsafe { md_client_call(client, method.as_ptr(), path.as_ptr(), body.as_ptr()) };
+        assert!(out.is_null());
+        assert!(last_error().contains("not valid JSON"));
+        unsafe { md_client_free(client) };
+    }
+
+    #[test]
+    fn free_null_is_noop() {
+        unsafe {
+            md_client_free(ptr::null_mut());
+            md_string_free(ptr::null_mut());
+        }
+    }
+}
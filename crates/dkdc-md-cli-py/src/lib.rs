@@ -1,12 +1,103 @@
+use dkdc_md_cli::MotherduckClient;
+use dkdc_md_cli::auth;
 use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
 
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    PyErr::new::<PyRuntimeError, _>(format!("{e:#}"))
+}
+
+/// Convert an API response into native Python objects via the stdlib `json` module.
+fn to_py_object(py: Python<'_>, value: &serde_json::Value) -> PyResult<Py<PyAny>> {
+    let json = py.import("json")?;
+    Ok(json.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
 #[pyfunction]
 fn run(argv: Vec<String>) -> PyResult<()> {
     dkdc_md_cli::run(argv.iter().map(|s| s.as_str()))
         .map_err(|e| PyErr::new::<PyRuntimeError, _>(e.to_string()))
 }
 
+/// Typed MotherDuck API client sharing the CLI's request and error handling.
+#[pyclass]
+struct Client {
+    inner: MotherduckClient,
+}
+
+#[pymethods]
+impl Client {
+    #[new]
+    #[pyo3(signature = (token=None))]
+    fn new(token: Option<&str>) -> PyResult<Self> {
+        let token = auth::resolve_token_or(token).map_err(to_py_err)?;
+        let inner = MotherduckClient::new(&token).map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    fn create_user(&self, py: Python<'_>, username: &str) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.create_user(username));
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn delete_user(&self, py: Python<'_>, username: &str) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.delete_user(username));
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn list_tokens(&self, py: Python<'_>, username: &str) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.list_tokens(username));
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    #[pyo3(signature = (username, name, ttl=None, token_type=None))]
+    fn create_token(
+        &self,
+        py: Python<'_>,
+        username: &str,
+        name: &str,
+        ttl: Option<u64>,
+        token_type: Option<&str>,
+    ) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.create_token(username, name, ttl, token_type));
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn delete_token(&self, py: Python<'_>, username: &str, token_id: &str) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.delete_token(username, token_id));
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn get_duckling_config(&self, py: Python<'_>, username: &str) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.get_duckling_config(username));
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn set_duckling_config(
+        &self,
+        py: Python<'_>,
+        username: &str,
+        rw_size: &str,
+        rs_size: &str,
+        flock_size: u32,
+    ) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| {
+            self.inner
+                .set_duckling_config(username, rw_size, rs_size, flock_size)
+        });
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn list_active_accounts(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let result = py.detach(|| self.inner.list_active_accounts());
+        to_py_object(py, &result.map_err(to_py_err)?)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
 #[pymodule]
 mod core {
     use super::*;
@@ -14,6 +105,7 @@ mod core {
     #[pymodule_init]
     fn module_init(m: &Bound<'_, PyModule>) -> PyResult<()> {
         m.add_function(wrap_pyfunction!(run, m)?)?;
+        m.add_class::<Client>()?;
         Ok(())
     }
 }
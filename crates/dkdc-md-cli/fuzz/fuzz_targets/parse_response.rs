@@ -0,0 +1,15 @@
+#![no_main]
+
+use dkdc_md_cli::client::parse_response;
+use libfuzzer_sys::fuzz_target;
+
+// First two bytes pick the status; the rest is the body, decoded lossily the way a
+// garbled proxy response would arrive.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let status = u16::from_be_bytes([data[0], data[1]]);
+    let text = String::from_utf8_lossy(&data[2..]).into_owned();
+    let _ = parse_response(status, text);
+});
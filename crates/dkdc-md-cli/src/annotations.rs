@@ -0,0 +1,185 @@
+//! Local ownership notes on service accounts (`md annotate`), kept in `annotations.toml`
+//! next to `config.toml` and merged into account listings and `md describe`. An account's
+//! intended end of life (`--expires`) lives here too, for `md service-account expiring`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const ANNOTATIONS_FILE: &str = "annotations.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotations {
+    /// Keyed by username.
+    #[serde(default)]
+    pub users: BTreeMap<String, Annotation>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// When the account should be deprovisioned (RFC 3339).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+}
+
+impl Annotation {
+    /// Set each given field; an empty value removes it.
+    pub fn update(&mut self, owner: Option<String>, ticket: Option<String>, note: Option<String>) {
+        for (field, value) in [
+            (&mut self.owner, owner),
+            (&mut self.ticket, ticket),
+            (&mut self.note, note),
+        ] {
+            if let Some(value) = value {
+                *field = Some(value).filter(|v| !v.is_empty());
+            }
+        }
+    }
+
+    /// Set or (with `None`) remove the intended end of life.
+    pub fn set_expires(&mut self, at: Option<DateTime<Utc>>) {
+        self.expires = at.map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Annotations {
+    /// Load annotations from `path`, or none if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str(&text).with_context(|| format!("invalid {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let text = toml::to_string(self).context("failed to serialize annotations")?;
+        std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Accounts meant to be gone by `by`, soonest first.
+    pub fn expiring(&self, by: DateTime<Utc>) -> Result<Vec<(&str, DateTime<Utc>)>> {
+        let mut expiring = Vec::new();
+        for (username, annotation) in &self.users {
+            let Some(expires) = &annotation.expires else {
+                continue;
+            };
+            let at = DateTime::parse_from_rfc3339(expires)
+                .with_context(|| format!("invalid expires '{expires}' for '{username}'"))?
+                .to_utc();
+            if at <= by {
+                expiring.push((username.as_str(), at));
+            }
+        }
+        expiring.sort_by_key(|(_, at)| *at);
+        Ok(expiring)
+    }
+
+    /// Add an `annotations` object to each annotated account in an
+    /// `{"accounts": [...]}` listing.
+    pub fn merge_into_accounts(&self, listing: &mut Value) {
+        for account in listing["accounts"].as_array_mut().into_iter().flatten() {
+            let annotation = account["username"].as_str().and_then(|u| self.users.get(u));
+            if let Some(annotation) = annotation {
+                account["annotations"] = serde_json::to_value(annotation)
+                    .expect("Annotation serialization is infallible");
+            }
+        }
+    }
+}
+
+/// `annotations.toml` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(ANNOTATIONS_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn update_sets_and_clears_fields() {
+        let mut annotation = Annotation::default();
+        annotation.update(Some("data-platform".into()), Some("JIRA-123".into()), None);
+        assert_eq!(annotation.owner.as_deref(), Some("data-platform"));
+        annotation.update(None, Some(String::new()), Some("nightly ETL".into()));
+        assert_eq!(annotation.ticket, None);
+        assert_eq!(annotation.owner.as_deref(), Some("data-platform"));
+        annotation.update(Some(String::new()), None, Some(String::new()));
+        assert!(annotation.is_empty());
+    }
+
+    #[test]
+    fn lists_accounts_past_their_lifetime_soonest_first() {
+        let mut annotations = Annotations::default();
+        for (username, expires) in [
+            ("svc_late", "2026-03-01T00:00:00Z"),
+            ("svc_early", "2026-01-01T00:00:00Z"),
+            ("svc_later", "2027-01-01T00:00:00Z"),
+        ] {
+            let mut annotation = Annotation::default();
+            annotation.set_expires(Some(expires.parse().unwrap()));
+            annotations.users.insert(username.into(), annotation);
+        }
+        annotations
+            .users
+            .insert("svc_kept".into(), Annotation::default());
+        let by = "2026-06-01T00:00:00Z".parse().unwrap();
+        let names: Vec<&str> = annotations
+            .expiring(by)
+            .unwrap()
+            .into_iter()
+            .map(|(username, _)| username)
+            .collect();
+        assert_eq!(names, ["svc_early", "svc_late"]);
+
+        annotations.users.get_mut("svc_kept").unwrap().expires = Some("soon".into());
+        let err = annotations.expiring(by).unwrap_err();
+        assert_eq!(err.to_string(), "invalid expires 'soon' for 'svc_kept'");
+    }
+
+    #[test]
+    fn merges_into_annotated_accounts_only() {
+        let mut annotations = Annotations::default();
+        annotations.users.insert(
+            "svc_etl".into(),
+            Annotation {
+                owner: Some("data-platform".into()),
+                ..Annotation::default()
+            },
+        );
+        let mut listing =
+            json!({ "accounts": [{ "username": "svc_etl" }, { "username": "svc_bi" }] });
+        annotations.merge_into_accounts(&mut listing);
+        assert_eq!(
+            listing,
+            json!({ "accounts": [
+                { "username": "svc_etl", "annotations": { "owner": "data-platform" } },
+                { "username": "svc_bi" }
+            ] })
+        );
+    }
+}
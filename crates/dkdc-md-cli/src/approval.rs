@@ -0,0 +1,325 @@
+//! Two-person approval: with `[approval] required = true`, destructive commands write a
+//! signed change request instead of running. A listed approver signs it with `md approve`,
+//! and `md execute` then runs the recorded command.
+
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::signing::{self, SigningKey};
+
+/// How long a change request can be approved and executed after it was made.
+const MAX_AGE: TimeDelta = TimeDelta::hours(24);
+
+/// A destructive command waiting for (or holding) a second operator's approval.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChangeRequest {
+    pub id: String,
+    /// RFC 3339 creation time.
+    pub created_at: String,
+    /// API the command was requested against; it only executes against the same one.
+    pub base_url: String,
+    /// `md` arguments without the program name or operator-specific flags.
+    pub args: Vec<String>,
+    /// Requester's public key.
+    pub requested_by: String,
+    /// Requester's signature over the fields above.
+    pub signature: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<Approval>,
+    /// RFC 3339 time `md execute` ran the command; a request executes once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Approval {
+    /// Approver's public key.
+    pub approved_by: String,
+    pub approved_at: String,
+    /// Approver's signature over the request (including the requester's signature).
+    pub signature: String,
+}
+
+impl ChangeRequest {
+    /// A request for `args`, signed by `key`.
+    pub fn new(key: &SigningKey, base_url: &str, args: Vec<String>, now: DateTime<Utc>) -> Self {
+        let mut request = Self {
+            id: new_id(),
+            created_at: now.to_rfc3339(),
+            base_url: base_url.to_string(),
+            args,
+            requested_by: key.public_key(),
+            signature: String::new(),
+            approval: None,
+            executed_at: None,
+        };
+        request.signature = key.sign(&request.signed_bytes());
+        request
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid change request {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text =
+            serde_json::to_string_pretty(self).expect("ChangeRequest serialization is infallible");
+        std::fs::write(path, text + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// `md-change-<id>.json`.
+    pub fn file_name(&self) -> PathBuf {
+        PathBuf::from(format!("md-change-{}.json", self.id))
+    }
+
+    /// Sign as approver. The approver must be listed in `approvers` and must not be the
+    /// requester, and the request must be intact, unexpired, and not yet approved.
+    pub fn approve(
+        &mut self,
+        key: &SigningKey,
+        approvers: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.check_pending(now)?;
+        anyhow::ensure!(
+            self.approval.is_none(),
+            "change request {} is already approved",
+            self.id
+        );
+        let approved_by = key.public_key();
+        check_approver(&approved_by, &self.requested_by, approvers)?;
+        let approved_at = now.to_rfc3339();
+        let signature = key.sign(&self.approval_bytes(&approved_by, &approved_at));
+        self.approval = Some(Approval {
+            approved_by,
+            approved_at,
+            signature,
+        });
+        Ok(())
+    }
+
+    /// Check the request may run now against `base_url`: both signatures verify, the
+    /// approver is listed, and it has neither expired nor run before.
+    pub fn check_executable(
+        &self,
+        approvers: &[String],
+        base_url: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.check_pending(now)?;
+        let Some(approval) = &self.approval else {
+            bail!(
+                "change request {} has not been approved (run `md approve` as a second operator)",
+                self.id
+            );
+        };
+        check_approver(&approval.approved_by, &self.requested_by, approvers)?;
+        signing::verify(
+            &approval.approved_by,
+            &self.approval_bytes(&approval.approved_by, &approval.approved_at),
+            &approval.signature,
+        )
+        .with_context(|| format!("approval of change request {} is invalid", self.id))?;
+        anyhow::ensure!(
+            self.base_url == base_url,
+            "change request {} was made for {}, not {base_url}",
+            self.id,
+            self.base_url
+        );
+        Ok(())
+    }
+
+    /// Intact, unexpired, and not executed.
+    fn check_pending(&self, now: DateTime<Utc>) -> Result<()> {
+        signing::verify(&self.requested_by, &self.signed_bytes(), &self.signature)
+            .with_context(|| format!("change request {} has been altered", self.id))?;
+        if let Some(at) = &self.executed_at {
+            bail!("change request {} was already executed at {at}", self.id);
+        }
+        let created = DateTime::parse_from_rfc3339(&self.created_at)
+            .with_context(|| format!("invalid created_at in change request {}", self.id))?;
+        anyhow::ensure!(
+            now - created.to_utc() < MAX_AGE,
+            "change request {} expired (made {}; requests are valid for 24h)",
+            self.id,
+            self.created_at
+        );
+        Ok(())
+    }
+
+    /// What the requester signs.
+    fn signed_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            &self.id,
+            &self.created_at,
+            &self.base_url,
+            &self.args,
+            &self.requested_by,
+        ))
+        .expect("tuple serialization is infallible")
+    }
+
+    /// What the approver signs: the request, the requester's signature, and the approval.
+    fn approval_bytes(&self, approved_by: &str, approved_at: &str) -> Vec<u8> {
+        serde_json::to_vec(&(
+            String::from_utf8(self.signed_bytes()).expect("JSON is UTF-8"),
+            &self.signature,
+            approved_by,
+            approved_at,
+        ))
+        .expect("tuple serialization is infallible")
+    }
+}
+
+fn check_approver(approver: &str, requester: &str, approvers: &[String]) -> Result<()> {
+    anyhow::ensure!(
+        !approvers.is_empty(),
+        "no approvers configured; list their public keys in `approval.approvers`"
+    );
+    anyhow::ensure!(
+        approver != requester,
+        "a change request cannot be approved with the requester's own key"
+    );
+    anyhow::ensure!(
+        approvers.iter().any(|k| k.eq_ignore_ascii_case(approver)),
+        "key {approver} is not listed in `approval.approvers`"
+    );
+    Ok(())
+}
+
+/// Random 8-hex-digit ID, short enough to read out to another operator.
+pub fn new_id() -> String {
+    let hash = RandomState::new().hash_one(SystemTime::now());
+    format!("{:08x}", hash as u32)
+}
+
+/// Arguments worth recording in a change request: everything except the program name and
+/// the flags that identify the operator rather than the change (token, config file).
+pub fn recorded_args(args: &[std::ffi::OsString]) -> Vec<String> {
+    const OPERATOR_FLAGS: &[&str] = &["--token", "--config"];
+    let mut recorded = Vec::new();
+    let mut iter = args
+        .iter()
+        .skip(1)
+        .map(|a| a.to_string_lossy().into_owned());
+    while let Some(arg) = iter.next() {
+        if OPERATOR_FLAGS.contains(&arg.as_str()) {
+            iter.next();
+        } else if arg == "--clear-clipboard"
+            || OPERATOR_FLAGS
+                .iter()
+                .any(|f| arg.starts_with(&format!("{f}=")))
+        {
+            continue;
+        } else {
+            recorded.push(arg);
+        }
+    }
+    recorded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2026-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn key() -> SigningKey {
+        SigningKey::ephemeral()
+    }
+
+    fn request(requester: &SigningKey) -> ChangeRequest {
+        let args = vec!["service-account".into(), "delete".into(), "svc_x".into()];
+        ChangeRequest::new(requester, "http://api", args, now())
+    }
+
+    #[test]
+    fn approved_request_is_executable_once_approved() {
+        let (requester, approver) = (key(), key());
+        let approvers = [approver.public_key()];
+        let mut request = request(&requester);
+        assert!(
+            request
+                .check_executable(&approvers, "http://api", now())
+                .is_err()
+        );
+        request.approve(&approver, &approvers, now()).unwrap();
+        request
+            .check_executable(&approvers, "http://api", now())
+            .unwrap();
+        assert!(
+            request
+                .check_executable(&approvers, "http://other", now())
+                .is_err()
+        );
+        let later = now() + TimeDelta::hours(25);
+        let err = request
+            .check_executable(&approvers, "http://api", later)
+            .unwrap_err();
+        assert!(err.to_string().contains("expired"));
+    }
+
+    #[test]
+    fn requester_and_unlisted_keys_cannot_approve() {
+        let (requester, stranger) = (key(), key());
+        let mut request = request(&requester);
+        let err = request
+            .approve(&requester, &[requester.public_key()], now())
+            .unwrap_err();
+        assert!(err.to_string().contains("requester's own key"));
+        let err = request
+            .approve(&stranger, &[requester.public_key()], now())
+            .unwrap_err();
+        assert!(err.to_string().contains("not listed"));
+        assert!(request.approve(&stranger, &[], now()).is_err());
+    }
+
+    #[test]
+    fn tampering_invalidates_request_and_approval() {
+        let (requester, approver) = (key(), key());
+        let approvers = [approver.public_key()];
+        let mut request = request(&requester);
+        request.approve(&approver, &approvers, now()).unwrap();
+        request.args[2] = "svc_prod".into();
+        let err = request
+            .check_executable(&approvers, "http://api", now())
+            .unwrap_err();
+        assert!(err.to_string().contains("altered"));
+    }
+
+    #[test]
+    fn recorded_args_drop_operator_flags() {
+        let args: Vec<std::ffi::OsString> = [
+            "md",
+            "--token",
+            "secret",
+            "-y",
+            "--config=/tmp/c.toml",
+            "token",
+            "delete",
+            "svc_x",
+            "abc",
+            "--clear-clipboard",
+        ]
+        .iter()
+        .map(Into::into)
+        .collect();
+        assert_eq!(
+            recorded_args(&args),
+            ["-y", "token", "delete", "svc_x", "abc"]
+        );
+    }
+}
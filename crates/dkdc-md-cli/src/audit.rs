@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// One row of an owners file: `username,owner[,owner_active]`.
+#[derive(Debug, Deserialize)]
+struct OwnerRecord {
+    username: String,
+    owner: String,
+    #[serde(default = "default_true")]
+    owner_active: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Known owners keyed by username.
+#[derive(Debug, Default)]
+pub struct Owners(HashMap<String, OwnerRecord>);
+
+impl Owners {
+    /// Read an owners CSV with a header row. `owner_active` is optional (`true`/`false`).
+    pub fn from_csv(reader: impl Read) -> Result<Self> {
+        let mut owners = HashMap::new();
+        for (i, record) in csv::Reader::from_reader(reader).deserialize().enumerate() {
+            let record: OwnerRecord =
+                record.with_context(|| format!("invalid owners file row {}", i + 1))?;
+            owners.insert(record.username.clone(), record);
+        }
+        Ok(Self(owners))
+    }
+}
+
+/// A service account with no known, current owner.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Orphan {
+    pub username: String,
+    pub owner: Option<String>,
+    pub tokens: usize,
+    pub reason: &'static str,
+}
+
+/// Cross-reference accounts (with their token counts) against the owners file.
+pub fn find_orphans(accounts: &[(String, usize)], owners: &Owners) -> Vec<Orphan> {
+    accounts
+        .iter()
+        .filter_map(|(username, tokens)| {
+            let (owner, reason) = match owners.0.get(username) {
+                None => (None, "no owner"),
+                Some(r) if r.owner.trim().is_empty() => (None, "no owner"),
+                Some(r) if !r.owner_active => (Some(r.owner.clone()), "owner departed"),
+                Some(_) => return None,
+            };
+            Some(Orphan {
+                username: username.clone(),
+                owner,
+                tokens: *tokens,
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Write orphans as CSV (with header) for ticket imports.
+pub fn write_csv(out: impl Write, orphans: &[Orphan]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    for orphan in orphans {
+        writer.serialize(orphan)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNERS: &str = "\
+username,owner,owner_active
+svc_etl,data-platform,true
+svc_old,alice,false
+svc_blank,,true
+";
+
+    fn accounts(names: &[(&str, usize)]) -> Vec<(String, usize)> {
+        names.iter().map(|(n, t)| (n.to_string(), *t)).collect()
+    }
+
+    #[test]
+    fn reports_missing_and_departed_owners() {
+        let owners = Owners::from_csv(OWNERS.as_bytes()).unwrap();
+        let orphans = find_orphans(
+            &accounts(&[
+                ("svc_etl", 2),
+                ("svc_old", 1),
+                ("svc_new", 0),
+                ("svc_blank", 3),
+            ]),
+            &owners,
+        );
+        assert_eq!(
+            orphans,
+            [
+                Orphan {
+                    username: "svc_old".into(),
+                    owner: Some("alice".into()),
+                    tokens: 1,
+                    reason: "owner departed",
+                },
+                Orphan {
+                    username: "svc_new".into(),
+                    owner: None,
+                    tokens: 0,
+                    reason: "no owner",
+                },
+                Orphan {
+                    username: "svc_blank".into(),
+                    owner: None,
+                    tokens: 3,
+                    reason: "no owner",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn owner_active_column_is_optional() {
+        let owners = Owners::from_csv("username,owner\nsvc_etl,bob\n".as_bytes()).unwrap();
+        assert!(find_orphans(&accounts(&[("svc_etl", 1)]), &owners).is_empty());
+    }
+
+    #[test]
+    fn invalid_owners_row_errors() {
+        let err = Owners::from_csv("username,owner,owner_active\nsvc,bob,maybe\n".as_bytes())
+            .unwrap_err();
+        assert!(err.to_string().contains("row 1"));
+    }
+
+    #[test]
+    fn csv_export_has_header() {
+        let orphans = [Orphan {
+            username: "svc_new".into(),
+            owner: None,
+            tokens: 2,
+            reason: "no owner",
+        }];
+        let mut buf = Vec::new();
+        write_csv(&mut buf, &orphans).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "username,owner,tokens,reason\nsvc_new,,2,no owner\n"
+        );
+    }
+}
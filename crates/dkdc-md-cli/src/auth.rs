@@ -1,6 +1,10 @@
 use std::io::Read;
+use std::process::{Command, Stdio};
 
 use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde_json::{Map, Value};
 
 const ENV_VARS: &[&str] = &[
     "motherduck_token",
@@ -9,15 +13,208 @@ const ENV_VARS: &[&str] = &[
     "MOTHERDUCK_API_KEY",
 ];
 
+/// `--token` value that reads the token from the system clipboard.
+pub const CLIPBOARD: &str = "clipboard";
+
+/// OS keyring service `md auth login` stores tokens under.
+pub const KEYRING_SERVICE: &str = "md";
+/// Keyring account for runs without a profile; with one, the profile's name is the account.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// Platform clipboard commands: `paste` prints the clipboard, `clear` empties it
+/// (run with an empty stdin, which is how `pbcopy`/`xclip` are cleared).
+#[derive(Debug, PartialEq, Eq)]
+struct ClipboardTool {
+    paste: &'static [&'static str],
+    clear: &'static [&'static str],
+}
+
+const PBPASTE: ClipboardTool = ClipboardTool {
+    paste: &["pbpaste"],
+    clear: &["pbcopy"],
+};
+const WL_CLIPBOARD: ClipboardTool = ClipboardTool {
+    paste: &["wl-paste", "--no-newline"],
+    clear: &["wl-copy", "--clear"],
+};
+const XCLIP: ClipboardTool = ClipboardTool {
+    paste: &["xclip", "-selection", "clipboard", "-o"],
+    clear: &["xclip", "-selection", "clipboard", "-i"],
+};
+const POWERSHELL: ClipboardTool = ClipboardTool {
+    paste: &["powershell", "-NoProfile", "-Command", "Get-Clipboard"],
+    clear: &[
+        "powershell",
+        "-NoProfile",
+        "-Command",
+        "Set-Clipboard -Value $null",
+    ],
+};
+
+fn clipboard_tool(
+    os: &str,
+    env_var: impl Fn(&str) -> Result<String, std::env::VarError>,
+) -> ClipboardTool {
+    match os {
+        "macos" => PBPASTE,
+        "windows" => POWERSHELL,
+        _ if env_var("WAYLAND_DISPLAY").is_ok_and(|v| !v.is_empty()) => WL_CLIPBOARD,
+        _ => XCLIP,
+    }
+}
+
+fn clipboard_command(argv: &[&str]) -> Command {
+    let mut cmd = Command::new(argv[0]);
+    cmd.args(&argv[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    cmd
+}
+
+fn check_status(argv: &[&str], status: std::process::ExitStatus) -> Result<()> {
+    anyhow::ensure!(status.success(), "{} exited with {status}", argv[0]);
+    Ok(())
+}
+
+fn paste_clipboard(argv: &[&str]) -> Result<String> {
+    let output = clipboard_command(argv)
+        .output()
+        .with_context(|| format!("failed to run {} (is it installed?)", argv[0]))?;
+    check_status(argv, output.status)?;
+    String::from_utf8(output.stdout).context("clipboard is not valid UTF-8")
+}
+
+fn read_token_from_clipboard() -> Result<String> {
+    let tool = clipboard_tool(std::env::consts::OS, |k| std::env::var(k));
+    let t = trimmed(&paste_clipboard(tool.paste)?);
+    anyhow::ensure!(!t.is_empty(), "clipboard was empty; expected a token");
+    Ok(t)
+}
+
+/// Empty the system clipboard, e.g. after reading a token from it.
+pub fn clear_clipboard() -> Result<()> {
+    let tool = clipboard_tool(std::env::consts::OS, |k| std::env::var(k));
+    // stdout is not captured: xclip forks a process that keeps serving the selection.
+    let status = clipboard_command(tool.clear)
+        .stdout(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to run {} (is it installed?)", tool.clear[0]))?;
+    check_status(tool.clear, status)
+}
+
 /// Trim whitespace and convert to an owned `String`.
 fn trimmed(s: &str) -> String {
     s.trim().to_string()
 }
 
-/// Resolve token: CLI flag takes precedence over env vars.
-/// Pass `Some("-")` to read from stdin.
+/// Resolve token: CLI flag takes precedence over env vars, which take precedence over the
+/// token `md auth login` stored in the OS keyring.
+/// Pass `Some("-")` to read from stdin, or `Some("clipboard")` to read the system clipboard.
 pub fn resolve_token_or(cli_token: Option<&str>) -> Result<String> {
-    resolve_token_or_with(cli_token, |k| std::env::var(k), std::io::stdin())
+    resolve_token_in(cli_token, DEFAULT_ACCOUNT)
+}
+
+/// Like [`resolve_token_or`], falling back to the keyring entry for `account`.
+pub fn resolve_token_in(cli_token: Option<&str>, account: &str) -> Result<String> {
+    if cli_token == Some(CLIPBOARD) {
+        return read_token_from_clipboard();
+    }
+    let resolved = resolve_token_or_with(cli_token, |k| std::env::var(k), std::io::stdin());
+    if cli_token.is_some() {
+        return resolved;
+    }
+    with_keyring_fallback(resolved, || keyring_token(account))
+}
+
+fn with_keyring_fallback(
+    resolved: Result<String>,
+    keyring: impl FnOnce() -> Result<Option<String>>,
+) -> Result<String> {
+    let Err(missing) = resolved else {
+        return resolved;
+    };
+    match keyring() {
+        Ok(Some(token)) => Ok(token),
+        Ok(None) => Err(missing),
+        Err(e) => Err(anyhow::anyhow!("{missing} ({e:#})")),
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    windows,
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+fn keyring_entry(account: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, account).context("cannot open the OS keyring")
+}
+
+/// keyring falls back to an in-memory mock elsewhere, which would accept a token and lose it.
+#[cfg(not(any(
+    target_os = "macos",
+    windows,
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd"
+)))]
+fn keyring_entry(_account: &str) -> Result<keyring::Entry> {
+    anyhow::bail!(
+        "no OS keyring on this platform; set MOTHERDUCK_TOKEN or a profile's token_command instead"
+    )
+}
+
+/// The token `md auth login` stored for `account`, if any.
+pub fn keyring_token(account: &str) -> Result<Option<String>> {
+    match keyring_entry(account)?.get_password() {
+        Ok(token) => Ok(Some(trimmed(&token)).filter(|t| !t.is_empty())),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e).context("cannot read the OS keyring"),
+    }
+}
+
+/// Store `token` for `account` in the OS keyring, replacing any earlier one.
+pub fn store_token(account: &str, token: &str) -> Result<()> {
+    keyring_entry(account)?
+        .set_password(token)
+        .context("cannot write to the OS keyring")
+}
+
+/// Remove the token stored for `account`; `Ok(false)` if there was none.
+pub fn forget_token(account: &str) -> Result<bool> {
+    match keyring_entry(account)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e).context("cannot write to the OS keyring"),
+    }
+}
+
+/// The claims in a token's JWT payload, e.g. for `md auth whoami`. They are read without
+/// checking the signature, which only the API can do. `None` if the token is not a JWT.
+pub fn token_claims(token: &str) -> Option<Map<String, Value>> {
+    let mut parts = token.split('.');
+    let (Some(_), Some(payload), Some(_), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let json = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// The first of the token env vars that is set, e.g. for `md auth status`.
+pub fn env_token_var() -> Option<&'static str> {
+    env_token_with(|k| std::env::var(k)).map(|(var, _)| var)
+}
+
+fn env_token_with(
+    env_var: impl Fn(&str) -> Result<String, std::env::VarError>,
+) -> Option<(&'static str, String)> {
+    ENV_VARS.iter().find_map(|var| {
+        let t = trimmed(&env_var(var).ok()?);
+        (!t.is_empty()).then_some((*var, t))
+    })
 }
 
 fn resolve_token_or_with(
@@ -49,19 +246,50 @@ fn read_token_from_reader(mut reader: impl Read) -> Result<String> {
 fn resolve_token_with(
     env_var: impl Fn(&str) -> Result<String, std::env::VarError>,
 ) -> Result<String> {
-    for var in ENV_VARS {
-        if let Ok(val) = env_var(var) {
-            let t = trimmed(&val);
-            if !t.is_empty() {
-                return Ok(t);
-            }
-        }
+    match env_token_with(env_var) {
+        Some((_, token)) => Ok(token),
+        None => bail!(
+            "No MotherDuck token found. Set one of: {}, or run `md auth login`",
+            ENV_VARS.join(", ")
+        ),
     }
+}
 
-    bail!(
-        "No MotherDuck token found. Set one of: {}",
-        ENV_VARS.join(", ")
-    )
+/// Read the token from env var `var` (a profile's `token_env`).
+pub fn token_from_env(var: &str) -> Result<String> {
+    token_from_env_with(var, |k| std::env::var(k))
+}
+
+fn token_from_env_with(
+    var: &str,
+    env_var: impl Fn(&str) -> Result<String, std::env::VarError>,
+) -> Result<String> {
+    let t = env_var(var).map(|v| trimmed(&v)).unwrap_or_default();
+    anyhow::ensure!(!t.is_empty(), "{var} is not set");
+    Ok(t)
+}
+
+/// Run `command` (a profile's `token_command`, split into words like a shell would but
+/// not run through one) and read the token from its stdout.
+pub fn token_from_command(command: &str) -> Result<String> {
+    let argv = shlex::split(command)
+        .filter(|argv| !argv.is_empty())
+        .with_context(|| format!("invalid token command '{command}'"))?;
+    let output = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("failed to run {}", argv[0]))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{} exited with {}",
+        argv[0],
+        output.status
+    );
+    let t = trimmed(&String::from_utf8(output.stdout).context("token is not valid UTF-8")?);
+    anyhow::ensure!(!t.is_empty(), "{} printed no token", argv[0]);
+    Ok(t)
 }
 
 #[cfg(test)]
@@ -126,6 +354,41 @@ mod tests {
         assert!(err.to_string().contains("No MotherDuck token found"));
     }
 
+    #[test]
+    fn reads_jwt_claims() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"username":"svc_etl","exp":1800000000}"#);
+        let claims = token_claims(&format!("eyJhbGciOiJIUzI1NiJ9.{payload}.sig")).unwrap();
+        assert_eq!(claims["username"], "svc_etl");
+        assert_eq!(claims["exp"], 1800000000);
+        assert!(token_claims("not-a-jwt").is_none());
+        assert!(token_claims("a.b.c").is_none());
+        assert!(token_claims(&format!("a.{payload}.c.d")).is_none());
+    }
+
+    #[test]
+    fn falls_back_to_keyring_after_env_vars() {
+        let env = env_with(&[("MOTHERDUCK_TOKEN", "env-token")]);
+        let stored = || Ok(Some("keyring-token".to_string()));
+        assert_eq!(
+            with_keyring_fallback(resolve_token_with(env), stored).unwrap(),
+            "env-token"
+        );
+        assert_eq!(
+            with_keyring_fallback(resolve_token_with(no_env), stored).unwrap(),
+            "keyring-token"
+        );
+        let err = with_keyring_fallback(resolve_token_with(no_env), || Ok(None)).unwrap_err();
+        assert!(err.to_string().ends_with("or run `md auth login`"));
+        let err = with_keyring_fallback(resolve_token_with(no_env), || {
+            Err(anyhow::anyhow!("no keyring daemon"))
+        })
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("`md auth login` (no keyring daemon)")
+        );
+    }
+
     // -- CLI token flag --
 
     #[test]
@@ -191,6 +454,25 @@ mod tests {
         assert_eq!(result.unwrap(), "env-tok");
     }
 
+    // -- clipboard --
+
+    #[test]
+    fn clipboard_tool_per_platform() {
+        assert_eq!(clipboard_tool("macos", no_env), PBPASTE);
+        assert_eq!(clipboard_tool("windows", no_env), POWERSHELL);
+        assert_eq!(clipboard_tool("linux", no_env), XCLIP);
+        let wayland = env_with(&[("WAYLAND_DISPLAY", "wayland-0")]);
+        assert_eq!(clipboard_tool("linux", wayland), WL_CLIPBOARD);
+        let empty = env_with(&[("WAYLAND_DISPLAY", "")]);
+        assert_eq!(clipboard_tool("freebsd", empty), XCLIP);
+    }
+
+    #[test]
+    fn missing_clipboard_tool_errors() {
+        let err = paste_clipboard(&["md-no-such-clipboard-tool"]).unwrap_err();
+        assert!(err.to_string().contains("is it installed?"));
+    }
+
     #[test]
     fn trims_whitespace_from_env_var() {
         let env = env_with(&[("MOTHERDUCK_TOKEN", "  tok-with-spaces  \n")]);
@@ -202,4 +484,26 @@ mod tests {
         let env = env_with(&[("motherduck_token", "  \n"), ("MOTHERDUCK_TOKEN", "real")]);
         assert_eq!(resolve_token_with(env).unwrap(), "real");
     }
+
+    #[test]
+    fn profile_token_env_must_be_set() {
+        let env = env_with(&[("MD_TOKEN_ACME", " acme-token\n"), ("MD_TOKEN_LABS", "")]);
+        assert_eq!(
+            token_from_env_with("MD_TOKEN_ACME", &env).unwrap(),
+            "acme-token"
+        );
+        let err = token_from_env_with("MD_TOKEN_LABS", &env).unwrap_err();
+        assert_eq!(err.to_string(), "MD_TOKEN_LABS is not set");
+    }
+
+    #[test]
+    fn invalid_token_command_errors() {
+        let err = token_from_command("op read 'unterminated").unwrap_err();
+        assert!(err.to_string().contains("invalid token command"));
+        let err = token_from_command("md-no-such-token-helper").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("failed to run md-no-such-token-helper")
+        );
+    }
 }
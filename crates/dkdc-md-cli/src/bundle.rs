@@ -0,0 +1,229 @@
+//! Signed command bundles: `md` commands prepared and signed on one machine (e.g. an
+//! offline workstation) and run with `md bundle execute` on another (e.g. a bastion host).
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::signing::{self, SigningKey};
+
+/// How long after signing a bundle may be executed.
+const MAX_AGE: TimeDelta = TimeDelta::days(7);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bundle {
+    pub id: String,
+    /// RFC 3339 creation time.
+    pub created_at: String,
+    /// API the bundle was prepared for; it only executes against the same one.
+    pub base_url: String,
+    /// `md` arguments of each command, without the program name.
+    pub commands: Vec<Vec<String>>,
+    /// Signer's public key.
+    pub signed_by: String,
+    /// Signature over the fields above.
+    pub signature: String,
+    /// RFC 3339 time `md bundle execute` started; a bundle executes once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executed_at: Option<String>,
+}
+
+impl Bundle {
+    pub fn new(
+        key: &SigningKey,
+        base_url: &str,
+        commands: Vec<Vec<String>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut bundle = Self {
+            id: crate::approval::new_id(),
+            created_at: now.to_rfc3339(),
+            base_url: base_url.to_string(),
+            commands,
+            signed_by: key.public_key(),
+            signature: String::new(),
+            executed_at: None,
+        };
+        bundle.signature = key.sign(&bundle.signed_bytes());
+        bundle
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("invalid bundle {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self).expect("Bundle serialization is infallible");
+        std::fs::write(path, text + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Check the signature matches the contents, whoever signed it.
+    pub fn check_intact(&self) -> Result<()> {
+        signing::verify(&self.signed_by, &self.signed_bytes(), &self.signature)
+            .with_context(|| format!("bundle {} has been altered", self.id))
+    }
+
+    /// Check the bundle is intact and signed by one of `trusted` keys.
+    pub fn verify(&self, trusted: &[String]) -> Result<()> {
+        self.check_intact()?;
+        anyhow::ensure!(
+            !trusted.is_empty(),
+            "no trusted signers configured; list their public keys in `bundles.trusted_signers`"
+        );
+        anyhow::ensure!(
+            trusted
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(&self.signed_by)),
+            "bundle {} is signed by {}, which is not listed in `bundles.trusted_signers`",
+            self.id,
+            self.signed_by
+        );
+        Ok(())
+    }
+
+    /// [`verify`](Self::verify), plus: not executed before, not expired, and made for
+    /// `base_url`.
+    pub fn check_executable(
+        &self,
+        trusted: &[String],
+        base_url: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        self.verify(trusted)?;
+        if let Some(at) = &self.executed_at {
+            bail!("bundle {} was already executed at {at}", self.id);
+        }
+        let created = DateTime::parse_from_rfc3339(&self.created_at)
+            .with_context(|| format!("invalid created_at in bundle {}", self.id))?;
+        anyhow::ensure!(
+            now - created.to_utc() < MAX_AGE,
+            "bundle {} expired (made {}; bundles are valid for 7 days)",
+            self.id,
+            self.created_at
+        );
+        anyhow::ensure!(
+            self.base_url == base_url,
+            "bundle {} was made for {}, not {base_url}",
+            self.id,
+            self.base_url
+        );
+        Ok(())
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            &self.id,
+            &self.created_at,
+            &self.base_url,
+            &self.commands,
+            &self.signed_by,
+        ))
+        .expect("tuple serialization is infallible")
+    }
+}
+
+/// One `md` command per line, shell-quoted and without `md`. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn parse_commands(text: &str) -> Result<Vec<Vec<String>>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(crate::schedule::split_command)
+        .collect()
+}
+
+/// Reject commands carrying flags that belong to the executing operator, not the bundle.
+pub fn check_command(args: &[String]) -> Result<()> {
+    const OPERATOR_FLAGS: &[&str] = &["--token", "--config", "--clear-clipboard"];
+    anyhow::ensure!(!args.is_empty(), "bundled commands must not be empty");
+    if let Some(flag) = args.iter().find(|a| {
+        OPERATOR_FLAGS
+            .iter()
+            .any(|f| *a == f || a.starts_with(&format!("{f}=")))
+    }) {
+        bail!("bundled commands cannot set {flag}; the executing operator supplies it");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2026-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn commands() -> Vec<Vec<String>> {
+        parse_commands(
+            "# rotate the ETL token\n\
+             token revoke-all svc_etl --reason 'quarterly rotation'\n\
+             \n\
+             token create svc_etl --name nightly --ttl 7776000\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_one_command_per_line() {
+        assert_eq!(
+            commands()[0],
+            [
+                "token",
+                "revoke-all",
+                "svc_etl",
+                "--reason",
+                "quarterly rotation"
+            ]
+        );
+        assert_eq!(commands().len(), 2);
+        assert!(parse_commands("token list 'unterminated").is_err());
+    }
+
+    #[test]
+    fn executes_only_when_trusted_fresh_and_unaltered() {
+        let (signer, stranger) = (SigningKey::ephemeral(), SigningKey::ephemeral());
+        let trusted = [signer.public_key()];
+        let mut bundle = Bundle::new(&signer, "http://api", commands(), now());
+        bundle
+            .check_executable(&trusted, "http://api", now())
+            .unwrap();
+        assert!(
+            bundle
+                .check_executable(&[stranger.public_key()], "http://api", now())
+                .is_err()
+        );
+        assert!(bundle.check_executable(&[], "http://api", now()).is_err());
+        assert!(
+            bundle
+                .check_executable(&trusted, "http://other", now())
+                .is_err()
+        );
+        let later = now() + TimeDelta::days(8);
+        assert!(
+            bundle
+                .check_executable(&trusted, "http://api", later)
+                .is_err()
+        );
+
+        bundle.commands[0][2] = "svc_prod".into();
+        assert!(bundle.check_intact().is_err());
+        let err = bundle.verify(&trusted).unwrap_err();
+        assert!(err.to_string().contains("altered"));
+    }
+
+    #[test]
+    fn rejects_operator_flags() {
+        let args = |s: &str| crate::schedule::split_command(s).unwrap();
+        check_command(&args("token list svc_etl")).unwrap();
+        assert!(check_command(&args("token list svc_etl --token abc")).is_err());
+        assert!(check_command(&args("--config=/tmp/x token list svc_etl")).is_err());
+        assert!(check_command(&[]).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::client::{
+    ApiError, MotherduckClient, active_accounts_path, user_instances_path, user_tokens_path,
+};
+use crate::resource::Resource;
+
+/// Probe results, cached next to `config.toml`.
+const CAPABILITIES_FILE: &str = "capabilities.json";
+/// How long a cached probe is trusted before `md capabilities` probes again.
+const MAX_AGE: TimeDelta = TimeDelta::days(1);
+/// Username the probes look up. Reads of a missing user return 404 when the endpoint is
+/// usable and 403 when it is not, so no real account is touched.
+pub(crate) const PROBE_USER: &str = "md_capabilities_probe";
+
+/// An area of the API a plan or token may not have access to.
+pub struct Capability {
+    pub name: &'static str,
+    pub description: &'static str,
+    probe_path: fn() -> String,
+}
+
+pub const CAPABILITIES: &[Capability] = &[
+    Capability {
+        name: "service-accounts",
+        description: "create and delete service accounts, manage their tokens",
+        probe_path: || user_tokens_path(PROBE_USER),
+    },
+    Capability {
+        name: "instances",
+        description: "read and change duckling configuration",
+        probe_path: || user_instances_path(PROBE_USER),
+    },
+    Capability {
+        name: "active-accounts",
+        description: "list active accounts and their ducklings",
+        probe_path: active_accounts_path,
+    },
+];
+
+/// Whether the current token can use a capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    Available,
+    /// 403: not enabled for the plan, or the token lacks the role.
+    NotEnabled,
+    /// 401: the token itself was rejected.
+    Unauthorized,
+    /// Any other failure (network, 5xx); says nothing about the plan.
+    Unknown,
+}
+
+impl Access {
+    fn from_probe(result: &Result<Value>) -> Self {
+        let status = match result {
+            Ok(_) => return Self::Available,
+            Err(e) => e.downcast_ref::<ApiError>().map(|e| e.status),
+        };
+        match status {
+            Some(404) => Self::Available,
+            Some(403) => Self::NotEnabled,
+            Some(401) => Self::Unauthorized,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Available => "available",
+            Self::NotEnabled => "not-enabled",
+            Self::Unauthorized => "unauthorized",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// The outcome of probing every capability with one token against one API.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    /// [`cache_key`] of the base URL and token the probe ran with.
+    key: String,
+    /// RFC 3339 time of the probe.
+    pub checked_at: String,
+    pub capabilities: BTreeMap<String, Access>,
+}
+
+impl Report {
+    pub fn probe(client: &MotherduckClient, key: &str, now: DateTime<Utc>) -> Self {
+        let capabilities = CAPABILITIES
+            .iter()
+            .map(|c| {
+                let result = client.call("GET", &(c.probe_path)(), None);
+                (c.name.to_string(), Access::from_probe(&result))
+            })
+            .collect();
+        Self {
+            key: key.to_string(),
+            checked_at: now.to_rfc3339(),
+            capabilities,
+        }
+    }
+
+    /// The cached report for `key`, if there is one younger than a day. A missing or
+    /// unreadable cache is not an error; it just means probing again.
+    pub fn load_fresh(path: &Path, key: &str, now: DateTime<Utc>) -> Option<Self> {
+        Self::load(path, key).filter(|r| {
+            DateTime::parse_from_rfc3339(&r.checked_at).is_ok_and(|t| now - t.to_utc() < MAX_AGE)
+        })
+    }
+
+    /// The cached report for `key`, however old.
+    pub fn load(path: &Path, key: &str) -> Option<Self> {
+        let text = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str::<Self>(&text)
+            .ok()
+            .filter(|r| r.key == key)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let text = serde_json::to_string_pretty(self).expect("Report serialization is infallible");
+        std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// `capabilities.json` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(CAPABILITIES_FILE)
+}
+
+/// Identifies the API and token a report belongs to without storing the token
+/// (64-bit FNV-1a, hex).
+pub fn cache_key(base_url: &str, token: &str) -> String {
+    let hash = [base_url.as_bytes(), b"\0", token.as_bytes()]
+        .concat()
+        .iter()
+        .fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+/// The capability a resource URI needs.
+pub fn for_resource(resource: &Resource) -> &'static str {
+    match resource {
+        Resource::User { .. } | Resource::Tokens { .. } | Resource::Token { .. } => {
+            "service-accounts"
+        }
+        Resource::Instances { .. } => "instances",
+        Resource::ActiveAccounts => "active-accounts",
+    }
+}
+
+/// "`<capability>` is not enabled" for a 403 the cached probe already predicted.
+pub fn explain(err: &anyhow::Error, capability: &str, report: Option<&Report>) -> Option<String> {
+    let forbidden = err
+        .downcast_ref::<ApiError>()
+        .is_some_and(|e| e.status == 403);
+    let report = report.filter(|r| r.capabilities.get(capability) == Some(&Access::NotEnabled))?;
+    forbidden.then(|| {
+        format!(
+            "`{capability}` is not enabled for your plan or token (per `md capabilities`, checked {}); \
+             ask an org admin to enable it or use a token with access",
+            report.checked_at
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(status: u16) -> anyhow::Error {
+        anyhow::Error::new(ApiError {
+            status,
+            message: "nope".into(),
+        })
+        .context("GET /v1/active_accounts")
+    }
+
+    fn report(access: Access, checked_at: &str) -> Report {
+        Report {
+            key: "k".into(),
+            checked_at: checked_at.into(),
+            capabilities: [("active-accounts".to_string(), access)].into(),
+        }
+    }
+
+    #[test]
+    fn classifies_probe_statuses() {
+        assert_eq!(Access::from_probe(&Ok(Value::Null)), Access::Available);
+        assert_eq!(Access::from_probe(&Err(api_error(404))), Access::Available);
+        assert_eq!(Access::from_probe(&Err(api_error(403))), Access::NotEnabled);
+        assert_eq!(
+            Access::from_probe(&Err(api_error(401))),
+            Access::Unauthorized
+        );
+        assert_eq!(Access::from_probe(&Err(api_error(500))), Access::Unknown);
+        assert_eq!(
+            Access::from_probe(&Err(anyhow::anyhow!("request failed"))),
+            Access::Unknown
+        );
+    }
+
+    #[test]
+    fn cache_key_depends_on_url_and_token() {
+        let key = cache_key("https://api.motherduck.com", "tok");
+        assert_eq!(key.len(), 16);
+        assert!(!key.contains("tok"));
+        assert_eq!(key, cache_key("https://api.motherduck.com", "tok"));
+        assert_ne!(key, cache_key("https://api.motherduck.com", "tok2"));
+        assert_ne!(key, cache_key("http://localhost", "tok"));
+    }
+
+    #[test]
+    fn explains_forbidden_when_probe_agrees() {
+        let not_enabled = report(Access::NotEnabled, "2026-01-01T00:00:00+00:00");
+        let msg = explain(&api_error(403), "active-accounts", Some(&not_enabled)).unwrap();
+        assert!(msg.starts_with("`active-accounts` is not enabled for your plan or token"));
+
+        let available = report(Access::Available, "2026-01-01T00:00:00+00:00");
+        assert!(explain(&api_error(403), "active-accounts", Some(&available)).is_none());
+        assert!(explain(&api_error(403), "active-accounts", None).is_none());
+        assert!(explain(&api_error(404), "active-accounts", Some(&not_enabled)).is_none());
+    }
+}
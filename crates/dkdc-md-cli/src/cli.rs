@@ -1,16 +1,82 @@
-use std::io::{IsTerminal, Write};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 use anyhow::{Context, Result, bail};
-use clap::{Parser, Subcommand, ValueEnum};
-use serde_json::Value;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde_json::{Value, json};
 
+use crate::annotations::{self, Annotations};
+use crate::approval::{self, ChangeRequest};
+use crate::audit;
 use crate::auth;
-use crate::client::MotherduckClient;
+use crate::bundle::{self, Bundle};
+use crate::capabilities::{self, Report};
+use crate::client::{self, ApiError, MotherduckClient, RetryOn};
+use crate::config::{self, Config, ConfirmLevel};
+use crate::dates::{self, Direction};
+use crate::defaults;
+use crate::deprecation;
+use crate::duckdb;
+use crate::executed::{self, Ledger};
+use crate::export;
+use crate::highlight;
+use crate::hints;
+use crate::inventory::{self, Inventory};
+use crate::middleware::{self, Stage};
+use crate::permissions;
+use crate::policy;
+use crate::psobject;
+use crate::query::Query;
+use crate::reconcile;
+use crate::reference;
+use crate::resource::Resource;
+use crate::sandbox::{self, Sandbox};
+use crate::schedule::{self, Schedules};
+use crate::search::{self, Index};
+use crate::sh;
+use crate::signing::{self, SigningKey};
+use crate::spec;
+use crate::validate;
+use crate::wait::{self, Condition};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
 enum OutputMode {
     Text,
     Json,
+    /// Shell variable assignments, for `eval "$(md ...)"`
+    Sh,
+    /// JSON on one line; `token list` and `account list-active` print one line per item
+    Ndjson,
+    /// One line of JSON shaped for PowerShell's `ConvertFrom-Json`
+    #[value(name = "psobject")]
+    PsObject,
+}
+
+/// When to color text and JSON output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// When stdout is a terminal and NO_COLOR is unset
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self, stdout_is_terminal: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => {
+                stdout_is_terminal && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, ValueEnum)]
@@ -49,23 +115,236 @@ impl TokenType {
     }
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum DucklingType {
+    ReadWrite,
+    ReadScaling,
+}
+
+impl DucklingType {
+    fn as_api_str(&self) -> &'static str {
+        match self {
+            Self::ReadWrite => "read_write",
+            Self::ReadScaling => "read_scaling",
+        }
+    }
+}
+
+/// Sections for `token list --group-by`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TokenGroupBy {
+    Type,
+    Name,
+    /// Live or expired
+    Status,
+}
+
+impl TokenGroupBy {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Type => "type",
+            Self::Name => "name",
+            Self::Status => "status",
+        }
+    }
+
+    fn group(self, token: &Value, now: chrono::DateTime<chrono::Utc>) -> String {
+        match self {
+            Self::Type => display_field(token, "token_type").to_string(),
+            Self::Name => display_field(token, "name").to_string(),
+            Self::Status if token_is_live(token, now) => "live".to_string(),
+            Self::Status => "expired".to_string(),
+        }
+    }
+}
+
+/// Sections for `account list-active --group-by`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum AccountGroupBy {
+    /// Statuses of the account's ducklings, e.g. "idle, running"
+    Status,
+    /// Owner from `md annotate`
+    Owner,
+    /// Ticket from `md annotate`
+    Ticket,
+}
+
+impl AccountGroupBy {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Owner => "owner",
+            Self::Ticket => "ticket",
+        }
+    }
+
+    fn group(self, account: &Value) -> String {
+        match self {
+            Self::Status => {
+                let statuses: BTreeSet<&str> = account["ducklings"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|d| display_field(d, "status"))
+                    .collect();
+                if statuses.is_empty() {
+                    "-".to_string()
+                } else {
+                    statuses.into_iter().collect::<Vec<_>>().join(", ")
+                }
+            }
+            Self::Owner => display_field(&account["annotations"], "owner").to_string(),
+            Self::Ticket => display_field(&account["annotations"], "ticket").to_string(),
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "md", version, about = "CLI for the MotherDuck REST API")]
 struct Cli {
-    /// Output format
-    #[arg(short, long, global = true, value_enum, default_value_t = OutputMode::Text)]
-    output: OutputMode,
+    /// Output format [default: text, or the command's entry in `[output]` in the config]
+    #[arg(short, long, global = true, value_enum)]
+    output: Option<OutputMode>,
 
-    /// API token (overrides env vars; use '-' to read from stdin)
+    /// Filter the JSON result with a jq expression, e.g. `.tokens[].id`, printing each value
+    /// it yields on its own line (strings unquoted in text output, as with `jq -r`)
+    #[arg(long, global = true, value_name = "EXPR")]
+    query: Option<String>,
+
+    /// Color text output and highlight JSON
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Same as --color never
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// API token (overrides env vars; use '-' to read from stdin, 'clipboard' for the clipboard)
     #[arg(long, global = true)]
     token: Option<String>,
 
+    /// Clear the clipboard after reading the token from it (with --token clipboard)
+    #[arg(long, global = true)]
+    clear_clipboard: bool,
+
+    /// API base URL (for proxies and testing)
+    #[arg(long, global = true, env = "MOTHERDUCK_API_URL", default_value = client::DEFAULT_BASE_URL, hide_default_value = true)]
+    base_url: String,
+
+    /// API version to call, e.g. v2 to opt into newer endpoints
+    #[arg(long, global = true, env = "MD_API_VERSION", default_value = client::DEFAULT_API_VERSION)]
+    api_version: String,
+
+    /// ID sent with every request so API-side logs can be matched to this run (default: random)
+    #[arg(long, global = true, env = "MD_CORRELATION_ID")]
+    correlation_id: Option<String>,
+
+    /// Appended to the user agent so API-side logs can attribute traffic to a pipeline,
+    /// e.g. "airflow-dag=provision"
+    #[arg(long, global = true, env = "MD_USER_AGENT_SUFFIX")]
+    user_agent_suffix: Option<String>,
+
+    /// Log each request, its status, and its latency to stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Print each API request to stderr as a curl command, with `$MOTHERDUCK_TOKEN` in place
+    /// of the token, e.g. to share a reproduction
+    #[arg(long, global = true)]
+    curl: bool,
+
+    /// Time limit for each HTTP request, e.g. 30s [default: `request_timeout` in `[defaults]`,
+    /// else 10s]
+    #[arg(long, global = true, env = "MD_REQUEST_TIMEOUT", value_parser = humantime::parse_duration)]
+    request_timeout: Option<std::time::Duration>,
+
+    /// Retry transient failures (connection errors, timeouts, 5xx) up to N times each
+    /// [default: `[retries]` in the config, else 0]
+    #[arg(long, global = true, env = "MD_RETRIES", value_name = "N")]
+    retries: Option<u32>,
+
+    /// Wait before the first retry, e.g. 1s; each later wait doubles, with jitter
+    #[arg(long, global = true, env = "MD_RETRY_DELAY", value_parser = humantime::parse_duration, default_value = "250ms")]
+    retry_delay: std::time::Duration,
+
+    /// Wait out rate limiting (429) as long as the API asks, up to 30s, and retry up to N
+    /// times; 0 fails at once
+    #[arg(
+        long,
+        global = true,
+        env = "MD_RATE_LIMIT_RETRIES",
+        value_name = "N",
+        default_value_t = 3
+    )]
+    rate_limit_retries: u32,
+
+    /// Config file (default: ~/.config/md/config.toml)
+    #[arg(long, global = true, env = config::CONFIG_ENV)]
+    config: Option<PathBuf>,
+
+    /// Profile from `[profiles]` in the config, for working with several organizations
+    /// [default: the one chosen with `md profile use`]
+    #[arg(long, global = true, env = "MD_PROFILE")]
+    profile: Option<String>,
+
     /// Skip confirmation prompts
     #[arg(short = 'y', long = "yes", global = true)]
     yes: bool,
 
+    /// Skip all confirmations, including typed ones for especially destructive operations
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// Never prompt: confirmations fail unless --yes (or --force) answers them
+    #[arg(long, global = true, env = "MD_NON_INTERACTIVE")]
+    non_interactive: bool,
+
+    /// Stable output for automation: a versioned JSON envelope, no prompts or color, and
+    /// distinct exit codes per failure class
+    #[arg(long, global = true, env = "MD_API_MODE")]
+    api_mode: bool,
+
+    /// Confirm prompts automatically when stdin is not a terminal (the previous default)
+    #[arg(long, global = true, env = "MD_ASSUME_YES_WHEN_PIPED")]
+    assume_yes_when_piped: bool,
+
+    /// Fail instead of printing warnings (e.g. tokens without expiry)
+    #[arg(long, global = true)]
+    warnings_as_errors: bool,
+
+    /// Run against a built-in demo org instead of the API, to explore commands safely
+    /// (kept in sandbox.json next to config.toml)
+    #[arg(long, global = true, env = "MD_SANDBOX")]
+    sandbox: bool,
+
+    /// Exit with an error when a list, count, or search finds nothing, e.g. to alert when no
+    /// duckling is running
+    #[arg(long, global = true)]
+    fail_if_empty: bool,
+
+    /// End text listings with a line of totals, e.g. "23 tokens, 4 expiring within 7d"
+    #[arg(long, global = true, env = "MD_SUMMARY_FOOTER")]
+    summary_footer: bool,
+
+    /// Leave out notes on stderr, such as what to run when a listing is empty
+    #[arg(short, long, global = true, env = "MD_QUIET")]
+    quiet: bool,
+
+    /// Answer list, count, search, and audit commands from the local inventory (`md sync`)
+    /// instead of the API
+    #[arg(long, global = true)]
+    local: bool,
+
     #[command(subcommand)]
     command: Commands,
+
+    /// Subcommand names, e.g. `token list`, for per-command settings.
+    #[arg(skip)]
+    command_path: String,
+
+    /// Whether `--base-url` or its env var was given, which wins over the profile's.
+    #[arg(skip)]
+    base_url_given: bool,
 }
 
 #[derive(Subcommand)]
@@ -80,29 +359,438 @@ enum Commands {
         #[command(subcommand)]
         command: TokenCommands,
     },
-    /// Manage duckling configuration
-    Duckling {
+    /// Manage instance (duckling) configuration
+    #[command(visible_alias = "duckling")]
+    Instance {
         #[command(subcommand)]
-        command: DucklingCommands,
+        command: InstanceCommands,
     },
     /// Manage accounts
     Account {
         #[command(subcommand)]
         command: AccountCommands,
     },
+    /// Audit accounts and tokens
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+    /// Fetch a resource by URI, e.g. md://users/svc_x/tokens
+    Get {
+        /// Resource URI
+        uri: Resource,
+    },
+    /// Delete a resource by URI, e.g. md://users/svc_x/tokens/abc123
+    Delete {
+        /// Resource URI
+        uri: Resource,
+    },
+    /// Show what a resource URI refers to (no API call)
+    Describe {
+        /// Resource URI
+        uri: Resource,
+    },
+    /// Run commands on a cron schedule
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommands,
+    },
+    /// Create a service account with a token and duckling config, rolling back on failure
+    #[command(after_long_help = "Examples:
+  md provision svc_etl --ttl 90d --rw-size standard
+  md provision svc_bi --template analyst")]
+    Provision(ProvisionArgs),
+    /// Offboard a service account: revoke its tokens, scale its flock to 0, delete it
+    Deprovision {
+        /// Username
+        username: String,
+    },
+    /// Print a DuckDB connection string for a user's ducklings, ready to paste into a client
+    #[command(after_long_help = "Examples:
+  md connect-string svc_etl --database analytics
+  md connect-string svc_bi --read-scaling --session-hint alice --secret -")]
+    ConnectString(ConnectStringArgs),
+    /// Check that a user can connect: run `SELECT 1` through the DuckDB CLI and report latency
+    #[command(after_long_help = "Examples:
+  md test-connection svc_etl
+  md test-connection svc_bi --read-scaling --secret -")]
+    TestConnection(TestConnectionArgs),
+    /// Find accounts and tokens by username, token name, or token ID
+    Search {
+        /// Text to look for (case-insensitive substring)
+        pattern: String,
+        /// Rebuild the index even if a recent one is cached
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Update the local inventory that `--local` reads (accounts, token metadata, configs)
+    Sync {
+        /// Only refresh these accounts (default: every active account)
+        usernames: Vec<String>,
+    },
+    /// Show what `md sync` found appearing, disappearing, or changing in the org
+    Changes {
+        /// Earliest sync to include: today, yesterday, a date, an RFC 3339 time, or e.g. 7d
+        #[arg(long, default_value = "7d")]
+        since: String,
+    },
+    /// Keep local ownership notes on service accounts, shown in `account list-active` and
+    /// `describe`
+    Annotate {
+        #[command(subcommand)]
+        command: AnnotateCommands,
+    },
+    /// Poll until a duckling or account reaches a state, e.g. between deployment steps
+    Wait {
+        #[command(subcommand)]
+        command: WaitCommands,
+    },
+    /// Check which API features this token can use (cached for a day)
+    Capabilities {
+        /// Probe again even if a recent result is cached
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show the role and capabilities a command needs, and the API calls it makes
+    #[command(after_long_help = "Examples:
+  md permissions
+  md permissions token create")]
+    Permissions {
+        /// Command, e.g. `token create` [default: every command]
+        command: Vec<String>,
+    },
+    /// Manage the key that signs change requests and approvals
+    SigningKey {
+        #[command(subcommand)]
+        command: SigningKeyCommands,
+    },
+    /// Approve another operator's change request (see `[approval]` in the config)
+    Approve {
+        /// Change request file (md-change-<id>.json)
+        file: PathBuf,
+    },
+    /// Run an approved change request
+    Execute {
+        /// Change request file (md-change-<id>.json)
+        file: PathBuf,
+    },
+    /// Sign commands on one machine and run them on another (see `[bundles]` in the config)
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommands,
+    },
+    /// Keep the token in the OS keyring instead of env vars or shell history
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+    /// Read and change settings in the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// List profiles and choose the active one (see `[profiles]` in the config)
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// Tools for maintainers tracking the MotherDuck API
+    Dev {
+        #[command(subcommand)]
+        command: DevCommands,
+    },
+    /// Print a shell completion script
+    #[command(after_long_help = "Examples:
+  md completions bash > ~/.local/share/bash-completion/completions/md
+  md completions zsh > ~/.zfunc/_md
+  md completions fish > ~/.config/fish/completions/md.fish
+  md completions powershell >> $PROFILE")]
+    Completions {
+        /// Shell to complete for
+        shell: clap_complete::Shell,
+    },
+    /// Generate files derived from the CLI itself
+    #[command(hide = true)]
+    Generate {
+        #[command(subcommand)]
+        command: GenerateCommands,
+    },
+}
+
+impl Commands {
+    /// Capabilities (see `md capabilities`) the command at `path` cannot run without: its
+    /// `permissions` entry, or the resource's for commands taking a resource URI.
+    fn required_capabilities(&self, path: &str) -> Vec<&'static str> {
+        match self {
+            Self::Get { uri } | Self::Delete { uri } => vec![capabilities::for_resource(uri)],
+            _ => permissions::lookup(path).map_or_else(Vec::new, |p| p.capabilities.to_vec()),
+        }
+    }
+
+    /// Commands that never call the API, so they run without a token.
+    fn local(&self) -> bool {
+        matches!(
+            self,
+            Self::SigningKey { .. }
+                | Self::Approve { .. }
+                | Self::Annotate { .. }
+                | Self::Permissions { .. }
+                | Self::Auth {
+                    command: AuthCommands::Login | AuthCommands::Logout | AuthCommands::Status
+                }
+                | Self::ServiceAccount {
+                    command: ServiceAccountCommands::Expiring {
+                        deprovision: false,
+                        ..
+                    }
+                }
+                | Self::Bundle {
+                    command: BundleCommands::Create { .. } | BundleCommands::Show { .. }
+                }
+                | Self::Instance {
+                    command: InstanceCommands::Validate { username: None, .. }
+                }
+        )
+    }
+
+    /// Read-only commands that `--local` can answer from the inventory.
+    fn reads_inventory(&self) -> bool {
+        matches!(
+            self,
+            Self::Token {
+                command: TokenCommands::List { .. }
+                    | TokenCommands::Count { .. }
+                    | TokenCommands::Reconcile { .. }
+            } | Self::Account {
+                command: AccountCommands::ListActive { stream: false, .. }
+                    | AccountCommands::Count { .. }
+                    | AccountCommands::Tree
+            } | Self::Audit { .. }
+                | Self::Search { .. }
+        )
+    }
+
+    /// Commands with results that `--fail-if-empty` can check.
+    fn lists(&self) -> bool {
+        matches!(
+            self,
+            Self::Token {
+                command: TokenCommands::List { .. } | TokenCommands::Count { .. }
+            } | Self::Account {
+                command: AccountCommands::ListActive { .. }
+                    | AccountCommands::Count { .. }
+                    | AccountCommands::Tree
+            } | Self::ServiceAccount {
+                command: ServiceAccountCommands::Expiring {
+                    deprovision: false,
+                    ..
+                }
+            } | Self::Search { .. }
+                | Self::Changes { .. }
+        )
+    }
+
+    /// Commands that run or sign other commands, which bundles cannot contain.
+    fn wraps_commands(&self) -> bool {
+        matches!(
+            self,
+            Self::Schedule { .. }
+                | Self::SigningKey { .. }
+                | Self::Approve { .. }
+                | Self::Execute { .. }
+                | Self::Bundle { .. }
+        )
+    }
+
+    /// Commands that change the org, which `[hooks]` run around. `test-connection` does
+    /// unless given `--secret`, since it then creates (and deletes) a token.
+    fn mutates(&self) -> bool {
+        self.destructive()
+            || matches!(
+                self,
+                Self::ServiceAccount {
+                    command: ServiceAccountCommands::Create { .. }
+                } | Self::Token {
+                    command: TokenCommands::Create { .. }
+                } | Self::Instance {
+                    command: InstanceCommands::Set { .. }
+                } | Self::Provision(_)
+                    | Self::TestConnection(TestConnectionArgs { secret: None, .. })
+            )
+    }
+
+    /// Commands that need a second operator's approval under `[approval] required = true`.
+    fn destructive(&self) -> bool {
+        matches!(
+            self,
+            Self::ServiceAccount {
+                command: ServiceAccountCommands::Delete { .. }
+                    | ServiceAccountCommands::Expiring {
+                        deprovision: true,
+                        ..
+                    }
+            } | Self::Token {
+                command: TokenCommands::Delete { .. } | TokenCommands::RevokeAll { .. }
+            } | Self::Instance {
+                command: InstanceCommands::ScaleDown { .. }
+            } | Self::Delete { .. }
+                | Self::Deprovision { .. }
+        )
+    }
+}
+
+#[derive(clap::Args)]
+struct ConnectStringArgs {
+    /// Username
+    username: String,
+    /// Connect to the read-scaling flock instead of the read-write duckling
+    #[arg(long)]
+    read_scaling: bool,
+    /// Database to open [default: the account's default database]
+    #[arg(long)]
+    database: Option<String>,
+    /// Send clients with the same hint to the same read-scaling replica
+    #[arg(long, requires = "read_scaling")]
+    session_hint: Option<String>,
+    /// The user's token to embed, or `-` to read it from stdin [default: none; DuckDB reads
+    /// `motherduck_token` from the environment]
+    #[arg(long)]
+    secret: Option<String>,
+    /// Include the token value in JSON output (redacted by default)
+    #[arg(long)]
+    show_secret: bool,
+}
+
+#[derive(clap::Args)]
+struct TestConnectionArgs {
+    /// Username
+    username: String,
+    /// Connect to the read-scaling flock instead of the read-write duckling
+    #[arg(long)]
+    read_scaling: bool,
+    /// Database to open [default: the account's default database]
+    #[arg(long)]
+    database: Option<String>,
+    /// The user's token, or `-` to read it from stdin [default: create a 5-minute token
+    /// and delete it afterwards]
+    #[arg(long)]
+    secret: Option<String>,
+    /// DuckDB CLI to run
+    #[arg(long, env = "MD_DUCKDB", default_value = "duckdb")]
+    duckdb: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct ProvisionArgs {
+    /// Username
+    username: String,
+    /// Config template (`[templates.<name>]`) supplying defaults for the options below
+    #[arg(long)]
+    template: Option<String>,
+    /// Name of the token to create [default: default]
+    #[arg(long)]
+    token_name: Option<String>,
+    /// Token time-to-live, e.g. 90d, 12h, or seconds (5m-365d)
+    #[arg(long, value_parser = parse_ttl)]
+    ttl: Option<u64>,
+    /// Token type [default: read-write]
+    #[arg(long, value_enum)]
+    token_type: Option<TokenType>,
+    /// Read-write instance size
+    #[arg(long, value_enum)]
+    rw_size: Option<InstanceSize>,
+    /// Read-scaling instance size
+    #[arg(long, value_enum)]
+    rs_size: Option<InstanceSize>,
+    /// Read-scaling flock size (0-64)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=64))]
+    flock_size: Option<u32>,
+    /// Include the token value in JSON output (redacted by default)
+    #[arg(long)]
+    show_secret: bool,
+    /// Field of the result (as in `-o json`) to print in text output, e.g. `token.id`
+    /// [default: token.token]
+    #[arg(long, value_name = "FIELD")]
+    print: Option<String>,
+}
+
+impl ProvisionArgs {
+    /// Fill options not given on the command line from `template`.
+    fn apply_template(&mut self, name: &str, template: &config::Template) -> Result<()> {
+        let parse_size = |s: &Option<String>| {
+            s.as_deref()
+                .map(|s| InstanceSize::from_str(s, true))
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("template '{name}': invalid instance size: {e}"))
+        };
+        if let Some(ttl) = template.ttl {
+            anyhow::ensure!(
+                policy::TTL_RANGE.contains(&ttl),
+                "template '{name}': ttl must be between 5m and 365d"
+            );
+        }
+        if let Some(n) = template.flock_size {
+            anyhow::ensure!(n <= 64, "template '{name}': flock_size must be 0-64");
+        }
+        let token_type = template
+            .token_type
+            .as_deref()
+            .map(|s| TokenType::from_str(s, true))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("template '{name}': invalid token type: {e}"))?;
+        self.token_name = self.token_name.take().or(template.token_name.clone());
+        self.ttl = self.ttl.or(template.ttl);
+        self.token_type = self.token_type.or(token_type);
+        self.rw_size = self.rw_size.or(parse_size(&template.rw_size)?);
+        self.rs_size = self.rs_size.or(parse_size(&template.rs_size)?);
+        self.flock_size = self.flock_size.or(template.flock_size);
+        Ok(())
+    }
 }
 
 #[derive(Subcommand)]
 enum ServiceAccountCommands {
     /// Create a new service account
     Create {
-        /// Username
+        /// Username (the `{name}` value when using --from-template)
         username: String,
+        /// Build the username from a template, e.g. 'team-{name}'
+        #[arg(long)]
+        from_template: Option<String>,
+        /// Extra template variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_key_val, requires = "from_template")]
+        vars: Vec<(String, String)>,
+        /// Succeed without changes if the service account already exists
+        #[arg(long)]
+        if_not_exists: bool,
+        /// Intended lifetime, e.g. 90d, or a date; recorded in the local annotations for
+        /// `md service-account expiring`
+        #[arg(long, value_name = "WHEN")]
+        expires: Option<String>,
+        /// Field of the result to print in text output [default: username]
+        #[arg(long, value_name = "FIELD")]
+        print: Option<String>,
     },
     /// Delete a service account
     Delete {
         /// Username
         username: String,
+        /// The username again, to delete an account with live tokens under
+        /// --non-interactive (--force does not skip that confirmation)
+        #[arg(long, value_name = "USERNAME")]
+        confirm: Option<String>,
+    },
+    /// List service accounts past the lifetime set with --expires
+    #[command(after_long_help = "Examples:
+  md service-account expiring --within 7d
+  md service-account expiring --deprovision --force")]
+    Expiring {
+        /// Also list accounts that expire within this long, e.g. 7d
+        #[arg(long, value_name = "DURATION")]
+        within: Option<String>,
+        /// Deprovision each listed account (see `md deprovision`)
+        #[arg(long)]
+        deprovision: bool,
     },
 }
 
@@ -112,20 +800,50 @@ enum TokenCommands {
     List {
         /// Username
         username: String,
+        /// Leave out expired tokens
+        #[arg(long, conflicts_with = "only_expired")]
+        hide_expired: bool,
+        /// Show only expired tokens
+        #[arg(long)]
+        only_expired: bool,
+        /// Only tokens created after this time: today, yesterday, a date, an RFC 3339 time,
+        /// or e.g. 30d (ago)
+        #[arg(long, value_name = "WHEN")]
+        created_after: Option<String>,
+        /// Only tokens created before this time (same formats as --created-after)
+        #[arg(long, value_name = "WHEN")]
+        created_before: Option<String>,
+        /// Only tokens that expire before this time: a date, an RFC 3339 time, or e.g. 7d
+        /// (from now); tokens without expiry never match
+        #[arg(long, value_name = "WHEN")]
+        expires_before: Option<String>,
+        /// Print the table in sections with per-section counts (text output)
+        #[arg(long, value_enum)]
+        group_by: Option<TokenGroupBy>,
     },
     /// Create a new access token
+    #[command(after_long_help = "Examples:
+  md token create svc_etl -n ci --ttl 90d
+  md token create svc_bi -n dashboards --token-type read-scaling -o json --show-secret
+  md token create svc_etl -n ci --print id")]
     Create {
         /// Username
         username: String,
         /// Token name
         #[arg(short, long)]
         name: String,
-        /// Time-to-live in seconds (300-31536000)
-        #[arg(long, value_parser = clap::value_parser!(u64).range(300..=31536000))]
+        /// Token time-to-live, e.g. 90d, 12h, or seconds (5m-365d)
+        #[arg(long, value_parser = parse_ttl)]
         ttl: Option<u64>,
         /// Token type
         #[arg(long, value_enum, default_value_t = TokenType::ReadWrite)]
         token_type: TokenType,
+        /// Include the token value in JSON output (redacted by default)
+        #[arg(long)]
+        show_secret: bool,
+        /// Field of the result to print in text output, e.g. `id` [default: token]
+        #[arg(long, value_name = "FIELD")]
+        print: Option<String>,
     },
     /// Delete an access token
     Delete {
@@ -134,20 +852,56 @@ enum TokenCommands {
         /// Token ID
         token_id: String,
     },
+    /// Delete every token for a user (e.g. after a credential leak)
+    RevokeAll {
+        /// Username
+        username: String,
+        /// Why the tokens are being revoked (echoed in the output for incident logs)
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Count tokens for users
+    #[command(group(clap::ArgGroup::new("targets").required(true)))]
+    Count {
+        /// Usernames to count tokens for
+        #[arg(group = "targets")]
+        usernames: Vec<String>,
+        /// Every active account
+        #[arg(long, group = "targets")]
+        all_users: bool,
+    },
+    /// Compare a token inventory CSV against live tokens and report differences
+    Reconcile {
+        /// Inventory CSV with columns username,id,name (id or name may be empty)
+        #[arg(long)]
+        inventory: PathBuf,
+        /// Also check every active account, so accounts missing from the inventory show
+        /// their tokens as untracked
+        #[arg(long)]
+        all_users: bool,
+    },
 }
 
 #[derive(Subcommand)]
-enum DucklingCommands {
-    /// Get duckling configuration for a user
+enum InstanceCommands {
+    /// Get instance configuration for a user
     Get {
         /// Username
         username: String,
     },
-    /// Set duckling configuration for a user (fetches current config, merges overrides)
+    /// Set instance configuration for a user (fetches current config, merges overrides)
+    #[command(after_long_help = "Examples:
+  md instance set svc_etl --rw-size jumbo
+  md instance set svc_bi --rs-size standard --flock-size 8
+  md instance get svc_bi -o json | jq '.read_scaling.flock_size = 8' | md instance set svc_bi -f - -y")]
     #[command(group(clap::ArgGroup::new("overrides").required(true).multiple(true)))]
     Set {
         /// Username
         username: String,
+        /// Complete config as JSON instead of the current one, `-` for stdin: an `instance
+        /// get` response or a `PUT` body
+        #[arg(short, long, group = "overrides")]
+        file: Option<PathBuf>,
         /// Read-write instance size
         #[arg(long, value_enum, group = "overrides")]
         rw_size: Option<InstanceSize>,
@@ -158,295 +912,4658 @@ enum DucklingCommands {
         #[arg(long, group = "overrides", value_parser = clap::value_parser!(u32).range(0..=64))]
         flock_size: Option<u32>,
     },
+    /// Check a proposed instance configuration without sending it, listing every problem
+    #[command(after_long_help = "Examples:
+  md instance validate --file instances.json
+  md instance validate svc_bi --rs-size standard --flock-size 32")]
+    Validate {
+        /// Apply the overrides to this user's current config, as `instance set` would
+        #[arg(conflicts_with = "file")]
+        username: Option<String>,
+        /// Proposed config as JSON, `-` for stdin: an `instance get` response or a `PUT` body
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// Read-write instance size
+        #[arg(long, value_enum)]
+        rw_size: Option<InstanceSize>,
+        /// Read-scaling instance size
+        #[arg(long, value_enum)]
+        rs_size: Option<InstanceSize>,
+        /// Read-scaling flock size
+        #[arg(long)]
+        flock_size: Option<u64>,
+    },
+    /// Lower read-scaling flock size for many users at once (shows a plan, confirms twice)
+    #[command(group(clap::ArgGroup::new("targets").required(true)))]
+    ScaleDown {
+        /// Usernames to scale down
+        #[arg(group = "targets")]
+        usernames: Vec<String>,
+        /// Every active account
+        #[arg(long, group = "targets")]
+        all_users: bool,
+        /// Target read-scaling flock size; only larger flocks are changed
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=64))]
+        flock_size: u32,
+    },
 }
 
 #[derive(Subcommand)]
-enum AccountCommands {
-    /// List active accounts
-    ListActive,
+enum ScheduleCommands {
+    /// Add a schedule, e.g. `md schedule add "instance set u --flock-size 8" --cron "0 9 * * 1-5"`
+    Add {
+        /// md arguments to run (quoted as in a shell)
+        command: String,
+        /// Five-field cron expression in local time
+        #[arg(long)]
+        cron: String,
+    },
+    /// List schedules and their next run
+    List,
+    /// Remove a schedule
+    Remove {
+        /// Schedule ID
+        id: u32,
+    },
+    /// Run due schedules until interrupted
+    Run,
 }
 
-// -- helpers --
+#[derive(Subcommand)]
+enum WaitCommands {
+    /// Wait until a user's duckling matches every --until condition
+    #[command(after_long_help = "Examples:
+  md wait duckling svc_etl --until status=running
+  md wait duckling svc_bi --type read-scaling --until flock_size=8 --timeout 5m")]
+    Duckling {
+        /// Username
+        username: String,
+        /// Condition: status=VALUE (e.g. running), size=VALUE, or flock_size=N; repeatable
+        #[arg(long, value_name = "KEY=VALUE", required = true)]
+        until: Vec<Condition>,
+        /// Which duckling to check
+        #[arg(long = "type", value_enum, default_value_t = DucklingType::ReadWrite)]
+        duckling_type: DucklingType,
+        #[command(flatten)]
+        poll: PollArgs,
+    },
+    /// Wait until a service account exists, or is gone after a deletion
+    Account {
+        /// Username
+        username: String,
+        /// State to wait for
+        #[arg(long, value_enum)]
+        until: AccountState,
+        #[command(flatten)]
+        poll: PollArgs,
+    },
+}
 
-fn print_json(value: &Value) {
-    println!(
-        "{}",
-        serde_json::to_string_pretty(value).expect("Value serialization is infallible")
-    );
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum AccountState {
+    Exists,
+    Deleted,
 }
 
-/// Extract a string field for display. Returns "-" for missing/null fields.
-fn display_field<'a>(value: &'a Value, key: &str) -> &'a str {
-    value[key].as_str().unwrap_or("-")
+#[derive(clap::Args)]
+struct PollArgs {
+    /// Give up (and fail) after this long, e.g. 10m
+    #[arg(long, default_value = "10m", value_parser = parse_duration)]
+    timeout: std::time::Duration,
+    /// Time between checks, e.g. 10s
+    #[arg(long, default_value = "10s", value_parser = parse_duration)]
+    interval: std::time::Duration,
 }
 
-/// Extract a string field for use as data. Returns None for missing/null fields.
-fn extract_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
-    value[key].as_str()
+#[derive(Subcommand)]
+enum AnnotateCommands {
+    /// Set notes on a service account (an empty value removes one); without options, show them
+    User {
+        /// Username
+        username: String,
+        /// Team or person responsible for the account
+        #[arg(long)]
+        owner: Option<String>,
+        /// Ticket tracking the account, e.g. JIRA-123
+        #[arg(long)]
+        ticket: Option<String>,
+        /// Free-form note
+        #[arg(long)]
+        note: Option<String>,
+        /// When the account should be deprovisioned, e.g. 90d or a date (see `md
+        /// service-account expiring`)
+        #[arg(long, value_name = "WHEN")]
+        expires: Option<String>,
+        /// Remove all notes from the account
+        #[arg(long, conflicts_with_all = ["owner", "ticket", "note", "expires"])]
+        clear: bool,
+    },
+    /// List annotated service accounts
+    List,
 }
 
-/// Print rows as a fixed-width table with a header.
-fn print_table(headers: &[&str], rows: &[Vec<String>]) {
-    if rows.is_empty() {
-        return;
-    }
+#[derive(Subcommand)]
+enum SigningKeyCommands {
+    /// Create a signing key next to the config file and print its public key
+    Generate,
+    /// Print the public key (what approvers list in `approval.approvers`)
+    Show,
+}
 
-    let widths: Vec<usize> = (0..headers.len())
-        .map(|i| {
-            let header_w = headers[i].len();
-            let max_row_w = rows
-                .iter()
-                .map(|r| r.get(i).map_or(0, |s| s.len()))
-                .max()
-                .unwrap_or(0);
-            header_w.max(max_row_w)
-        })
-        .collect();
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print a setting, e.g. `defaults.output` (every setting under it, for a table)
+    Get {
+        /// Dotted key
+        key: String,
+    },
+    /// Change a setting, keeping the rest of the file (comments included) as it is
+    #[command(after_long_help = "Examples:
+  md config set defaults.output json
+  md config set defaults.request_timeout 30s
+  md config set output.token.list sh")]
+    Set {
+        /// Dotted key
+        key: String,
+        /// Value, as TOML (`true`, `8`, `\"text\"`) or plain text
+        value: String,
+    },
+    /// Print every setting in the config file
+    List,
+}
 
-    let last = headers.len() - 1;
+#[derive(Subcommand)]
+enum AuthCommands {
+    /// Store a token in the OS keyring (for the active profile, if any)
+    #[command(
+        after_long_help = "The token is read from --token if given, else prompted for \
+without echo, else read from stdin.
 
-    // Header
-    for (i, h) in headers.iter().enumerate() {
-        if i < last {
-            print!("{:<width$}  ", h, width = widths[i]);
-        } else {
-            println!("{h}");
-        }
-    }
+Examples:
+  md auth login
+  md --profile labs auth login
+  op read op://ops/motherduck/token | md auth login"
+    )]
+    Login,
+    /// Remove the stored token from the OS keyring
+    Logout,
+    /// Show where the token comes from and whether one is stored
+    Status,
+    /// Check the token with the API and show who it belongs to, its type, and its expiry
+    Whoami,
+}
 
-    // Rows
-    for row in rows {
-        for (i, val) in row.iter().enumerate() {
-            if i < last {
-                print!("{:<width$}  ", val, width = widths[i]);
-            } else {
-                println!("{val}");
-            }
-        }
-    }
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// List profiles, marking the active one
+    List,
+    /// Make a profile the default for commands run without --profile
+    Use {
+        /// Profile name
+        name: String,
+    },
 }
 
-fn print_duckling_config(value: &Value) {
-    let rw = display_field(&value["read_write"], "instance_size");
-    let rs = display_field(&value["read_scaling"], "instance_size");
-    let flock = match value["read_scaling"]["flock_size"].as_u64() {
-        Some(n) => n.to_string(),
-        None => "-".to_string(),
-    };
-    println!("read_write:   {rw}");
-    println!("read_scaling: {rs} (flock_size: {flock})");
+#[derive(Subcommand)]
+enum DevCommands {
+    /// Compare the endpoints and fields md uses with an OpenAPI spec (YAML or JSON)
+    #[command(
+        after_long_help = "Reports operations in the spec that md does not call, and \
+endpoints or body fields md uses that the spec no longer has. Exits non-zero when md uses \
+something the spec removed.
+
+Examples:
+  md dev check-spec --spec openapi.yaml
+  md -o json dev check-spec --spec openapi.json"
+    )]
+    CheckSpec {
+        /// OpenAPI spec file
+        #[arg(long)]
+        spec: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum GenerateCommands {
+    /// Markdown reference of every command, its options, and examples
+    Docs,
+}
+
+#[derive(Subcommand)]
+enum BundleCommands {
+    /// Sign commands into a bundle file
+    Create {
+        /// Bundle file to write
+        file: PathBuf,
+        /// An `md` command without `md`, e.g. "token revoke-all svc_etl" (repeatable)
+        #[arg(long = "command", value_name = "COMMAND")]
+        commands: Vec<String>,
+        /// Read commands from a file, one per line (`#` starts a comment)
+        #[arg(long, value_name = "FILE")]
+        from: Option<PathBuf>,
+    },
+    /// Check a bundle's signature and list its commands
+    Show {
+        /// Bundle file
+        file: PathBuf,
+    },
+    /// Run a bundle signed by a trusted signer, stopping at the first failure
+    Execute {
+        /// Bundle file
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccountCommands {
+    /// List active accounts
+    ListActive {
+        /// Print one JSON object per line as accounts are received (for very large orgs)
+        #[arg(long)]
+        stream: bool,
+        /// Print the table in sections with per-section counts (text output)
+        #[arg(long, value_enum, conflicts_with = "stream")]
+        group_by: Option<AccountGroupBy>,
+    },
+    /// Show active accounts with nested ducklings (status, size) and token counts
+    Tree,
+    /// Count active accounts
+    Count {
+        /// Only accounts with a duckling in this status, e.g. running
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Write every account, its duckling configuration, and token metadata (no secrets) to
+    /// one JSON archive
+    Export {
+        /// Archive path (default: md-export-<UTC timestamp>.json in the current directory)
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Report service accounts with no known or current owner
+    Orphans {
+        /// Owners CSV with columns username,owner[,owner_active]
+        #[arg(long)]
+        owners: PathBuf,
+        /// Also write the report as CSV to this path
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+}
+
+// -- helpers --
+
+/// Parse a token TTL given as seconds or a duration such as `90d`, within the API's
+/// 5 minute to 365 day range.
+fn parse_ttl(s: &str) -> Result<u64, String> {
+    let secs = match s.trim().parse::<u64>() {
+        Ok(n) => n,
+        Err(_) => config::parse_duration_secs(s).map_err(|e| e.to_string())?,
+    };
+    if !policy::TTL_RANGE.contains(&secs) {
+        return Err(format!("{s} is not between 5m and 365d"));
+    }
+    Ok(secs)
+}
+
+/// Parse a duration such as `30s` or `10m`.
+fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    humantime::parse_duration(s.trim())
+        .map_err(|_| format!("invalid duration '{s}' (expected e.g. 10s, 10m)"))
+}
+
+/// Parse a `KEY=VALUE` argument.
+fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{s}'")),
+    }
+}
+
+/// Print a structured result as JSON, one-line JSON (`-o ndjson`), shell assignments
+/// (`-o sh`), or PowerShell-friendly JSON (`-o psobject`).
+fn print_value(out: &mut dyn Write, mode: OutputMode, value: &Value) -> io::Result<()> {
+    match mode {
+        OutputMode::Sh => sh::write(out, "", value),
+        OutputMode::PsObject => writeln!(out, "{}", psobject::shape(value)),
+        OutputMode::Ndjson => writeln!(out, "{value}"),
+        OutputMode::Json | OutputMode::Text => print_json(out, value),
+    }
+}
+
+/// Print a list result, e.g. `{"tokens": [...]}`: with `-o ndjson` one line per item of
+/// the array under `key`, without the wrapper; otherwise as [`print_value`] would.
+fn print_list(out: &mut dyn Write, mode: OutputMode, value: &Value, key: &str) -> io::Result<()> {
+    match (mode, value[key].as_array()) {
+        (OutputMode::Ndjson, Some(items)) => items.iter().try_for_each(|i| writeln!(out, "{i}")),
+        _ => print_value(out, mode, value),
+    }
+}
+
+/// Print what `--query` yielded, a value per line: strings unquoted in text output, as
+/// `jq -r` would. `-o sh` and `-o psobject` take one value, so several become an array.
+fn print_query_results(
+    out: &mut dyn Write,
+    mode: OutputMode,
+    values: Vec<Value>,
+) -> io::Result<()> {
+    match mode {
+        OutputMode::Text => values.iter().try_for_each(|v| match v {
+            Value::String(s) => writeln!(out, "{s}"),
+            v => print_json(out, v),
+        }),
+        OutputMode::Json => values.iter().try_for_each(|v| print_json(out, v)),
+        OutputMode::Ndjson => values.iter().try_for_each(|v| writeln!(out, "{v}")),
+        OutputMode::Sh | OutputMode::PsObject => {
+            let value = match <[Value; 1]>::try_from(values) {
+                Ok([value]) => value,
+                Err(values) => Value::Array(values),
+            };
+            print_value(out, mode, &value)
+        }
+    }
+}
+
+/// A created token for `-o sh`: `TOKEN_SECRET` and `TOKEN_TYPE` read better than
+/// `TOKEN_TOKEN` and `TOKEN_TOKEN_TYPE`.
+fn sh_token(mut token: Value) -> Value {
+    if let Some(map) = token.as_object_mut() {
+        for (from, to) in [("token", "secret"), ("token_type", "type")] {
+            if let Some(v) = map.remove(from) {
+                map.insert(to.to_string(), v);
+            }
+        }
+    }
+    token
+}
+
+/// Pretty-print `value` straight into `out`: org-wide listings and exports run to megabytes,
+/// and rendering them into a `String` first would hold a second copy in memory.
+fn print_json(out: &mut dyn Write, value: &Value) -> io::Result<()> {
+    let mut out = io::BufWriter::new(out);
+    serde_json::to_writer_pretty(&mut out, value)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+/// Extract a string field for display. Returns "-" for missing/null fields.
+fn display_field<'a>(value: &'a Value, key: &str) -> &'a str {
+    value[key].as_str().unwrap_or("-")
+}
+
+/// Text output of a create command: the field at `print` (a dotted path into the JSON
+/// result, e.g. `token.id`), or at `default`. Strings print bare and other values as JSON.
+/// A missing `--print` field is an error, so a typo does not hand a script an empty value.
+fn print_created(
+    out: &mut dyn Write,
+    result: &Value,
+    print: Option<String>,
+    default: &str,
+) -> Result<()> {
+    let path = print.as_deref().unwrap_or(default);
+    let mut value = result;
+    for key in path.split('.') {
+        value = match value.get(key) {
+            Some(field) => field,
+            None if print.is_none() => &Value::Null,
+            None => match value.as_object() {
+                Some(fields) => bail!(
+                    "--print: no field '{path}' in the result (fields: {})",
+                    fields.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+                None => bail!("--print: no field '{path}' in the result"),
+            },
+        };
+    }
+    match value {
+        Value::String(s) => writeln!(out, "{s}")?,
+        Value::Null => writeln!(out, "-")?,
+        other => writeln!(out, "{other}")?,
+    }
+    Ok(())
+}
+
+/// Extract a string field for use as data. Returns None for missing/null fields.
+fn extract_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value[key].as_str()
+}
+
+/// Print rows as a fixed-width table with a header. Rows are anything sliceable into cells,
+/// e.g. `[&str; N]` borrowed from a response or `[Cow<str>; N]` when some cells are
+/// formatted, so large listings are not copied into owned strings first.
+fn print_table<C: AsRef<str>>(
+    out: &mut dyn Write,
+    headers: &[&str],
+    rows: &[impl AsRef<[C]>],
+) -> io::Result<()> {
+    print_table_styled(out, headers, rows, &[])
+}
+
+/// Like [`print_table`], wrapping row `i` in the ANSI SGR sequence `styles[i]` (if any).
+/// Styles are applied after padding so escape codes do not skew column widths.
+fn print_table_styled<C: AsRef<str>>(
+    out: &mut dyn Write,
+    headers: &[&str],
+    rows: &[impl AsRef<[C]>],
+    styles: &[Option<&str>],
+) -> io::Result<()> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|i| {
+            let header_w = headers[i].len();
+            let max_row_w = rows
+                .iter()
+                .map(|r| r.as_ref().get(i).map_or(0, |s| s.as_ref().len()))
+                .max()
+                .unwrap_or(0);
+            header_w.max(max_row_w)
+        })
+        .collect();
+
+    let last = headers.len() - 1;
+    // One write per buffer rather than per cell, which matters on a line-buffered stdout.
+    let mut out = io::BufWriter::new(out);
+
+    // Header
+    for (i, h) in headers.iter().enumerate() {
+        if i < last {
+            write!(out, "{:<width$}  ", h, width = widths[i])?;
+        } else {
+            writeln!(out, "{h}")?;
+        }
+    }
+
+    // Rows
+    for (r, row) in rows.iter().enumerate() {
+        let style = styles.get(r).copied().flatten();
+        if let Some(style) = style {
+            write!(out, "\x1b[{style}m")?;
+        }
+        for (i, val) in row.as_ref().iter().enumerate() {
+            let val = val.as_ref();
+            if i < last {
+                write!(out, "{:<width$}  ", val, width = widths[i])?;
+            } else {
+                out.write_all(val.as_bytes())?;
+            }
+        }
+        if style.is_some() {
+            write!(out, "\x1b[0m")?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+fn print_instance_config(out: &mut dyn Write, value: &Value) -> io::Result<()> {
+    let rw = display_field(&value["read_write"], "instance_size");
+    let rs = display_field(&value["read_scaling"], "instance_size");
+    let flock = match value["read_scaling"]["flock_size"].as_u64() {
+        Some(n) => n.to_string(),
+        None => "-".to_string(),
+    };
+    writeln!(out, "read_write:   {rw}")?;
+    writeln!(out, "read_scaling: {rs} (flock_size: {flock})")
+}
+
+/// SGR style for expired tokens in color mode: red, struck through.
+const EXPIRED_STYLE: &str = "31;9";
+
+/// Render a `token list` response as a table (see [`token_table`]).
+fn print_tokens(
+    out: &mut dyn Write,
+    value: &Value,
+    now: chrono::DateTime<chrono::Utc>,
+    color: bool,
+) -> io::Result<()> {
+    let Some(tokens) = value["tokens"].as_array() else {
+        return Ok(());
+    };
+    token_table(out, &tokens.iter().collect::<Vec<_>>(), now, color)
+}
+
+/// Render tokens as a table. Tokens expired at `now` are struck through in red with
+/// `color`, and marked `EXPIRED` otherwise.
+fn token_table(
+    out: &mut dyn Write,
+    tokens: &[&Value],
+    now: chrono::DateTime<chrono::Utc>,
+    color: bool,
+) -> io::Result<()> {
+    let mut styles = Vec::with_capacity(tokens.len());
+    let rows: Vec<[Cow<str>; 4]> = tokens
+        .iter()
+        .map(|t| {
+            let expired = !token_is_live(t, now);
+            styles.push((expired && color).then_some(EXPIRED_STYLE));
+            let expires = match t["expire_at"].as_str() {
+                Some(s) if !s.is_empty() && expired && !color => format!("{s} EXPIRED").into(),
+                Some(s) if !s.is_empty() => s.into(),
+                _ => "never".into(),
+            };
+            [
+                display_field(t, "id").into(),
+                display_field(t, "name").into(),
+                display_field(t, "token_type").into(),
+                expires,
+            ]
+        })
+        .collect();
+    print_table_styled(out, &["ID", "NAME", "TYPE", "EXPIRES"], &rows, &styles)
+}
+
+/// Keep only the tokens in a `token list` response for which `keep` is true.
+fn filter_tokens(value: &mut Value, keep: impl Fn(&Value) -> bool) {
+    if let Some(tokens) = value["tokens"].as_array_mut() {
+        tokens.retain(|t| keep(t));
+    }
+}
+
+/// Render an `account list-active` response as a table (see [`account_table`]).
+fn print_accounts(out: &mut dyn Write, value: &Value) -> io::Result<()> {
+    let Some(accounts) = value["accounts"].as_array() else {
+        return Ok(());
+    };
+    account_table(out, &accounts.iter().collect::<Vec<_>>())
+}
+
+/// Render active accounts as a table.
+fn account_table(out: &mut dyn Write, accounts: &[&Value]) -> io::Result<()> {
+    let rows: Vec<[Cow<str>; 4]> = accounts
+        .iter()
+        .map(|acct| {
+            let mut ducklings = String::new();
+            for (i, d) in acct["ducklings"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .enumerate()
+            {
+                if i > 0 {
+                    ducklings.push_str(", ");
+                }
+                ducklings.push_str(display_field(d, "type"));
+                ducklings.push_str(" (");
+                ducklings.push_str(display_field(d, "status"));
+                ducklings.push(')');
+            }
+            let annotation = |key| acct["annotations"][key].as_str().unwrap_or("-").into();
+            [
+                display_field(acct, "username").into(),
+                ducklings.into(),
+                annotation("owner"),
+                annotation("ticket"),
+            ]
+        })
+        .collect();
+    // Annotation columns only when `md annotate` has been used on a listed account.
+    if !accounts.iter().any(|a| a.get("annotations").is_some()) {
+        let rows: Vec<&[Cow<str>]> = rows.iter().map(|r| &r[..2]).collect();
+        return print_table(out, &["USERNAME", "DUCKLINGS"], &rows);
+    }
+    print_table(out, &["USERNAME", "DUCKLINGS", "OWNER", "TICKET"], &rows)
+}
+
+/// Print the `key` array of a listing in sections sorted by `group`: each is headed by
+/// `label`, the group, and its item count, and printed with `print`. A total follows.
+fn print_grouped(
+    out: &mut dyn Write,
+    listing: &Value,
+    key: &str,
+    label: &str,
+    group: impl Fn(&Value) -> String,
+    mut print: impl FnMut(&mut dyn Write, &[&Value]) -> io::Result<()>,
+) -> io::Result<()> {
+    let items = listing[key].as_array().map_or(&[][..], Vec::as_slice);
+    let mut groups: BTreeMap<String, Vec<&Value>> = BTreeMap::new();
+    for item in items {
+        groups.entry(group(item)).or_default().push(item);
+    }
+    for (i, (name, members)) in groups.into_iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{label}: {name} ({})", members.len())?;
+        print(out, &members)?;
+    }
+    if !items.is_empty() {
+        writeln!(out, "\ntotal: {}", items.len())?;
+    }
+    Ok(())
+}
+
+/// `n` and `noun`, made plural unless `n` is 1: "1 token", "2 tokens".
+fn plural(n: usize, noun: &str) -> String {
+    format!("{n} {noun}{}", if n == 1 { "" } else { "s" })
+}
+
+/// How soon an expiry is worth calling out in `--summary-footer`.
+const EXPIRING_SOON: chrono::TimeDelta = chrono::TimeDelta::days(7);
+
+/// Totals for the `key` items of a listing: the count, plus for tokens how many expire
+/// within [`EXPIRING_SOON`] (and have expired), and for accounts how many have a running
+/// duckling.
+fn summary_footer(key: &str, items: &[Value], now: chrono::DateTime<chrono::Utc>) -> String {
+    let noun = key.strip_suffix('s').unwrap_or(key);
+    let mut parts = vec![plural(items.len(), noun)];
+    match key {
+        "tokens" => {
+            let expired = items.iter().filter(|t| !token_is_live(t, now)).count();
+            let expiring = items
+                .iter()
+                .filter(|t| token_is_live(t, now) && !token_is_live(t, now + EXPIRING_SOON))
+                .count();
+            parts.push(format!("{expiring} expiring within 7d"));
+            if expired > 0 {
+                parts.push(format!("{expired} expired"));
+            }
+        }
+        "accounts" => {
+            let running = items
+                .iter()
+                .filter(|a| {
+                    a["ducklings"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .any(|d| d["status"] == "running")
+                })
+                .count();
+            parts.push(format!("{running} running"));
+        }
+        _ => {}
+    }
+    parts.join(", ")
+}
+
+/// Render `account tree` output: one line per account, ducklings nested beneath.
+fn print_account_tree(out: &mut dyn Write, value: &Value) -> io::Result<()> {
+    for acct in value["accounts"].as_array().into_iter().flatten() {
+        writeln!(
+            out,
+            "{} ({})",
+            display_field(acct, "username"),
+            plural(
+                acct["tokens"]
+                    .as_u64()
+                    .and_then(|n| usize::try_from(n).ok())
+                    .unwrap_or(0),
+                "token"
+            )
+        )?;
+        let ducklings = acct["ducklings"].as_array().map_or(&[][..], Vec::as_slice);
+        for (i, d) in ducklings.iter().enumerate() {
+            let branch = if i + 1 == ducklings.len() {
+                "└─"
+            } else {
+                "├─"
+            };
+            let mut details = vec![display_field(d, "status").to_string()];
+            if let Some(size) = extract_str(d, "instance_size") {
+                details.push(size.to_string());
+            }
+            if let Some(n) = d["flock_size"].as_u64() {
+                details.push(format!("flock {n}"));
+            }
+            writeln!(
+                out,
+                "{branch} {} ({})",
+                display_field(d, "type"),
+                details.join(", ")
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// A confirmation that would have prompted under `--non-interactive`. Reported as
+/// `"code": "confirmation_required"` in `-o json` errors.
+#[derive(Debug)]
+struct ConfirmationRequired {
+    /// The question that was not asked, e.g. "Delete token 'abc'?".
+    action: String,
+    /// The flag that answers it without a prompt.
+    flag: &'static str,
+}
+
+impl std::fmt::Display for ConfirmationRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "confirmation required ({}); pass {} to proceed without prompting",
+            self.action, self.flag
+        )
+    }
+}
+
+impl std::error::Error for ConfirmationRequired {}
+
+/// A listing that came back empty under `--fail-if-empty`.
+#[derive(Debug)]
+struct NoResults;
+
+impl std::fmt::Display for NoResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("no results (--fail-if-empty)")
+    }
+}
+
+impl std::error::Error for NoResults {}
+
+/// `md wait` gave up before its condition held.
+#[derive(Debug)]
+struct TimedOut(String);
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// A `[hooks] pre` program exited non-zero, so the command did not run.
+#[derive(Debug)]
+struct HookRefused(String);
+
+impl std::fmt::Display for HookRefused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HookRefused {}
+
+/// A batch command that failed for some items after printing its report.
+#[derive(Debug)]
+struct PartialFailure(String);
+
+impl std::fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+impl ConfirmationRequired {
+    fn new(prompt: &str, flag: &'static str) -> Self {
+        let action = prompt.trim_end().trim_end_matches("[y/N]").trim_end();
+        Self {
+            action: action.trim_end_matches(':').to_string(),
+            flag,
+        }
+    }
+}
+
+/// Ask the user for confirmation on the error stream before a destructive operation.
+/// Returns Ok(()) if confirmed, Err if declined. Auto-confirms with `--yes`/`--force`,
+/// or with `confirm = "never"`. Without a terminal it fails unless `--assume-yes-when-piped`
+/// is given, and under `--non-interactive` it always fails.
+fn confirm(ctx: &mut Ctx, prompt: &str) -> Result<()> {
+    if ctx.yes || ctx.force || ctx.config.confirm == ConfirmLevel::Never {
+        return Ok(());
+    }
+    if ctx.non_interactive {
+        bail!(ConfirmationRequired::new(prompt, "--yes"));
+    }
+    let Some(answer) = prompt_answer(ctx, prompt)? else {
+        if ctx.assume_yes_when_piped {
+            return Ok(());
+        }
+        bail!(ConfirmationRequired::new(prompt, "--yes"));
+    };
+    let answer = answer.to_lowercase();
+    if answer == "y" || answer == "yes" {
+        Ok(())
+    } else {
+        bail!("aborted")
+    }
+}
+
+/// Like [`confirm`], for non-destructive changes: only prompts with `confirm = "always"`.
+fn confirm_change(ctx: &mut Ctx, prompt: &str) -> Result<()> {
+    if ctx.config.confirm == ConfirmLevel::Always {
+        confirm(ctx, prompt)
+    } else {
+        Ok(())
+    }
+}
+
+/// For especially destructive operations: the user must type `expected` exactly. `--yes`
+/// is not enough; only `--force` (or `confirm = "never"`) skips it, and without a terminal
+/// it fails rather than proceeding.
+fn confirm_typed(ctx: &mut Ctx, prompt: &str, expected: &str) -> Result<()> {
+    if ctx.force || ctx.config.confirm == ConfirmLevel::Never {
+        return Ok(());
+    }
+    if ctx.non_interactive {
+        bail!(ConfirmationRequired::new(prompt, "--force"));
+    }
+    match prompt_answer(ctx, prompt)? {
+        Some(answer) if answer == expected => Ok(()),
+        Some(_) => bail!("aborted"),
+        None => bail!("this operation needs a typed confirmation; pass --force to skip it"),
+    }
+}
+
+/// Show `prompt` and read a trimmed line from stdin, or `None` without a terminal.
+fn prompt_answer(ctx: &mut Ctx, prompt: &str) -> Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    ctx.flush_client_log()?;
+    write!(ctx.err, "{prompt}")?;
+    ctx.err.flush().context("failed to flush stderr")?;
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .context("failed to read confirmation")?;
+    Ok(Some(input.trim().to_string()))
+}
+
+/// Placeholder for secrets left out of output.
+const REDACTED: &str = "***";
+
+/// Per-invocation state shared by the command handlers. All output goes through `out`
+/// (results) and `err` (prompts and diagnostics) so callers can capture it.
+struct Ctx<'a> {
+    client: &'a MotherduckClient,
+    config: &'a Config,
+    config_path: Option<&'a Path>,
+    /// Identifies the base URL and token in local caches (capabilities, search index).
+    cache_key: &'a str,
+    /// The inventory to read from instead of the API (`--local`).
+    local: Option<&'a Inventory>,
+    mode: OutputMode,
+    /// Style text output with ANSI colors.
+    color: bool,
+    yes: bool,
+    force: bool,
+    /// Fail instead of prompting (`--non-interactive`).
+    non_interactive: bool,
+    /// Treat a missing terminal as "yes" (`--assume-yes-when-piped`).
+    assume_yes_when_piped: bool,
+    warnings_as_errors: bool,
+    /// End text listings with a totals line (`--summary-footer`).
+    summary_footer: bool,
+    /// Fail when a listing is empty (`--fail-if-empty`).
+    fail_if_empty: bool,
+    /// Leave out notes such as empty-listing hints (`--quiet`).
+    quiet: bool,
+    out: &'a mut dyn Write,
+    err: &'a mut dyn Write,
+}
+
+impl Ctx<'_> {
+    /// Print a warning to the error stream, or fail with it under `--warnings-as-errors`.
+    /// Warn before the action the warning is about so that policy failures prevent it.
+    /// Print the client's `--verbose` and `--curl` lines so far.
+    fn flush_client_log(&mut self) -> io::Result<()> {
+        for line in self.client.take_log() {
+            writeln!(self.err, "{line}")?;
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, msg: &str) -> Result<()> {
+        if self.warnings_as_errors {
+            bail!("{msg} (warning treated as error)");
+        }
+        writeln!(self.err, "warning: {msg}")?;
+        Ok(())
+    }
+
+    /// After printing `count` results, fail under `--fail-if-empty` if there were none.
+    fn check_empty(&self, count: usize) -> Result<()> {
+        if self.fail_if_empty && count == 0 {
+            return Err(NoResults.into());
+        }
+        Ok(())
+    }
+
+    /// Print a note to the error stream, unless `--quiet`.
+    fn note(&mut self, msg: &str) -> io::Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        writeln!(self.err, "note: {msg}")
+    }
+
+    /// After a text listing of `count` results, say on stderr that it found nothing, so an
+    /// empty result does not look like a failure. `note` says what was looked for and, where
+    /// it helps, what to run next.
+    fn note_empty(&mut self, count: usize, note: &str) -> io::Result<()> {
+        if count > 0 || self.mode != OutputMode::Text || self.quiet {
+            return Ok(());
+        }
+        writeln!(self.err, "{note}")
+    }
+
+    /// After a text listing, print its `--summary-footer` line if requested.
+    fn footer(&mut self, listing: &Value, key: &str) -> io::Result<()> {
+        if !self.summary_footer {
+            return Ok(());
+        }
+        let items = listing[key].as_array().map_or(&[][..], Vec::as_slice);
+        writeln!(
+            self.out,
+            "{}",
+            summary_footer(key, items, chrono::Utc::now())
+        )
+    }
+}
+
+// -- command handlers --
+
+fn handle_service_account(ctx: &mut Ctx, command: ServiceAccountCommands) -> Result<()> {
+    match command {
+        ServiceAccountCommands::Create {
+            username,
+            from_template,
+            vars,
+            if_not_exists,
+            expires,
+            print,
+        } => {
+            let username = match from_template {
+                Some(template) => {
+                    let mut bindings = vec![("name", username.as_str())];
+                    bindings.extend(vars.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+                    policy::expand_template(&template, &bindings)?
+                }
+                None => username,
+            };
+            policy::check_username(&ctx.config.policy, &username)?;
+            // Resolve before creating, so a bad value or missing config directory does not
+            // leave an account without its recorded lifetime.
+            let expires =
+                match time_flag("--expires", expires, chrono::Utc::now(), Direction::Ahead)? {
+                    Some(at) => Some((at, annotations_path(ctx)?)),
+                    None => None,
+                };
+            if if_not_exists && ctx.client.user_exists(&username)? {
+                writeln!(ctx.err, "service account '{username}' already exists")?;
+                let result = json!({ "username": username });
+                match ctx.mode {
+                    OutputMode::Json
+                    | OutputMode::Ndjson
+                    | OutputMode::Sh
+                    | OutputMode::PsObject => print_value(ctx.out, ctx.mode, &result)?,
+                    OutputMode::Text => print_created(ctx.out, &result, print, "username")?,
+                }
+                return Ok(());
+            }
+            confirm_change(ctx, &format!("Create service account '{username}'? [y/N] "))?;
+            let result = ctx.client.create_user(&username)?;
+            if let Some((at, path)) = expires {
+                let mut annotations = Annotations::load(&path)?;
+                annotations
+                    .users
+                    .entry(username.clone())
+                    .or_default()
+                    .set_expires(Some(at));
+                annotations.save(&path).with_context(|| {
+                    format!("created '{username}' but failed to record when it expires")
+                })?;
+            }
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &result)?
+                }
+                OutputMode::Text => print_created(ctx.out, &result, print, "username")?,
+            }
+        }
+        ServiceAccountCommands::Delete { username, confirm } => {
+            let result = delete_service_account(ctx, &username, confirm.as_deref())?;
+            if ctx.mode != OutputMode::Text {
+                print_value(ctx.out, ctx.mode, &result)?;
+            }
+        }
+        ServiceAccountCommands::Expiring {
+            within,
+            deprovision,
+        } => expiring_service_accounts(ctx, within, deprovision)?,
+    }
+    Ok(())
+}
+
+/// Accounts whose `--expires` time has passed (or passes within `within`), optionally
+/// deprovisioning each and dropping its annotations.
+fn expiring_service_accounts(
+    ctx: &mut Ctx,
+    within: Option<String>,
+    deprovision: bool,
+) -> Result<()> {
+    let now = chrono::Utc::now();
+    let empty = match &within {
+        Some(within) => format!("no service accounts expire within {within}"),
+        None => "no service accounts are past their --expires time".to_string(),
+    };
+    let by = time_flag("--within", within, now, Direction::Ahead)?.unwrap_or(now);
+    let path = annotations_path(ctx)?;
+    let mut annotations = Annotations::load(&path)?;
+    let expiring: Vec<(
+        String,
+        chrono::DateTime<chrono::Utc>,
+        annotations::Annotation,
+    )> = annotations
+        .expiring(by)?
+        .into_iter()
+        .map(|(username, at)| {
+            (
+                username.to_string(),
+                at,
+                annotations.users[username].clone(),
+            )
+        })
+        .collect();
+    let expires =
+        |at: &chrono::DateTime<chrono::Utc>| at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject
+            if !deprovision =>
+        {
+            let accounts: Vec<Value> = expiring
+                .iter()
+                .map(|(username, at, a)| {
+                    json!({
+                        "username": username,
+                        "expires": expires(at),
+                        "expired": *at <= now,
+                        "owner": a.owner,
+                        "ticket": a.ticket,
+                    })
+                })
+                .collect();
+            print_value(ctx.out, ctx.mode, &json!({ "accounts": accounts }))?;
+        }
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {}
+        OutputMode::Text => {
+            let rows: Vec<Vec<String>> = expiring
+                .iter()
+                .map(|(username, at, a)| {
+                    let field = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".into());
+                    vec![
+                        username.clone(),
+                        expires(at),
+                        field(&a.owner),
+                        field(&a.ticket),
+                    ]
+                })
+                .collect();
+            print_table(ctx.out, &["USERNAME", "EXPIRES", "OWNER", "TICKET"], &rows)?;
+        }
+    }
+    if !deprovision {
+        ctx.note_empty(expiring.len(), &empty)?;
+        return ctx.check_empty(expiring.len());
+    }
+    let mut results = Vec::new();
+    for (username, at, _) in &expiring {
+        if *at > now {
+            writeln!(ctx.err, "skipping '{username}': expires {}", expires(at))?;
+            continue;
+        }
+        let result = deprovision_account(ctx, username)?;
+        annotations.users.remove(username);
+        annotations.save(&path)?;
+        if ctx.mode == OutputMode::Text {
+            print_deprovisioned(ctx, &result)?;
+        }
+        results.push(result);
+    }
+    if ctx.mode != OutputMode::Text {
+        print_value(ctx.out, ctx.mode, &json!({ "deprovisioned": results }))?;
+    }
+    Ok(())
+}
+
+/// Confirm and delete a service account after showing what depends on it. Unexpired
+/// tokens or running ducklings mean live workloads, which need `--force`; otherwise an
+/// account with tokens needs the username typed back.
+/// `confirm_name` is `--confirm`: the username, standing in for the typed confirmation under
+/// `--non-interactive`.
+fn delete_service_account(
+    ctx: &mut Ctx,
+    username: &str,
+    confirm_name: Option<&str>,
+) -> Result<Value> {
+    let tokens = match ctx.client.list_tokens(username) {
+        Ok(tokens) => tokens,
+        // A missing user has no dependents; let the delete report the 404.
+        Err(e)
+            if e.downcast_ref::<ApiError>()
+                .is_some_and(|e| e.status == 404) =>
+        {
+            json!({})
+        }
+        Err(e) => return Err(e),
+    };
+    let token_list = tokens["tokens"].as_array().map_or(&[][..], Vec::as_slice);
+    let now = chrono::Utc::now();
+    let unexpired = token_list.iter().filter(|t| token_is_live(t, now)).count();
+
+    let accounts = ctx.client.list_active_accounts()?;
+    let ducklings: Vec<&Value> = accounts["accounts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|a| extract_str(a, "username") == Some(username))
+        .flat_map(|a| a["ducklings"].as_array().into_iter().flatten())
+        .collect();
+    let running = ducklings
+        .iter()
+        .filter(|d| extract_str(d, "status") == Some("running"))
+        .count();
+
+    if !token_list.is_empty() {
+        writeln!(
+            ctx.err,
+            "service account '{username}' has {}:",
+            plural(token_list.len(), "token")
+        )?;
+        print_tokens(ctx.err, &tokens, chrono::Utc::now(), ctx.color)?;
+    }
+    if !ducklings.is_empty() {
+        let status: Vec<String> = ducklings
+            .iter()
+            .map(|d| {
+                format!(
+                    "{} ({})",
+                    display_field(d, "type"),
+                    display_field(d, "status")
+                )
+            })
+            .collect();
+        writeln!(ctx.err, "ducklings: {}", status.join(", "))?;
+    }
+    if unexpired > 0 {
+        ctx.warn(&format!(
+            "{} will stop working",
+            plural(unexpired, "unexpired token")
+        ))?;
+    }
+    if running > 0 {
+        let verb = if running == 1 { "is" } else { "are" };
+        ctx.warn(&format!("{} {verb} running", plural(running, "duckling")))?;
+    }
+    if (unexpired > 0 || running > 0) && !ctx.force {
+        bail!(
+            "refusing to delete '{username}' with live tokens or running ducklings; pass --force"
+        );
+    }
+
+    let prompt = format!("Type '{username}' to delete it: ");
+    if unexpired > 0 {
+        // Live credentials are what the typed confirmation is for, so neither --force nor
+        // confirm = "never" skips it.
+        match confirm_name {
+            Some(name) if ctx.non_interactive => {
+                anyhow::ensure!(
+                    name == username,
+                    "--confirm '{name}' does not match '{username}'"
+                );
+            }
+            Some(_) => bail!("--confirm only applies with --non-interactive"),
+            None if ctx.non_interactive => {
+                bail!(ConfirmationRequired::new(&prompt, "--confirm <username>"))
+            }
+            None => match prompt_answer(ctx, &prompt)? {
+                Some(answer) if answer == username => {}
+                Some(_) => bail!("aborted"),
+                None => bail!(
+                    "'{username}' has live tokens, so deleting it needs a typed confirmation; \
+                     pass --non-interactive --confirm {username} to skip it"
+                ),
+            },
+        }
+    } else if token_list.is_empty() {
+        confirm(ctx, &format!("Delete service account '{username}'? [y/N] "))?;
+    } else {
+        confirm_typed(ctx, &prompt, username)?;
+    }
+    ctx.client.delete_user(username)
+}
+
+/// Whether a token is still usable at `now`: no expiry, a future expiry, or an expiry we
+/// cannot parse (assume the worst).
+fn token_is_live(token: &Value, now: chrono::DateTime<chrono::Utc>) -> bool {
+    match extract_str(token, "expire_at").filter(|s| !s.is_empty()) {
+        None => true,
+        Some(s) => chrono::DateTime::parse_from_rfc3339(s).map_or(true, |t| t > now),
+    }
+}
+
+/// A token's RFC 3339 `key` field, if set and valid.
+fn token_time(token: &Value, key: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let s = extract_str(token, key).filter(|s| !s.is_empty())?;
+    chrono::DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|t| t.to_utc())
+}
+
+/// Parse an optional time flag such as `--created-after` (see [`dates::parse`]).
+fn time_flag(
+    flag: &str,
+    value: Option<String>,
+    now: chrono::DateTime<chrono::Utc>,
+    direction: Direction,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+    value
+        .map(|v| dates::parse(&v, now, direction).with_context(|| format!("invalid {flag} '{v}'")))
+        .transpose()
+}
+
+fn handle_token(ctx: &mut Ctx, command: TokenCommands) -> Result<()> {
+    match command {
+        TokenCommands::List {
+            username,
+            hide_expired,
+            only_expired,
+            created_after,
+            created_before,
+            expires_before,
+            group_by,
+        } => {
+            let now = chrono::Utc::now();
+            let filtered = hide_expired
+                || only_expired
+                || created_after.is_some()
+                || created_before.is_some()
+                || expires_before.is_some();
+            let created_after = time_flag("--created-after", created_after, now, Direction::Ago)?;
+            let created_before =
+                time_flag("--created-before", created_before, now, Direction::Ago)?;
+            let expires_before =
+                time_flag("--expires-before", expires_before, now, Direction::Ahead)?;
+            let mut result = read_tokens(ctx, &username)?;
+            if hide_expired || only_expired {
+                filter_tokens(&mut result, |t| token_is_live(t, now) == hide_expired);
+            }
+            if created_after.is_some() || created_before.is_some() {
+                let undated = result["tokens"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|t| token_time(t, "created_at").is_none())
+                    .count();
+                if undated > 0 {
+                    ctx.warn(&format!(
+                        "left out {} the API returned without a creation time",
+                        plural(undated, "token")
+                    ))?;
+                }
+                filter_tokens(&mut result, |t| {
+                    token_time(t, "created_at").is_some_and(|created| {
+                        created_after.is_none_or(|after| created > after)
+                            && created_before.is_none_or(|before| created < before)
+                    })
+                });
+            }
+            if let Some(limit) = expires_before {
+                filter_tokens(&mut result, |t| {
+                    token_time(t, "expire_at").is_some_and(|expires| expires < limit)
+                });
+            }
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_list(ctx.out, ctx.mode, &result, "tokens")?
+                }
+                OutputMode::Text => match group_by {
+                    Some(by) => print_grouped(
+                        ctx.out,
+                        &result,
+                        "tokens",
+                        by.label(),
+                        |t| by.group(t, now),
+                        |out, tokens| token_table(out, tokens, now, ctx.color),
+                    )?,
+                    None => print_tokens(ctx.out, &result, now, ctx.color)?,
+                },
+            }
+            if ctx.mode == OutputMode::Text {
+                ctx.footer(&result, "tokens")?;
+            }
+            let count = result["tokens"].as_array().map_or(0, Vec::len);
+            let empty = if filtered {
+                format!("no tokens for '{username}' match the filters")
+            } else {
+                format!(
+                    "no tokens for '{username}'; create one with `md token create {username} \
+                     --name <name>`"
+                )
+            };
+            ctx.note_empty(count, &empty)?;
+            ctx.check_empty(count)?;
+        }
+        TokenCommands::Create {
+            username,
+            name,
+            ttl,
+            token_type,
+            show_secret,
+            print,
+        } => {
+            let config = ctx.config;
+            let ttl = defaults::token_ttl(&config.defaults.ttl, token_type.as_api_str(), ttl)?;
+            let ttl = policy::apply_ttl_policy(&config.policy, ttl, |m| ctx.warn(m))?;
+            if ttl.is_none() {
+                ctx.warn(&format!(
+                    "token '{name}' will never expire; pass --ttl to set an expiry"
+                ))?;
+            }
+            confirm_change(
+                ctx,
+                &format!("Create token '{name}' for '{username}'? [y/N] "),
+            )?;
+            let mut result =
+                ctx.client
+                    .create_token(&username, &name, ttl, Some(token_type.as_api_str()))?;
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::PsObject => {
+                    // JSON output tends to end up in CI logs; keep the secret out unless asked.
+                    if !show_secret && result.get("token").is_some() {
+                        result["token"] = json!(REDACTED);
+                        ctx.note("token value redacted; pass --show-secret to include it")?;
+                    }
+                    print_value(ctx.out, ctx.mode, &result)?
+                }
+                // Meant for `eval`, like text output, so the secret is included.
+                OutputMode::Sh => sh::write(ctx.out, "token", &sh_token(result))?,
+                OutputMode::Text => print_created(ctx.out, &result, print, "token")?,
+            }
+        }
+        TokenCommands::Delete { username, token_id } => {
+            confirm(ctx, &format!("Delete token '{token_id}'? [y/N] "))?;
+            let result = ctx.client.delete_token(&username, &token_id)?;
+            if ctx.mode != OutputMode::Text {
+                print_value(ctx.out, ctx.mode, &result)?;
+            }
+        }
+        TokenCommands::Count {
+            mut usernames,
+            all_users,
+        } => {
+            if all_users {
+                usernames = active_usernames(ctx)?;
+            }
+            let mut by_user = serde_json::Map::new();
+            let lists = read_tokens_bulk(ctx, &usernames);
+            for (username, tokens) in usernames.into_iter().zip(lists) {
+                let n = tokens?["tokens"].as_array().map_or(0, Vec::len);
+                by_user.insert(username, n.into());
+            }
+            let total: u64 = by_user.values().filter_map(Value::as_u64).sum();
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(
+                        ctx.out,
+                        ctx.mode,
+                        &json!({ "count": total, "by_user": by_user }),
+                    )?
+                }
+                OutputMode::Text => writeln!(ctx.out, "{total}")?,
+            }
+            ctx.check_empty(usize::try_from(total)?)?;
+        }
+        TokenCommands::Reconcile {
+            inventory,
+            all_users,
+        } => {
+            let file = File::open(&inventory)
+                .with_context(|| format!("failed to open {}", inventory.display()))?;
+            let tracked = reconcile::Inventory::from_csv(file)?;
+            let mut usernames = tracked.usernames();
+            if all_users {
+                for username in active_usernames(ctx)? {
+                    if !usernames.contains(&username) {
+                        usernames.push(username);
+                    }
+                }
+            }
+            let mut live = Vec::new();
+            let lists = read_tokens_bulk(ctx, &usernames);
+            for (username, tokens) in usernames.into_iter().zip(lists) {
+                let tokens = match tokens {
+                    // A deleted account has no tokens; its inventory rows show as missing.
+                    Err(e)
+                        if e.downcast_ref::<ApiError>()
+                            .is_some_and(|e| e.status == 404) =>
+                    {
+                        json!({})
+                    }
+                    tokens => tokens?,
+                };
+                live.push((username, tokens));
+            }
+            let report = reconcile::reconcile(&tracked, &live);
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &json!(report))?
+                }
+                OutputMode::Text => {
+                    let rows: Vec<Vec<String>> = report
+                        .untracked
+                        .iter()
+                        .map(|d| (d, "untracked"))
+                        .chain(report.missing.iter().map(|d| (d, "missing")))
+                        .map(|(d, status)| {
+                            vec![
+                                d.username.clone(),
+                                d.id.clone().unwrap_or_else(|| "-".to_string()),
+                                d.name.clone().unwrap_or_else(|| "-".to_string()),
+                                status.to_string(),
+                            ]
+                        })
+                        .collect();
+                    if !rows.is_empty() {
+                        print_table(ctx.out, &["USERNAME", "ID", "NAME", "STATUS"], &rows)?;
+                    }
+                    writeln!(
+                        ctx.out,
+                        "{} matched, {} untracked, {} missing",
+                        report.matched,
+                        report.untracked.len(),
+                        report.missing.len()
+                    )?;
+                }
+            }
+        }
+        TokenCommands::RevokeAll { username, reason } => {
+            let tokens = ctx.client.list_tokens(&username)?;
+            let ids: Vec<&str> = tokens["tokens"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|t| extract_str(t, "id"))
+                .collect();
+            if !ids.is_empty() {
+                confirm(
+                    ctx,
+                    &format!(
+                        "Revoke all {} for '{username}'? [y/N] ",
+                        plural(ids.len(), "token")
+                    ),
+                )?;
+            }
+            // Keep going on failure so one bad token does not leave the rest live.
+            let mut revoked = Vec::new();
+            let mut failed = Vec::new();
+            for id in ids {
+                match ctx.client.delete_token(&username, id) {
+                    Ok(_) => revoked.push(id),
+                    Err(e) => {
+                        writeln!(ctx.err, "error: failed to revoke '{id}': {e:#}")?;
+                        failed.push(id);
+                    }
+                }
+            }
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(
+                        ctx.out,
+                        ctx.mode,
+                        &json!({
+                            "username": username,
+                            "reason": reason,
+                            "revoked": revoked,
+                            "failed": failed,
+                        }),
+                    )?
+                }
+                OutputMode::Text => {
+                    let reason = reason
+                        .map(|r| format!(" (reason: {r})"))
+                        .unwrap_or_default();
+                    writeln!(
+                        ctx.out,
+                        "revoked {} for '{username}'{reason}",
+                        plural(revoked.len(), "token")
+                    )?;
+                }
+            }
+            if !failed.is_empty() {
+                bail!(PartialFailure(format!(
+                    "failed to revoke {} of {}",
+                    failed.len(),
+                    plural(failed.len() + revoked.len(), "token")
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_instance(ctx: &mut Ctx, command: InstanceCommands) -> Result<()> {
+    let result = match command {
+        InstanceCommands::ScaleDown {
+            usernames,
+            all_users,
+            flock_size,
+        } => return scale_down(ctx, usernames, all_users, flock_size),
+        InstanceCommands::Validate {
+            username,
+            file,
+            rw_size,
+            rs_size,
+            flock_size,
+        } => {
+            let base = match (file, username) {
+                (Some(path), _) => read_instance_config(&path)?,
+                (None, Some(username)) => ctx.client.get_duckling_config(&username)?,
+                (None, None) => json!({}),
+            };
+            return validate_instance(ctx, base, rw_size, rs_size, flock_size);
+        }
+        InstanceCommands::Get { username } => ctx.client.get_duckling_config(&username)?,
+        InstanceCommands::Set {
+            username,
+            file,
+            rw_size,
+            rs_size,
+            flock_size,
+        } => {
+            let current = match file {
+                Some(path) => {
+                    let config = read_instance_config(&path)?;
+                    let errors = validate::violations(&config, None);
+                    if !errors.is_empty() {
+                        bail!(
+                            "invalid config in {}:\n  {}",
+                            source_name(&path),
+                            errors.join("\n  ")
+                        );
+                    }
+                    config
+                }
+                None => ctx.client.get_duckling_config(&username)?,
+            };
+            let config = merge_duckling_config(&current, rw_size, rs_size, flock_size)?;
+            let kept = unknown_config_fields(&config);
+            let kept = if kept.is_empty() {
+                String::new()
+            } else {
+                format!(", keeping {}", kept.join(", "))
+            };
+            confirm_change(
+                ctx,
+                &format!(
+                    "Set '{username}' to {}/{} (flock_size {}){kept}? [y/N] ",
+                    config["read_write"]["instance_size"]
+                        .as_str()
+                        .unwrap_or_default(),
+                    config["read_scaling"]["instance_size"]
+                        .as_str()
+                        .unwrap_or_default(),
+                    config["read_scaling"]["flock_size"],
+                ),
+            )?;
+            ctx.client.put_duckling_config(&username, &config)?
+        }
+    };
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(ctx.out, ctx.mode, &result)?
+        }
+        OutputMode::Text => print_instance_config(ctx.out, &result)?,
+    }
+    Ok(())
+}
+
+/// Apply `instance set`-style overrides to the current duckling config, returning the config
+/// to send. Fields besides the three md sets (settings added to the API since) are kept as
+/// read, so the PUT does not reset them to their defaults.
+fn merge_duckling_config(
+    current: &Value,
+    rw_size: Option<InstanceSize>,
+    rs_size: Option<InstanceSize>,
+    flock_size: Option<u32>,
+) -> Result<Value> {
+    let rw = match rw_size {
+        Some(s) => s.as_api_str(),
+        None => extract_str(&current["read_write"], "instance_size")
+            .context("current config missing read_write.instance_size")?,
+    };
+    let rs = match rs_size {
+        Some(s) => s.as_api_str(),
+        None => extract_str(&current["read_scaling"], "instance_size")
+            .context("current config missing read_scaling.instance_size")?,
+    };
+    let flock = match flock_size {
+        Some(n) => n,
+        None => current["read_scaling"]["flock_size"]
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .context("current config missing read_scaling.flock_size")?,
+    };
+    let mut config = current
+        .as_object()
+        .cloned()
+        .context("current config is not an object")?;
+    for (section, key, value) in [
+        ("read_write", "instance_size", json!(rw)),
+        ("read_scaling", "instance_size", json!(rs)),
+        ("read_scaling", "flock_size", json!(flock)),
+    ] {
+        config
+            .entry(section)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .with_context(|| format!("current config {section} is not an object"))?
+            .insert(key.to_string(), value);
+    }
+    Ok(Value::Object(config))
+}
+
+/// `path` as shown in messages; `-` is stdin.
+fn source_name(path: &Path) -> String {
+    if path == Path::new("-") {
+        "stdin".to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// A duckling config from a file (`-` for stdin): an `instance get` response, or a `PUT`
+/// body with the config under `config`.
+fn read_instance_config(path: &Path) -> Result<Value> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("failed to read stdin")?;
+        text
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+    };
+    let mut body: Value = serde_json::from_str(&text)
+        .with_context(|| format!("{} is not valid JSON", source_name(path)))?;
+    Ok(match body.get_mut("config") {
+        Some(config) => config.take(),
+        None => body,
+    })
+}
+
+/// `instance validate`: apply the overrides to `config` and report every violation,
+/// failing if there are any. Plan access comes from a fresh `md capabilities` report.
+fn validate_instance(
+    ctx: &mut Ctx,
+    mut config: Value,
+    rw_size: Option<InstanceSize>,
+    rs_size: Option<InstanceSize>,
+    flock_size: Option<u64>,
+) -> Result<()> {
+    let overrides = [
+        (
+            "read_write",
+            "instance_size",
+            rw_size.map(|s| json!(s.as_api_str())),
+        ),
+        (
+            "read_scaling",
+            "instance_size",
+            rs_size.map(|s| json!(s.as_api_str())),
+        ),
+        ("read_scaling", "flock_size", flock_size.map(|n| json!(n))),
+    ];
+    for (section, key, value) in overrides {
+        // A config that is not an object, or has a section that is not one, is reported
+        // by the validator as it stands.
+        if let (Some(value), Some(map)) = (value, config.as_object_mut())
+            && let Some(section) = map
+                .entry(section)
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+        {
+            section.insert(key.to_string(), value);
+        }
+    }
+
+    let report = ctx.config_path.and_then(|path| {
+        Report::load_fresh(
+            &capabilities::path_for(path),
+            ctx.cache_key,
+            chrono::Utc::now(),
+        )
+    });
+    if report.is_none() {
+        ctx.note("plan access not checked; run `md capabilities` to cache it")?;
+    }
+    let errors = validate::violations(&config, report.as_ref());
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "valid": errors.is_empty(), "violations": errors, "config": config }),
+            )?
+        }
+        OutputMode::Text if errors.is_empty() => writeln!(ctx.out, "config is valid")?,
+        OutputMode::Text => {
+            for e in &errors {
+                writeln!(ctx.out, "{e}")?;
+            }
+        }
+    }
+    if !errors.is_empty() {
+        bail!("{} in the proposed config", plural(errors.len(), "problem"));
+    }
+    Ok(())
+}
+
+/// Dotted paths of the fields in a duckling config that md does not set itself.
+fn unknown_config_fields(config: &Value) -> Vec<String> {
+    let known = |section: &str, key: &str| match section {
+        "read_write" => key == "instance_size",
+        "read_scaling" => key == "instance_size" || key == "flock_size",
+        _ => false,
+    };
+    let mut fields = Vec::new();
+    for (section, value) in config.as_object().into_iter().flatten() {
+        match value.as_object() {
+            Some(map) if matches!(section.as_str(), "read_write" | "read_scaling") => fields
+                .extend(
+                    map.keys()
+                        .filter(|key| !known(section, key))
+                        .map(|key| format!("{section}.{key}")),
+                ),
+            _ => fields.push(section.clone()),
+        }
+    }
+    fields
+}
+
+/// Usernames of every active account.
+fn active_usernames(ctx: &Ctx) -> Result<Vec<String>> {
+    let accounts = read_active_accounts(ctx)?;
+    Ok(accounts["accounts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| extract_str(a, "username"))
+        .map(str::to_string)
+        .collect())
+}
+
+// -- reads that `--local` answers from the inventory --
+
+fn read_active_accounts(ctx: &Ctx) -> Result<Value> {
+    match ctx.local {
+        Some(inventory) => Ok(inventory.active_accounts()),
+        None => ctx.client.list_active_accounts(),
+    }
+}
+
+fn read_tokens(ctx: &Ctx, username: &str) -> Result<Value> {
+    match ctx.local {
+        Some(inventory) => inventory.tokens(username),
+        None => ctx.client.list_tokens(username),
+    }
+}
+
+fn read_tokens_bulk(ctx: &Ctx, usernames: &[String]) -> Vec<Result<Value>> {
+    match ctx.local {
+        Some(inventory) => usernames.iter().map(|u| inventory.tokens(u)).collect(),
+        None => ctx.client.list_tokens_bulk(usernames),
+    }
+}
+
+fn read_duckling_config(ctx: &Ctx, username: &str) -> Result<Value> {
+    match ctx.local {
+        Some(inventory) => inventory.instances(username),
+        None => ctx.client.get_duckling_config(username),
+    }
+}
+
+fn handle_sync(ctx: &mut Ctx, usernames: &[String]) -> Result<()> {
+    let path = inventory_path(ctx.config_path)?;
+    let previous = Inventory::load(&path, ctx.cache_key);
+    let now = chrono::Utc::now();
+    let (inventory, summary) =
+        Inventory::sync(previous, ctx.client, ctx.cache_key, usernames, now)?;
+    inventory.save(&path)?;
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(ctx.out, ctx.mode, &json!(summary))?
+        }
+        OutputMode::Text => writeln!(
+            ctx.out,
+            "synced {} ({} added, {} updated, {} removed)",
+            plural(summary.accounts, "account"),
+            summary.added,
+            summary.updated,
+            summary.removed
+        )?,
+    }
+    Ok(())
+}
+
+fn handle_changes(ctx: &mut Ctx, since: &str) -> Result<()> {
+    let since = dates::parse(since, chrono::Utc::now(), Direction::Ago)
+        .with_context(|| format!("invalid --since '{since}'"))?;
+    let path = inventory_path(ctx.config_path)?;
+    let inventory = Inventory::load(&path, ctx.cache_key)
+        .context("no local inventory for this API and token; run `md sync` first")?;
+    let changes = inventory.changes_since(since);
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(ctx.out, ctx.mode, &json!({ "changes": changes }))?
+        }
+        OutputMode::Text => {
+            let rows: Vec<[&str; 5]> = changes
+                .iter()
+                .map(|c| {
+                    [
+                        &c.at,
+                        c.kind.as_str(),
+                        &c.username,
+                        &c.subject,
+                        c.detail.as_deref().unwrap_or("-"),
+                    ]
+                })
+                .collect();
+            print_table(
+                ctx.out,
+                &["SYNCED_AT", "CHANGE", "USERNAME", "SUBJECT", "DETAIL"],
+                &rows,
+            )?;
+        }
+    }
+    let empty = format!(
+        "no changes since {} (last synced {})",
+        since.to_rfc3339(),
+        inventory.synced_at
+    );
+    ctx.note_empty(changes.len(), &empty)?;
+    ctx.check_empty(changes.len())
+}
+
+fn inventory_path(config_path: Option<&Path>) -> Result<PathBuf> {
+    let config_path =
+        config_path.context("no config directory for the local inventory; pass --config")?;
+    Ok(inventory::path_for(config_path))
+}
+
+/// Lower read-scaling flocks above `target` to `target`, keeping instance sizes.
+fn scale_down(
+    ctx: &mut Ctx,
+    mut usernames: Vec<String>,
+    all_users: bool,
+    target: u32,
+) -> Result<()> {
+    if all_users {
+        usernames = active_usernames(ctx)?;
+    }
+
+    let mut plan = Vec::new();
+    let configs = ctx.client.get_duckling_configs(&usernames);
+    for (username, current) in usernames.into_iter().zip(configs) {
+        let current = current?;
+        let flock = current["read_scaling"]["flock_size"].as_u64().unwrap_or(0);
+        if flock > u64::from(target) {
+            plan.push((username, current, flock));
+        }
+    }
+    if plan.is_empty() {
+        writeln!(ctx.err, "nothing to scale down")?;
+    } else {
+        let rows: Vec<Vec<String>> = plan
+            .iter()
+            .map(|(u, _, flock)| vec![u.clone(), flock.to_string(), target.to_string()])
+            .collect();
+        print_table(
+            ctx.err,
+            &["USERNAME", "FLOCK_SIZE", "NEW_FLOCK_SIZE"],
+            &rows,
+        )?;
+        let n = plan.len();
+        confirm(
+            ctx,
+            &format!("Scale down read-scaling for {}? [y/N] ", plural(n, "user")),
+        )?;
+        confirm_typed(ctx, &format!("Type {n} to confirm: "), &n.to_string())?;
+    }
+
+    let mut changed = Vec::new();
+    let mut failed = Vec::new();
+    for (username, current, flock) in &plan {
+        let result = merge_duckling_config(current, None, None, Some(target))
+            .and_then(|config| ctx.client.put_duckling_config(username, &config));
+        match result {
+            Ok(_) => changed.push(json!({ "username": username, "from": flock, "to": target })),
+            Err(e) => {
+                writeln!(ctx.err, "error: failed to scale down '{username}': {e:#}")?;
+                failed.push(username.as_str());
+            }
+        }
+    }
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "changed": changed, "failed": failed }),
+            )?
+        }
+        OutputMode::Text => writeln!(ctx.out, "scaled down {}", plural(changed.len(), "user"))?,
+    }
+    if !failed.is_empty() {
+        bail!(PartialFailure(format!(
+            "failed to scale down {} of {}",
+            failed.len(),
+            plural(plan.len(), "user")
+        )));
+    }
+    Ok(())
+}
+
+/// Empty-listing note for the active accounts commands.
+const NO_ACTIVE_ACCOUNTS: &str = "no active accounts";
+
+fn handle_account(ctx: &mut Ctx, command: AccountCommands) -> Result<()> {
+    match command {
+        AccountCommands::ListActive { stream: true, .. } => {
+            let out = &mut *ctx.out;
+            let mut count = 0;
+            ctx.client.for_each_active_account(|account| {
+                count += 1;
+                writeln!(out, "{account}")?;
+                Ok(())
+            })?;
+            ctx.note_empty(count, NO_ACTIVE_ACCOUNTS)?;
+            ctx.check_empty(count)?;
+        }
+        AccountCommands::ListActive {
+            stream: false,
+            group_by,
+        } => {
+            let mut result = read_active_accounts(ctx)?;
+            load_annotations(ctx)?.merge_into_accounts(&mut result);
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_list(ctx.out, ctx.mode, &result, "accounts")?
+                }
+                OutputMode::Text => match group_by {
+                    Some(by) => print_grouped(
+                        ctx.out,
+                        &result,
+                        "accounts",
+                        by.label(),
+                        |a| by.group(a),
+                        account_table,
+                    )?,
+                    None => print_accounts(ctx.out, &result)?,
+                },
+            }
+            if ctx.mode == OutputMode::Text {
+                ctx.footer(&result, "accounts")?;
+            }
+            let count = result["accounts"].as_array().map_or(0, Vec::len);
+            ctx.note_empty(count, NO_ACTIVE_ACCOUNTS)?;
+            ctx.check_empty(count)?;
+        }
+        AccountCommands::Count { status } => {
+            let result = read_active_accounts(ctx)?;
+            let accounts = result["accounts"].as_array().map_or(&[][..], Vec::as_slice);
+            let has_status = |a: &Value, status: &str| {
+                a["ducklings"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|d| extract_str(d, "status") == Some(status))
+            };
+            let count = match &status {
+                Some(status) => accounts.iter().filter(|a| has_status(a, status)).count(),
+                None => accounts.len(),
+            };
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    let mut by_status = std::collections::BTreeMap::<&str, usize>::new();
+                    for acct in accounts {
+                        let mut statuses: Vec<&str> = acct["ducklings"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|d| extract_str(d, "status"))
+                            .collect();
+                        statuses.sort_unstable();
+                        statuses.dedup();
+                        for s in statuses {
+                            *by_status.entry(s).or_default() += 1;
+                        }
+                    }
+                    print_value(
+                        ctx.out,
+                        ctx.mode,
+                        &json!({ "count": count, "by_status": by_status }),
+                    )?
+                }
+                OutputMode::Text => writeln!(ctx.out, "{count}")?,
+            }
+            ctx.check_empty(count)?;
+        }
+        AccountCommands::Tree => {
+            let result = read_active_accounts(ctx)?;
+            let mut accounts = Vec::new();
+            for acct in result["accounts"].as_array().into_iter().flatten() {
+                let Some(username) = extract_str(acct, "username") else {
+                    continue;
+                };
+                let tokens = read_tokens(ctx, username)?;
+                let config = read_duckling_config(ctx, username)?;
+                let ducklings: Vec<Value> = acct["ducklings"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|d| {
+                        let mut d = d.clone();
+                        let kind = extract_str(&d, "type").unwrap_or_default().to_string();
+                        for key in ["instance_size", "flock_size"] {
+                            let v = &config[&kind][key];
+                            if !v.is_null() {
+                                d[key] = v.clone();
+                            }
+                        }
+                        d
+                    })
+                    .collect();
+                accounts.push(json!({
+                    "username": username,
+                    "tokens": tokens["tokens"].as_array().map_or(0, Vec::len),
+                    "ducklings": ducklings,
+                }));
+            }
+            let accounts_len = accounts.len();
+            let tree = json!({ "accounts": accounts });
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &tree)?
+                }
+                OutputMode::Text => print_account_tree(ctx.out, &tree)?,
+            }
+            ctx.note_empty(accounts_len, NO_ACTIVE_ACCOUNTS)?;
+            ctx.check_empty(accounts_len)?;
+        }
+        AccountCommands::Export { path } => {
+            let now = chrono::Utc::now();
+            let archive = export::build(ctx.client, now)?;
+            let path = path.unwrap_or_else(|| export::default_path(Path::new("."), now));
+            File::create(&path)
+                .and_then(|mut file| print_json(&mut file, &archive))
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            let count = archive["accounts"].as_array().map_or(0, Vec::len);
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(
+                        ctx.out,
+                        ctx.mode,
+                        &json!({ "path": path, "accounts": count }),
+                    )?
+                }
+                OutputMode::Text => writeln!(
+                    ctx.out,
+                    "exported {} to {}",
+                    plural(count, "account"),
+                    path.display()
+                )?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_audit(ctx: &mut Ctx, command: AuditCommands) -> Result<()> {
+    match command {
+        AuditCommands::Orphans { owners, csv } => {
+            let file = File::open(&owners)
+                .with_context(|| format!("failed to open {}", owners.display()))?;
+            let owners = audit::Owners::from_csv(file)?;
+
+            let accounts = read_active_accounts(ctx)?;
+            let mut counts = Vec::new();
+            for acct in accounts["accounts"].as_array().into_iter().flatten() {
+                let Some(username) = extract_str(acct, "username") else {
+                    continue;
+                };
+                let tokens = read_tokens(ctx, username)?;
+                let count = tokens["tokens"].as_array().map_or(0, Vec::len);
+                counts.push((username.to_string(), count));
+            }
+            let orphans = audit::find_orphans(&counts, &owners);
+
+            if let Some(path) = csv {
+                let file = File::create(&path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                audit::write_csv(file, &orphans)?;
+            }
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &json!({ "orphans": orphans }))?
+                }
+                OutputMode::Text => {
+                    let rows: Vec<Vec<String>> = orphans
+                        .iter()
+                        .map(|o| {
+                            vec![
+                                o.username.clone(),
+                                o.owner.clone().unwrap_or_else(|| "-".to_string()),
+                                o.tokens.to_string(),
+                                o.reason.to_string(),
+                            ]
+                        })
+                        .collect();
+                    print_table(ctx.out, &["USERNAME", "OWNER", "TOKENS", "REASON"], &rows)?;
+                    ctx.note_empty(rows.len(), "no orphaned accounts")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// -- resource URIs --
+
+fn handle_get(ctx: &mut Ctx, resource: &Resource) -> Result<()> {
+    match resource {
+        Resource::User { username } => {
+            if !ctx.client.user_exists(username)? {
+                bail!("service account '{username}' not found");
+            }
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &json!({ "username": username }))?
+                }
+                OutputMode::Text => writeln!(ctx.out, "{username}")?,
+            }
+        }
+        Resource::Tokens { username } => {
+            let result = ctx.client.list_tokens(username)?;
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &result)?
+                }
+                OutputMode::Text => print_tokens(ctx.out, &result, chrono::Utc::now(), ctx.color)?,
+            }
+        }
+        Resource::Token { username, token_id } => {
+            let tokens = ctx.client.list_tokens(username)?;
+            let token = tokens["tokens"]
+                .as_array()
+                .and_then(|list| list.iter().find(|t| t["id"] == token_id.as_str()))
+                .with_context(|| format!("token '{token_id}' not found for '{username}'"))?;
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, token)?
+                }
+                OutputMode::Text => print_tokens(
+                    ctx.out,
+                    &json!({ "tokens": [token] }),
+                    chrono::Utc::now(),
+                    ctx.color,
+                )?,
+            }
+        }
+        Resource::Instances { username } => {
+            let result = ctx.client.get_duckling_config(username)?;
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &result)?
+                }
+                OutputMode::Text => print_instance_config(ctx.out, &result)?,
+            }
+        }
+        Resource::ActiveAccounts => {
+            let result = ctx.client.list_active_accounts()?;
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &result)?
+                }
+                OutputMode::Text => print_accounts(ctx.out, &result)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_delete(ctx: &mut Ctx, resource: &Resource) -> Result<()> {
+    let result = match resource {
+        Resource::User { username } => delete_service_account(ctx, username, None)?,
+        Resource::Token { username, token_id } => {
+            confirm(ctx, &format!("Delete token '{token_id}'? [y/N] "))?;
+            ctx.client.delete_token(username, token_id)?
+        }
+        other => bail!("{other} cannot be deleted ({} is read-only)", other.kind()),
+    };
+    if ctx.mode != OutputMode::Text {
+        print_value(ctx.out, ctx.mode, &result)?;
+    }
+    Ok(())
+}
+
+fn handle_describe(ctx: &mut Ctx, resource: &Resource) -> Result<()> {
+    let uri = resource.to_string();
+    let api_path = resource.api_path();
+    let verbs = resource.verbs();
+    let annotation = match resource {
+        Resource::User { username } => load_annotations(ctx)?.users.remove(username),
+        _ => None,
+    };
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            let mut value = json!({
+                "uri": uri,
+                "kind": resource.kind(),
+                "api_path": api_path,
+                "verbs": verbs,
+            });
+            if let Some(annotation) = &annotation {
+                value["annotations"] = json!(annotation);
+            }
+            print_value(ctx.out, ctx.mode, &value)?
+        }
+        OutputMode::Text => {
+            writeln!(ctx.out, "uri:      {uri}")?;
+            writeln!(ctx.out, "kind:     {}", resource.kind())?;
+            writeln!(ctx.out, "api_path: {api_path}")?;
+            writeln!(ctx.out, "verbs:    {}", verbs.join(", "))?;
+            if let Some(annotation) = &annotation {
+                print_annotation(ctx.out, annotation)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// -- wait --
+
+fn handle_wait(ctx: &mut Ctx, command: WaitCommands) -> Result<()> {
+    let client = ctx.client;
+    type Check<'a> = Box<dyn FnMut() -> Result<(String, bool)> + 'a>;
+    let (subject, wanted, poll, mut check): (String, String, PollArgs, Check) = match command {
+        WaitCommands::Duckling {
+            username,
+            until,
+            duckling_type,
+            poll,
+        } => {
+            let kind = duckling_type.as_api_str();
+            let wanted = until.iter().map(ToString::to_string).collect::<Vec<_>>();
+            (
+                format!("'{username}' {kind}"),
+                wanted.join(" "),
+                poll,
+                Box::new(move || observe_duckling(client, &username, kind, &until)),
+            )
+        }
+        WaitCommands::Account {
+            username,
+            until,
+            poll,
+        } => (
+            format!("'{username}'"),
+            match until {
+                AccountState::Exists => "exists",
+                AccountState::Deleted => "deleted",
+            }
+            .to_string(),
+            poll,
+            Box::new(move || {
+                let exists = client.user_exists(&username)?;
+                let seen = if exists { "exists" } else { "deleted" };
+                Ok((seen.to_string(), exists == (until == AccountState::Exists)))
+            }),
+        ),
+    };
+    let started = std::time::Instant::now();
+    let mut last = String::new();
+    let err = &mut *ctx.err;
+    let reached = wait::poll(poll.timeout, poll.interval, || {
+        let (seen, holds) = check()?;
+        if seen != last {
+            writeln!(err, "{subject}: {seen}")?;
+            last = seen;
+        }
+        Ok(holds)
+    })?;
+    if !reached {
+        return Err(TimedOut(format!(
+            "timed out after {} waiting for {subject} to reach {wanted} (last seen: {last})",
+            humantime::format_duration(poll.timeout)
+        ))
+        .into());
+    }
+    let waited = started.elapsed().as_secs();
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "reached": wanted, "waited_secs": waited }),
+            )?
+        }
+        OutputMode::Text => writeln!(ctx.err, "reached {wanted} after {waited}s")?,
+    }
+    Ok(())
+}
+
+/// The `--until` conditions with their current values (e.g. "status=idle"), and whether
+/// all of them hold. A user without an instance config yet has no size or flock size.
+fn observe_duckling(
+    client: &MotherduckClient,
+    username: &str,
+    kind: &str,
+    until: &[Condition],
+) -> Result<(String, bool)> {
+    let account = if until.iter().any(|c| !c.field.in_config()) {
+        let listing = client.list_active_accounts()?;
+        listing["accounts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|a| a["username"] == username)
+            .cloned()
+    } else {
+        None
+    };
+    let config = if until.iter().any(|c| c.field.in_config()) {
+        match client.get_duckling_config(username) {
+            Err(e)
+                if e.downcast_ref::<ApiError>()
+                    .is_some_and(|e| e.status == 404) =>
+            {
+                Value::Null
+            }
+            config => config?,
+        }
+    } else {
+        Value::Null
+    };
+    let mut seen = Vec::new();
+    let mut all = true;
+    for condition in until {
+        let value = condition.observe(kind, account.as_ref(), &config);
+        all &= value == condition.value;
+        seen.push(
+            Condition {
+                value,
+                ..condition.clone()
+            }
+            .to_string(),
+        );
+    }
+    Ok((seen.join(" "), all))
+}
+
+// -- annotations --
+
+fn annotations_path(ctx: &Ctx) -> Result<PathBuf> {
+    let config_path = ctx
+        .config_path
+        .context("no config directory for annotations; pass --config")?;
+    Ok(annotations::path_for(config_path))
+}
+
+/// Annotations next to the config file, or none without a config directory.
+fn load_annotations(ctx: &Ctx) -> Result<Annotations> {
+    match ctx.config_path {
+        Some(path) => Annotations::load(&annotations::path_for(path)),
+        None => Ok(Annotations::default()),
+    }
+}
+
+fn print_annotation(out: &mut dyn Write, annotation: &annotations::Annotation) -> io::Result<()> {
+    for (label, value) in [
+        ("owner:   ", &annotation.owner),
+        ("ticket:  ", &annotation.ticket),
+        ("note:    ", &annotation.note),
+        ("expires: ", &annotation.expires),
+    ] {
+        if let Some(value) = value {
+            writeln!(out, "{label} {value}")?;
+        }
+    }
+    Ok(())
+}
+
+fn handle_annotate(ctx: &mut Ctx, command: AnnotateCommands) -> Result<()> {
+    let path = annotations_path(ctx)?;
+    let mut annotations = Annotations::load(&path)?;
+    match command {
+        AnnotateCommands::User {
+            username,
+            owner,
+            ticket,
+            note,
+            expires,
+            clear,
+        } => {
+            let changing =
+                clear || owner.is_some() || ticket.is_some() || note.is_some() || expires.is_some();
+            if changing {
+                // As with the other fields, an empty value removes it.
+                let expires = match expires.as_deref() {
+                    None => None,
+                    Some("") => Some(None),
+                    Some(_) => Some(time_flag(
+                        "--expires",
+                        expires,
+                        chrono::Utc::now(),
+                        Direction::Ahead,
+                    )?),
+                };
+                let annotation = annotations.users.entry(username.clone()).or_default();
+                if clear {
+                    *annotation = Default::default();
+                }
+                annotation.update(owner, ticket, note);
+                if let Some(at) = expires {
+                    annotation.set_expires(at);
+                }
+                if annotation.is_empty() {
+                    annotations.users.remove(&username);
+                }
+                annotations.save(&path)?;
+            }
+            let annotation = annotations.users.remove(&username).unwrap_or_default();
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(
+                        ctx.out,
+                        ctx.mode,
+                        &json!({ "username": username, "annotations": annotation }),
+                    )?
+                }
+                OutputMode::Text if annotation.is_empty() => {
+                    writeln!(ctx.err, "no annotations for '{username}'")?
+                }
+                OutputMode::Text => print_annotation(ctx.out, &annotation)?,
+            }
+        }
+        AnnotateCommands::List => match ctx.mode {
+            OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                print_value(ctx.out, ctx.mode, &json!({ "users": annotations.users }))?
+            }
+            OutputMode::Text => {
+                let rows: Vec<Vec<String>> = annotations
+                    .users
+                    .iter()
+                    .map(|(username, a)| {
+                        let field = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".into());
+                        vec![
+                            username.clone(),
+                            field(&a.owner),
+                            field(&a.ticket),
+                            field(&a.note),
+                        ]
+                    })
+                    .collect();
+                print_table(ctx.out, &["USERNAME", "OWNER", "TICKET", "NOTE"], &rows)?;
+                ctx.note_empty(
+                    rows.len(),
+                    "no annotations; add some with `md annotate user <username> --owner <owner>`",
+                )?;
+            }
+        },
+    }
+    Ok(())
+}
+
+// -- provisioning --
+
+/// Create a service account, its first token, and (if any sizing is given) its duckling
+/// config. A failure after the account exists deletes what was created.
+fn provision(ctx: &mut Ctx, mut args: ProvisionArgs) -> Result<()> {
+    if let Some(name) = args.template.take() {
+        let template = ctx
+            .config
+            .templates
+            .get(&name)
+            .with_context(|| format!("no template '{name}' in the config"))?;
+        args.apply_template(&name, template)?;
+    }
+    let ProvisionArgs {
+        username,
+        token_name,
+        ttl,
+        token_type,
+        rw_size,
+        rs_size,
+        flock_size,
+        show_secret,
+        print,
+        ..
+    } = args;
+    let username = username.as_str();
+    let token_name = token_name.as_deref().unwrap_or("default");
+    let token_type = token_type.unwrap_or(TokenType::ReadWrite);
+    policy::check_username(&ctx.config.policy, username)?;
+    let config = ctx.config;
+    let ttl = defaults::token_ttl(&config.defaults.ttl, token_type.as_api_str(), ttl)?;
+    let ttl = policy::apply_ttl_policy(&config.policy, ttl, |m| ctx.warn(m))?;
+    if ttl.is_none() {
+        ctx.warn(&format!(
+            "token '{token_name}' will never expire; pass --ttl to set an expiry"
+        ))?;
+    }
+    confirm_change(
+        ctx,
+        &format!("Provision service account '{username}' with token '{token_name}'? [y/N] "),
+    )?;
+
+    ctx.client.create_user(username)?;
+    writeln!(ctx.err, "created service account '{username}'")?;
+    let mut token = ctx
+        .client
+        .create_token(username, token_name, ttl, Some(token_type.as_api_str()))
+        .map_err(|e| rollback(ctx, username, None, e))?;
+    let token_id = extract_str(&token, "id").map(str::to_string);
+    writeln!(ctx.err, "created token '{token_name}'")?;
+
+    let mut instances = Value::Null;
+    if rw_size.is_some() || rs_size.is_some() || flock_size.is_some() {
+        let set = ctx
+            .client
+            .get_duckling_config(username)
+            .and_then(|current| {
+                let config = merge_duckling_config(&current, rw_size, rs_size, flock_size)?;
+                ctx.client.put_duckling_config(username, &config)
+            });
+        instances = set.map_err(|e| rollback(ctx, username, token_id.as_deref(), e))?;
+        writeln!(ctx.err, "configured ducklings")?;
+    }
+
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::PsObject => {
+            if !show_secret && token.get("token").is_some() {
+                token["token"] = json!(REDACTED);
+                ctx.note("token value redacted; pass --show-secret to include it")?;
+            }
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "username": username, "token": token, "instances": instances }),
+            )?
+        }
+        OutputMode::Sh => sh::write(
+            ctx.out,
+            "",
+            &json!({ "username": username, "token": sh_token(token), "instances": instances }),
+        )?,
+        OutputMode::Text => print_created(
+            ctx.out,
+            &json!({ "username": username, "token": token, "instances": instances }),
+            print,
+            "token.token",
+        )?,
+    }
+    Ok(())
+}
+
+/// Undo a partial `provision`: delete the token (if created), then the account. The
+/// returned error explains what was rolled back, or what to clean up by hand.
+fn rollback(
+    ctx: &mut Ctx,
+    username: &str,
+    token_id: Option<&str>,
+    err: anyhow::Error,
+) -> anyhow::Error {
+    let _ = writeln!(ctx.err, "provisioning failed; rolling back '{username}'");
+    let undone = token_id
+        .map_or(Ok(Value::Null), |id| ctx.client.delete_token(username, id))
+        .and_then(|_| ctx.client.delete_user(username));
+    match undone {
+        Ok(_) => err.context(format!(
+            "provisioning '{username}' failed; the service account was deleted again"
+        )),
+        Err(e) => err.context(format!(
+            "provisioning '{username}' failed and rollback failed ({e:#}); delete the \
+             service account by hand"
+        )),
+    }
+}
+
+/// `connect-string`: an `md:` DuckDB connection string for `username`'s read-write duckling
+/// or, with `--read-scaling`, its read-scaling flock. Checks that the flock exists, and warns
+/// when the user has no read-scaling token to connect with.
+fn connect_string(ctx: &mut Ctx, args: ConnectStringArgs) -> Result<()> {
+    let username = &args.username;
+    let config = ctx.client.get_duckling_config(username)?;
+    if args.read_scaling {
+        let flock = config["read_scaling"]["flock_size"].as_u64().unwrap_or(0);
+        if flock == 0 {
+            bail!(
+                "'{username}' has no read-scaling ducklings (flock_size 0); \
+                 add some with `md instance set {username} --flock-size <n>`"
+            );
+        }
+        let tokens = ctx.client.list_tokens(username)?;
+        let has_token = tokens["tokens"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|t| t["token_type"] == TokenType::ReadScaling.as_api_str());
+        if !has_token {
+            ctx.warn(&format!(
+                "'{username}' has no read-scaling token; create one with \
+                 `md token create {username} -n <name> --token-type read-scaling`"
+            ))?;
+        }
+    }
+    let secret = match args.secret.as_deref() {
+        Some(s) if s.trim().is_empty() => bail!("--secret must not be empty"),
+        Some(s) => Some(auth::resolve_token_or(Some(s))?),
+        None => None,
+    };
+    let dsn = |secret: Option<&str>| {
+        let mut params = Vec::new();
+        if let Some(secret) = secret {
+            params.push(("motherduck_token", secret));
+        }
+        if let Some(hint) = &args.session_hint {
+            params.push(("session_hint", hint.as_str()));
+        }
+        connection_string(args.database.as_deref(), &params)
+    };
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            let shown = match &secret {
+                Some(_) if !args.show_secret && ctx.mode != OutputMode::Sh => {
+                    ctx.note("token value redacted; pass --show-secret to include it")?;
+                    dsn(Some(REDACTED))
+                }
+                _ => dsn(secret.as_deref()),
+            };
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({
+                    "username": username,
+                    "read_scaling": args.read_scaling,
+                    "connection_string": shown,
+                }),
+            )?
+        }
+        OutputMode::Text => writeln!(ctx.out, "{}", dsn(secret.as_deref()))?,
+    }
+    if secret.is_none() {
+        ctx.note(
+            "no --secret; DuckDB reads the token from the motherduck_token environment variable",
+        )?;
+    }
+    Ok(())
+}
+
+/// `test-connection`: connect as `username` through the DuckDB CLI and run `SELECT 1`.
+/// Without `--secret`, a token with the shortest TTL the API allows is created for the
+/// check and deleted afterwards, whether or not the check passed.
+fn test_connection(ctx: &mut Ctx, args: TestConnectionArgs) -> Result<()> {
+    let username = &args.username;
+    let token_type = if args.read_scaling {
+        TokenType::ReadScaling
+    } else {
+        TokenType::ReadWrite
+    };
+    let (secret, temporary) = match args.secret.as_deref() {
+        Some(s) if s.trim().is_empty() => bail!("--secret must not be empty"),
+        Some(s) => (auth::resolve_token_or(Some(s))?, None),
+        None => {
+            let name = format!("md-test-connection-{}", chrono::Utc::now().timestamp());
+            confirm_change(
+                ctx,
+                &format!("Create temporary token '{name}' for '{username}'? [y/N] "),
+            )?;
+            let token = ctx.client.create_token(
+                username,
+                &name,
+                Some(*policy::TTL_RANGE.start()),
+                Some(token_type.as_api_str()),
+            )?;
+            let secret = extract_str(&token, "token")
+                .context("token create response has no token value")?
+                .to_string();
+            let id = extract_str(&token, "id").map(str::to_string);
+            writeln!(ctx.err, "created temporary token '{name}'")?;
+            (secret, Some((name, id)))
+        }
+    };
+
+    let dsn = connection_string(args.database.as_deref(), &[]);
+    let result = duckdb::select_one(&args.duckdb, &dsn, &secret);
+
+    if let Some((name, id)) = &temporary {
+        let deleted = match id {
+            Some(id) => ctx.client.delete_token(username, id).map(drop),
+            None => Err(anyhow::anyhow!("token create response has no id")),
+        };
+        match deleted {
+            Ok(()) => writeln!(ctx.err, "deleted temporary token '{name}'")?,
+            Err(e) => ctx.warn(&format!(
+                "failed to delete temporary token '{name}' (it expires in {}): {e:#}",
+                policy::fmt_secs(*policy::TTL_RANGE.start())
+            ))?,
+        }
+    }
+
+    let elapsed = result.with_context(|| format!("connection test failed for '{username}'"))?;
+    let latency_ms = elapsed.as_millis();
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({
+                    "username": username,
+                    "ok": true,
+                    "read_scaling": args.read_scaling,
+                    "latency_ms": latency_ms,
+                    "temporary_token": temporary.is_some(),
+                }),
+            )?
+        }
+        OutputMode::Text => writeln!(
+            ctx.out,
+            "ok: '{username}' connected and ran SELECT 1 in {latency_ms} ms"
+        )?,
+    }
+    Ok(())
+}
+
+/// Characters left as-is in connection string parameter values (`*` so a redacted token
+/// reads as `***`).
+const PARAM_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'*');
+
+/// `md:[database][?key=value&...]`, percent-encoding the database and values.
+fn connection_string(database: Option<&str>, params: &[(&str, &str)]) -> String {
+    let mut dsn = format!(
+        "md:{}",
+        utf8_percent_encode(database.unwrap_or_default(), PARAM_VALUE)
+    );
+    for (i, (key, value)) in params.iter().enumerate() {
+        dsn.push(if i == 0 { '?' } else { '&' });
+        dsn.push_str(key);
+        dsn.push('=');
+        dsn.extend(utf8_percent_encode(value, PARAM_VALUE));
+    }
+    dsn
+}
+
+/// The reverse of `provision`, in dependency order: revoke every token, scale the
+/// read-scaling flock to 0, then delete the account. Shows the plan and asks for the
+/// username to be typed back; stops at the first failed step.
+fn deprovision(ctx: &mut Ctx, username: &str) -> Result<()> {
+    let result = deprovision_account(ctx, username)?;
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(ctx.out, ctx.mode, &result)?
+        }
+        OutputMode::Text => print_deprovisioned(ctx, &result)?,
+    }
+    Ok(())
+}
+
+fn print_deprovisioned(ctx: &mut Ctx, result: &Value) -> io::Result<()> {
+    writeln!(
+        ctx.out,
+        "deprovisioned '{}' (revoked {})",
+        display_field(result, "username"),
+        plural(result["revoked"].as_array().map_or(0, Vec::len), "token")
+    )
+}
+
+/// Confirm, then revoke every token, scale the flock to 0, and delete the account.
+fn deprovision_account(ctx: &mut Ctx, username: &str) -> Result<Value> {
+    let tokens = ctx.client.list_tokens(username)?;
+    let ids: Vec<&str> = tokens["tokens"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| extract_str(t, "id"))
+        .collect();
+    let config = ctx.client.get_duckling_config(username)?;
+    let flock = config["read_scaling"]["flock_size"].as_u64().unwrap_or(0);
+
+    writeln!(ctx.err, "deprovisioning '{username}':")?;
+    if !ids.is_empty() {
+        writeln!(
+            ctx.err,
+            "  revoke {}: {}",
+            plural(ids.len(), "token"),
+            ids.join(", ")
+        )?;
+    }
+    if flock > 0 {
+        writeln!(ctx.err, "  scale read-scaling flock {flock} -> 0")?;
+    }
+    writeln!(ctx.err, "  delete service account")?;
+    confirm_typed(
+        ctx,
+        &format!("Type '{username}' to deprovision it: "),
+        username,
+    )?;
+
+    for id in &ids {
+        ctx.client
+            .delete_token(username, id)
+            .with_context(|| format!("failed to revoke token '{id}'; nothing else was changed"))?;
+    }
+    if flock > 0 {
+        let config = merge_duckling_config(&config, None, None, Some(0))?;
+        ctx.client
+            .put_duckling_config(username, &config)
+            .context("failed to scale down; tokens were already revoked")?;
+    }
+    ctx.client
+        .delete_user(username)
+        .context("failed to delete the service account; tokens were already revoked")?;
+    Ok(json!({
+        "username": username,
+        "revoked": ids,
+        "flock_size": { "from": flock, "to": 0 },
+        "deleted": true,
+    }))
+}
+
+fn handle_search(ctx: &mut Ctx, pattern: &str, refresh: bool) -> Result<()> {
+    let path = ctx.config_path.map(search::path_for);
+    let now = chrono::Utc::now();
+    let cached = match &path {
+        Some(path) if !refresh => Index::load_fresh(path, ctx.cache_key, now),
+        _ => None,
+    };
+    let index = match (ctx.local, cached) {
+        (Some(inventory), _) => {
+            anyhow::ensure!(!refresh, "--refresh cannot be combined with --local");
+            let lists: Vec<(&str, Value)> = inventory
+                .accounts
+                .keys()
+                .map(|u| Ok((u.as_str(), inventory.tokens(u)?)))
+                .collect::<Result<_>>()?;
+            Index::from_listings(ctx.cache_key, now, lists.iter().map(|(u, l)| (*u, l)))
+        }
+        (None, Some(index)) => index,
+        (None, None) => {
+            let usernames = active_usernames(ctx)?;
+            let index = Index::build(ctx.client, usernames, ctx.cache_key, now)?;
+            if let Some(path) = &path {
+                index.save(path)?;
+            }
+            index
+        }
+    };
+    let matches = index.search(pattern);
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(ctx.out, ctx.mode, &json!(matches))?
+        }
+        OutputMode::Text => {
+            let rows: Vec<[&str; 4]> = matches
+                .iter()
+                .map(|m| {
+                    [
+                        m.username,
+                        m.field.as_str(),
+                        m.token_id.unwrap_or("-"),
+                        m.token_name.unwrap_or("-"),
+                    ]
+                })
+                .collect();
+            print_table(
+                ctx.out,
+                &["USERNAME", "MATCH", "TOKEN_ID", "TOKEN_NAME"],
+                &rows,
+            )?;
+        }
+    }
+    ctx.note_empty(
+        matches.len(),
+        &format!("no accounts or tokens match '{pattern}'"),
+    )?;
+    ctx.check_empty(matches.len())
+}
+
+fn handle_capabilities(ctx: &mut Ctx, refresh: bool) -> Result<()> {
+    let path = ctx.config_path.map(capabilities::path_for);
+    let now = chrono::Utc::now();
+    let cached = match &path {
+        Some(path) if !refresh => Report::load_fresh(path, ctx.cache_key, now),
+        _ => None,
+    };
+    let report = match cached {
+        Some(report) => {
+            writeln!(
+                ctx.err,
+                "cached from {}; pass --refresh to check again",
+                report.checked_at
+            )?;
+            report
+        }
+        None => {
+            let report = Report::probe(ctx.client, ctx.cache_key, now);
+            if let Some(path) = &path {
+                report.save(path)?;
+            }
+            report
+        }
+    };
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({
+                    "checked_at": report.checked_at,
+                    "capabilities": report.capabilities,
+                }),
+            )?
+        }
+        OutputMode::Text => {
+            let rows: Vec<Vec<String>> = capabilities::CAPABILITIES
+                .iter()
+                .map(|c| {
+                    let access = report
+                        .capabilities
+                        .get(c.name)
+                        .map_or("unknown", |a| a.as_str());
+                    vec![c.name.into(), access.into(), c.description.into()]
+                })
+                .collect();
+            print_table(ctx.out, &["CAPABILITY", "ACCESS", "DESCRIPTION"], &rows)?;
+        }
+    }
+    Ok(())
+}
+
+fn handle_permissions(ctx: &mut Ctx, command: &str) -> Result<()> {
+    let entries: Vec<&permissions::Permission> = if command.is_empty() {
+        permissions::PERMISSIONS.iter().collect()
+    } else {
+        vec![permissions::lookup(command).with_context(|| {
+            format!("unknown command `{command}`; run `md permissions` for the list")
+        })?]
+    };
+    let report = ctx.config_path.and_then(|path| {
+        Report::load_fresh(
+            &capabilities::path_for(path),
+            ctx.cache_key,
+            chrono::Utc::now(),
+        )
+    });
+    // Whether this token has the capability, per a fresh `md capabilities` probe.
+    let access = |name: &str| {
+        report
+            .as_ref()
+            .and_then(|r| r.capabilities.get(name))
+            .map(|a| a.as_str())
+    };
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            let list: Vec<Value> = entries
+                .iter()
+                .map(|p| {
+                    let capabilities: Vec<Value> = p
+                        .capabilities
+                        .iter()
+                        .map(|c| json!({ "name": c, "access": access(c) }))
+                        .collect();
+                    json!({
+                        "command": p.command,
+                        "role": p.role,
+                        "capabilities": capabilities,
+                        "calls": p.calls,
+                    })
+                })
+                .collect();
+            print_value(ctx.out, ctx.mode, &json!({ "permissions": list }))?
+        }
+        OutputMode::Text if command.is_empty() => {
+            let rows: Vec<Vec<String>> = entries
+                .iter()
+                .map(|p| {
+                    let capabilities = if p.capabilities.is_empty() {
+                        "-".to_string()
+                    } else {
+                        p.capabilities.join(", ")
+                    };
+                    vec![p.command.into(), p.role.as_str().into(), capabilities]
+                })
+                .collect();
+            print_table(ctx.out, &["COMMAND", "ROLE", "CAPABILITIES"], &rows)?;
+        }
+        OutputMode::Text => {
+            let p = entries[0];
+            writeln!(ctx.out, "role: {} ({})", p.role.as_str(), p.role.describe())?;
+            if p.capabilities.is_empty() {
+                writeln!(ctx.out, "capabilities: none")?;
+            } else {
+                writeln!(ctx.out, "capabilities:")?;
+                for c in p.capabilities {
+                    let access = access(c).unwrap_or("not checked; run `md capabilities`");
+                    writeln!(ctx.out, "  {c} ({access})")?;
+                }
+            }
+            if !p.calls.is_empty() {
+                writeln!(ctx.out, "calls:")?;
+                for call in p.calls {
+                    writeln!(ctx.out, "  {call}")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_config(
+    path: &Path,
+    command: &ConfigCommands,
+    mode: OutputMode,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let text = read_config_text(path)?;
+    let invalid = || format!("invalid config {}", path.display());
+    let entries = match command {
+        ConfigCommands::Set { key, value } => {
+            let text = config::set(&text, key, value).with_context(invalid)?;
+            write_config_text(path, &text)?;
+            config::get(&text, key)?
+        }
+        ConfigCommands::Get { key } => {
+            let entries = config::get(&text, key).with_context(invalid)?;
+            if entries.is_empty() {
+                bail!("{key} is not set in {}", path.display());
+            }
+            entries
+        }
+        ConfigCommands::List => config::entries(&text).with_context(invalid)?,
+    };
+    match mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            let map: serde_json::Map<String, Value> = entries
+                .into_iter()
+                .map(|(k, v)| Ok((k, serde_json::to_value(v)?)))
+                .collect::<serde_json::Result<_>>()?;
+            print_value(out, mode, &Value::Object(map))?
+        }
+        OutputMode::Text => match (command, entries.as_slice()) {
+            // A single setting prints bare, for `$(md config get ...)`.
+            (ConfigCommands::Get { key }, [(k, v)]) if k == key => match v {
+                toml::Value::String(s) => writeln!(out, "{s}")?,
+                v => writeln!(out, "{v}")?,
+            },
+            _ => {
+                for (k, v) in entries {
+                    writeln!(out, "{k} = {v}")?;
+                }
+            }
+        },
+    }
+    Ok(())
+}
+
+/// The config file's text; a missing file is empty.
+fn read_config_text(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn write_config_text(path: &Path, text: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The token from a profile's `token_env` or `token_command`, if it sets either; `--token`
+/// wins over both.
+fn profile_token(profile: &config::Profile) -> Option<Result<String>> {
+    match (&profile.token_env, &profile.token_command) {
+        (Some(var), _) => Some(auth::token_from_env(var)),
+        (None, Some(command)) => Some(auth::token_from_command(command)),
+        (None, None) => None,
+    }
+}
+
+fn check_spec(path: &Path, mode: OutputMode, out: &mut dyn Write) -> Result<()> {
+    let drift = spec::check(&spec::load(path)?, spec::ENDPOINTS);
+    match mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(out, mode, &serde_json::to_value(&drift)?)?
+        }
+        OutputMode::Text => {
+            let section = |out: &mut dyn Write, title: &str, items: &[String]| -> io::Result<()> {
+                if !items.is_empty() {
+                    writeln!(out, "{title}:")?;
+                    for item in items {
+                        writeln!(out, "  {item}")?;
+                    }
+                }
+                Ok(())
+            };
+            let removed: Vec<String> = drift
+                .removed_fields
+                .iter()
+                .map(|f| format!("{} {}: {}", f.endpoint, f.body, f.field))
+                .collect();
+            section(out, "removed endpoints", &drift.removed_endpoints)?;
+            section(out, "removed fields", &removed)?;
+            section(out, "not implemented", &drift.unimplemented)?;
+            section(out, "no schema (fields unchecked)", &drift.unchecked)?;
+            if drift == spec::Drift::default() {
+                writeln!(
+                    out,
+                    "no drift: the spec has everything md uses, and nothing more"
+                )?;
+            }
+        }
+    }
+    if drift.breaking() {
+        bail!(
+            "md uses endpoints or fields that {} does not have",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn handle_profile(
+    path: &Path,
+    command: &ProfileCommands,
+    flag: Option<&str>,
+    mode: OutputMode,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let config = Config::load(path)?;
+    match command {
+        ProfileCommands::List => {
+            let active = config.active_profile(flag)?.map(|(name, _)| name);
+            let token = |p: &config::Profile| match (&p.token_env, &p.token_command) {
+                (Some(var), _) => format!("env {var}"),
+                (None, Some(command)) => format!("command {command}"),
+                (None, None) => "default".to_string(),
+            };
+            match mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    let profiles: Vec<Value> = config
+                        .profiles
+                        .iter()
+                        .map(|(name, p)| {
+                            json!({
+                                "name": name,
+                                "active": Some(name.as_str()) == active,
+                                "token_env": p.token_env,
+                                "token_command": p.token_command,
+                                "base_url": p.base_url,
+                                "output": p.output,
+                            })
+                        })
+                        .collect();
+                    print_value(
+                        out,
+                        mode,
+                        &json!({ "active": active, "profiles": profiles }),
+                    )?
+                }
+                OutputMode::Text => {
+                    let rows: Vec<Vec<String>> = config
+                        .profiles
+                        .iter()
+                        .map(|(name, p)| {
+                            vec![
+                                if Some(name.as_str()) == active {
+                                    "*"
+                                } else {
+                                    ""
+                                }
+                                .to_string(),
+                                name.clone(),
+                                token(p),
+                                p.base_url.clone().unwrap_or_else(|| "-".to_string()),
+                                p.output.clone().unwrap_or_else(|| "-".to_string()),
+                            ]
+                        })
+                        .collect();
+                    print_table(
+                        out,
+                        &["ACTIVE", "NAME", "TOKEN", "BASE URL", "OUTPUT"],
+                        &rows,
+                    )?;
+                }
+            }
+        }
+        ProfileCommands::Use { name } => {
+            config.active_profile(Some(name))?;
+            let value = toml_edit::Value::from(name.as_str()).to_string();
+            let text = config::set(&read_config_text(path)?, "profile", &value)
+                .with_context(|| format!("invalid config {}", path.display()))?;
+            write_config_text(path, &text)?;
+            match mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(out, mode, &json!({ "profile": name }))?
+                }
+                OutputMode::Text => writeln!(out, "now using profile '{name}'")?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where the token for this run comes from, in resolution order, without reading it. An
+/// unavailable keyring is not a source.
+fn token_source(
+    flag: Option<&str>,
+    profile: Option<&config::Profile>,
+    account: &str,
+) -> Option<String> {
+    if flag.is_some() {
+        return Some("--token".to_string());
+    }
+    if let Some(p) = profile {
+        match (&p.token_env, &p.token_command) {
+            (Some(var), _) => return Some(format!("profile env {var}")),
+            (None, Some(command)) => return Some(format!("profile command {command}")),
+            (None, None) => {}
+        }
+    }
+    if let Some(var) = auth::env_token_var() {
+        return Some(format!("env {var}"));
+    }
+    auth::keyring_token(account)
+        .ok()
+        .flatten()
+        .map(|_| "keyring".to_string())
+}
+
+fn handle_auth(
+    cli: &Cli,
+    command: &AuthCommands,
+    profile: Option<&config::Profile>,
+    account: &str,
+    mode: OutputMode,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    match command {
+        AuthCommands::Login => {
+            let token = match cli.token.as_deref() {
+                Some(flag) => auth::resolve_token_or(Some(flag))?,
+                None if io::stdin().is_terminal() => {
+                    let token = rpassword::prompt_password("MotherDuck token: ")
+                        .context("failed to read token")?;
+                    anyhow::ensure!(!token.trim().is_empty(), "no token entered");
+                    token.trim().to_string()
+                }
+                None => auth::resolve_token_or(Some("-"))?,
+            };
+            auth::store_token(account, &token)?;
+            if let Some(source) = token_source(None, profile, account)
+                && source != "keyring"
+            {
+                writeln!(
+                    err,
+                    "warning: {source} takes precedence over the stored token"
+                )?;
+            }
+            match mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(out, mode, &json!({ "account": account, "stored": true }))?
+                }
+                OutputMode::Text => {
+                    writeln!(out, "stored token for '{account}' in the OS keyring")?
+                }
+            }
+        }
+        AuthCommands::Logout => {
+            let removed = auth::forget_token(account)?;
+            match mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(
+                        out,
+                        mode,
+                        &json!({ "account": account, "removed": removed }),
+                    )?
+                }
+                OutputMode::Text if removed => {
+                    writeln!(out, "removed the stored token for '{account}'")?
+                }
+                OutputMode::Text => writeln!(out, "no stored token for '{account}'")?,
+            }
+        }
+        AuthCommands::Status => {
+            // Reported rather than fatal: a token from elsewhere still works without a keyring.
+            let stored = auth::keyring_token(account).map(|t| t.is_some());
+            let source = token_source(cli.token.as_deref(), profile, account);
+            match mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(
+                        out,
+                        mode,
+                        &json!({
+                            "account": account,
+                            "source": source,
+                            "stored": stored.as_ref().ok(),
+                            "keyring_error": stored.as_ref().err().map(|e| format!("{e:#}")),
+                        }),
+                    )?
+                }
+                OutputMode::Text => {
+                    writeln!(out, "token: {}", source.as_deref().unwrap_or("none"))?;
+                    match &stored {
+                        Ok(stored) => writeln!(
+                            out,
+                            "keyring: {} for '{account}'",
+                            if *stored { "stored" } else { "nothing stored" }
+                        )?,
+                        Err(e) => writeln!(out, "keyring: unavailable ({e:#})")?,
+                    }
+                }
+            }
+            if source.is_none() {
+                bail!("no MotherDuck token found; run `md auth login`");
+            }
+        }
+        AuthCommands::Whoami => unreachable!("needs the client"),
+    }
+    Ok(())
+}
+
+/// The first of `names` set in `claims`, as text.
+fn claim(claims: &serde_json::Map<String, Value>, names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| match claims.get(*name)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    })
+}
+
+/// A JWT time claim (seconds since the epoch) as RFC 3339.
+fn claim_time(claims: &serde_json::Map<String, Value>, name: &str) -> Option<String> {
+    let secs = claims.get(name)?.as_i64()?;
+    chrono::DateTime::from_timestamp(secs, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+fn whoami(ctx: &mut Ctx, token: &str) -> Result<()> {
+    // Any authenticated token gets a 200 or a 404 looking up a user's tokens; a 401 or
+    // 403 means the API refused it.
+    ctx.client
+        .user_exists(capabilities::PROBE_USER)
+        .context("the API did not accept the token")?;
+    let claims = auth::token_claims(token).unwrap_or_default();
+    if claims.is_empty() {
+        ctx.warn("the token is not a JWT, so md cannot tell who it belongs to")?;
+    }
+    let user = claim(&claims, &["username", "email", "sub"]);
+    let org = claim(&claims, &["organization", "org"]);
+    let token_type = claim(&claims, &["token_type", "tokenType"]);
+    let issued = claim_time(&claims, "iat");
+    let expires = claim_time(&claims, "exp");
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({
+                    "valid": true,
+                    "user": user,
+                    "organization": org,
+                    "token_type": token_type,
+                    "issued_at": issued,
+                    "expires_at": expires,
+                }),
+            )?
+        }
+        OutputMode::Text => {
+            let field = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+            writeln!(
+                ctx.out,
+                "valid:   yes (accepted by {})",
+                ctx.client.base_url()
+            )?;
+            writeln!(ctx.out, "user:    {}", field(user))?;
+            writeln!(ctx.out, "org:     {}", field(org))?;
+            writeln!(ctx.out, "type:    {}", field(token_type))?;
+            writeln!(ctx.out, "issued:  {}", field(issued))?;
+            let expires = expires
+                .unwrap_or_else(|| if claims.is_empty() { "-" } else { "never" }.to_string());
+            writeln!(ctx.out, "expires: {expires}")?;
+        }
+    }
+    Ok(())
+}
+
+// -- two-person approval --
+
+fn signing_key_path(ctx: &Ctx) -> Result<PathBuf> {
+    let config_path = ctx
+        .config_path
+        .context("no config directory for the signing key; pass --config")?;
+    Ok(signing::path_for(config_path))
+}
+
+fn handle_signing_key(ctx: &mut Ctx, command: SigningKeyCommands) -> Result<()> {
+    let path = signing_key_path(ctx)?;
+    let key = match command {
+        SigningKeyCommands::Generate => {
+            let key = SigningKey::generate(&path)?;
+            writeln!(ctx.err, "wrote {}", path.display())?;
+            key
+        }
+        SigningKeyCommands::Show => SigningKey::load(&path)?,
+    };
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "public_key": key.public_key() }),
+            )?
+        }
+        OutputMode::Text => writeln!(ctx.out, "{}", key.public_key())?,
+    }
+    Ok(())
+}
+
+/// Instead of running a destructive command, record it as a signed change request.
+fn request_approval(ctx: &mut Ctx, base_url: &str, args: &[String]) -> Result<()> {
+    let key = SigningKey::load(&signing_key_path(ctx)?)?;
+    let request = ChangeRequest::new(&key, base_url, args.to_vec(), chrono::Utc::now());
+    let path = request.file_name();
+    request.save(&path)?;
+    bail!(
+        "approval required: wrote change request {0}; a second operator approves it with \
+         `md approve {0}`, then run `md execute {0}`",
+        path.display()
+    )
+}
+
+fn describe_change(ctx: &mut Ctx, request: &ChangeRequest) -> Result<()> {
+    let command = shlex::try_join(request.args.iter().map(String::as_str))
+        .unwrap_or_else(|_| request.args.join(" "));
+    writeln!(ctx.err, "change request {}: md {command}", request.id)?;
+    writeln!(
+        ctx.err,
+        "  requested by {} at {} against {}",
+        request.requested_by, request.created_at, request.base_url
+    )?;
+    Ok(())
+}
+
+fn approve(ctx: &mut Ctx, file: &Path) -> Result<()> {
+    let key = SigningKey::load(&signing_key_path(ctx)?)?;
+    let mut request = ChangeRequest::load(file)?;
+    describe_change(ctx, &request)?;
+    request.approve(&key, &ctx.config.approval.approvers, chrono::Utc::now())?;
+    confirm(
+        ctx,
+        &format!("Approve change request {}? [y/N] ", request.id),
+    )?;
+    request.save(file)?;
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "id": request.id, "approved_by": key.public_key() }),
+            )?
+        }
+        OutputMode::Text => writeln!(ctx.out, "approved {}", request.id)?,
+    }
+    Ok(())
+}
+
+/// Run the command recorded in an approved change request as if it had been typed, with
+/// this operator's token and config. The request is recorded in the executed ledger before
+/// it runs, so an approval is good for one attempt.
+fn execute_change(
+    cli: &Cli,
+    file: &Path,
+    config: &Config,
+    config_path: Option<&Path>,
+    inv: &Invocation,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let mut request = ChangeRequest::load(file)?;
+    let now = chrono::Utc::now();
+    request.check_executable(&config.approval.approvers, &cli.base_url, now)?;
+    let mut inner = parse_stored(&request.args)
+        .with_context(|| format!("invalid command in change request {}", request.id))?;
+    anyhow::ensure!(
+        inner.command.destructive(),
+        "change request {} is not for a command that needs approval",
+        request.id
+    );
+    inherit_operator_flags(&mut inner, cli);
+
+    let config_path =
+        config_path.context("no config directory for the executed ledger; pass --config")?;
+    Ledger::new(executed::path_for(config_path)).claim(
+        "change request",
+        &request.id,
+        &request.signature,
+        now,
+    )?;
+    request.executed_at = Some(now.to_rfc3339());
+    request.save(file)?;
+    writeln!(
+        err,
+        "executing change request {} (approved by {})",
+        request.id,
+        request
+            .approval
+            .as_ref()
+            .map_or("", |a| a.approved_by.as_str())
+    )?;
+    let approved = Invocation {
+        recorded_args: request.args.clone(),
+        approved: true,
+        ..*inv
+    };
+    execute(inner, &approved, out, err)
+}
+
+/// Parse `md` arguments stored in a change request or bundle, applying deprecations.
+fn parse_stored(args: &[String]) -> Result<Cli, clap::Error> {
+    let (args, _) = deprecation::migrate(
+        &Cli::command(),
+        deprecation::DEPRECATIONS,
+        std::iter::once("md".into())
+            .chain(args.iter().map(Into::into))
+            .collect(),
+    );
+    let matches = Cli::command().try_get_matches_from(args)?;
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    cli.command_path = command_path(&matches);
+    cli.base_url_given = base_url_given(&matches);
+    Ok(cli)
+}
+
+/// Give a stored command the flags that belong to the operator running it.
+fn inherit_operator_flags(inner: &mut Cli, cli: &Cli) {
+    inner.token = cli.token.clone();
+    inner.clear_clipboard = cli.clear_clipboard;
+    inner.config = cli.config.clone();
+    inner.profile = cli.profile.clone();
+    inner.yes |= cli.yes;
+    inner.non_interactive |= cli.non_interactive;
+    inner.assume_yes_when_piped |= cli.assume_yes_when_piped;
+    inner.verbose |= cli.verbose;
+    inner.curl |= cli.curl;
+    inner.summary_footer |= cli.summary_footer;
+    inner.quiet |= cli.quiet;
+    inner.sandbox |= cli.sandbox;
+    inner.output = inner.output.or(cli.output);
+    inner.user_agent_suffix = inner
+        .user_agent_suffix
+        .take()
+        .or_else(|| cli.user_agent_suffix.clone());
+}
+
+// -- bundles --
+
+fn create_bundle(
+    ctx: &mut Ctx,
+    base_url: &str,
+    file: &Path,
+    commands: Vec<String>,
+    from: Option<&Path>,
+) -> Result<()> {
+    let mut bundled = commands
+        .iter()
+        .map(|c| schedule::split_command(c))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(from) = from {
+        let text = std::fs::read_to_string(from)
+            .with_context(|| format!("failed to read {}", from.display()))?;
+        bundled.extend(bundle::parse_commands(&text)?);
+    }
+    anyhow::ensure!(
+        !bundled.is_empty(),
+        "no commands to bundle; pass --command or --from"
+    );
+    for args in &bundled {
+        let command =
+            shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" "));
+        bundle::check_command(args)?;
+        let inner =
+            parse_stored(args).with_context(|| format!("invalid bundled command '{command}'"))?;
+        anyhow::ensure!(
+            !inner.command.wraps_commands(),
+            "'{command}' cannot be bundled"
+        );
+    }
+    let key = SigningKey::load(&signing_key_path(ctx)?)?;
+    let bundle = Bundle::new(&key, base_url, bundled, chrono::Utc::now());
+    bundle.save(file)?;
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({ "id": bundle.id, "file": file, "commands": bundle.commands.len() }),
+            )?
+        }
+        OutputMode::Text => writeln!(ctx.out, "{}", file.display())?,
+    }
+    Ok(())
+}
+
+fn describe_bundle(w: &mut dyn Write, bundle: &Bundle) -> Result<()> {
+    writeln!(
+        w,
+        "bundle {}: {} for {}",
+        bundle.id,
+        plural(bundle.commands.len(), "command"),
+        bundle.base_url
+    )?;
+    writeln!(
+        w,
+        "  signed by {} at {}",
+        bundle.signed_by, bundle.created_at
+    )?;
+    for (i, args) in bundle.commands.iter().enumerate() {
+        let command =
+            shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" "));
+        writeln!(w, "  {}. md {command}", i + 1)?;
+    }
+    Ok(())
+}
+
+fn show_bundle(ctx: &mut Ctx, file: &Path) -> Result<()> {
+    let bundle = Bundle::load(file)?;
+    bundle.check_intact()?;
+    let trusted = bundle.verify(&ctx.config.bundles.trusted_signers).is_ok();
+    match ctx.mode {
+        OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+            print_value(
+                ctx.out,
+                ctx.mode,
+                &json!({
+                    "id": bundle.id,
+                    "created_at": bundle.created_at,
+                    "base_url": bundle.base_url,
+                    "signed_by": bundle.signed_by,
+                    "trusted": trusted,
+                    "commands": bundle.commands,
+                    "executed_at": bundle.executed_at,
+                }),
+            )?
+        }
+        OutputMode::Text => {
+            describe_bundle(ctx.out, &bundle)?;
+            if !trusted {
+                writeln!(ctx.out, "  signer is not in `bundles.trusted_signers`")?;
+            }
+            if let Some(at) = &bundle.executed_at {
+                writeln!(ctx.out, "  executed at {at}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run each command of a trusted bundle as if it had been typed, with this operator's
+/// token and config. Like change requests, the bundle is recorded in the executed ledger
+/// before the first command runs, so it is good for one attempt.
+fn execute_bundle(ctx: &mut Ctx, cli: &Cli, file: &Path, inv: &Invocation) -> Result<()> {
+    let mut bundle = Bundle::load(file)?;
+    let now = chrono::Utc::now();
+    bundle.check_executable(&ctx.config.bundles.trusted_signers, &cli.base_url, now)?;
+    let commands = bundle
+        .commands
+        .iter()
+        .map(|args| {
+            bundle::check_command(args)?;
+            let mut inner = parse_stored(args)
+                .with_context(|| format!("invalid command in bundle {}", bundle.id))?;
+            anyhow::ensure!(
+                !inner.command.wraps_commands(),
+                "bundle {} contains a command that cannot be bundled",
+                bundle.id
+            );
+            inherit_operator_flags(&mut inner, cli);
+            Ok(inner)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    describe_bundle(ctx.err, &bundle)?;
+    confirm(
+        ctx,
+        &format!(
+            "Run {} from bundle {}? [y/N] ",
+            plural(commands.len(), "command"),
+            bundle.id
+        ),
+    )?;
+
+    let config_path = ctx
+        .config_path
+        .context("no config directory for the executed ledger; pass --config")?;
+    Ledger::new(executed::path_for(config_path)).claim(
+        "bundle",
+        &bundle.id,
+        &bundle.signature,
+        now,
+    )?;
+    bundle.executed_at = Some(now.to_rfc3339());
+    bundle.save(file)?;
+    for (i, (inner, args)) in commands.into_iter().zip(&bundle.commands).enumerate() {
+        let inv = Invocation {
+            recorded_args: args.clone(),
+            ..*inv
+        };
+        execute(inner, &inv, &mut *ctx.out, &mut *ctx.err).with_context(|| {
+            format!(
+                "bundle command {} failed; later commands did not run",
+                i + 1
+            )
+        })?;
+    }
+    Ok(())
+}
+
+// -- schedules --
+
+fn handle_schedule(ctx: &mut Ctx, command: ScheduleCommands) -> Result<()> {
+    let config_path = ctx
+        .config_path
+        .context("no config directory for schedules; pass --config")?;
+    let path = schedule::path_for(config_path);
+    let mut schedules = Schedules::load(&path)?;
+    match command {
+        ScheduleCommands::Add { command, cron } => {
+            let id = schedules.add(&cron, &command)?;
+            let args = schedules.entries.last().expect("just added").args()?;
+            let scheduled = parse_stored(&args)
+                .with_context(|| format!("invalid scheduled command '{command}'"))?;
+            // By the parsed subcommand, so that e.g. a token named "schedule" is fine.
+            anyhow::ensure!(
+                !matches!(scheduled.command, Commands::Schedule { .. }),
+                "scheduled commands cannot manage schedules"
+            );
+            schedules.save(&path)?;
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    print_value(ctx.out, ctx.mode, &json!({ "id": id }))?
+                }
+                OutputMode::Text => writeln!(ctx.out, "{id}")?,
+            }
+        }
+        ScheduleCommands::List => {
+            let now = chrono::Local::now();
+            let mut listed = Vec::new();
+            for s in &schedules.entries {
+                let next = s.next_after(&now)?.format("%Y-%m-%d %H:%M").to_string();
+                listed.push((s, next));
+            }
+            match ctx.mode {
+                OutputMode::Json | OutputMode::Ndjson | OutputMode::Sh | OutputMode::PsObject => {
+                    let entries: Vec<Value> = listed
+                        .iter()
+                        .map(|(s, next)| {
+                            json!({ "id": s.id, "cron": s.cron, "command": s.command, "next": next })
+                        })
+                        .collect();
+                    print_value(ctx.out, ctx.mode, &json!({ "schedules": entries }))?;
+                }
+                OutputMode::Text => {
+                    let rows: Vec<Vec<String>> = listed
+                        .into_iter()
+                        .map(|(s, next)| {
+                            vec![s.id.to_string(), s.cron.clone(), next, s.command.clone()]
+                        })
+                        .collect();
+                    print_table(ctx.out, &["ID", "CRON", "NEXT", "COMMAND"], &rows)?;
+                    ctx.note_empty(
+                        rows.len(),
+                        "no schedules; add one with `md schedule add \"<command>\" --cron \
+                         \"<expression>\"`",
+                    )?;
+                }
+            }
+        }
+        ScheduleCommands::Remove { id } => {
+            schedules.remove(id)?;
+            schedules.save(&path)?;
+        }
+        ScheduleCommands::Run => run_schedules(ctx, &path, config_path)?,
+    }
+    Ok(())
+}
+
+/// Daemon loop: sleep until the next run, execute due commands as child `md` processes
+/// (so one failing or exiting command cannot take the loop down), repeat. The file is
+/// re-read at least once a minute so `schedule add/remove` apply without a restart.
+fn run_schedules(ctx: &mut Ctx, path: &Path, config_path: &Path) -> Result<()> {
+    const RELOAD: std::time::Duration = std::time::Duration::from_secs(60);
+    let exe = std::env::current_exe().context("failed to locate md executable")?;
+    writeln!(ctx.err, "running schedules from {}", path.display())?;
+    loop {
+        let schedules = Schedules::load(path)?;
+        let now = chrono::Local::now();
+        let Some((when, due)) = schedules.next_due(&now)? else {
+            std::thread::sleep(RELOAD);
+            continue;
+        };
+        let wait = (when - now).to_std().unwrap_or_default();
+        if wait > RELOAD {
+            std::thread::sleep(RELOAD);
+            continue;
+        }
+        std::thread::sleep(wait);
+        for s in due {
+            writeln!(
+                ctx.err,
+                "{} running #{}: md {}",
+                when.format("%Y-%m-%d %H:%M"),
+                s.id,
+                s.command
+            )?;
+            let status = Command::new(&exe)
+                .arg("--config")
+                .arg(config_path)
+                .args(s.args()?)
+                .stdin(Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => writeln!(ctx.err, "schedule #{} failed: {status}", s.id)?,
+                Err(e) => writeln!(ctx.err, "schedule #{} failed to start: {e}", s.id)?,
+            }
+        }
+    }
+}
+
+/// Run a `[hooks]` program, copying its output to stderr. Whether it exited successfully.
+fn run_hook(
+    ctx: &mut Ctx,
+    program: &Path,
+    stage: Stage,
+    inv: &middleware::Invocation,
+    outcome: Option<Result<(), String>>,
+) -> Result<bool> {
+    let output = middleware::run(program, inv, &middleware::env(stage, inv, outcome))?;
+    ctx.err.write_all(&output.stdout)?;
+    ctx.err.write_all(&output.stderr)?;
+    Ok(output.status.success())
+}
+
+/// Arguments (other than the `--token` value) that look like credentials, abbreviated.
+/// Values are split on `/`, `=`, and whitespace so URIs and scheduled commands are covered.
+fn secret_like_args(args: &[std::ffi::OsString]) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut iter = args.iter().skip(1).filter_map(|a| a.to_str());
+    while let Some(arg) = iter.next() {
+        if arg == "--token" {
+            iter.next();
+            continue;
+        }
+        if arg.starts_with("--token=") {
+            continue;
+        }
+        for part in arg.split(|c: char| c == '/' || c == '=' || c.is_whitespace()) {
+            if policy::looks_like_secret(part) {
+                found.push(format!("{}...", part.chars().take(6).collect::<String>()));
+            }
+        }
+    }
+    found
+}
+
+// -- main dispatch --
+
+/// Parse CLI arguments and execute the corresponding MotherDuck API command.
+pub fn run<I, T>(args: I) -> Result<()>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let stdout = io::stdout();
+    let is_terminal = stdout.is_terminal();
+    run_to(args, &mut stdout.lock(), &mut io::stderr(), is_terminal)
+}
+
+/// Like [`run`], but writes results to `out` and prompts/diagnostics to `err`. Output is
+/// only colored with `--color always`.
+pub fn run_with<I, T>(args: I, out: &mut dyn Write, err: &mut dyn Write) -> Result<()>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    run_to(args, out, err, false)
+}
+
+fn run_to<I, T>(
+    args: I,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+    out_is_terminal: bool,
+) -> Result<()>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let args = args.into_iter().map(Into::into).collect();
+    let (args, deprecations) =
+        deprecation::migrate(&Cli::command(), deprecation::DEPRECATIONS, args);
+    let secrets = secret_like_args(&args);
+    let recorded_args = approval::recorded_args(&args);
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli =
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut Cli::command()).exit());
+    cli.command_path = command_path(&matches);
+    cli.base_url_given = base_url_given(&matches);
+    let api_mode = cli.api_mode;
+    let usage = if api_mode {
+        api_mode_settings(&mut cli)
+    } else {
+        Ok(())
+    };
+    let output = Cell::new(cli.output.unwrap_or(OutputMode::Text));
+    let color = !api_mode && !cli.no_color && cli.color.enabled(out_is_terminal);
+    let correlation_id = cli
+        .correlation_id
+        .clone()
+        .unwrap_or_else(client::new_correlation_id);
+    let mut query = None;
+    let usage = usage.and_then(|()| {
+        query = cli.query.as_deref().map(Query::parse).transpose()?;
+        Ok(())
+    });
+    // Under --api-mode the command's output is collected and wrapped in the envelope; under
+    // --query it is collected to be filtered.
+    let mut collected = Vec::new();
+    let mut tracked = TrackedWriter {
+        inner: if api_mode || query.is_some() {
+            &mut collected
+        } else {
+            &mut *out
+        },
+        written: false,
+    };
+    #[cfg(feature = "otel")]
+    match crate::otel::Exporter::from_env() {
+        Ok(Some(exporter)) => crate::otel::start(exporter, &correlation_id),
+        Ok(None) => {}
+        Err(e) => writeln!(err, "warning: not exporting traces: {e:#}")?,
+    }
+    let invocation = Invocation {
+        color: color && query.is_none(),
+        correlation_id: &correlation_id,
+        deprecations: &deprecations,
+        secrets: &secrets,
+        recorded_args,
+        approved: false,
+        output: &output,
+    };
+    let mut result = usage.and_then(|()| execute(cli, &invocation, &mut tracked, err));
+    let written = tracked.written;
+    if let Some(query) = &query
+        && written
+    {
+        let mode = output.get();
+        let mut filtered = Vec::new();
+        let mut highlighted;
+        let target: &mut dyn Write = if api_mode {
+            &mut filtered
+        } else if color && matches!(mode, OutputMode::Json | OutputMode::Ndjson) {
+            highlighted = highlight::Json::new(&mut *out);
+            &mut highlighted
+        } else {
+            &mut *out
+        };
+        let printed = query
+            .apply(&collected)
+            .and_then(|values| Ok(print_query_results(target, mode, values)?));
+        result = result.and(printed);
+        collected = filtered;
+    }
+    #[cfg(feature = "otel")]
+    if let Err(e) = crate::otel::finish(&format!("md {}", command_path(&matches)), &result) {
+        writeln!(err, "warning: {e:#}")?;
+    }
+    if api_mode {
+        print_json(out, &api_envelope(&collected, &result, &correlation_id))?;
+        return result.map_err(|e| {
+            anyhow::Error::new(ApiModeError {
+                exit_code: Failure::of(&e).exit_code(),
+                error: e,
+            })
+        });
+    }
+    // In JSON modes stdout carries exactly one document: the result (which may itself
+    // report partial failures) or, if nothing was printed, the error.
+    let mode = output.get();
+    if let (Err(e), OutputMode::Json | OutputMode::Ndjson | OutputMode::PsObject, false) =
+        (&result, mode, written)
+    {
+        // Best effort: the error itself is still returned for the caller to report.
+        let _ = print_value(out, mode, &error_json(e, &correlation_id));
+    }
+    result
+}
+
+/// What `--api-mode` implies: JSON output and no prompts.
+fn api_mode_settings(cli: &mut Cli) -> Result<()> {
+    cli.non_interactive = true;
+    match cli.output.replace(OutputMode::Json) {
+        None | Some(OutputMode::Json) => Ok(()),
+        Some(other) => bail!(
+            "--api-mode always prints JSON; drop -o {}",
+            other
+                .to_possible_value()
+                .expect("no skipped variants")
+                .get_name()
+        ),
+    }
+}
+
+/// Whether the base URL came from `--base-url` or `MOTHERDUCK_API_URL` rather than the default.
+fn base_url_given(matches: &clap::ArgMatches) -> bool {
+    matches.value_source("base_url") != Some(clap::parser::ValueSource::DefaultValue)
+}
+
+/// Subcommand names without arguments, e.g. `token create`.
+fn command_path(matches: &clap::ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name);
+        current = sub;
+    }
+    names.join(" ")
+}
+
+/// Remembers whether anything was written through it.
+struct TrackedWriter<'a> {
+    inner: &'a mut dyn Write,
+    written: bool,
+}
+
+impl Write for TrackedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written |= !buf.is_empty();
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `{"error": {"status": ..., "code": ..., "message": ..., "correlation_id": ...}}` for
+/// `-o json` failures. `status` is the HTTP status for API errors and null otherwise; `code`
+/// is `"confirmation_required"` for a prompt refused under `--non-interactive` and null
+/// otherwise; `message` is the full error chain.
+fn error_json(e: &anyhow::Error, correlation_id: &str) -> Value {
+    let status = e.downcast_ref::<ApiError>().map(|api| api.status);
+    let code = e
+        .downcast_ref::<ConfirmationRequired>()
+        .map(|_| "confirmation_required");
+    json!({
+        "error": {
+            "status": status,
+            "code": code,
+            "message": format!("{e:#}"),
+            "correlation_id": correlation_id,
+        }
+    })
+}
+
+/// Version of the `--api-mode` envelope. Bump it only for incompatible changes; adding
+/// fields or failure codes is compatible.
+const API_MODE_VERSION: u32 = 1;
+
+/// The single stdout document under `--api-mode`:
+/// `{"version": 1, "ok": ..., "correlation_id": ..., "result": ..., "error": ...}`.
+/// `result` is what the command printed as JSON (null if nothing; an array for line-delimited
+/// output), present on failure too for partial-failure reports. `error` is null on success.
+fn api_envelope(output: &[u8], result: &Result<()>, correlation_id: &str) -> Value {
+    let text = String::from_utf8_lossy(output);
+    let printed = if text.trim().is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(&text)
+            .or_else(|_| text.lines().map(serde_json::from_str::<Value>).collect())
+            .unwrap_or_else(|_| Value::String(text.into_owned()))
+    };
+    let error = result.as_ref().err().map(|e| {
+        let failure = Failure::of(e);
+        json!({
+            "code": failure.code(),
+            "exit_code": failure.exit_code(),
+            "status": e.downcast_ref::<ApiError>().map(|api| api.status),
+            "message": format!("{e:#}"),
+        })
+    });
+    json!({
+        "version": API_MODE_VERSION,
+        "ok": result.is_ok(),
+        "correlation_id": correlation_id,
+        "result": printed,
+        "error": error,
+    })
+}
+
+/// Failure classes with their stable `--api-mode` exit codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Failure {
+    Other,
+    ConfirmationRequired,
+    Unauthorized,
+    NotFound,
+    RateLimited,
+    Rejected,
+    ServerError,
+    Unreachable,
+    Partial,
+    Empty,
+    TimedOut,
+    Refused,
+}
+
+impl Failure {
+    fn of(e: &anyhow::Error) -> Self {
+        if e.downcast_ref::<PartialFailure>().is_some() {
+            return Self::Partial;
+        }
+        if e.downcast_ref::<ConfirmationRequired>().is_some() {
+            return Self::ConfirmationRequired;
+        }
+        if e.downcast_ref::<NoResults>().is_some() {
+            return Self::Empty;
+        }
+        if e.downcast_ref::<TimedOut>().is_some() {
+            return Self::TimedOut;
+        }
+        if e.downcast_ref::<HookRefused>().is_some() {
+            return Self::Refused;
+        }
+        if let Some(api) = e.downcast_ref::<ApiError>() {
+            return match api.status {
+                401 | 403 => Self::Unauthorized,
+                404 => Self::NotFound,
+                429 => Self::RateLimited,
+                500.. => Self::ServerError,
+                _ => Self::Rejected,
+            };
+        }
+        if e.chain().any(|c| c.is::<ureq::Error>()) {
+            return Self::Unreachable;
+        }
+        Self::Other
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Self::Other => "error",
+            Self::ConfirmationRequired => "confirmation_required",
+            Self::Unauthorized => "unauthorized",
+            Self::NotFound => "not_found",
+            Self::RateLimited => "rate_limited",
+            Self::Rejected => "rejected",
+            Self::ServerError => "server_error",
+            Self::Unreachable => "unreachable",
+            Self::Partial => "partial_failure",
+            Self::Empty => "empty",
+            Self::TimedOut => "timed_out",
+            Self::Refused => "refused",
+        }
+    }
+
+    /// 2 is left to clap for usage errors.
+    fn exit_code(self) -> u8 {
+        match self {
+            Self::Other => 1,
+            Self::ConfirmationRequired => 3,
+            Self::Unauthorized => 4,
+            Self::NotFound => 5,
+            Self::RateLimited => 6,
+            Self::Rejected => 7,
+            Self::ServerError => 8,
+            Self::Unreachable => 9,
+            Self::Partial => 10,
+            Self::Empty => 11,
+            Self::TimedOut => 12,
+            Self::Refused => 13,
+        }
+    }
+}
+
+/// An error from a run under `--api-mode`, carrying the exit code the process should use.
+#[derive(Debug)]
+pub struct ApiModeError {
+    pub exit_code: u8,
+    error: anyhow::Error,
 }
 
-/// Ask the user for confirmation on stderr. Returns Ok(()) if confirmed, Err if declined.
-/// Auto-confirms if `--yes` was passed or if stdin is not a terminal.
-fn confirm(prompt: &str, yes: bool) -> Result<()> {
-    if yes || !std::io::stdin().is_terminal() {
-        return Ok(());
+impl std::fmt::Display for ApiModeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
     }
-    eprint!("{prompt}");
-    std::io::stderr()
-        .flush()
-        .context("failed to flush stderr")?;
+}
 
-    let mut input = String::new();
-    std::io::stdin()
-        .read_line(&mut input)
-        .context("failed to read confirmation")?;
-    let answer = input.trim().to_lowercase();
-    if answer == "y" || answer == "yes" {
-        Ok(())
-    } else {
-        bail!("aborted")
-    }
+impl std::error::Error for ApiModeError {}
+
+/// Whether `e` came from talking to the API (an error response or a transport failure),
+/// as opposed to failing locally before any request was sent.
+fn reached_api(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|c| c.is::<ApiError>() || c.is::<ureq::Error>())
 }
 
-// -- command handlers --
+/// Stands in for the API token in commands that never call the API ([`Commands::local`]).
+const LOCAL_ONLY_TOKEN: &str = "unused";
 
-fn handle_service_account(
-    client: &MotherduckClient,
-    command: ServiceAccountCommands,
-    mode: OutputMode,
-    yes: bool,
-) -> Result<()> {
-    match command {
-        ServiceAccountCommands::Create { username } => {
-            let result = client.create_user(&username)?;
-            match mode {
-                OutputMode::Json => print_json(&result),
-                OutputMode::Text => println!("{}", display_field(&result, "username")),
-            }
-        }
-        ServiceAccountCommands::Delete { username } => {
-            confirm(&format!("Delete service account '{username}'? [y/N] "), yes)?;
-            let result = client.delete_user(&username)?;
-            if mode == OutputMode::Json {
-                print_json(&result);
-            }
-        }
-    }
-    Ok(())
+/// Per-run settings worked out before dispatch.
+struct Invocation<'a> {
+    color: bool,
+    correlation_id: &'a str,
+    deprecations: &'a [String],
+    secrets: &'a [String],
+    /// Arguments to put in a change request if the command needs approval.
+    recorded_args: Vec<String>,
+    /// Running an approved change request (`md execute`), so no new approval is needed.
+    approved: bool,
+    /// Output mode once config defaults are applied, for reporting errors.
+    output: &'a Cell<OutputMode>,
 }
 
-fn handle_token(
-    client: &MotherduckClient,
-    command: TokenCommands,
-    mode: OutputMode,
-    yes: bool,
-) -> Result<()> {
-    match command {
-        TokenCommands::List { username } => {
-            let result = client.list_tokens(&username)?;
-            match mode {
-                OutputMode::Json => print_json(&result),
-                OutputMode::Text => {
-                    if let Some(tokens) = result["tokens"].as_array() {
-                        let rows: Vec<Vec<String>> = tokens
-                            .iter()
-                            .map(|t| {
-                                vec![
-                                    display_field(t, "id").to_string(),
-                                    display_field(t, "name").to_string(),
-                                    display_field(t, "token_type").to_string(),
-                                    match t["expire_at"].as_str() {
-                                        Some(s) if !s.is_empty() => s.to_string(),
-                                        _ => "never".to_string(),
-                                    },
-                                ]
-                            })
-                            .collect();
-                        print_table(&["ID", "NAME", "TYPE", "EXPIRES"], &rows);
-                    }
-                }
-            }
-        }
-        TokenCommands::Create {
-            username,
-            name,
-            ttl,
-            token_type,
-        } => {
-            let result =
-                client.create_token(&username, &name, ttl, Some(token_type.as_api_str()))?;
-            match mode {
-                OutputMode::Json => print_json(&result),
-                OutputMode::Text => println!("{}", display_field(&result, "token")),
-            }
+/// `--output` if given, else the command's `[output]` entry in the config, else the active
+/// profile's `output`, else `output` in `[defaults]`, else text.
+/// Every `[output]` entry is checked so that a typo fails on any command, not silently.
+fn resolve_output(
+    cli: &Cli,
+    config: &Config,
+    profile: Option<&config::Profile>,
+) -> Result<OutputMode> {
+    // One message for every place a mode is set, listing the modes from the enum itself.
+    let parse = |name: &str, place: &str| {
+        OutputMode::from_str(name, true).map_err(|_| {
+            let modes: Vec<_> = OutputMode::value_variants()
+                .iter()
+                .filter_map(|m| Some(m.to_possible_value()?.get_name().to_string()))
+                .collect();
+            let (last, rest) = modes.split_last().expect("OutputMode has variants");
+            anyhow::anyhow!(
+                "invalid output '{name}' {place} (expected {}, or {last})",
+                rest.join(", ")
+            )
+        })
+    };
+    let root = Cli::command();
+    let mut resolved = None;
+    for (path, name) in &config.output {
+        let mut command = &root;
+        for part in path.split(' ') {
+            command = command
+                .find_subcommand(part)
+                .with_context(|| format!("unknown command `{path}` in [output]"))?;
         }
-        TokenCommands::Delete { username, token_id } => {
-            confirm(&format!("Delete token '{token_id}'? [y/N] "), yes)?;
-            let result = client.delete_token(&username, &token_id)?;
-            if mode == OutputMode::Json {
-                print_json(&result);
-            }
+        let mode = parse(name, &format!("for `{path}` in [output]"))?;
+        if *path == cli.command_path {
+            resolved = Some(mode);
         }
     }
-    Ok(())
+    let profile = match profile.and_then(|p| p.output.as_deref()) {
+        Some(name) => Some(parse(name, "in the profile")?),
+        None => None,
+    };
+    let default = match &config.defaults.output {
+        Some(name) => Some(parse(name, "in [defaults]")?),
+        None => None,
+    };
+    Ok(cli
+        .output
+        .or(resolved)
+        .or(profile)
+        .or(default)
+        .unwrap_or(OutputMode::Text))
 }
 
-fn handle_duckling(
-    client: &MotherduckClient,
-    command: DucklingCommands,
-    mode: OutputMode,
-) -> Result<()> {
-    let result = match command {
-        DucklingCommands::Get { username } => client.get_duckling_config(&username)?,
-        DucklingCommands::Set {
-            username,
-            rw_size,
-            rs_size,
-            flock_size,
-        } => {
-            let current = client.get_duckling_config(&username)?;
-            let rw = match rw_size {
-                Some(s) => s.as_api_str(),
-                None => extract_str(&current["read_write"], "instance_size")
-                    .context("current config missing read_write.instance_size")?,
-            };
-            let rs = match rs_size {
-                Some(s) => s.as_api_str(),
-                None => extract_str(&current["read_scaling"], "instance_size")
-                    .context("current config missing read_scaling.instance_size")?,
-            };
-            let flock = match flock_size {
-                Some(n) => n,
-                None => current["read_scaling"]["flock_size"]
-                    .as_u64()
-                    .and_then(|v| u32::try_from(v).ok())
-                    .context("current config missing read_scaling.flock_size")?,
-            };
-            client.set_duckling_config(&username, rw, rs, flock)?
+fn execute(mut cli: Cli, inv: &Invocation, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+    // Before loading the config: a shell profile sourcing completions should not break on
+    // a bad config file.
+    if let Commands::Completions { shell } = cli.command {
+        let mut root = Cli::command();
+        let name = root.get_name().to_string();
+        // clap_complete panics on write errors, so render first and report them normally.
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut root, name, &mut script);
+        return Ok(out.write_all(&script)?);
+    }
+    let config_path = cli.config.clone().or_else(config::default_path);
+    // Also before loading it, so `md config set` can fix a broken one.
+    if let Commands::Config { command } = &cli.command {
+        let path = config_path.context("no config file location; pass --config")?;
+        return handle_config(&path, command, cli.output.unwrap_or(OutputMode::Text), out);
+    }
+    if let Commands::Profile { command } = &cli.command {
+        let path = config_path.context("no config file location; pass --config")?;
+        let mode = cli.output.unwrap_or(OutputMode::Text);
+        return handle_profile(&path, command, cli.profile.as_deref(), mode, out);
+    }
+    let config = match &config_path {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    let profile = config.active_profile(cli.profile.as_deref())?;
+    if let Some((_, profile)) = profile
+        && let Some(url) = &profile.base_url
+        && !cli.base_url_given
+    {
+        cli.base_url = url.clone();
+    }
+    if cli.sandbox {
+        cli.base_url = sandbox::BASE_URL.to_string();
+    }
+    let mode = resolve_output(&cli, &config, profile.map(|(_, p)| p))?;
+    inv.output.set(mode);
+    // `run_to` filters the JSON and prints the results in `mode`.
+    let mode = if cli.query.is_some() {
+        OutputMode::Json
+    } else {
+        mode
+    };
+    let mut highlighted;
+    let out: &mut dyn Write = if inv.color && matches!(mode, OutputMode::Json | OutputMode::Ndjson)
+    {
+        highlighted = highlight::Json::new(out);
+        &mut highlighted
+    } else {
+        out
+    };
+    let account = profile.map_or(auth::DEFAULT_ACCOUNT, |(name, _)| name);
+    if let Commands::Auth { command } = &cli.command
+        && !matches!(command, AuthCommands::Whoami)
+    {
+        return handle_auth(
+            &cli,
+            command,
+            profile.map(|(_, p)| p),
+            account,
+            mode,
+            out,
+            err,
+        );
+    }
+    if let Commands::Execute { file } = &cli.command {
+        return execute_change(&cli, file, &config, config_path.as_deref(), inv, out, err);
+    }
+    if let Commands::Generate {
+        command: GenerateCommands::Docs,
+    } = &cli.command
+    {
+        return Ok(write!(out, "{}", reference::markdown(&Cli::command()))?);
+    }
+    if let Commands::Dev {
+        command: DevCommands::CheckSpec { spec },
+    } = &cli.command
+    {
+        return check_spec(spec, mode, out);
+    }
+    if cli.clear_clipboard && cli.token.as_deref() != Some(auth::CLIPBOARD) {
+        bail!("--clear-clipboard requires --token {}", auth::CLIPBOARD);
+    }
+    anyhow::ensure!(
+        !cli.fail_if_empty || cli.command.lists(),
+        "--fail-if-empty is not supported by `md {}`",
+        cli.command_path
+    );
+    let sandbox = if cli.sandbox {
+        let sandbox = Sandbox::open(config_path.as_deref().map(sandbox::path_for))?;
+        match sandbox.path() {
+            Some(path) => writeln!(err, "sandbox: using the demo org in {}", path.display())?,
+            None => writeln!(err, "sandbox: using a demo org for this run only")?,
         }
+        Some(sandbox)
+    } else {
+        None
     };
-    match mode {
-        OutputMode::Json => print_json(&result),
-        OutputMode::Text => print_duckling_config(&result),
+    let token = if sandbox.is_some() {
+        sandbox::TOKEN.to_string()
+    } else {
+        let from_profile = profile
+            .filter(|_| cli.token.is_none())
+            .and_then(|(name, p)| {
+                Some(profile_token(p)?.with_context(|| format!("no token for profile '{name}'")))
+            });
+        match from_profile.unwrap_or_else(|| auth::resolve_token_in(cli.token.as_deref(), account))
+        {
+            Err(_) if cli.token.is_none() && cli.command.local() => LOCAL_ONLY_TOKEN.to_string(),
+            token => token?,
+        }
+    };
+    if cli.clear_clipboard {
+        auth::clear_clipboard().context("failed to clear clipboard")?;
     }
-    Ok(())
-}
-
-fn handle_account(
-    client: &MotherduckClient,
-    command: AccountCommands,
-    mode: OutputMode,
-) -> Result<()> {
-    match command {
-        AccountCommands::ListActive => {
-            let result = client.list_active_accounts()?;
-            match mode {
-                OutputMode::Json => print_json(&result),
-                OutputMode::Text => {
-                    if let Some(accounts) = result["accounts"].as_array() {
-                        let rows: Vec<Vec<String>> = accounts
-                            .iter()
-                            .map(|acct| {
-                                let username = display_field(acct, "username").to_string();
-                                let ducklings = acct["ducklings"]
-                                    .as_array()
-                                    .map(|ds| {
-                                        ds.iter()
-                                            .map(|d| {
-                                                format!(
-                                                    "{} ({})",
-                                                    display_field(d, "type"),
-                                                    display_field(d, "status"),
-                                                )
-                                            })
-                                            .collect::<Vec<_>>()
-                                            .join(", ")
-                                    })
-                                    .unwrap_or_default();
-                                vec![username, ducklings]
-                            })
-                            .collect();
-                        print_table(&["USERNAME", "DUCKLINGS"], &rows);
-                    }
-                }
-            }
+    let mut builder = MotherduckClient::builder(&token)
+        .base_url(&cli.base_url)
+        .api_version(&cli.api_version)
+        .correlation_id(inv.correlation_id)
+        .verbose(cli.verbose)
+        .curl(cli.curl);
+    let timeout = cli.request_timeout.or(config
+        .defaults
+        .request_timeout
+        .map(std::time::Duration::from_secs));
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder = match cli.retries {
+        Some(retries) => builder.retries(retries),
+        None => builder
+            .retries_on(RetryOn::Connect, config.retries.connect)
+            .retries_on(RetryOn::Timeout, config.retries.timeout)
+            .retries_on(RetryOn::ServerError, config.retries.server_error),
+    };
+    builder = builder
+        .retry_delay(cli.retry_delay)
+        .rate_limit_retries(cli.rate_limit_retries);
+    if let Some(bytes) = config.max_response_size {
+        builder = builder.max_response_size(bytes);
+    }
+    if let Some(suffix) = &cli.user_agent_suffix {
+        builder = builder.user_agent_suffix(suffix);
+    }
+    if let Some(sandbox) = sandbox {
+        builder = builder.sandbox(sandbox);
+    }
+    let client = builder.build()?;
+    if cli.assume_yes_when_piped && !config.policy.allow_assume_yes_when_piped {
+        bail!("--assume-yes-when-piped is disabled by policy (allow_assume_yes_when_piped)");
+    }
+    let cache_key = capabilities::cache_key(&cli.base_url, &token);
+    let inventory = if cli.local {
+        anyhow::ensure!(
+            cli.command.reads_inventory(),
+            "--local is not supported by `md {}`",
+            cli.command_path
+        );
+        let path = inventory_path(config_path.as_deref())?;
+        let inventory = Inventory::load(&path, &cache_key)
+            .context("no local inventory for this API and token; run `md sync` first")?;
+        writeln!(err, "from local inventory synced {}", inventory.synced_at)?;
+        Some(inventory)
+    } else {
+        None
+    };
+    let mut ctx = Ctx {
+        client: &client,
+        config: &config,
+        config_path: config_path.as_deref(),
+        cache_key: &cache_key,
+        local: inventory.as_ref(),
+        mode,
+        color: inv.color,
+        yes: cli.yes || config.defaults.yes,
+        force: cli.force,
+        non_interactive: cli.non_interactive,
+        assume_yes_when_piped: cli.assume_yes_when_piped,
+        warnings_as_errors: cli.warnings_as_errors,
+        summary_footer: cli.summary_footer,
+        fail_if_empty: cli.fail_if_empty,
+        quiet: cli.quiet,
+        out,
+        err,
+    };
+    for msg in inv.deprecations {
+        ctx.warn(msg)?;
+    }
+    if !inv.secrets.is_empty() {
+        for secret in inv.secrets {
+            ctx.warn(&format!(
+                "argument '{secret}' looks like a secret token; it would be sent in an API \
+                 path and may be logged"
+            ))?;
         }
+        confirm(&mut ctx, "Continue anyway? [y/N] ")?;
     }
-    Ok(())
-}
 
-// -- main dispatch --
+    if config.approval.required && !inv.approved && cli.command.destructive() {
+        return request_approval(&mut ctx, &cli.base_url, &inv.recorded_args);
+    }
 
-/// Parse CLI arguments and execute the corresponding MotherDuck API command.
-pub fn run<I, T>(args: I) -> Result<()>
-where
-    I: IntoIterator<Item = T>,
-    T: Into<std::ffi::OsString> + Clone,
-{
-    let cli = Cli::parse_from(args);
-    let mode = cli.output;
-    let yes = cli.yes;
-    let token = auth::resolve_token_or(cli.token.as_deref())?;
-    let client = MotherduckClient::new(&token)?;
+    if let Commands::Bundle {
+        command: BundleCommands::Execute { file },
+    } = &cli.command
+    {
+        return execute_bundle(&mut ctx, &cli, file, inv);
+    }
+
+    // Stop before any hook or API call if the cached probe says the token will be refused.
+    let required = cli.command.required_capabilities(&cli.command_path);
+    if ctx.local.is_none()
+        && let Some(path) = config_path.as_deref()
+    {
+        let report = Report::load_fresh(
+            &capabilities::path_for(path),
+            &cache_key,
+            chrono::Utc::now(),
+        );
+        if let Some(reason) = permissions::preflight(&cli.command_path, &required, report.as_ref())
+        {
+            bail!(reason);
+        }
+    }
 
-    match cli.command {
-        Commands::ServiceAccount { command } => handle_service_account(&client, command, mode, yes),
-        Commands::Token { command } => handle_token(&client, command, mode, yes),
-        Commands::Duckling { command } => handle_duckling(&client, command, mode),
-        Commands::Account { command } => handle_account(&client, command, mode),
+    let hook_inv = middleware::Invocation {
+        command: &cli.command_path,
+        args: &inv.recorded_args,
+        base_url: &cli.base_url,
+        correlation_id: inv.correlation_id,
+    };
+    let config_dir = config_path.as_deref().and_then(Path::parent);
+    let hooks = cli.command.mutates().then_some(&config.hooks);
+    if let Some(pre) = hooks.and_then(|h| h.pre.as_ref()) {
+        let pre = middleware::resolve(pre, config_dir);
+        if !run_hook(&mut ctx, &pre, Stage::Pre, &hook_inv, None)? {
+            return Err(HookRefused(format!(
+                "pre hook {} refused `md {}`",
+                pre.display(),
+                cli.command_path
+            ))
+            .into());
+        }
+    }
+    let result = match cli.command {
+        Commands::ServiceAccount { command } => handle_service_account(&mut ctx, command),
+        Commands::Token { command } => handle_token(&mut ctx, command),
+        Commands::Instance { command } => handle_instance(&mut ctx, command),
+        Commands::Account { command } => handle_account(&mut ctx, command),
+        Commands::Audit { command } => handle_audit(&mut ctx, command),
+        Commands::Get { uri } => handle_get(&mut ctx, &uri),
+        Commands::Delete { uri } => handle_delete(&mut ctx, &uri),
+        Commands::Describe { uri } => handle_describe(&mut ctx, &uri),
+        Commands::Schedule { command } => handle_schedule(&mut ctx, command),
+        Commands::Provision(args) => provision(&mut ctx, args),
+        Commands::Deprovision { username } => deprovision(&mut ctx, &username),
+        Commands::ConnectString(args) => connect_string(&mut ctx, args),
+        Commands::TestConnection(args) => test_connection(&mut ctx, args),
+        Commands::Search { pattern, refresh } => handle_search(&mut ctx, &pattern, refresh),
+        Commands::Sync { usernames } => handle_sync(&mut ctx, &usernames),
+        Commands::Changes { since } => handle_changes(&mut ctx, &since),
+        Commands::Annotate { command } => handle_annotate(&mut ctx, command),
+        Commands::Wait { command } => handle_wait(&mut ctx, command),
+        Commands::Capabilities { refresh } => handle_capabilities(&mut ctx, refresh),
+        Commands::Permissions { command } => handle_permissions(&mut ctx, &command.join(" ")),
+        Commands::Auth {
+            command: AuthCommands::Whoami,
+        } => whoami(&mut ctx, &token),
+        Commands::SigningKey { command } => handle_signing_key(&mut ctx, command),
+        Commands::Approve { file } => approve(&mut ctx, &file),
+        Commands::Execute { .. }
+        | Commands::Config { .. }
+        | Commands::Profile { .. }
+        | Commands::Auth {
+            command: AuthCommands::Login | AuthCommands::Logout | AuthCommands::Status,
+        }
+        | Commands::Dev { .. }
+        | Commands::Completions { .. }
+        | Commands::Generate { .. } => {
+            unreachable!("handled before the client is built")
+        }
+        Commands::Bundle { command } => match command {
+            BundleCommands::Create {
+                file,
+                commands,
+                from,
+            } => create_bundle(&mut ctx, &cli.base_url, &file, commands, from.as_deref()),
+            BundleCommands::Show { file } => show_bundle(&mut ctx, &file),
+            BundleCommands::Execute { .. } => unreachable!("handled above"),
+        },
+    };
+    ctx.flush_client_log()?;
+    // The client leaves response warnings to the caller, so `--warnings-as-errors` covers them.
+    for msg in client.take_warnings() {
+        ctx.warn(&msg)?;
+    }
+    if let Some(post) = hooks.and_then(|h| h.post.as_ref()) {
+        let post = middleware::resolve(post, config_dir);
+        let outcome = result.as_ref().map(drop).map_err(|e| format!("{e:#}"));
+        // The command has already run, so a failing post hook only warns.
+        match run_hook(&mut ctx, &post, Stage::Post, &hook_inv, Some(outcome)) {
+            Ok(true) => {}
+            Ok(false) => ctx.warn(&format!("post hook {} failed", post.display()))?,
+            Err(e) => ctx.warn(&format!("{e:#}"))?,
+        }
     }
+    result.map_err(|e| {
+        let explained = config_path.as_deref().and_then(|path| {
+            let report = Report::load(&capabilities::path_for(path), &cache_key);
+            required
+                .iter()
+                .find_map(|name| capabilities::explain(&e, name, report.as_ref()))
+        });
+        let advice = explained.or_else(|| hints::hint(&e).map(String::from));
+        // JSON errors carry the ID in their own field.
+        let id = (!matches!(
+            mode,
+            OutputMode::Json | OutputMode::Ndjson | OutputMode::PsObject
+        ) && reached_api(&e))
+        .then(|| format!("correlation id {}", inv.correlation_id));
+        match (advice, id) {
+            (Some(advice), Some(id)) => e.context(format!("{advice} ({id})")),
+            (Some(text), None) | (None, Some(text)) => e.context(text),
+            (None, None) => e,
+        }
+    })
 }
 
 #[cfg(test)]
@@ -476,24 +5593,101 @@ mod tests {
 
     // -- CLI parsing --
 
+    #[test]
+    fn help_examples_parse() {
+        fn check(cmd: &clap::Command) {
+            let examples = cmd.get_after_long_help().map(|h| h.to_string());
+            // Each md stage of a pipeline is an example of its own.
+            let stages = examples
+                .iter()
+                .flat_map(|h| h.lines().flat_map(|l| l.split(" | ")));
+            for line in stages {
+                if let Some(args) = line.trim().strip_prefix("md ") {
+                    // Output redirection is the shell's, not md's.
+                    let args = args.split(" >").next().unwrap();
+                    let mut argv = vec!["md".to_string()];
+                    argv.extend(schedule::split_command(args).unwrap());
+                    if let Err(e) = Cli::try_parse_from(&argv) {
+                        panic!("example `{}` does not parse: {e}", line.trim());
+                    }
+                }
+            }
+            cmd.get_subcommands().for_each(check);
+        }
+        check(&Cli::command());
+    }
+
     #[test]
     fn parse_service_account_create() {
         let cli = parse(&["md", "service-account", "create", "svc_test"]).unwrap();
         match cli.command {
             Commands::ServiceAccount {
-                command: ServiceAccountCommands::Create { username },
+                command: ServiceAccountCommands::Create { username, .. },
             } => assert_eq!(username, "svc_test"),
             _ => panic!("expected ServiceAccount Create"),
         }
     }
 
+    #[test]
+    fn parse_service_account_create_from_template() {
+        let cli = parse(&[
+            "md",
+            "service-account",
+            "create",
+            "data",
+            "--from-template",
+            "{env}-{name}",
+            "--var",
+            "env=prod",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::ServiceAccount {
+                command:
+                    ServiceAccountCommands::Create {
+                        from_template,
+                        vars,
+                        ..
+                    },
+            } => {
+                assert_eq!(from_template.as_deref(), Some("{env}-{name}"));
+                assert_eq!(vars, [("env".to_string(), "prod".to_string())]);
+            }
+            _ => panic!("expected ServiceAccount Create"),
+        }
+    }
+
+    #[test]
+    fn parse_var_requires_from_template() {
+        assert!(parse(&["md", "service-account", "create", "u", "--var", "a=b"]).is_err());
+    }
+
+    #[test]
+    fn parse_ttl_accepts_seconds_and_durations() {
+        assert_eq!(parse_ttl("3600"), Ok(3600));
+        assert_eq!(parse_ttl("90d"), Ok(90 * 86400));
+        assert!(parse_ttl("1m").is_err());
+        assert!(parse_ttl("400d").is_err());
+        assert!(parse_ttl("soon").is_err());
+    }
+
+    #[test]
+    fn parse_key_val_rejects_missing_equals() {
+        assert!(parse_key_val("novalue").is_err());
+        assert!(parse_key_val("=v").is_err());
+        assert_eq!(parse_key_val("k=").unwrap(), ("k".into(), "".into()));
+    }
+
     #[test]
     fn parse_service_account_delete() {
         let cli = parse(&["md", "service-account", "delete", "svc_test"]).unwrap();
         match cli.command {
             Commands::ServiceAccount {
-                command: ServiceAccountCommands::Delete { username },
-            } => assert_eq!(username, "svc_test"),
+                command: ServiceAccountCommands::Delete { username, confirm },
+            } => {
+                assert_eq!(username, "svc_test");
+                assert_eq!(confirm, None);
+            }
             _ => panic!("expected ServiceAccount Delete"),
         }
     }
@@ -521,6 +5715,7 @@ mod tests {
                         name,
                         ttl,
                         token_type,
+                        ..
                     },
             } => {
                 assert_eq!(username, "svc_test");
@@ -556,24 +5751,72 @@ mod tests {
 
     #[test]
     fn parse_invalid_instance_size_fails() {
-        assert!(parse(&["md", "duckling", "set", "u", "--rw-size", "tiny"]).is_err());
+        assert!(parse(&["md", "instance", "set", "u", "--rw-size", "tiny"]).is_err());
     }
 
     #[test]
-    fn parse_duckling_set_requires_at_least_one_override() {
-        assert!(parse(&["md", "duckling", "set", "u"]).is_err());
+    fn parse_instance_set_requires_at_least_one_override() {
+        assert!(parse(&["md", "instance", "set", "u"]).is_err());
     }
 
     #[test]
     fn parse_global_output_flag() {
         let cli = parse(&["md", "-o", "json", "account", "list-active"]).unwrap();
-        assert_eq!(cli.output, OutputMode::Json);
+        assert_eq!(cli.output, Some(OutputMode::Json));
     }
 
     #[test]
-    fn parse_default_output_is_text() {
+    fn parse_output_defaults_from_config() {
         let cli = parse(&["md", "account", "list-active"]).unwrap();
-        assert_eq!(cli.output, OutputMode::Text);
+        assert_eq!(cli.output, None);
+        let mut config = Config::default();
+        assert_eq!(
+            resolve_output(&cli, &config, None).unwrap(),
+            OutputMode::Text
+        );
+        config.defaults.output = Some("json".into());
+        let profile = config::Profile {
+            output: Some("sh".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_output(&cli, &config, Some(&profile)).unwrap(),
+            OutputMode::Sh
+        );
+        let cli = Cli {
+            command_path: "account list-active".into(),
+            ..cli
+        };
+        config
+            .output
+            .insert("account list-active".into(), "psobject".into());
+        assert_eq!(
+            resolve_output(&cli, &config, Some(&profile)).unwrap(),
+            OutputMode::PsObject
+        );
+        config.output.insert("token lst".into(), "json".into());
+        assert!(resolve_output(&cli, &config, None).is_err());
+    }
+
+    #[test]
+    fn permissions_cover_every_visible_command() {
+        fn leaves(cmd: &clap::Command, prefix: &str, into: &mut Vec<String>) {
+            for sub in cmd.get_subcommands() {
+                if sub.is_hide_set() || sub.get_name() == "help" {
+                    continue;
+                }
+                let path = format!("{prefix}{}", sub.get_name());
+                if sub.has_subcommands() {
+                    leaves(sub, &format!("{path} "), into);
+                } else {
+                    into.push(path);
+                }
+            }
+        }
+        let mut commands = Vec::new();
+        leaves(&Cli::command(), "", &mut commands);
+        let registered: Vec<&str> = permissions::PERMISSIONS.iter().map(|p| p.command).collect();
+        assert_eq!(commands, registered);
     }
 
     // -- --token flag --
@@ -622,6 +5865,14 @@ mod tests {
         assert_eq!(cli.token.as_deref(), Some("tok"));
     }
 
+    #[test]
+    fn parse_warnings_as_errors_flag() {
+        let cli = parse(&["md", "token", "list", "u", "--warnings-as-errors"]).unwrap();
+        assert!(cli.warnings_as_errors);
+        let cli = parse(&["md", "token", "list", "u"]).unwrap();
+        assert!(!cli.warnings_as_errors);
+    }
+
     #[test]
     fn parse_yes_defaults_to_false() {
         let cli = parse(&["md", "account", "list-active"]).unwrap();
@@ -642,12 +5893,34 @@ mod tests {
         assert_eq!(display_field(&v, "name"), "-");
     }
 
+    #[test]
+    fn plural_adds_s_unless_one() {
+        assert_eq!(plural(0, "token"), "0 tokens");
+        assert_eq!(plural(1, "token"), "1 token");
+        assert_eq!(plural(2, "unexpired token"), "2 unexpired tokens");
+    }
+
     #[test]
     fn display_field_returns_dash_for_null() {
         let v = serde_json::json!({"name": null});
         assert_eq!(display_field(&v, "name"), "-");
     }
 
+    #[test]
+    fn token_is_live_checks_expiry() {
+        let now = chrono::Utc::now();
+        assert!(token_is_live(&json!({ "expire_at": null }), now));
+        assert!(token_is_live(
+            &json!({ "expire_at": "2999-01-01T00:00:00Z" }),
+            now
+        ));
+        assert!(!token_is_live(
+            &json!({ "expire_at": "2000-01-01T00:00:00Z" }),
+            now
+        ));
+        assert!(token_is_live(&json!({ "expire_at": "soon" }), now));
+    }
+
     #[test]
     fn extract_str_returns_value() {
         let v = serde_json::json!({"size": "standard"});
@@ -666,25 +5939,289 @@ mod tests {
         assert_eq!(extract_str(&v, "count"), None);
     }
 
+    /// Capture a renderer's output as a string.
+    fn render(f: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut buf = Vec::new();
+        f(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    /// Fixed "now" for renderers that depend on the time.
+    fn now() -> chrono::DateTime<chrono::Utc> {
+        "2026-06-01T00:00:00Z".parse().unwrap()
+    }
+
     #[test]
     fn print_table_empty_rows_no_output() {
-        // Should not panic or print anything
-        print_table(&["A", "B"], &[]);
+        assert_eq!(
+            render(|out| print_table::<&str>(out, &["A", "B"], &[[]; 0])),
+            ""
+        );
     }
 
     #[test]
     fn print_table_single_row() {
-        print_table(&["A", "B"], &[vec!["short".into(), "x".into()]]);
+        let out = render(|out| print_table(out, &["A", "B"], &[["short", "x"]]));
+        insta::assert_snapshot!(out);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn print_table_never_panics(
+            headers in proptest::collection::vec("[A-Z]{1,8}", 1..6),
+            rows in proptest::collection::vec(proptest::collection::vec(".*", 0..8), 0..16),
+        ) {
+            let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+            print_table(&mut io::sink(), &headers, &rows).unwrap();
+        }
+
+        #[test]
+        fn display_helpers_never_panic(key in ".*", text in ".*") {
+            let value: Value = serde_json::from_str(&text).unwrap_or(Value::String(text));
+            let _ = display_field(&value, &key);
+            let _ = extract_str(&value, &key);
+            print_instance_config(&mut io::sink(), &value).unwrap();
+        }
     }
 
     #[test]
     fn print_table_varying_widths() {
-        print_table(
-            &["ID", "NAME"],
-            &[
-                vec!["1".into(), "alice".into()],
-                vec!["1000".into(), "b".into()],
+        let out = render(|out| print_table(out, &["ID", "NAME"], &[["1", "alice"], ["1000", "b"]]));
+        insta::assert_snapshot!(out);
+    }
+
+    #[test]
+    fn connection_string_encodes_parameters() {
+        assert_eq!(connection_string(None, &[]), "md:");
+        assert_eq!(
+            connection_string(
+                Some("my db"),
+                &[("motherduck_token", "ey.J-x_1"), ("session_hint", "a&b=c")]
+            ),
+            "md:my%20db?motherduck_token=ey.J-x_1&session_hint=a%26b%3Dc"
+        );
+    }
+
+    #[test]
+    fn merge_reports_unknown_config_fields() {
+        let current = json!({
+            "read_write": { "instance_size": "standard", "cooldown_seconds": 300 },
+            "read_scaling": { "instance_size": "standard", "flock_size": 4 },
+            "region": "us-east-1"
+        });
+        let config =
+            merge_duckling_config(&current, Some(InstanceSize::Pulse), None, None).unwrap();
+        assert_eq!(config["read_write"]["instance_size"], "pulse");
+        assert_eq!(config["read_write"]["cooldown_seconds"], 300);
+        assert_eq!(
+            unknown_config_fields(&config),
+            ["read_write.cooldown_seconds", "region"]
+        );
+        assert!(unknown_config_fields(&json!({ "read_write": {} })).is_empty());
+        assert!(merge_duckling_config(&json!([]), None, None, None).is_err());
+    }
+
+    /// Rendering time for large listings. Run with
+    /// `cargo test --release -p dkdc-md-cli --lib -- --ignored --nocapture render_large`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn render_large_listings() {
+        let tokens = serde_json::json!({ "tokens": (0..100_000).map(|i| serde_json::json!({
+            "id": format!("tok_{i:06}"),
+            "name": format!("token-{i}"),
+            "token_type": "read_write",
+            "expire_at": if i % 3 == 0 { Value::Null } else { "2026-01-01T00:00:00Z".into() },
+        })).collect::<Vec<_>>() });
+        let accounts = serde_json::json!({ "accounts": (0..100_000).map(|i| serde_json::json!({
+            "username": format!("svc_{i:06}"),
+            "ducklings": [
+                { "type": "read_write", "status": "running" },
+                { "type": "read_scaling", "status": "idle" }
             ],
+        })).collect::<Vec<_>>() });
+        let time = |name: &str, f: &dyn Fn(&mut dyn Write) -> io::Result<()>| {
+            let started = std::time::Instant::now();
+            for _ in 0..10 {
+                f(&mut io::sink()).unwrap();
+            }
+            eprintln!("{name}: {:?} per 100k rows", started.elapsed() / 10);
+        };
+        time("print_tokens", &|out| {
+            print_tokens(out, &tokens, now(), false)
+        });
+        time("print_accounts", &|out| print_accounts(out, &accounts));
+    }
+
+    // -- text renderers --
+
+    #[test]
+    fn snapshot_duckling_config() {
+        let value = serde_json::json!({
+            "read_write": { "instance_size": "jumbo" },
+            "read_scaling": { "instance_size": "pulse", "flock_size": 16 }
+        });
+        insta::assert_snapshot!(render(|out| print_instance_config(out, &value)));
+    }
+
+    #[test]
+    fn snapshot_duckling_config_missing_fields() {
+        let value = serde_json::json!({ "read_write": {} });
+        insta::assert_snapshot!(render(|out| print_instance_config(out, &value)));
+    }
+
+    #[test]
+    fn snapshot_tokens() {
+        let value = serde_json::json!({
+            "tokens": [
+                { "id": "a1", "name": "ci", "token_type": "read_write", "expire_at": "2030-01-01T00:00:00Z" },
+                { "id": "b22", "name": "reader", "token_type": "read_scaling", "expire_at": null },
+                { "id": "c333", "name": null, "expire_at": "" }
+            ]
+        });
+        insta::assert_snapshot!(render(|out| print_tokens(out, &value, now(), false)));
+    }
+
+    #[test]
+    fn snapshot_tokens_marks_expired() {
+        let value = serde_json::json!({
+            "tokens": [
+                { "id": "a1", "name": "old", "token_type": "read_write", "expire_at": "2026-01-01T00:00:00Z" },
+                { "id": "b22", "name": "new", "token_type": "read_write", "expire_at": "2030-01-01T00:00:00Z" }
+            ]
+        });
+        insta::assert_snapshot!(render(|out| print_tokens(out, &value, now(), false)));
+    }
+
+    #[test]
+    fn tokens_color_strikes_expired_rows() {
+        let value = serde_json::json!({
+            "tokens": [
+                { "id": "a1", "name": "old", "token_type": "read_write", "expire_at": "2026-01-01T00:00:00Z" },
+                { "id": "b22", "name": "new", "token_type": "read_write", "expire_at": null }
+            ]
+        });
+        let out = render(|out| print_tokens(out, &value, now(), true));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[1],
+            "\x1b[31;9ma1   old   read_write  2026-01-01T00:00:00Z\x1b[0m"
+        );
+        assert_eq!(lines[2], "b22  new   read_write  never");
+        assert!(!out.contains("EXPIRED"));
+    }
+
+    #[test]
+    fn grouped_tokens_print_sections_and_total() {
+        let value = serde_json::json!({
+            "tokens": [
+                { "id": "a1", "name": "ci", "token_type": "read_write", "expire_at": null },
+                { "id": "b22", "name": "bi", "token_type": "read_scaling", "expire_at": null },
+                { "id": "c3", "name": "etl", "token_type": "read_write", "expire_at": null }
+            ]
+        });
+        let by = TokenGroupBy::Type;
+        let out = render(|out| {
+            print_grouped(
+                out,
+                &value,
+                "tokens",
+                by.label(),
+                |t| by.group(t, now()),
+                |out, tokens| token_table(out, tokens, now(), false),
+            )
+        });
+        assert_eq!(
+            out,
+            "type: read_scaling (1)\n\
+             ID   NAME  TYPE          EXPIRES\n\
+             b22  bi    read_scaling  never\n\
+             \n\
+             type: read_write (2)\n\
+             ID  NAME  TYPE        EXPIRES\n\
+             a1  ci    read_write  never\n\
+             c3  etl   read_write  never\n\
+             \n\
+             total: 3\n"
+        );
+        assert_eq!(
+            AccountGroupBy::Status.group(&serde_json::json!({
+                "ducklings": [{ "status": "running" }, { "status": "idle" }, { "status": "idle" }]
+            })),
+            "idle, running"
+        );
+    }
+
+    #[test]
+    fn summary_footer_counts_expiring_tokens_and_running_accounts() {
+        let tokens = [
+            serde_json::json!({ "expire_at": "2026-01-01T00:00:00Z" }),
+            serde_json::json!({ "expire_at": null }),
+            serde_json::json!({ "expire_at": (now() + chrono::TimeDelta::days(3)).to_rfc3339() }),
+            serde_json::json!({ "expire_at": (now() + chrono::TimeDelta::days(30)).to_rfc3339() }),
+        ];
+        assert_eq!(
+            summary_footer("tokens", &tokens, now()),
+            "4 tokens, 1 expiring within 7d, 1 expired"
+        );
+        let accounts = [serde_json::json!({ "ducklings": [{ "status": "running" }] })];
+        assert_eq!(
+            summary_footer("accounts", &accounts, now()),
+            "1 account, 1 running"
         );
     }
+
+    #[test]
+    fn color_choice_respects_terminal() {
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+
+    #[test]
+    fn snapshot_accounts() {
+        let value = serde_json::json!({
+            "accounts": [
+                {
+                    "username": "svc_etl",
+                    "ducklings": [
+                        { "type": "read_write", "status": "running" },
+                        { "type": "read_scaling", "status": "idle" }
+                    ]
+                },
+                { "username": "svc_bi", "ducklings": [] },
+                { "username": "svc_x" }
+            ]
+        });
+        insta::assert_snapshot!(render(|out| print_accounts(out, &value)));
+    }
+
+    #[test]
+    fn snapshot_account_tree() {
+        let value = serde_json::json!({
+            "accounts": [
+                {
+                    "username": "svc_etl",
+                    "tokens": 2,
+                    "ducklings": [
+                        { "type": "read_write", "status": "running", "instance_size": "jumbo" },
+                        {
+                            "type": "read_scaling",
+                            "status": "idle",
+                            "instance_size": "pulse",
+                            "flock_size": 4
+                        }
+                    ]
+                },
+                { "username": "svc_bi", "tokens": 0, "ducklings": [] },
+                { "username": "svc_x" }
+            ]
+        });
+        insta::assert_snapshot!(render(|out| print_account_tree(out, &value)));
+    }
+
+    #[test]
+    fn snapshot_json() {
+        let value = serde_json::json!({ "username": "svc_etl", "nested": { "n": 1 } });
+        insta::assert_snapshot!(render(|out| print_json(out, &value)));
+    }
 }
@@ -1,28 +1,49 @@
-use std::time::Duration;
+use std::hash::{BuildHasher, RandomState};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
 use serde::Serialize;
 use serde_json::{Value, json};
-use ureq::{Agent, http};
+use ureq::http;
 
-const BASE_URL: &str = "https://api.motherduck.com";
+use crate::sandbox::Sandbox;
+use crate::transport::{self, Transport};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.motherduck.com";
 const TIMEOUT: Duration = Duration::from_secs(10);
 const USER_AGENT_VALUE: &str = concat!("dkdc-md-cli/", env!("CARGO_PKG_VERSION"));
 const SUCCESS_STATUS: std::ops::Range<u16> = 200..300;
 const CONTENT_TYPE_JSON: &str = "application/json";
+/// Methods safe to send twice; see [`RetryOn`].
+const RETRIED_METHODS: &[http::Method] =
+    &[http::Method::GET, http::Method::PUT, http::Method::DELETE];
+/// Default wait before the first retry, doubling for each one after.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Default number of times a 429 is waited out; see [`MotherduckClientBuilder::rate_limit_retries`].
+const RATE_LIMIT_RETRIES: u32 = 3;
+/// Requests in flight at once when a bulk method falls back to one request per user.
+const BULK_WORKERS: usize = 8;
+/// Header carrying the per-invocation correlation ID, so API-side logs can be matched to a run.
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+/// Default cap on a buffered response body.
+pub const DEFAULT_MAX_RESPONSE_BYTES: u64 = 10 * 1024 * 1024;
 
-// API path segments
+// API path segments. Paths are built against `/v1`; `api_version` rewrites the prefix.
 const API_V1: &str = "/v1";
-const USERS: &str = "users";
-const TOKENS: &str = "tokens";
-const INSTANCES: &str = "instances";
-const ACTIVE_ACCOUNTS: &str = "active_accounts";
+pub const DEFAULT_API_VERSION: &str = "v1";
+pub(crate) const USERS: &str = "users";
+pub(crate) const TOKENS: &str = "tokens";
+pub(crate) const INSTANCES: &str = "instances";
+pub(crate) const ACTIVE_ACCOUNTS: &str = "active_accounts";
 
 /// Characters that must be percent-encoded in a URL path segment.
 const PATH_SEGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'#').add(b'%').add(b'/').add(b'?');
 
-fn encode_path(s: &str) -> String {
+pub(crate) fn encode_path(s: &str) -> String {
     utf8_percent_encode(s, PATH_SEGMENT).to_string()
 }
 
@@ -30,15 +51,15 @@ fn users_path() -> String {
     format!("{API_V1}/{USERS}")
 }
 
-fn user_path(username: &str) -> String {
+pub(crate) fn user_path(username: &str) -> String {
     format!("{API_V1}/{USERS}/{}", encode_path(username))
 }
 
-fn user_tokens_path(username: &str) -> String {
+pub(crate) fn user_tokens_path(username: &str) -> String {
     format!("{API_V1}/{USERS}/{}/{TOKENS}", encode_path(username))
 }
 
-fn user_token_path(username: &str, token_id: &str) -> String {
+pub(crate) fn user_token_path(username: &str, token_id: &str) -> String {
     format!(
         "{API_V1}/{USERS}/{}/{TOKENS}/{}",
         encode_path(username),
@@ -46,27 +67,147 @@ fn user_token_path(username: &str, token_id: &str) -> String {
     )
 }
 
-fn user_instances_path(username: &str) -> String {
+pub(crate) fn user_instances_path(username: &str) -> String {
     format!("{API_V1}/{USERS}/{}/{INSTANCES}", encode_path(username))
 }
 
-fn active_accounts_path() -> String {
+pub(crate) fn active_accounts_path() -> String {
     format!("{API_V1}/{ACTIVE_ACCOUNTS}")
 }
 
+/// Blocking client for the MotherDuck REST API. Every method returns the raw JSON response.
 pub struct MotherduckClient {
-    agent: Agent,
+    transport: Arc<dyn Transport>,
+    base_url: String,
     bearer: String,
+    api_version: String,
+    /// Retry counts, indexed by [`RetryOn`].
+    retries: [u32; 3],
+    retry_delay: Duration,
+    rate_limit_retries: u32,
+    /// Extra headers from [`MotherduckClientBuilder::header`].
+    headers: Vec<(String, String)>,
+    user_agent: String,
+    max_response_bytes: u64,
+    correlation_id: String,
+    verbose: bool,
+    curl: bool,
+    hooks: Vec<Arc<dyn Hooks>>,
+    /// Not printed by the client; see [`MotherduckClient::take_warnings`].
+    warnings: Mutex<Vec<String>>,
+    /// `--verbose` and `--curl` lines; see [`MotherduckClient::take_log`].
+    log: Mutex<Vec<String>>,
 }
 
 impl std::fmt::Debug for MotherduckClient {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MotherduckClient")
+            .field("base_url", &self.base_url)
             .field("bearer", &"[redacted]")
+            .field("api_version", &self.api_version)
+            .field("retries", &self.retries)
+            .field("retry_delay", &self.retry_delay)
+            .field("rate_limit_retries", &self.rate_limit_retries)
+            .field("user_agent", &self.user_agent)
+            .field("max_response_bytes", &self.max_response_bytes)
+            .field("correlation_id", &self.correlation_id)
+            .field("hooks", &self.hooks.len())
             .finish()
     }
 }
 
+/// A non-2xx response from the API. Errors returned by the client can be downcast to this
+/// to branch on the status, e.g. to treat a 404 as "not found" rather than a failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API error ({}): {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Kinds of failure a request is retried after, each with its own count; see
+/// [`MotherduckClientBuilder::retries_on`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryOn {
+    /// The host was not found or the connection failed, so the request never reached the
+    /// API. Retried for every method.
+    Connect,
+    /// The request timed out or lost its connection after it was sent. Retried for `GET`,
+    /// `PUT`, and `DELETE` only: a `POST` may have been acted on.
+    Timeout,
+    /// A 5xx response. Retried for `GET`, `PUT`, and `DELETE` only, like timeouts.
+    ServerError,
+}
+
+impl RetryOn {
+    /// Why a request is being retried, for verbose logs.
+    fn reason(self) -> &'static str {
+        match self {
+            Self::Connect => "connection failed",
+            Self::Timeout => "timed out",
+            Self::ServerError => "server error",
+        }
+    }
+}
+
+/// Instrumentation for code embedding the client: custom logging, metrics, or extra headers.
+/// Register with [`MotherduckClientBuilder::hooks`]; both methods default to doing nothing.
+pub trait Hooks: Send + Sync {
+    /// Called before each request is sent. Headers pushed onto `headers` are added to the
+    /// request; the client's own headers (authorization, user agent, correlation ID) cannot
+    /// be replaced.
+    fn on_request(&self, request: &RequestInfo<'_>, headers: &mut Vec<(String, String)>) {
+        let _ = (request, headers);
+    }
+
+    /// Called after each request with its status, or the transport error if no response
+    /// arrived.
+    fn on_response(&self, request: &RequestInfo<'_>, response: &ResponseInfo<'_>) {
+        let _ = (request, response);
+    }
+}
+
+/// The request a [`Hooks`] method is called for.
+#[derive(Debug)]
+pub struct RequestInfo<'a> {
+    pub method: &'a str,
+    /// Full URL, including the base URL and API version.
+    pub url: &'a str,
+    pub correlation_id: &'a str,
+}
+
+/// How a request ended, as passed to [`Hooks::on_response`].
+#[derive(Debug)]
+pub struct ResponseInfo<'a> {
+    /// HTTP status, or the transport error.
+    pub status: std::result::Result<u16, &'a str>,
+    pub elapsed: Duration,
+}
+
+/// Headers [`Hooks::on_request`] may not set.
+const RESERVED_HEADERS: &[&str] = &["authorization", "user-agent", CORRELATION_ID_HEADER];
+
+/// `Ok(false)` for a 404, the result otherwise.
+fn found(result: Result<Value>) -> Result<bool> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.downcast_ref::<ApiError>()
+                .is_some_and(|e| e.status == 404) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
 #[derive(Serialize)]
 struct CreateTokenRequest<'a> {
     name: &'a str,
@@ -76,71 +217,510 @@ struct CreateTokenRequest<'a> {
     token_type: Option<&'a str>,
 }
 
+/// Builds a [`MotherduckClient`]; start with [`MotherduckClient::builder`].
+pub struct MotherduckClientBuilder {
+    token: String,
+    base_url: String,
+    api_version: String,
+    timeout: Duration,
+    retries: [u32; 3],
+    retry_delay: Duration,
+    rate_limit_retries: u32,
+    proxy: Option<String>,
+    headers: Vec<(String, String)>,
+    user_agent_suffix: Option<String>,
+    max_response_bytes: u64,
+    correlation_id: Option<String>,
+    verbose: bool,
+    curl: bool,
+    hooks: Vec<Arc<dyn Hooks>>,
+    /// Replaces HTTP, e.g. with the sandbox.
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl MotherduckClientBuilder {
+    /// Send requests to `base_url` instead of the public MotherDuck API.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Send requests to the `version` endpoints (e.g. `v2`) instead of `v1`. Every path,
+    /// including those passed to [`call`](MotherduckClient::call), is still written against
+    /// `/v1`.
+    pub fn api_version(mut self, version: &str) -> Self {
+        self.api_version = version.to_string();
+        self
+    }
+
+    /// Give up on a request after `timeout` (default 10s), including reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retry failed requests up to `retries` times (default 0) for each kind of failure in
+    /// [`RetryOn`], backing off exponentially with jitter (see
+    /// [`retry_delay`](Self::retry_delay)) or as long as a 5xx's `Retry-After` asks. `POST`
+    /// and `PATCH` are retried only when they cannot have reached the API. A 429 counts
+    /// against [`rate_limit_retries`](Self::rate_limit_retries) instead.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = [retries; 3];
+        self
+    }
+
+    /// Retry up to `retries` times after failures of one kind, e.g. connection failures,
+    /// which are always safe to retry, more often than 5xx responses.
+    pub fn retries_on(mut self, on: RetryOn, retries: u32) -> Self {
+        self.retries[on as usize] = retries;
+        self
+    }
+
+    /// Wait about `delay` (default 250ms) before the first retry, doubling for each one
+    /// after, up to 30s. Each wait is between half and all of that, at random, so clients
+    /// that failed together do not retry together.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Wait out a 429 and send the request again, up to `retries` times (default 3), for
+    /// any method: the API did not act on it. The wait is what `Retry-After` asks (seconds
+    /// or an HTTP date), else the [`retry_delay`](Self::retry_delay) backoff. A 429 asking
+    /// for more than 30s is returned rather than waited on, as is every 429 with 0, e.g. for
+    /// interactive use.
+    pub fn rate_limit_retries(mut self, retries: u32) -> Self {
+        self.rate_limit_retries = retries;
+        self
+    }
+
+    /// Connect through `proxy`, e.g. `http://proxy:3128`. Without one, the usual
+    /// `HTTPS_PROXY`/`ALL_PROXY` variables apply.
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Add a header to every request. The client's own headers (authorization, user agent,
+    /// correlation ID) cannot be replaced.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Append `suffix` to the user agent, e.g. to attribute traffic to one pipeline.
+    pub fn user_agent_suffix(mut self, suffix: &str) -> Self {
+        self.user_agent_suffix = Some(suffix.to_string());
+        self
+    }
+
+    /// Fail requests whose response body is larger than `bytes` instead of buffering it.
+    /// Streaming methods such as
+    /// [`for_each_active_account`](MotherduckClient::for_each_active_account) are not
+    /// limited.
+    pub fn max_response_size(mut self, bytes: u64) -> Self {
+        self.max_response_bytes = bytes;
+        self
+    }
+
+    /// Send `id` as the correlation ID instead of a generated one, e.g. to propagate an
+    /// external trace. It must be 1-128 visible ASCII characters.
+    pub fn correlation_id(mut self, id: &str) -> Self {
+        self.correlation_id = Some(id.to_string());
+        self
+    }
+
+    /// Log each request, its status, and its latency, and each retry, for
+    /// [`take_log`](MotherduckClient::take_log).
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Log each request as an equivalent `curl` command before sending it, with
+    /// `$MOTHERDUCK_TOKEN` in place of the token so the command can be shared. See
+    /// [`take_log`](MotherduckClient::take_log).
+    pub fn curl(mut self, curl: bool) -> Self {
+        self.curl = curl;
+        self
+    }
+
+    /// Call `hooks` around every request. Hooks registered earlier run first.
+    pub fn hooks(mut self, hooks: impl Hooks + 'static) -> Self {
+        self.hooks.push(Arc::new(hooks));
+        self
+    }
+
+    /// Serve requests from `sandbox` instead of sending them.
+    pub(crate) fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.transport = Some(Arc::new(sandbox));
+        self
+    }
+
+    /// Validate the configuration and build the client.
+    pub fn build(self) -> Result<MotherduckClient> {
+        anyhow::ensure!(
+            !self.token.is_empty(),
+            "MotherDuck API token must not be empty"
+        );
+        let version = &self.api_version;
+        anyhow::ensure!(
+            version
+                .strip_prefix('v')
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())),
+            "invalid API version '{version}' (expected e.g. v1, v2)"
+        );
+        let correlation_id = match self.correlation_id {
+            Some(id) => {
+                anyhow::ensure!(
+                    (1..=128).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic()),
+                    "invalid correlation ID '{id}' (expected 1-128 visible ASCII characters)"
+                );
+                id
+            }
+            None => new_correlation_id(),
+        };
+        let user_agent = match &self.user_agent_suffix {
+            Some(suffix) => {
+                anyhow::ensure!(
+                    (1..=256).contains(&suffix.len())
+                        && suffix.bytes().all(|b| b == b' ' || b.is_ascii_graphic()),
+                    "invalid user agent suffix '{suffix}' (expected 1-256 printable ASCII \
+                     characters)"
+                );
+                format!("{USER_AGENT_VALUE} {suffix}")
+            }
+            None => USER_AGENT_VALUE.to_string(),
+        };
+        for (name, value) in &self.headers {
+            check_header(name, value)?;
+        }
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(transport::Http::new(self.timeout, self.proxy.as_deref())?),
+        };
+
+        Ok(MotherduckClient {
+            transport,
+            base_url: self.base_url,
+            bearer: format!("Bearer {}", self.token),
+            api_version: self.api_version,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            rate_limit_retries: self.rate_limit_retries,
+            headers: self.headers,
+            user_agent,
+            max_response_bytes: self.max_response_bytes,
+            correlation_id,
+            verbose: self.verbose,
+            curl: self.curl,
+            hooks: self.hooks,
+            warnings: Mutex::default(),
+            log: Mutex::default(),
+        })
+    }
+}
+
+/// Reject headers that are malformed or would replace one the client sets itself.
+fn check_header(name: &str, value: &str) -> Result<()> {
+    if RESERVED_HEADERS
+        .iter()
+        .any(|h| name.eq_ignore_ascii_case(h))
+    {
+        bail!("the {name} header is set by the client and cannot be replaced");
+    }
+    http::HeaderName::from_bytes(name.as_bytes())
+        .with_context(|| format!("invalid header name '{name}'"))?;
+    http::HeaderValue::from_str(value)
+        .with_context(|| format!("invalid value for header {name}"))?;
+    Ok(())
+}
+
 impl MotherduckClient {
+    /// A client with the default configuration; shorthand for `builder(token).build()`.
     pub fn new(token: &str) -> Result<Self> {
-        anyhow::ensure!(!token.is_empty(), "MotherDuck API token must not be empty");
-        let agent = Agent::config_builder()
-            .timeout_global(Some(TIMEOUT))
-            .http_status_as_error(false)
-            .build()
-            .into();
+        Self::builder(token).build()
+    }
 
-        Ok(Self {
-            agent,
-            bearer: format!("Bearer {token}"),
-        })
+    pub fn builder(token: &str) -> MotherduckClientBuilder {
+        MotherduckClientBuilder {
+            token: token.to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_version: DEFAULT_API_VERSION.to_string(),
+            timeout: TIMEOUT,
+            retries: [0; 3],
+            retry_delay: RETRY_DELAY,
+            rate_limit_retries: RATE_LIMIT_RETRIES,
+            proxy: None,
+            headers: Vec::new(),
+            user_agent_suffix: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            correlation_id: None,
+            verbose: false,
+            curl: false,
+            hooks: Vec::new(),
+            transport: None,
+        }
+    }
+
+    /// The API requests are sent to, without a trailing slash.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The ID sent in the [`CORRELATION_ID_HEADER`] of every request.
+    pub fn correlation_id(&self) -> &str {
+        &self.correlation_id
+    }
+
+    /// Warnings about responses since the last call, e.g. a body that was not valid UTF-8.
+    /// The client does not print them, so the caller decides where they go.
+    pub fn take_warnings(&self) -> Vec<String> {
+        std::mem::take(&mut *self.warnings.lock().unwrap())
+    }
+
+    /// Lines logged by [`verbose`](MotherduckClientBuilder::verbose) and
+    /// [`curl`](MotherduckClientBuilder::curl) since the last call, in order. Like warnings,
+    /// the client does not print them.
+    pub fn take_log(&self) -> Vec<String> {
+        std::mem::take(&mut *self.log.lock().unwrap())
+    }
+
+    fn log(&self, line: String) {
+        self.log.lock().unwrap().push(line);
+    }
+
+    fn url(&self, path: &str) -> String {
+        match path.strip_prefix(API_V1) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                format!("{}/{}{rest}", self.base_url, self.api_version)
+            }
+            _ => format!("{}{path}", self.base_url),
+        }
     }
 
     fn get(&self, path: &str) -> Result<Value> {
-        let url = format!("{BASE_URL}{path}");
-        let resp = self
-            .agent
-            .get(&url)
-            .header(http::header::AUTHORIZATION, &self.bearer)
-            .header(http::header::USER_AGENT, USER_AGENT_VALUE)
-            .call()
-            .context("request failed")?;
-        handle_response(resp).with_context(|| format!("GET {path}"))
+        let resp = self.send_get(path)?;
+        self.handle_response(resp)
+            .with_context(|| format!("GET {path}"))
+    }
+
+    fn send_get(&self, path: &str) -> Result<http::Response<ureq::Body>> {
+        self.send(http::Method::GET, path, None)
     }
 
     fn delete(&self, path: &str) -> Result<Value> {
-        let url = format!("{BASE_URL}{path}");
-        let resp = self
-            .agent
-            .delete(&url)
-            .header(http::header::AUTHORIZATION, &self.bearer)
-            .header(http::header::USER_AGENT, USER_AGENT_VALUE)
-            .call()
-            .context("request failed")?;
-        handle_response(resp).with_context(|| format!("DELETE {path}"))
+        let resp = self.send(http::Method::DELETE, path, None)?;
+        self.handle_response(resp)
+            .with_context(|| format!("DELETE {path}"))
     }
 
     fn post_json(&self, path: &str, body: &impl Serialize) -> Result<Value> {
-        let url = format!("{BASE_URL}{path}");
-        let bytes = serde_json::to_vec(body).context("failed to serialize request")?;
-        let resp = self
-            .agent
-            .post(&url)
-            .header(http::header::AUTHORIZATION, &self.bearer)
-            .header(http::header::USER_AGENT, USER_AGENT_VALUE)
-            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_JSON)
-            .send(&bytes)
-            .context("request failed")?;
-        handle_response(resp).with_context(|| format!("POST {path}"))
+        self.send_json(http::Method::POST, path, body)
     }
 
     fn put_json(&self, path: &str, body: &impl Serialize) -> Result<Value> {
-        let url = format!("{BASE_URL}{path}");
+        self.send_json(http::Method::PUT, path, body)
+    }
+
+    fn send_json(&self, method: http::Method, path: &str, body: &impl Serialize) -> Result<Value> {
         let bytes = serde_json::to_vec(body).context("failed to serialize request")?;
-        let resp = self
-            .agent
-            .put(&url)
+        let resp = self.send(method.clone(), path, Some(bytes))?;
+        self.handle_response(resp)
+            .with_context(|| format!("{method} {path}"))
+    }
+
+    /// Every request goes through here, so they all carry the same headers, are retried
+    /// the same way, and are all logged in verbose mode.
+    fn send(
+        &self,
+        method: http::Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<http::Response<ureq::Body>> {
+        let url = self.url(path);
+        // Retries so far, per kind of failure, and of 429s.
+        let (mut retried, mut throttled) = ([0u32; 3], 0);
+        loop {
+            let result = self.send_once(&method, &url, body.as_deref())?;
+            let rate_limited = matches!(&result, Ok(resp) if resp.status() == 429);
+            let class = match rate_limited {
+                true => None,
+                false => retry_class(&method, &result),
+            };
+            let wait = match &result {
+                Ok(resp) if rate_limited => self.rate_limit_wait(resp, throttled),
+                _ => class
+                    .filter(|on| retried[*on as usize] < self.retries[*on as usize])
+                    .map(|_| self.retry_wait(&result, retried.iter().sum())),
+            };
+            let Some(delay) = wait else {
+                return result.context("request failed");
+            };
+            let (reason, count, of) = match class {
+                Some(on) => (
+                    on.reason(),
+                    &mut retried[on as usize],
+                    self.retries[on as usize],
+                ),
+                None => ("rate limited", &mut throttled, self.rate_limit_retries),
+            };
+            *count += 1;
+            if self.verbose {
+                self.log(format!(
+                    "md: {reason}; retrying in {} ms (retry {count} of {of})",
+                    delay.as_millis()
+                ));
+            }
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// The wait before the `attempt`th retry (from 0): `retry_delay`, doubled per retry.
+    fn backoff(&self, attempt: u32) -> Duration {
+        jitter(
+            self.retry_delay
+                .saturating_mul(1 << attempt.min(16))
+                .min(MAX_RETRY_DELAY),
+        )
+    }
+
+    /// How long to wait before sending a request again after it got a 429, or `None` to
+    /// return the 429.
+    fn rate_limit_wait(
+        &self,
+        resp: &http::Response<ureq::Body>,
+        throttled: u32,
+    ) -> Option<Duration> {
+        if throttled >= self.rate_limit_retries {
+            return None;
+        }
+        match retry_after(resp, SystemTime::now()) {
+            Some(wait) if wait > MAX_RETRY_DELAY => None,
+            Some(wait) => Some(wait),
+            None => Some(self.backoff(throttled)),
+        }
+    }
+
+    /// How long to wait before retrying after `result`, the `attempt`th retry (from 0).
+    fn retry_wait(
+        &self,
+        result: &Result<http::Response<ureq::Body>, ureq::Error>,
+        attempt: u32,
+    ) -> Duration {
+        match result {
+            Ok(resp) => retry_after(resp, SystemTime::now())
+                .map_or_else(|| self.backoff(attempt), |d| d.min(MAX_RETRY_DELAY)),
+            Err(_) => self.backoff(attempt),
+        }
+    }
+
+    /// One attempt at a request. The outer error is a request that could not be built, the
+    /// inner one a transport failure.
+    fn send_once(
+        &self,
+        method: &http::Method,
+        url: &str,
+        body: Option<&[u8]>,
+    ) -> Result<Result<http::Response<ureq::Body>, ureq::Error>> {
+        let info = RequestInfo {
+            method: method.as_str(),
+            url,
+            correlation_id: &self.correlation_id,
+        };
+        let mut request = http::Request::builder()
+            .method(method.clone())
+            .uri(url)
             .header(http::header::AUTHORIZATION, &self.bearer)
-            .header(http::header::USER_AGENT, USER_AGENT_VALUE)
-            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_JSON)
-            .send(&bytes)
-            .context("request failed")?;
-        handle_response(resp).with_context(|| format!("PUT {path}"))
+            .header(http::header::USER_AGENT, &self.user_agent)
+            .header(CORRELATION_ID_HEADER, &self.correlation_id);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        for hooks in &self.hooks {
+            let mut headers = Vec::new();
+            hooks.on_request(&info, &mut headers);
+            for (name, value) in headers {
+                check_header(&name, &value).context("invalid header from request hooks")?;
+                request = request.header(name, value);
+            }
+        }
+        #[cfg(feature = "otel")]
+        let span = crate::otel::HttpSpan::start();
+        #[cfg(feature = "otel")]
+        let request = match &span {
+            Some(span) => request.header("traceparent", span.traceparent()),
+            None => request,
+        };
+        let started = Instant::now();
+        let request = match body {
+            Some(_) => request.header(http::header::CONTENT_TYPE, CONTENT_TYPE_JSON),
+            None => request,
+        }
+        .body(())
+        .context("invalid request")?;
+        if self.curl {
+            self.log(curl_command(&request, body));
+        }
+        let result = self.transport.run(request, body);
+        #[cfg(feature = "otel")]
+        if let Some(span) = span {
+            let outcome = match &result {
+                Ok(resp) => Ok(resp.status().as_u16()),
+                Err(e) => Err(e.to_string()),
+            };
+            span.end(method.as_str(), url, outcome);
+        }
+        if !self.hooks.is_empty() {
+            let error = result.as_ref().err().map(ToString::to_string);
+            let response = ResponseInfo {
+                status: match &result {
+                    Ok(resp) => Ok(resp.status().as_u16()),
+                    Err(_) => Err(error.as_deref().unwrap_or_default()),
+                },
+                elapsed: started.elapsed(),
+            };
+            for hooks in &self.hooks {
+                hooks.on_response(&info, &response);
+            }
+        }
+        if self.verbose {
+            let outcome = match &result {
+                Ok(resp) => resp.status().to_string(),
+                Err(e) => format!("failed ({e})"),
+            };
+            self.log(format!(
+                "md: {method} {url} -> {outcome} in {} ms [correlation id {}]",
+                started.elapsed().as_millis(),
+                self.correlation_id
+            ));
+        }
+        Ok(result)
+    }
+
+    /// Send a request to an arbitrary API path such as `/v1/active_accounts`.
+    /// `GET` and `DELETE` take no body; `POST`, `PUT`, and `PATCH` require one.
+    pub fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value> {
+        anyhow::ensure!(path.starts_with('/'), "path must start with '/': {path}");
+        match (method.to_ascii_uppercase().as_str(), body) {
+            ("GET", None) => self.get(path),
+            ("DELETE", None) => self.delete(path),
+            ("POST", Some(body)) => self.post_json(path, body),
+            ("PUT", Some(body)) => self.put_json(path, body),
+            // No endpoint md wraps takes `PATCH` (instances take a full `PUT`).
+            ("PATCH", Some(body)) => self.send_json(http::Method::PATCH, path, body),
+            ("GET" | "DELETE", Some(_)) => bail!("{method} requests do not take a body"),
+            ("POST" | "PUT" | "PATCH", None) => bail!("{method} requests require a body"),
+            (other, _) => bail!("unsupported HTTP method: {other}"),
+        }
+    }
+
+    fn handle_response(&self, resp: http::Response<ureq::Body>) -> Result<Value> {
+        let status = resp.status().as_u16();
+        let bytes = read_body(resp.into_body(), self.max_response_bytes)?;
+        let (text, warning) = decode_body(status, bytes);
+        self.warnings.lock().unwrap().extend(warning);
+        parse_response(status, text)
     }
 
     // -- Users --
@@ -153,6 +733,12 @@ impl MotherduckClient {
         self.delete(&user_path(username))
     }
 
+    /// Whether `username` exists. There is no `GET` for a single user, so this lists
+    /// the user's tokens (a small response) and treats a 404 as absent.
+    pub fn user_exists(&self, username: &str) -> Result<bool> {
+        found(self.list_tokens(username))
+    }
+
     // -- Tokens --
 
     pub fn list_tokens(&self, username: &str) -> Result<Value> {
@@ -180,6 +766,17 @@ impl MotherduckClient {
         self.delete(&user_token_path(username, token_id))
     }
 
+    /// Whether `username` has a token with id `token_id`. A missing user counts as absent.
+    pub fn token_exists(&self, username: &str, token_id: &str) -> Result<bool> {
+        let tokens = match self.list_tokens(username) {
+            Ok(tokens) => tokens,
+            Err(e) => return found(Err(e)),
+        };
+        Ok(tokens["tokens"]
+            .as_array()
+            .is_some_and(|list| list.iter().any(|t| t["id"] == token_id)))
+    }
+
     // -- Ducklings --
 
     pub fn get_duckling_config(&self, username: &str) -> Result<Value> {
@@ -193,34 +790,216 @@ impl MotherduckClient {
         rs_size: &str,
         rs_flock_size: u32,
     ) -> Result<Value> {
-        self.put_json(
-            &user_instances_path(username),
+        self.put_duckling_config(
+            username,
             &json!({
-                "config": {
-                    "read_write": { "instance_size": rw_size },
-                    "read_scaling": { "instance_size": rs_size, "flock_size": rs_flock_size }
-                }
+                "read_write": { "instance_size": rw_size },
+                "read_scaling": { "instance_size": rs_size, "flock_size": rs_flock_size }
             }),
         )
     }
 
+    /// Replace the duckling config with `config` as given, e.g. one read with
+    /// [`get_duckling_config`](Self::get_duckling_config) and edited, so fields this client
+    /// does not know about are sent back unchanged.
+    pub fn put_duckling_config(&self, username: &str, config: &Value) -> Result<Value> {
+        self.put_json(&user_instances_path(username), &json!({ "config": config }))
+    }
+
     // -- Accounts --
 
     pub fn list_active_accounts(&self) -> Result<Value> {
         self.get(&active_accounts_path())
     }
+
+    /// [`list_tokens`](Self::list_tokens) for each of `usernames`, in the same order.
+    pub fn list_tokens_bulk(&self, usernames: &[String]) -> Vec<Result<Value>> {
+        self.per_user(usernames, |u| self.list_tokens(u))
+    }
+
+    /// [`get_duckling_config`](Self::get_duckling_config) for each of `usernames`, in the
+    /// same order.
+    pub fn get_duckling_configs(&self, usernames: &[String]) -> Vec<Result<Value>> {
+        self.per_user(usernames, |u| self.get_duckling_config(u))
+    }
+
+    /// Run `f` for each user, [`BULK_WORKERS`] at a time, keeping input order. The API has
+    /// no batch endpoints yet; when it does, bulk methods should send chunks of users to
+    /// them here instead of one request each.
+    fn per_user<T: Send>(
+        &self,
+        usernames: &[String],
+        f: impl Fn(&str) -> Result<T> + Sync,
+    ) -> Vec<Result<T>> {
+        let chunk = usernames.len().div_ceil(BULK_WORKERS).max(1);
+        std::thread::scope(|s| {
+            let handles: Vec<_> = usernames
+                .chunks(chunk)
+                .map(|names| s.spawn(|| names.iter().map(|u| f(u)).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("bulk request worker panicked"))
+                .collect()
+        })
+    }
+
+    /// Like [`list_active_accounts`](Self::list_active_accounts), but call `f` with each
+    /// account as it is parsed instead of buffering the whole response.
+    pub fn for_each_active_account(&self, f: impl FnMut(Value) -> Result<()>) -> Result<()> {
+        let path = active_accounts_path();
+        let mut resp = self.send_get(&path)?;
+        if !SUCCESS_STATUS.contains(&resp.status().as_u16()) {
+            return self
+                .handle_response(resp)
+                .map(drop)
+                .with_context(|| format!("GET {path}"));
+        }
+        let reader = resp.body_mut().as_reader();
+        crate::stream::for_each_in_array(reader, "accounts", f)
+            .with_context(|| format!("GET {path}"))
+    }
+}
+
+/// The kind of failure `result` is, or `None` if it is not one to retry: a response other
+/// than a 5xx, or a failure after a request that is not safe to send twice was sent.
+fn retry_class(
+    method: &http::Method,
+    result: &Result<http::Response<ureq::Body>, ureq::Error>,
+) -> Option<RetryOn> {
+    let idempotent = RETRIED_METHODS.contains(method);
+    match result {
+        Err(e) if not_sent(e) => Some(RetryOn::Connect),
+        Err(_) if idempotent => Some(RetryOn::Timeout),
+        Ok(resp) if idempotent && resp.status().is_server_error() => Some(RetryOn::ServerError),
+        _ => None,
+    }
 }
 
-fn handle_response(mut resp: http::Response<ureq::Body>) -> Result<Value> {
-    let status = resp.status().as_u16();
-    let text = resp
-        .body_mut()
-        .read_to_string()
+/// Whether `error` happened before the request reached the API, so that sending it again
+/// cannot repeat its effect.
+fn not_sent(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::HostNotFound | ureq::Error::ConnectionFailed => true,
+        ureq::Error::Timeout(timeout) => {
+            matches!(timeout, ureq::Timeout::Resolve | ureq::Timeout::Connect)
+        }
+        ureq::Error::Io(e) => e.kind() == std::io::ErrorKind::ConnectionRefused,
+        _ => false,
+    }
+}
+
+/// `request` as a `curl` command line. The token is left to the shell as
+/// `$MOTHERDUCK_TOKEN`, and the user agent to curl.
+fn curl_command(request: &http::Request<()>, body: Option<&[u8]>) -> String {
+    let quote = |s: &str| shlex::try_quote(s).map_or_else(|_| format!("'{s}'"), Into::into);
+    let mut command = String::from("curl");
+    if request.method() != http::Method::GET {
+        command.push_str(&format!(" -X {}", request.method()));
+    }
+    command.push_str(&format!(" {}", quote(&request.uri().to_string())));
+    for (name, value) in request.headers() {
+        if name == http::header::USER_AGENT {
+            continue;
+        }
+        if name == http::header::AUTHORIZATION {
+            command.push_str(r#" -H "Authorization: Bearer $MOTHERDUCK_TOKEN""#);
+            continue;
+        }
+        let value = String::from_utf8_lossy(value.as_bytes());
+        command.push_str(&format!(" -H {}", quote(&format!("{name}: {value}"))));
+    }
+    if let Some(body) = body {
+        command.push_str(&format!(
+            " --data-raw {}",
+            quote(&String::from_utf8_lossy(body))
+        ));
+    }
+    command
+}
+
+/// The wait `resp`'s `Retry-After` header asks for at `now`: a number of seconds or an
+/// HTTP date (zero once it has passed).
+fn retry_after<B>(resp: &http::Response<B>, now: SystemTime) -> Option<Duration> {
+    let value = resp
+        .headers()
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = SystemTime::from(chrono::DateTime::parse_from_rfc2822(value).ok()?);
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// A random wait between half of `delay` and all of it.
+fn jitter(delay: Duration) -> Duration {
+    let half = delay / 2;
+    let spread = u64::try_from(half.as_nanos()).unwrap_or(u64::MAX);
+    let random = RandomState::new().hash_one(Instant::now());
+    half + Duration::from_nanos(random % spread.saturating_add(1))
+}
+
+/// A random UUID-formatted (version 4) ID, unique per client.
+pub fn new_correlation_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let state = RandomState::new();
+    let hi = state.hash_one((nanos, std::process::id()));
+    let lo = state.hash_one(hi);
+    let (hi, lo) = ((hi & !0xf000) | 0x4000, (lo & !(0b11 << 62)) | (0b10 << 62));
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        hi >> 32,
+        (hi >> 16) & 0xffff,
+        hi & 0xffff,
+        lo >> 48,
+        lo & 0xffff_ffff_ffff
+    )
+}
+
+/// Read a body, failing once it grows past `limit` bytes rather than reading unbounded
+/// data. A `Content-Length` over the limit fails before reading.
+fn read_body(body: ureq::Body, limit: u64) -> Result<Vec<u8>> {
+    let too_large = || {
+        anyhow!(
+            "response body exceeds the {limit}-byte limit (raise max_response_size in the config)"
+        )
+    };
+    if body.content_length().is_some_and(|len| len > limit) {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::new();
+    body.into_reader()
+        .take(limit + 1)
+        .read_to_end(&mut bytes)
         .context("failed to read response body")?;
-    parse_response(status, text)
+    if bytes.len() as u64 > limit {
+        return Err(too_large());
+    }
+    Ok(bytes)
 }
 
-fn parse_response(status: u16, text: String) -> Result<Value> {
+/// Decode a body as UTF-8, replacing invalid sequences (with a warning) rather than failing:
+/// proxies sometimes garble error bodies, and the status is still worth reporting.
+fn decode_body(status: u16, bytes: Vec<u8>) -> (String, Option<String>) {
+    match String::from_utf8(bytes) {
+        Ok(text) => (text, None),
+        Err(e) => (
+            String::from_utf8_lossy(e.as_bytes()).into_owned(),
+            Some(format!(
+                "HTTP {status} response body is not valid UTF-8; invalid bytes replaced"
+            )),
+        ),
+    }
+}
+
+/// Interpret a response the way the client does: JSON bodies are parsed, non-JSON success
+/// bodies become a JSON string, and non-2xx statuses become errors carrying the API message.
+pub fn parse_response(status: u16, text: String) -> Result<Value> {
     match serde_json::from_str::<Value>(&text) {
         Ok(body) if SUCCESS_STATUS.contains(&status) => Ok(body),
         Ok(body) => {
@@ -228,16 +1007,88 @@ fn parse_response(status: u16, text: String) -> Result<Value> {
                 .get("message")
                 .and_then(|m| m.as_str())
                 .unwrap_or(&text);
-            bail!("API error ({status}): {message}");
+            bail!(ApiError {
+                status,
+                message: message.to_string(),
+            });
         }
         Err(_) if SUCCESS_STATUS.contains(&status) => Ok(Value::String(text)),
-        Err(_) => bail!("API error ({status}): {text}"),
+        Err(_) => bail!(ApiError {
+            status,
+            message: text
+        }),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn jitter_stays_within_half_to_full_delay() {
+        let delay = Duration::from_millis(400);
+        for _ in 0..100 {
+            let wait = jitter(delay);
+            assert!((delay / 2..=delay).contains(&wait), "{wait:?}");
+        }
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn curl_command_hides_the_token_and_quotes_for_the_shell() {
+        let request = http::Request::builder()
+            .method(http::Method::POST)
+            .uri("https://api.motherduck.com/v1/users/svc_etl/tokens")
+            .header(http::header::AUTHORIZATION, "Bearer secret")
+            .header(http::header::USER_AGENT, "dkdc-md-cli/1")
+            .header(CORRELATION_ID_HEADER, "abc")
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_JSON)
+            .body(())
+            .unwrap();
+        assert_eq!(
+            curl_command(&request, Some(br#"{"name":"it's"}"#)),
+            r#"curl -X POST https://api.motherduck.com/v1/users/svc_etl/tokens -H "Authorization: Bearer $MOTHERDUCK_TOKEN" -H 'x-correlation-id: abc' -H 'content-type: application/json' --data-raw "{\"name\":\"it's\"}""#
+        );
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_http_dates() {
+        let with = |value: &str| {
+            http::Response::builder()
+                .header(http::header::RETRY_AFTER, value)
+                .body(())
+                .unwrap()
+        };
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_470);
+        assert_eq!(
+            retry_after(&with("120"), now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(&with("Wed, 21 Oct 2015 07:28:00 GMT"), now),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            retry_after(&with("Wed, 21 Oct 2015 07:27:00 GMT"), now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&with("soon"), now), None);
+        assert_eq!(retry_after(&http::Response::new(()), now), None);
+    }
+
+    #[test]
+    fn only_unsent_requests_count_as_not_sent() {
+        assert!(not_sent(&ureq::Error::ConnectionFailed));
+        assert!(not_sent(&ureq::Error::Timeout(ureq::Timeout::Connect)));
+        assert!(!not_sent(&ureq::Error::Timeout(
+            ureq::Timeout::RecvResponse
+        )));
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert!(not_sent(&ureq::Error::Io(refused)));
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(!not_sent(&ureq::Error::Io(reset)));
+    }
 
     #[test]
     fn encode_path_preserves_safe_chars() {
@@ -249,6 +1100,20 @@ mod tests {
         assert_eq!(encode_path("a/b?c#d e"), "a%2Fb%3Fc%23d%20e");
     }
 
+    #[test]
+    fn garbled_error_body_keeps_status() {
+        let (text, warning) = decode_body(502, b"bad \xff\xfe gateway".to_vec());
+        assert_eq!(text, "bad \u{fffd}\u{fffd} gateway");
+        assert!(
+            warning
+                .unwrap()
+                .starts_with("HTTP 502 response body is not valid UTF-8")
+        );
+        let err = parse_response(502, text).unwrap_err();
+        assert_eq!(err.downcast_ref::<ApiError>().unwrap().status, 502);
+        assert_eq!(decode_body(200, b"{}".to_vec()), ("{}".into(), None));
+    }
+
     #[test]
     fn parse_response_success_json() {
         let result = parse_response(200, r#"{"username": "svc_test"}"#.into()).unwrap();
@@ -268,6 +1133,28 @@ mod tests {
         assert!(err.to_string().contains("user not found"));
     }
 
+    #[test]
+    fn parse_response_error_downcasts_to_api_error() {
+        let err = parse_response(404, r#"{"message": "user not found"}"#.into())
+            .context("GET /v1/users/x/tokens")
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ApiError>(),
+            Some(&ApiError {
+                status: 404,
+                message: "user not found".into()
+            })
+        );
+    }
+
+    #[test]
+    fn found_maps_only_404_to_false() {
+        let not_found = parse_response(404, "{}".into());
+        assert!(!found(not_found).unwrap());
+        assert!(found(Ok(json!({}))).unwrap());
+        assert!(found(parse_response(500, "boom".into())).is_err());
+    }
+
     #[test]
     fn parse_response_error_non_json() {
         let err = parse_response(500, "Internal Server Error".into()).unwrap_err();
@@ -318,6 +1205,75 @@ mod tests {
         assert!(!debug.contains("secret-token"));
     }
 
+    #[test]
+    fn api_version_rewrites_v1_prefix() {
+        let client = MotherduckClient::builder("tok")
+            .base_url("http://localhost")
+            .api_version("v2")
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.url(&user_tokens_path("svc")),
+            "http://localhost/v2/users/svc/tokens"
+        );
+        assert_eq!(client.url("/v10/users"), "http://localhost/v10/users");
+        for bad in ["2", "v"] {
+            let builder = MotherduckClient::builder("tok").api_version(bad);
+            assert!(builder.build().is_err());
+        }
+    }
+
+    #[test]
+    fn correlation_ids_are_uuid_shaped_and_unique() {
+        let id = new_correlation_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(id.as_bytes()[14], b'4');
+        assert!(matches!(id.as_bytes()[19], b'8' | b'9' | b'a' | b'b'));
+        assert_ne!(id, new_correlation_id());
+    }
+
+    #[test]
+    fn correlation_id_is_validated() {
+        let client = MotherduckClient::new("tok").unwrap();
+        assert_eq!(client.correlation_id().len(), 36);
+        let client = MotherduckClient::builder("tok")
+            .correlation_id("trace-abc.123")
+            .build()
+            .unwrap();
+        assert_eq!(client.correlation_id(), "trace-abc.123");
+        for bad in ["", "has space", "new\nline", &"x".repeat(129)] {
+            let builder = MotherduckClient::builder("tok").correlation_id(bad);
+            assert!(builder.build().is_err());
+        }
+    }
+
+    #[test]
+    fn base_url_trims_trailing_slash() {
+        let client = MotherduckClient::builder("tok")
+            .base_url("http://localhost:8080/")
+            .build()
+            .unwrap();
+        assert_eq!(client.base_url, "http://localhost:8080");
+    }
+
+    #[test]
+    fn builder_validates_headers_proxy_and_user_agent() {
+        let client = MotherduckClient::builder("tok")
+            .user_agent_suffix("airflow-dag=provision")
+            .header("x-tenant", "acme")
+            .proxy("http://proxy:3128")
+            .build()
+            .unwrap();
+        assert!(client.user_agent.ends_with(" airflow-dag=provision"));
+        let builder = || MotherduckClient::builder("tok");
+        assert!(builder().header("Authorization", "x").build().is_err());
+        assert!(builder().header("bad header", "x").build().is_err());
+        assert!(builder().header("x-ok", "new\nline").build().is_err());
+        assert!(builder().proxy("ftp://proxy").build().is_err());
+        assert!(builder().user_agent_suffix("").build().is_err());
+        assert!(builder().user_agent_suffix("tab\there").build().is_err());
+    }
+
     #[test]
     fn parse_response_boundary_status_299() {
         let result = parse_response(299, r#"{"ok": true}"#.into()).unwrap();
@@ -330,8 +1286,84 @@ mod tests {
         assert!(err.to_string().contains("300"));
     }
 
+    #[test]
+    fn call_rejects_relative_path() {
+        let client = MotherduckClient::new("tok").unwrap();
+        let err = client.call("GET", "v1/users", None).unwrap_err();
+        assert!(err.to_string().contains("must start with '/'"));
+    }
+
+    #[test]
+    fn call_rejects_body_mismatch() {
+        let client = MotherduckClient::new("tok").unwrap();
+        let err = client
+            .call("get", "/v1/users", Some(&json!({})))
+            .unwrap_err();
+        assert!(err.to_string().contains("do not take a body"));
+        let err = client.call("PUT", "/v1/users", None).unwrap_err();
+        assert!(err.to_string().contains("require a body"));
+        let err = client.call("patch", "/v1/users", None).unwrap_err();
+        assert!(err.to_string().contains("require a body"));
+        let err = client.call("TRACE", "/v1/users", None).unwrap_err();
+        assert!(err.to_string().contains("unsupported HTTP method"));
+    }
+
     #[test]
     fn encode_path_percent_encodes_percent() {
         assert_eq!(encode_path("100%done"), "100%25done");
     }
+
+    // -- property tests --
+
+    /// Arbitrary JSON up to a few levels deep.
+    fn arb_json() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            ".*".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+                prop::collection::hash_map(".*", inner, 0..8)
+                    .prop_map(|m| Value::Object(m.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn parse_response_never_panics(status in any::<u16>(), text in ".*") {
+            let _ = parse_response(status, text);
+        }
+
+        #[test]
+        fn parse_response_success_round_trips_json(value in arb_json()) {
+            let parsed = parse_response(200, value.to_string()).unwrap();
+            prop_assert_eq!(parsed, value);
+        }
+
+        #[test]
+        fn parse_response_error_always_reports_status(status in 300u16..1000, text in ".*") {
+            let err = parse_response(status, text).unwrap_err();
+            let prefix = format!("API error ({status})");
+            prop_assert!(err.to_string().starts_with(&prefix));
+        }
+
+        #[test]
+        fn parse_response_handles_deep_nesting(depth in 0usize..2048) {
+            let text = format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+            let _ = parse_response(200, text.clone());
+            let _ = parse_response(500, text);
+        }
+
+        #[test]
+        fn encode_path_round_trips(s in ".*") {
+            let encoded = encode_path(&s);
+            prop_assert!(!encoded.contains(['/', '?', '#', ' ']));
+            let decoded = percent_encoding::percent_decode_str(&encoded).decode_utf8().unwrap();
+            prop_assert_eq!(decoded, s);
+        }
+    }
 }
@@ -0,0 +1,717 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+
+/// Env var pointing at an alternate config file.
+pub const CONFIG_ENV: &str = "MD_CONFIG";
+
+/// User configuration from `config.toml`. Every field is optional; a missing file is
+/// the same as an empty one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Which operations prompt for confirmation.
+    pub confirm: ConfirmLevel,
+    /// Largest response body to buffer, e.g. `"50MiB"` (stored as bytes).
+    #[serde(deserialize_with = "de_size_bytes")]
+    pub max_response_size: Option<u64>,
+    pub retries: Retries,
+    pub policy: Policy,
+    /// Named account shapes for `md provision --template <name>` (`[templates.<name>]`).
+    pub templates: BTreeMap<String, Template>,
+    pub approval: Approval,
+    pub bundles: Bundles,
+    /// Values for options left off the command line (`[defaults]`).
+    pub defaults: Defaults,
+    /// Default `--output` per command, e.g. `token.list = "json"` (`[output]`), keyed by
+    /// command path (`"token list"`).
+    #[serde(deserialize_with = "de_command_table")]
+    pub output: BTreeMap<String, String>,
+    pub hooks: Hooks,
+    /// Profile used when `--profile` is not given (`md profile use`).
+    pub profile: Option<String>,
+    /// Settings per organization (`[profiles.<name>]`).
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings for one organization, chosen with `--profile <name>` or `profile = "<name>"`.
+/// Command-line flags win over these.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Env var holding the org's token, e.g. `"MD_TOKEN_ACME"`.
+    pub token_env: Option<String>,
+    /// Command printing the org's token, e.g. `"op read op://ops/md-acme/token"`.
+    pub token_command: Option<String>,
+    /// API base URL, e.g. for an org reached through a proxy.
+    pub base_url: Option<String>,
+    /// Output format for commands without an `[output]` entry; wins over `[defaults]`.
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    /// Token TTL by token type, used when `--ttl` is omitted (`[defaults.ttl]`).
+    pub ttl: TtlDefaults,
+    /// Output format for commands without an `[output]` entry, e.g. `"json"`.
+    pub output: Option<String>,
+    /// Answer confirmation prompts as if `--yes` were passed.
+    pub yes: bool,
+    /// HTTP request timeout when `--request-timeout` is omitted, e.g. `"30s"` (stored as
+    /// seconds).
+    #[serde(deserialize_with = "de_duration_secs")]
+    pub request_timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtlDefaults {
+    /// e.g. `"90d"` (stored as seconds).
+    #[serde(deserialize_with = "de_duration_secs")]
+    pub read_write: Option<u64>,
+    /// e.g. `"7d"` (stored as seconds).
+    #[serde(deserialize_with = "de_duration_secs")]
+    pub read_scaling: Option<u64>,
+}
+
+/// How often to retry each kind of failed request (`[retries]`) when `--retries` is not
+/// given. Unset kinds are not retried.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retries {
+    /// Host not found or connection failed: the request was not sent, so every method is
+    /// retried.
+    pub connect: u32,
+    /// Timed out or disconnected after sending; reads, updates, and deletes only.
+    pub timeout: u32,
+    /// 5xx responses; reads, updates, and deletes only.
+    pub server_error: u32,
+}
+
+/// Two-person approval for destructive commands (`[approval]`).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Approval {
+    /// Write a change request instead of running destructive commands directly.
+    pub required: bool,
+    /// Public keys (`md signing-key show`) allowed to approve change requests.
+    pub approvers: Vec<String>,
+}
+
+/// Programs run around mutating commands (`[hooks]`). A path with a directory part is
+/// relative to the config file's directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Run before the command; a non-zero exit stops it.
+    pub pre: Option<PathBuf>,
+    /// Run after the command, with its outcome in `MD_STATUS`.
+    pub post: Option<PathBuf>,
+}
+
+/// Signed command bundles (`[bundles]`).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bundles {
+    /// Public keys (`md signing-key show`) whose bundles `md bundle execute` runs.
+    pub trusted_signers: Vec<String>,
+}
+
+/// Confirmation strictness (`confirm = "..."` in the config).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmLevel {
+    /// Prompt before every change, including creates and config updates.
+    Always,
+    /// Prompt before deletes and other destructive operations.
+    #[default]
+    Destructive,
+    /// Never prompt (as if `--force` were always passed).
+    Never,
+}
+
+/// Guardrails enforced locally before any API call.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Refuse to create tokens without `--ttl`.
+    pub require_ttl: bool,
+    /// Longest TTL `token create` may request, e.g. `"90d"` (stored as seconds).
+    #[serde(deserialize_with = "de_duration_secs")]
+    pub max_ttl: Option<u64>,
+    /// Clamp TTLs above `max_ttl` (with a warning) instead of refusing them.
+    pub clamp_ttl: bool,
+    /// Regex new service account usernames must match in full, e.g. `"svc_[a-z0-9_]+"`.
+    pub username_pattern: Option<String>,
+    /// Honor `--assume-yes-when-piped`. Set to `false` to make every unattended destructive
+    /// run pass `--yes` explicitly.
+    pub allow_assume_yes_when_piped: bool,
+}
+
+/// Defaults for `md provision --template`; options given on the command line win.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Template {
+    pub token_name: Option<String>,
+    /// Token TTL, e.g. `"90d"` (stored as seconds).
+    #[serde(deserialize_with = "de_duration_secs")]
+    pub ttl: Option<u64>,
+    /// `read-write` or `read-scaling`.
+    pub token_type: Option<String>,
+    /// Read-write instance size, e.g. `standard`.
+    pub rw_size: Option<String>,
+    /// Read-scaling instance size.
+    pub rs_size: Option<String>,
+    pub flock_size: Option<u32>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            require_ttl: false,
+            max_ttl: None,
+            clamp_ttl: false,
+            username_pattern: None,
+            allow_assume_yes_when_piped: true,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from `path`, or defaults if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                Self::parse(&text).with_context(|| format!("invalid config {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read config {}", path.display())),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        for (name, profile) in &config.profiles {
+            anyhow::ensure!(
+                profile.token_env.is_none() || profile.token_command.is_none(),
+                "profile '{name}' sets both token_env and token_command"
+            );
+        }
+        if let Some(name) = &config.profile {
+            config.named_profile(name)?;
+        }
+        Ok(config)
+    }
+
+    /// The profile named by `--profile`, else the one `profile` selects, if either is set.
+    pub fn active_profile<'a>(
+        &'a self,
+        flag: Option<&'a str>,
+    ) -> Result<Option<(&'a str, &'a Profile)>> {
+        match flag.or(self.profile.as_deref()) {
+            Some(name) => Ok(Some((name, self.named_profile(name)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn named_profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).with_context(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                format!("unknown profile '{name}' (no [profiles] in the config)")
+            } else {
+                format!("unknown profile '{name}' (expected {})", known.join(", "))
+            }
+        })
+    }
+}
+
+/// `md config get`: the value at dotted `key` (e.g. `defaults.output`) in config `text`,
+/// flattened into `(key, value)` entries if it is a table; empty if it is not set.
+pub fn get(text: &str, key: &str) -> Result<Vec<(String, toml::Value)>> {
+    let table: toml::Table = toml::from_str(text)?;
+    let mut value = toml::Value::Table(table);
+    for part in key.split('.') {
+        match value {
+            toml::Value::Table(mut table) => match table.remove(part) {
+                Some(v) => value = v,
+                None => return Ok(Vec::new()),
+            },
+            _ => return Ok(Vec::new()),
+        }
+    }
+    let mut entries = Vec::new();
+    flatten(key, value, &mut entries);
+    Ok(entries)
+}
+
+/// `md config list`: every value set in config `text` as `(dotted key, value)`.
+pub fn entries(text: &str) -> Result<Vec<(String, toml::Value)>> {
+    let table: toml::Table = toml::from_str(text)?;
+    let mut entries = Vec::new();
+    flatten("", toml::Value::Table(table), &mut entries);
+    Ok(entries)
+}
+
+fn flatten(prefix: &str, value: toml::Value, into: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let path = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&path, value, into);
+            }
+        }
+        value => into.push((prefix.to_string(), value)),
+    }
+}
+
+/// `md config set`: config `text` with dotted `key` set to `value`, keeping comments and
+/// layout. `value` is read as TOML (`true`, `5`, `"x"`), else taken as a string (`json`,
+/// `90d`). Fails if the result is not a valid config, so a typo never gets written.
+pub fn set(text: &str, key: &str, value: &str) -> Result<String> {
+    let mut doc: toml_edit::DocumentMut = text.parse()?;
+    let parts: Vec<&str> = key.split('.').collect();
+    anyhow::ensure!(
+        parts.iter().all(|p| !p.is_empty()),
+        "invalid key '{key}' (expected e.g. defaults.output)"
+    );
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+    let (last, tables) = parts.split_last().expect("split yields at least one part");
+    let mut item = doc.as_item_mut();
+    for (i, part) in tables.iter().enumerate() {
+        let table = item
+            .as_table_like_mut()
+            .with_context(|| format!("'{}' is not a table", parts[..i].join(".")))?;
+        if table.get(part).is_none() {
+            let mut new = toml_edit::Table::new();
+            new.set_implicit(true);
+            table.insert(part, toml_edit::Item::Table(new));
+        }
+        item = table.get_mut(part).expect("inserted above");
+    }
+    let table = item
+        .as_table_like_mut()
+        .with_context(|| format!("'{}' is not a table", tables.join(".")))?;
+    match table.get_mut(last).and_then(|i| i.as_value_mut()) {
+        // Keep the existing value's surrounding whitespace and trailing comment.
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = value;
+            *existing.decor_mut() = decor;
+        }
+        None => {
+            table.insert(last, toml_edit::value(value));
+        }
+    }
+    let text = doc.to_string();
+    Config::parse(&text).with_context(|| format!("cannot set {key}"))?;
+    Ok(text)
+}
+
+/// Default config location: `$XDG_CONFIG_HOME/md/config.toml`, falling back to
+/// `~/.config/md/config.toml` (`%APPDATA%\md\config.toml` on Windows).
+pub fn default_path() -> Option<PathBuf> {
+    default_path_with(|k| std::env::var(k))
+}
+
+fn default_path_with(
+    env_var: impl Fn(&str) -> Result<String, std::env::VarError>,
+) -> Option<PathBuf> {
+    let non_empty = |k: &str| env_var(k).ok().filter(|v| !v.is_empty());
+    let base = non_empty("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("HOME").map(|h| Path::new(&h).join(".config")))
+        .or_else(|| non_empty("APPDATA").map(PathBuf::from))?;
+    Some(base.join("md").join("config.toml"))
+}
+
+/// Parse a human duration such as `"90d"` or `"12h"` into whole seconds.
+pub fn parse_duration_secs(s: &str) -> Result<u64> {
+    let d = humantime::parse_duration(s.trim())
+        .with_context(|| format!("invalid duration '{s}' (expected e.g. 30m, 12h, 90d)"))?;
+    Ok(d.as_secs())
+}
+
+/// Parse a byte size such as `"512KB"` or `"50MiB"` into bytes. A bare number is bytes;
+/// `KB`/`MB`/`GB` are decimal and `KiB`/`MiB`/`GiB` binary.
+pub fn parse_size_bytes(s: &str) -> Result<u64> {
+    const UNITS: &[(&str, u64)] = &[
+        ("KiB", 1 << 10),
+        ("MiB", 1 << 20),
+        ("GiB", 1 << 30),
+        ("KB", 1_000),
+        ("MB", 1_000_000),
+        ("GB", 1_000_000_000),
+        ("B", 1),
+    ];
+    let s = s.trim();
+    let invalid = || format!("invalid size '{s}' (expected e.g. 512KB, 50MiB)");
+    let (digits, unit) = UNITS
+        .iter()
+        .find_map(|(suffix, n)| Some((s.strip_suffix(suffix)?, *n)))
+        .unwrap_or((s, 1));
+    let n: u64 = digits.trim().parse().with_context(invalid)?;
+    n.checked_mul(unit).with_context(invalid)
+}
+
+fn de_size_bytes<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    let s = String::deserialize(d)?;
+    parse_size_bytes(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn de_duration_secs<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    let s = String::deserialize(d)?;
+    parse_duration_secs(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Flatten nested tables of strings (`token.list = "json"`) into space-separated command
+/// paths (`"token list"`).
+fn de_command_table<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<String, String>, D::Error> {
+    fn flatten(
+        prefix: &str,
+        table: toml::Table,
+        into: &mut BTreeMap<String, String>,
+    ) -> Result<(), String> {
+        for (key, value) in table {
+            let path = if prefix.is_empty() {
+                key
+            } else {
+                format!("{prefix} {key}")
+            };
+            match value {
+                toml::Value::String(s) => {
+                    into.insert(path, s);
+                }
+                toml::Value::Table(table) => flatten(&path, table, into)?,
+                other => {
+                    return Err(format!(
+                        "`{path}`: expected a string, found {}",
+                        other.type_str()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    let mut paths = BTreeMap::new();
+    flatten("", toml::Table::deserialize(d)?, &mut paths).map_err(serde::de::Error::custom)?;
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_with<'a>(
+        vars: &'a [(&'a str, &'a str)],
+    ) -> impl Fn(&str) -> Result<String, std::env::VarError> + 'a {
+        move |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+                .ok_or(std::env::VarError::NotPresent)
+        }
+    }
+
+    #[test]
+    fn empty_config_is_default() {
+        let config = Config::parse("").unwrap();
+        assert!(!config.policy.require_ttl);
+        assert!(config.policy.max_ttl.is_none());
+    }
+
+    #[test]
+    fn parses_policy() {
+        let config = Config::parse(
+            r#"
+            [policy]
+            require_ttl = true
+            max_ttl = "90d"
+            clamp_ttl = true
+            "#,
+        )
+        .unwrap();
+        assert!(config.policy.require_ttl);
+        assert_eq!(config.policy.max_ttl, Some(90 * 86400));
+        assert!(config.policy.clamp_ttl);
+    }
+
+    #[test]
+    fn parses_confirm_level() {
+        assert_eq!(
+            Config::parse("").unwrap().confirm,
+            ConfirmLevel::Destructive
+        );
+        let config = Config::parse("confirm = \"always\"").unwrap();
+        assert_eq!(config.confirm, ConfirmLevel::Always);
+        assert!(Config::parse("confirm = \"sometimes\"").is_err());
+    }
+
+    #[test]
+    fn assume_yes_when_piped_is_allowed_by_default() {
+        assert!(
+            Config::parse("")
+                .unwrap()
+                .policy
+                .allow_assume_yes_when_piped
+        );
+        let config = Config::parse("[policy]\nrequire_ttl = true").unwrap();
+        assert!(config.policy.allow_assume_yes_when_piped);
+        let config = Config::parse("[policy]\nallow_assume_yes_when_piped = false").unwrap();
+        assert!(!config.policy.allow_assume_yes_when_piped);
+    }
+
+    #[test]
+    fn parses_username_pattern() {
+        let config = Config::parse("[policy]\nusername_pattern = 'svc_[a-z]+'").unwrap();
+        assert_eq!(
+            config.policy.username_pattern.as_deref(),
+            Some("svc_[a-z]+")
+        );
+    }
+
+    #[test]
+    fn parses_templates() {
+        let config = Config::parse(
+            r#"
+            [templates.etl-worker]
+            ttl = "90d"
+            token_type = "read-write"
+            rw_size = "standard"
+            flock_size = 2
+            "#,
+        )
+        .unwrap();
+        let template = &config.templates["etl-worker"];
+        assert_eq!(template.ttl, Some(90 * 86400));
+        assert_eq!(template.rw_size.as_deref(), Some("standard"));
+        assert_eq!(template.flock_size, Some(2));
+        assert!(template.token_name.is_none());
+        assert!(
+            Config::parse(
+                "[templates.x]
+ttl_days = 3"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_approval() {
+        assert!(!Config::parse("").unwrap().approval.required);
+        let config = Config::parse("[approval]\nrequired = true\napprovers = [\"ab12\"]").unwrap();
+        assert!(config.approval.required);
+        assert_eq!(config.approval.approvers, ["ab12"]);
+    }
+
+    #[test]
+    fn parses_bundles() {
+        assert!(
+            Config::parse("")
+                .unwrap()
+                .bundles
+                .trusted_signers
+                .is_empty()
+        );
+        let config = Config::parse("[bundles]\ntrusted_signers = [\"ab12\"]").unwrap();
+        assert_eq!(config.bundles.trusted_signers, ["ab12"]);
+    }
+
+    #[test]
+    fn parses_ttl_defaults() {
+        let config = Config::parse("[defaults.ttl]\nread_scaling = \"7d\"").unwrap();
+        assert_eq!(config.defaults.ttl.read_scaling, Some(7 * 86400));
+        assert!(config.defaults.ttl.read_write.is_none());
+        assert!(Config::parse("[defaults.ttl]\nread_only = \"7d\"").is_err());
+    }
+
+    #[test]
+    fn parses_output_as_command_paths() {
+        let config = Config::parse(
+            r#"
+            [output]
+            token.list = "json"
+            "account list-active" = "psobject"
+            search = "text"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.output["token list"], "json");
+        assert_eq!(config.output["account list-active"], "psobject");
+        assert_eq!(config.output["search"], "text");
+        assert!(Config::parse("[output]\ntoken.list = 1").is_err());
+    }
+
+    #[test]
+    fn parses_general_defaults() {
+        let config =
+            Config::parse("[defaults]\noutput = \"json\"\nyes = true\nrequest_timeout = \"30s\"\n")
+                .unwrap();
+        assert_eq!(config.defaults.output.as_deref(), Some("json"));
+        assert!(config.defaults.yes);
+        assert_eq!(config.defaults.request_timeout, Some(30));
+    }
+
+    #[test]
+    fn parses_retries_per_failure() {
+        let config = Config::parse("[retries]\nconnect = 5\nserver_error = 1\n").unwrap();
+        assert_eq!(
+            (
+                config.retries.connect,
+                config.retries.timeout,
+                config.retries.server_error
+            ),
+            (5, 0, 1)
+        );
+        assert!(Config::parse("[retries]\ndns = 1\n").is_err());
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let text = "profile = \"acme\"\n\n[profiles.acme]\ntoken_env = \"MD_TOKEN_ACME\"\n\n\
+                    [profiles.labs]\nbase_url = \"http://proxy:8080\"\noutput = \"json\"\n";
+        let config = Config::parse(text).unwrap();
+        let (name, profile) = config.active_profile(None).unwrap().unwrap();
+        assert_eq!(name, "acme");
+        assert_eq!(profile.token_env.as_deref(), Some("MD_TOKEN_ACME"));
+        let (_, labs) = config.active_profile(Some("labs")).unwrap().unwrap();
+        assert_eq!(labs.base_url.as_deref(), Some("http://proxy:8080"));
+        let err = config.active_profile(Some("acm")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown profile 'acm' (expected acme, labs)"
+        );
+        assert!(
+            Config::parse("")
+                .unwrap()
+                .active_profile(None)
+                .unwrap()
+                .is_none()
+        );
+
+        let err = Config::parse("profile = \"acme\"").unwrap_err();
+        assert!(err.to_string().contains("no [profiles] in the config"));
+        let err =
+            Config::parse("[profiles.a]\ntoken_env = \"X\"\ntoken_command = \"y\"").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("sets both token_env and token_command")
+        );
+    }
+
+    #[test]
+    fn set_keeps_comments_and_validates() {
+        let text =
+            "# team settings\nconfirm = \"always\"\n\n[policy]\nrequire_ttl = true # audit\n";
+        let text = set(text, "defaults.output", "json").unwrap();
+        let text = set(&text, "defaults.yes", "true").unwrap();
+        let text = set(&text, "output.token.list", "sh").unwrap();
+        let text = set(&text, "policy.require_ttl", "false").unwrap();
+        assert!(text.starts_with("# team settings\n"));
+        assert!(text.contains("require_ttl = false # audit"));
+        let config = Config::parse(&text).unwrap();
+        assert_eq!(config.defaults.output.as_deref(), Some("json"));
+        assert!(config.defaults.yes);
+        assert_eq!(config.output["token list"], "sh");
+
+        let err = set(&text, "defaults.colour", "never").unwrap_err();
+        assert!(format!("{err:#}").contains("unknown field `colour`"));
+        assert!(set(&text, "confirm.level", "x").is_err());
+        assert!(set(&text, "defaults..yes", "x").is_err());
+    }
+
+    #[test]
+    fn gets_and_lists_dotted_keys() {
+        let text =
+            "confirm = \"never\"\n[defaults]\nyes = true\n[defaults.ttl]\nread_write = \"90d\"\n";
+        assert_eq!(
+            get(text, "defaults.yes").unwrap(),
+            [("defaults.yes".to_string(), toml::Value::Boolean(true))]
+        );
+        assert_eq!(
+            get(text, "defaults.ttl").unwrap(),
+            [(
+                "defaults.ttl.read_write".to_string(),
+                toml::Value::String("90d".into())
+            )]
+        );
+        assert!(get(text, "defaults.output").unwrap().is_empty());
+        assert!(get(text, "confirm.x").unwrap().is_empty());
+        let keys: Vec<String> = entries(text).unwrap().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, ["confirm", "defaults.ttl.read_write", "defaults.yes"]);
+    }
+
+    #[test]
+    fn rejects_bad_duration() {
+        let err = Config::parse("[policy]\nmax_ttl = \"soon\"").unwrap_err();
+        assert!(format!("{err:#}").contains("invalid duration"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::parse("[policy]\nrequire_tll = true").is_err());
+    }
+
+    #[test]
+    fn missing_file_is_default() {
+        let config = Config::load(Path::new("/nonexistent/md/config.toml")).unwrap();
+        assert!(!config.policy.require_ttl);
+    }
+
+    #[test]
+    fn default_path_prefers_xdg() {
+        let env = env_with(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/u")]);
+        assert_eq!(
+            default_path_with(env),
+            Some(PathBuf::from("/xdg/md/config.toml"))
+        );
+    }
+
+    #[test]
+    fn default_path_falls_back_to_home() {
+        let env = env_with(&[("XDG_CONFIG_HOME", ""), ("HOME", "/home/u")]);
+        assert_eq!(
+            default_path_with(env),
+            Some(PathBuf::from("/home/u/.config/md/config.toml"))
+        );
+    }
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size_bytes("4096").unwrap(), 4096);
+        assert_eq!(parse_size_bytes("512KB").unwrap(), 512_000);
+        assert_eq!(parse_size_bytes(" 50MiB ").unwrap(), 50 << 20);
+        assert_eq!(parse_size_bytes("1 GB").unwrap(), 1_000_000_000);
+        assert!(parse_size_bytes("lots").is_err());
+        assert!(parse_size_bytes("-1MB").is_err());
+        assert!(parse_size_bytes("99999999999GiB").is_err());
+    }
+
+    #[test]
+    fn parses_max_response_size() {
+        assert!(Config::parse("").unwrap().max_response_size.is_none());
+        let config = Config::parse("max_response_size = \"50MiB\"").unwrap();
+        assert_eq!(config.max_response_size, Some(50 << 20));
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration_secs("300s").unwrap(), 300);
+        assert_eq!(parse_duration_secs("12h").unwrap(), 12 * 3600);
+        assert_eq!(parse_duration_secs(" 7d ").unwrap(), 7 * 86400);
+        assert!(parse_duration_secs("7").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Points in time given on the command line (`md changes --since`, `token list
+//! --created-after`, ...): `today`, `yesterday` (local midnight), a date, an RFC 3339
+//! time, or a duration such as `7d` counted from now.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+
+/// Which way a duration counts from now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// `7d` is a week ago, e.g. for `--since`.
+    Ago,
+    /// `7d` is a week from now, e.g. for `--expires-before`.
+    Ahead,
+}
+
+pub fn parse(s: &str, now: DateTime<Utc>, direction: Direction) -> Result<DateTime<Utc>> {
+    let midnight = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .and_then(|t| t.and_local_timezone(Local).earliest())
+            .map(|t| t.to_utc())
+            .with_context(|| format!("no local midnight on {date}"))
+    };
+    let today = now.with_timezone(&Local).date_naive();
+    match s.trim() {
+        "today" => midnight(today),
+        "yesterday" => midnight(today - TimeDelta::days(1)),
+        s => {
+            if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+                return midnight(date);
+            }
+            if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+                return Ok(time.to_utc());
+            }
+            let secs = crate::config::parse_duration_secs(s).with_context(|| {
+                format!("invalid time '{s}' (expected today, yesterday, a date, or e.g. 7d)")
+            })?;
+            let delta = TimeDelta::seconds(i64::try_from(secs)?);
+            Ok(match direction {
+                Direction::Ago => now - delta,
+                Direction::Ahead => now + delta,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_times_and_durations() {
+        let now: DateTime<Utc> = "2026-06-10T12:00:00Z".parse().unwrap();
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(
+            parse("7d", now, Direction::Ago).unwrap(),
+            at("2026-06-03T12:00:00Z")
+        );
+        assert_eq!(
+            parse("7d", now, Direction::Ahead).unwrap(),
+            at("2026-06-17T12:00:00Z")
+        );
+        assert_eq!(
+            parse("2026-06-01T00:00:00Z", now, Direction::Ago).unwrap(),
+            at("2026-06-01T00:00:00Z")
+        );
+        let yesterday = parse("yesterday", now, Direction::Ago).unwrap();
+        let today = parse("today", now, Direction::Ago).unwrap();
+        assert!(yesterday < today && today <= now);
+        assert!(parse("last tuesday", now, Direction::Ago).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Values from `[defaults]` in the config for options left off the command line. They are
+//! resolved before policy checks, so policy applies to a default just as to a flag.
+
+use anyhow::Result;
+
+use crate::config::TtlDefaults;
+use crate::policy::{TTL_RANGE, fmt_secs};
+
+/// TTL for a new token of `token_type` (`read_write` or `read_scaling`): `ttl` if given,
+/// else the configured default for the type, else none.
+pub fn token_ttl(
+    defaults: &TtlDefaults,
+    token_type: &str,
+    ttl: Option<u64>,
+) -> Result<Option<u64>> {
+    if ttl.is_some() {
+        return Ok(ttl);
+    }
+    let default = match token_type {
+        "read_write" => defaults.read_write,
+        "read_scaling" => defaults.read_scaling,
+        _ => None,
+    };
+    if let Some(secs) = default {
+        anyhow::ensure!(
+            TTL_RANGE.contains(&secs),
+            "defaults.ttl.{token_type} is {}, which is not between 5m and 365d",
+            fmt_secs(secs)
+        );
+    }
+    Ok(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86400;
+
+    fn defaults() -> TtlDefaults {
+        TtlDefaults {
+            read_write: Some(90 * DAY),
+            read_scaling: Some(7 * DAY),
+        }
+    }
+
+    #[test]
+    fn default_depends_on_token_type() {
+        assert_eq!(
+            token_ttl(&defaults(), "read_write", None).unwrap(),
+            Some(90 * DAY)
+        );
+        assert_eq!(
+            token_ttl(&defaults(), "read_scaling", None).unwrap(),
+            Some(7 * DAY)
+        );
+        assert_eq!(
+            token_ttl(&TtlDefaults::default(), "read_write", None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn explicit_ttl_wins() {
+        assert_eq!(
+            token_ttl(&defaults(), "read_scaling", Some(3600)).unwrap(),
+            Some(3600)
+        );
+    }
+
+    #[test]
+    fn out_of_range_default_is_an_error() {
+        let defaults = TtlDefaults {
+            read_write: Some(60),
+            read_scaling: None,
+        };
+        let err = token_ttl(&defaults, "read_write", None).unwrap_err();
+        assert!(err.to_string().contains("defaults.ttl.read_write"));
+    }
+}
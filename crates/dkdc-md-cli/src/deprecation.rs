@@ -0,0 +1,210 @@
+//! Renamed commands and flags. Old spellings keep working: arguments are rewritten to the
+//! new spelling before clap parses them, and each rewrite produces a warning. To rename
+//! something, change the clap definition and add an entry to [`DEPRECATIONS`].
+
+use std::ffi::OsString;
+
+use clap::Command;
+
+/// A deprecated spelling and its replacement.
+#[derive(Debug)]
+#[allow(dead_code)] // variants are only constructed by entries in DEPRECATIONS
+pub enum Deprecation {
+    /// Subcommand `old` of the command at `parent` (e.g. `&[]` for top level) is now `new`.
+    Command {
+        parent: &'static [&'static str],
+        old: &'static str,
+        new: &'static str,
+    },
+    /// Flag `old` (e.g. `--foo`) is replaced by the arguments in `new`.
+    Flag {
+        old: &'static str,
+        new: &'static [&'static str],
+    },
+}
+
+/// Every deprecated spelling the CLI still accepts.
+pub const DEPRECATIONS: &[Deprecation] = &[];
+
+/// Rewrite deprecated spellings in `args` (including the program name) for `cmd`,
+/// returning the new arguments and one warning per rewrite.
+pub fn migrate(
+    cmd: &Command,
+    table: &[Deprecation],
+    args: Vec<OsString>,
+) -> (Vec<OsString>, Vec<String>) {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    let mut cmd = &cmd;
+    let mut path: Vec<String> = Vec::new();
+    let mut warnings = Vec::new();
+    let mut out = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    out.extend(args.next());
+
+    while let Some(arg) = args.next() {
+        let Some(s) = arg.to_str() else {
+            out.push(arg);
+            continue;
+        };
+        if s == "--" {
+            out.push(arg);
+            out.extend(args.by_ref());
+            break;
+        }
+        if s.starts_with('-') {
+            let name = s.split_once('=').map_or(s, |(n, _)| n);
+            let replacement = table.iter().find_map(|d| match d {
+                Deprecation::Flag { old, new } if *old == name => Some(new),
+                _ => None,
+            });
+            if let Some(new) = replacement {
+                warnings.push(format!("{name} is deprecated; use {}", new.join(" ")));
+                out.extend(new.iter().map(OsString::from));
+                continue;
+            }
+            let takes_value = !s.contains('=') && flag_takes_value(cmd, s);
+            out.push(arg);
+            if takes_value {
+                out.extend(args.next());
+            }
+            continue;
+        }
+
+        let renamed = table.iter().find_map(|d| match d {
+            Deprecation::Command { parent, old, new } if *old == s && *parent == path => Some(*new),
+            _ => None,
+        });
+        let name = match renamed {
+            Some(new) => {
+                let prefix: String = path.iter().map(|p| format!("{p} ")).collect();
+                warnings.push(format!(
+                    "`md {prefix}{s}` is deprecated; use `md {prefix}{new}`"
+                ));
+                new.to_string()
+            }
+            None => s.to_string(),
+        };
+        if let Some(sub) = cmd.find_subcommand(&name) {
+            cmd = sub;
+            path.push(sub.get_name().to_string());
+        }
+        out.push(name.into());
+    }
+    (out, warnings)
+}
+
+/// Whether `flag` (`--long` or `-s`, possibly with an attached value) consumes the next
+/// argument as its value.
+fn flag_takes_value(cmd: &Command, flag: &str) -> bool {
+    let arg = if let Some(long) = flag.strip_prefix("--") {
+        cmd.get_arguments().find(|a| {
+            a.get_long() == Some(long) || a.get_all_aliases().is_some_and(|v| v.contains(&long))
+        })
+    } else {
+        let mut chars = flag[1..].chars();
+        let (Some(short), None) = (chars.next(), chars.next()) else {
+            // `-ojson` carries its value; `-yv` are switches.
+            return false;
+        };
+        cmd.get_arguments().find(|a| a.get_short() == Some(short))
+    };
+    arg.is_some_and(|a| a.get_action().takes_values())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn cmd() -> Command {
+        Command::new("md")
+            .arg(Arg::new("output").short('o').long("output").global(true))
+            .arg(
+                Arg::new("yes")
+                    .short('y')
+                    .long("yes")
+                    .global(true)
+                    .action(ArgAction::SetTrue),
+            )
+            .subcommand(
+                Command::new("instance")
+                    .subcommand(Command::new("get").arg(Arg::new("username")))
+                    .subcommand(Command::new("list")),
+            )
+            .subcommand(Command::new("token").subcommand(Command::new("list")))
+    }
+
+    const TABLE: &[Deprecation] = &[
+        Deprecation::Command {
+            parent: &[],
+            old: "duckling",
+            new: "instance",
+        },
+        Deprecation::Command {
+            parent: &["instance"],
+            old: "show",
+            new: "get",
+        },
+        Deprecation::Flag {
+            old: "--stream",
+            new: &["--output", "ndjson"],
+        },
+    ];
+
+    fn run(args: &[&str]) -> (Vec<String>, Vec<String>) {
+        let args = args.iter().map(OsString::from).collect();
+        let (out, warnings) = migrate(&cmd(), TABLE, args);
+        let out = out.into_iter().map(|a| a.into_string().unwrap()).collect();
+        (out, warnings)
+    }
+
+    #[test]
+    fn current_spelling_is_untouched() {
+        let args = ["md", "-o", "json", "instance", "get", "svc"];
+        let (out, warnings) = run(&args);
+        assert_eq!(out, args);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn renames_commands_at_their_position() {
+        let (out, warnings) = run(&["md", "-o", "json", "duckling", "show", "duckling"]);
+        assert_eq!(out, ["md", "-o", "json", "instance", "get", "duckling"]);
+        assert_eq!(
+            warnings,
+            [
+                "`md duckling` is deprecated; use `md instance`",
+                "`md instance show` is deprecated; use `md instance get`",
+            ]
+        );
+    }
+
+    #[test]
+    fn flag_values_are_not_commands() {
+        let (out, warnings) = run(&["md", "--output", "duckling", "token", "list"]);
+        assert_eq!(out, ["md", "--output", "duckling", "token", "list"]);
+        assert!(warnings.is_empty());
+        let (out, _) = run(&["md", "-y", "duckling", "get", "x"]);
+        assert_eq!(out, ["md", "-y", "instance", "get", "x"]);
+    }
+
+    #[test]
+    fn rewrites_flags_anywhere_before_double_dash() {
+        let (out, warnings) = run(&["md", "token", "list", "--stream", "--", "--stream"]);
+        assert_eq!(
+            out,
+            [
+                "md", "token", "list", "--output", "ndjson", "--", "--stream"
+            ]
+        );
+        assert_eq!(warnings, ["--stream is deprecated; use --output ndjson"]);
+    }
+
+    #[test]
+    fn command_renames_are_scoped_to_parent() {
+        let (out, warnings) = run(&["md", "token", "show"]);
+        assert_eq!(out, ["md", "token", "show"]);
+        assert!(warnings.is_empty());
+    }
+}
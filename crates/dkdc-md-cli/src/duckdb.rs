@@ -0,0 +1,32 @@
+//! Queries through the `duckdb` CLI, for checks that need a real MotherDuck connection
+//! (`md test-connection`). md does not link DuckDB; the CLI is looked up on `PATH` unless
+//! a path is given.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+
+/// Open `dsn` as `token` and run `SELECT 1`, returning how long the whole run took
+/// (starting DuckDB, loading the MotherDuck extension, connecting, and the query). The
+/// token goes in `motherduck_token` rather than on the command line, where `ps` shows it.
+pub fn select_one(program: &Path, dsn: &str, token: &str) -> Result<Duration> {
+    let started = Instant::now();
+    let output = Command::new(program)
+        .args(["-noheader", "-list", dsn, "SELECT 1"])
+        .env("motherduck_token", token)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to run {} (is DuckDB installed?)", program.display()))?;
+    let elapsed = started.elapsed();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("duckdb exited with {}: {}", output.status, stderr.trim());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.trim() != "1" {
+        bail!("SELECT 1 returned {:?}", stdout.trim());
+    }
+    Ok(elapsed)
+}
@@ -0,0 +1,63 @@
+//! Ledger of executed change requests and bundles, kept next to `config.toml`. Their own
+//! `executed_at` is not covered by the signature, so the file cannot be trusted to say
+//! whether it already ran; `md execute` and `md bundle execute` check and append to this
+//! ledger instead.
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+
+const LEDGER_FILE: &str = "executed.log";
+
+/// Append-only record, one tab-separated line per execution: time, kind, id, and
+/// signature. Entries are matched by signature, which is unique to the signed content.
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Record that the `kind` signed with `signature` is executing, failing if it already
+    /// did. Nothing should run unless this succeeds.
+    pub fn claim(&self, kind: &str, id: &str, signature: &str, now: DateTime<Utc>) -> Result<()> {
+        if let Some(at) = self.executed_at(signature)? {
+            bail!("{kind} {id} was already executed at {at}");
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        writeln!(file, "{}\t{kind}\t{id}\t{signature}", now.to_rfc3339())
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    /// When the content signed with `signature` was executed, if it was.
+    fn executed_at(&self, signature: &str) -> Result<Option<String>> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.path.display()));
+            }
+        };
+        Ok(text.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (fields.last() == Some(&signature)).then(|| fields[0].to_string())
+        }))
+    }
+}
+
+/// `executed.log` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(LEDGER_FILE)
+}
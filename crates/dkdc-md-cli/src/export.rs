@@ -0,0 +1,94 @@
+//! `md account export`: every active account with its duckling configuration and token
+//! metadata, in one JSON archive for disaster-recovery records. Never includes secrets.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+use crate::client::MotherduckClient;
+
+/// Bumped when the archive layout changes incompatibly.
+const FORMAT_VERSION: u64 = 1;
+
+/// Fetch everything for the archive. Fails if any account cannot be read, since a partial
+/// backup is easy to mistake for a complete one.
+pub fn build(client: &MotherduckClient, now: DateTime<Utc>) -> Result<Value> {
+    let active = client.list_active_accounts()?;
+    let accounts: Vec<&Value> = active["accounts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|a| a["username"].is_string())
+        .collect();
+    let usernames: Vec<String> = accounts
+        .iter()
+        .filter_map(|a| a["username"].as_str())
+        .map(str::to_string)
+        .collect();
+    let tokens = client.list_tokens_bulk(&usernames);
+    let configs = client.get_duckling_configs(&usernames);
+    let mut exported = Vec::new();
+    for (((username, account), tokens), config) in
+        usernames.iter().zip(&accounts).zip(tokens).zip(configs)
+    {
+        let tokens = tokens.with_context(|| format!("failed to export '{username}'"))?;
+        let config = config.with_context(|| format!("failed to export '{username}'"))?;
+        exported.push(json!({
+            "username": username,
+            "account": account,
+            "instances": config,
+            "tokens": token_metadata(&tokens),
+        }));
+    }
+    Ok(json!({
+        "format": "md-export",
+        "version": FORMAT_VERSION,
+        "exported_at": now.to_rfc3339(),
+        "base_url": client.base_url(),
+        "accounts": exported,
+    }))
+}
+
+/// Token entries from a listing, without any secret value.
+pub fn token_metadata(list: &Value) -> Vec<Value> {
+    list["tokens"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|t| {
+            let mut t = t.clone();
+            if let Some(t) = t.as_object_mut() {
+                t.remove("token");
+            }
+            t
+        })
+        .collect()
+}
+
+/// `md-export-<UTC timestamp>.json` in `dir`.
+pub fn default_path(dir: &Path, now: DateTime<Utc>) -> PathBuf {
+    dir.join(format!("md-export-{}.json", now.format("%Y%m%dT%H%M%SZ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_metadata_drops_secrets() {
+        let list = json!({ "tokens": [{ "id": "t1", "name": "ci", "token": "md_secret" }] });
+        assert_eq!(token_metadata(&list), [json!({ "id": "t1", "name": "ci" })]);
+        assert!(token_metadata(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn default_path_is_timestamped() {
+        let now = "2026-06-01T12:30:05Z".parse().unwrap();
+        assert_eq!(
+            default_path(Path::new("backups"), now),
+            Path::new("backups/md-export-20260601T123005Z.json")
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Syntax highlighting for `-o json` on a terminal. [`Json`] colors JSON text as it is
+//! written, so it covers everything a command prints (pretty documents, NDJSON streams,
+//! raw API responses from `md get`) without each printer knowing about color.
+
+use std::io::{self, Write};
+
+/// SGR styles, as in `jq`: keys bold blue, strings green; md adds numbers and literals.
+const KEY: &str = "1;34";
+const STRING: &str = "32";
+const NUMBER: &str = "36";
+const LITERAL: &str = "35";
+
+/// Where the highlighter is in the text; a token may span several `write` calls.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Between,
+    String { escaped: bool },
+    Scalar,
+}
+
+/// Writes JSON text to `inner` with ANSI colors. Text that is not JSON passes through,
+/// possibly with odd colors, but unchanged otherwise.
+pub struct Json<'a> {
+    inner: &'a mut dyn Write,
+    /// Open containers, `true` for objects.
+    nesting: Vec<bool>,
+    /// Inside an object, after `{` or `,`: the next string is a key.
+    expect_key: bool,
+    state: State,
+}
+
+impl<'a> Json<'a> {
+    pub fn new(inner: &'a mut dyn Write) -> Self {
+        Self {
+            inner,
+            nesting: Vec::new(),
+            expect_key: false,
+            state: State::Between,
+        }
+    }
+}
+
+fn start(buf: &mut Vec<u8>, style: &str) {
+    buf.extend_from_slice(format!("\x1b[{style}m").as_bytes());
+}
+
+fn reset(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(b"\x1b[0m");
+}
+
+impl Write for Json<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut colored = Vec::with_capacity(buf.len() * 2);
+        for &b in buf {
+            match self.state {
+                State::String { escaped } => {
+                    colored.push(b);
+                    self.state = match b {
+                        _ if escaped => State::String { escaped: false },
+                        b'\\' => State::String { escaped: true },
+                        b'"' => {
+                            reset(&mut colored);
+                            State::Between
+                        }
+                        _ => State::String { escaped: false },
+                    };
+                    continue;
+                }
+                State::Scalar if b.is_ascii_alphanumeric() || b"+-.".contains(&b) => {
+                    colored.push(b);
+                    continue;
+                }
+                State::Scalar => {
+                    reset(&mut colored);
+                    self.state = State::Between;
+                }
+                State::Between => {}
+            }
+            match b {
+                b'"' => {
+                    let in_object = self.nesting.last() == Some(&true);
+                    start(
+                        &mut colored,
+                        if in_object && self.expect_key {
+                            KEY
+                        } else {
+                            STRING
+                        },
+                    );
+                    self.state = State::String { escaped: false };
+                }
+                b'{' | b'[' => {
+                    self.nesting.push(b == b'{');
+                    self.expect_key = b == b'{';
+                }
+                b'}' | b']' => {
+                    self.nesting.pop();
+                    self.expect_key = false;
+                }
+                b',' => self.expect_key = self.nesting.last() == Some(&true),
+                b':' => self.expect_key = false,
+                b'-' | b'0'..=b'9' => {
+                    start(&mut colored, NUMBER);
+                    self.state = State::Scalar;
+                }
+                b't' | b'f' | b'n' => {
+                    start(&mut colored, LITERAL);
+                    self.state = State::Scalar;
+                }
+                _ => {}
+            }
+            colored.push(b);
+        }
+        self.inner.write_all(&colored)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight(chunks: &[&str]) -> String {
+        let mut out = Vec::new();
+        let mut json = Json::new(&mut out);
+        for chunk in chunks {
+            json.write_all(chunk.as_bytes()).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn colors_keys_strings_numbers_and_literals() {
+        assert_eq!(
+            highlight(&[r#"{"a": ["x\"}", -1.5e3, true], "b": null}"#]),
+            "{\x1b[1;34m\"a\"\x1b[0m: [\x1b[32m\"x\\\"}\"\x1b[0m, \x1b[36m-1.5e3\x1b[0m, \
+             \x1b[35mtrue\x1b[0m], \x1b[1;34m\"b\"\x1b[0m: \x1b[35mnull\x1b[0m}"
+        );
+    }
+
+    #[test]
+    fn tokens_may_span_writes() {
+        assert_eq!(
+            highlight(&["{\"ke", "y\": 4", "2}\n"]),
+            highlight(&["{\"key\": 42}\n"])
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use crate::client::ApiError;
+
+/// Remediation advice for a common failure, added as context before the error is printed.
+pub fn hint(err: &anyhow::Error) -> Option<&'static str> {
+    let Some(api) = err.downcast_ref::<ApiError>() else {
+        return err
+            .chain()
+            .any(|c| c.is::<ureq::Error>())
+            .then_some("could not reach the API; check your network connection and --base-url");
+    };
+    let message = api.message.to_ascii_lowercase();
+    Some(match api.status {
+        401 => {
+            "the token was rejected (expired or revoked?); pass a current one with --token \
+             or MOTHERDUCK_TOKEN"
+        }
+        403 if message.contains("read_scaling") || message.contains("read scaling") => {
+            "read scaling is not enabled for your organization; enable it in the plan settings \
+             or use --flock-size 0"
+        }
+        403 => {
+            "this token is not allowed to do that; managing service accounts needs an admin \
+             token (`md capabilities` shows what it can use)"
+        }
+        429 => "the API is rate limiting requests; wait a moment and retry",
+        500..=599 => "the MotherDuck API failed on its side; retry shortly",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(status: u16, message: &str) -> anyhow::Error {
+        anyhow::Error::new(ApiError {
+            status,
+            message: message.into(),
+        })
+        .context("GET /v1/active_accounts")
+    }
+
+    #[test]
+    fn hints_by_status() {
+        assert!(
+            hint(&api_error(401, "Unauthorized"))
+                .unwrap()
+                .contains("--token")
+        );
+        assert!(
+            hint(&api_error(403, "forbidden"))
+                .unwrap()
+                .contains("admin token")
+        );
+        assert!(
+            hint(&api_error(429, "slow down"))
+                .unwrap()
+                .contains("retry")
+        );
+        assert!(
+            hint(&api_error(503, "unavailable"))
+                .unwrap()
+                .contains("retry")
+        );
+        assert!(hint(&api_error(404, "not found")).is_none());
+        assert!(hint(&api_error(400, "bad request")).is_none());
+    }
+
+    #[test]
+    fn read_scaling_403_gets_specific_hint() {
+        let err = api_error(403, "Read scaling is not available on this plan");
+        assert!(
+            hint(&err)
+                .unwrap()
+                .starts_with("read scaling is not enabled")
+        );
+    }
+
+    #[test]
+    fn non_api_errors_have_no_hint() {
+        assert!(hint(&anyhow::anyhow!("invalid duration")).is_none());
+    }
+}
@@ -0,0 +1,552 @@
+//! Local inventory of accounts, token metadata, and duckling configs, kept in a SQLite
+//! database next to `config.toml` by `md sync` so read-only commands can answer with
+//! `--local` instead of calling the API. A sync writes only the rows it changed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::client::MotherduckClient;
+use crate::export::token_metadata;
+
+const INVENTORY_FILE: &str = "inventory.db";
+/// How long changes found by `md sync` are kept for `md changes`.
+const CHANGE_RETENTION: TimeDelta = TimeDelta::days(90);
+
+/// JSON columns hold API responses as they came, so the tables need no migration when
+/// the API adds fields.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (name TEXT PRIMARY KEY, value TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS accounts (
+        username TEXT PRIMARY KEY,
+        synced_at TEXT NOT NULL,
+        account TEXT NOT NULL,
+        tokens TEXT NOT NULL,
+        instances TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS changes (
+        id INTEGER PRIMARY KEY,
+        at TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        username TEXT NOT NULL,
+        subject TEXT NOT NULL,
+        detail TEXT
+    );
+    CREATE INDEX IF NOT EXISTS changes_at ON changes (at);
+";
+
+#[derive(Debug)]
+pub struct Inventory {
+    /// [`cache_key`](crate::capabilities::cache_key) of the base URL and token synced with.
+    key: String,
+    /// RFC 3339 time of the last sync.
+    pub synced_at: String,
+    pub accounts: BTreeMap<String, Account>,
+    /// What each sync found different from the one before, oldest first.
+    pub changes: Vec<Change>,
+    /// What [`save`](Self::save) has to write: accounts added, refetched, or with a new
+    /// list entry; accounts that disappeared; and how many of `changes` are new.
+    dirty: BTreeSet<String>,
+    removed: Vec<String>,
+    new_changes: usize,
+    /// Whether the database's rows are replaced rather than updated: the first sync, or
+    /// one for another API or token.
+    fresh: bool,
+}
+
+#[derive(Debug)]
+pub struct Account {
+    /// RFC 3339 time this account's tokens and config were fetched.
+    pub synced_at: String,
+    /// The account's entry in `GET /v1/active_accounts`.
+    pub account: Value,
+    /// Token metadata, without secrets.
+    pub tokens: Vec<Value>,
+    pub instances: Value,
+}
+
+/// What a sync changed.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SyncSummary {
+    pub accounts: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// Entries added to the change feed.
+    pub changes: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Appeared,
+    Disappeared,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Appeared => "appeared",
+            Self::Disappeared => "disappeared",
+            Self::Changed => "changed",
+        }
+    }
+}
+
+/// One difference between two syncs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// RFC 3339 time of the sync that found it.
+    pub at: String,
+    pub kind: ChangeKind,
+    pub username: String,
+    /// `account`, `token <id>`, or `instances`.
+    pub subject: String,
+    /// E.g. the token name, or `read_scaling.flock_size: 4 -> 0`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Inventory {
+    /// Update `previous` (or start afresh) from the API. The account list is always
+    /// re-read; tokens and configs are fetched for new accounts and for `only`, or for every
+    /// account when `only` is empty.
+    pub fn sync(
+        previous: Option<Self>,
+        client: &MotherduckClient,
+        key: &str,
+        only: &[String],
+        now: DateTime<Utc>,
+    ) -> Result<(Self, SyncSummary)> {
+        // The first sync has nothing to compare against, so it records no changes.
+        let (mut accounts, mut changes, baseline) = match previous {
+            Some(previous) => (previous.accounts, previous.changes, true),
+            None if only.is_empty() => (BTreeMap::new(), Vec::new(), false),
+            None => bail!("no local inventory yet; run `md sync` without usernames first"),
+        };
+        let at = now.to_rfc3339();
+        let mut found = Vec::new();
+        let listed = client.list_active_accounts()?;
+        let active: BTreeMap<String, Value> = listed["accounts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|a| Some((a["username"].as_str()?.to_string(), a.clone())))
+            .collect();
+        if let Some(missing) = only.iter().find(|u| !active.contains_key(*u)) {
+            bail!("'{missing}' is not an active account");
+        }
+
+        let mut summary = SyncSummary::default();
+        let mut removed = Vec::new();
+        accounts.retain(|username, _| {
+            let keep = active.contains_key(username);
+            if !keep {
+                summary.removed += 1;
+                removed.push(username.clone());
+                found.push(change(
+                    &at,
+                    ChangeKind::Disappeared,
+                    username,
+                    "account",
+                    None,
+                ));
+            }
+            keep
+        });
+        let fetch: Vec<String> = active
+            .keys()
+            .filter(|u| only.is_empty() || only.contains(u) || !accounts.contains_key(*u))
+            .cloned()
+            .collect();
+        let mut dirty: BTreeSet<String> = fetch.iter().cloned().collect();
+        let tokens = client.list_tokens_bulk(&fetch);
+        let configs = client.get_duckling_configs(&fetch);
+        for ((username, tokens), config) in fetch.into_iter().zip(tokens).zip(configs) {
+            let tokens = tokens.with_context(|| format!("failed to sync '{username}'"))?;
+            let instances = config.with_context(|| format!("failed to sync '{username}'"))?;
+            let account = Account {
+                synced_at: at.clone(),
+                account: active[&username].clone(),
+                tokens: token_metadata(&tokens),
+                instances,
+            };
+            match accounts.get(&username) {
+                Some(old) => {
+                    summary.updated += 1;
+                    diff_account(&at, &username, old, &account, &mut found);
+                }
+                None => {
+                    summary.added += 1;
+                    found.push(change(
+                        &at,
+                        ChangeKind::Appeared,
+                        &username,
+                        "account",
+                        None,
+                    ));
+                }
+            }
+            accounts.insert(username, account);
+        }
+        // Accounts not refetched still get their current list entry (e.g. duckling status).
+        for (username, entry) in active {
+            if let Some(account) = accounts.get_mut(&username)
+                && account.account != entry
+            {
+                account.account = entry;
+                dirty.insert(username);
+            }
+        }
+        summary.accounts = accounts.len();
+        if baseline {
+            summary.changes = found.len();
+            changes.extend(found);
+        }
+        changes.retain(|c| {
+            DateTime::parse_from_rfc3339(&c.at).is_ok_and(|t| now - t.to_utc() < CHANGE_RETENTION)
+        });
+        let inventory = Self {
+            key: key.to_string(),
+            synced_at: at,
+            accounts,
+            changes,
+            dirty,
+            removed,
+            new_changes: summary.changes,
+            fresh: !baseline,
+        };
+        Ok((inventory, summary))
+    }
+
+    /// The inventory synced for `key`, however old.
+    pub fn load(path: &Path, key: &str) -> Option<Self> {
+        let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+        Self::read(&db, key).ok().flatten()
+    }
+
+    fn read(db: &Connection, key: &str) -> rusqlite::Result<Option<Self>> {
+        let meta = |name: &str| {
+            db.query_row("SELECT value FROM meta WHERE name = ?1", [name], |r| {
+                r.get::<_, String>(0)
+            })
+            .optional()
+        };
+        if meta("key")?.as_deref() != Some(key) {
+            return Ok(None);
+        }
+        let synced_at = meta("synced_at")?.unwrap_or_default();
+        let json = |text: String| serde_json::from_str(&text).unwrap_or(Value::Null);
+        let accounts = db
+            .prepare("SELECT username, synced_at, account, tokens, instances FROM accounts")?
+            .query_map([], |r| {
+                let account = Account {
+                    synced_at: r.get(1)?,
+                    account: json(r.get(2)?),
+                    tokens: serde_json::from_str(&r.get::<_, String>(3)?).unwrap_or_default(),
+                    instances: json(r.get(4)?),
+                };
+                Ok((r.get(0)?, account))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        let changes = db
+            .prepare("SELECT at, kind, username, subject, detail FROM changes ORDER BY id")?
+            .query_map([], |r| {
+                Ok(Change {
+                    at: r.get(0)?,
+                    kind: match r.get::<_, String>(1)?.as_str() {
+                        "appeared" => ChangeKind::Appeared,
+                        "disappeared" => ChangeKind::Disappeared,
+                        _ => ChangeKind::Changed,
+                    },
+                    username: r.get(2)?,
+                    subject: r.get(3)?,
+                    detail: r.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Some(Self {
+            key: key.to_string(),
+            synced_at,
+            accounts,
+            changes,
+            dirty: BTreeSet::new(),
+            removed: Vec::new(),
+            new_changes: 0,
+            fresh: false,
+        }))
+    }
+
+    /// Write what the last sync changed, in one transaction.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        self.write(path)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn write(&self, path: &Path) -> rusqlite::Result<()> {
+        let mut db = Connection::open(path)?;
+        let tx = db.transaction()?;
+        tx.execute_batch(SCHEMA)?;
+        if self.fresh {
+            tx.execute_batch("DELETE FROM accounts; DELETE FROM changes;")?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (name, value) VALUES ('key', ?1), ('synced_at', ?2)",
+            params![self.key, self.synced_at],
+        )?;
+        for username in &self.removed {
+            tx.execute("DELETE FROM accounts WHERE username = ?1", [username])?;
+        }
+        for username in &self.dirty {
+            let a = &self.accounts[username];
+            tx.execute(
+                "INSERT OR REPLACE INTO accounts (username, synced_at, account, tokens, instances) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    username,
+                    a.synced_at,
+                    a.account.to_string(),
+                    serde_json::to_string(&a.tokens).expect("JSON values serialize"),
+                    a.instances.to_string()
+                ],
+            )?;
+        }
+        for c in &self.changes[self.changes.len() - self.new_changes..] {
+            tx.execute(
+                "INSERT INTO changes (at, kind, username, subject, detail) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![c.at, c.kind.as_str(), c.username, c.subject, c.detail],
+            )?;
+        }
+        // RFC 3339 times from `Utc` all end in +00:00, so they compare as text.
+        if let Ok(synced) = DateTime::parse_from_rfc3339(&self.synced_at) {
+            let cutoff = (synced.to_utc() - CHANGE_RETENTION).to_rfc3339();
+            tx.execute("DELETE FROM changes WHERE at < ?1", [cutoff])?;
+        }
+        tx.commit()
+    }
+
+    /// Shaped like the `GET /v1/active_accounts` response.
+    pub fn active_accounts(&self) -> Value {
+        let accounts: Vec<&Value> = self.accounts.values().map(|a| &a.account).collect();
+        json!({ "accounts": accounts })
+    }
+
+    /// Shaped like the `GET /v1/users/{username}/tokens` response.
+    pub fn tokens(&self, username: &str) -> Result<Value> {
+        Ok(json!({ "tokens": self.account(username)?.tokens }))
+    }
+
+    /// Shaped like the `GET /v1/users/{username}/instances` response.
+    pub fn instances(&self, username: &str) -> Result<Value> {
+        Ok(self.account(username)?.instances.clone())
+    }
+
+    /// Changes found by syncs at or after `since`.
+    pub fn changes_since(&self, since: DateTime<Utc>) -> Vec<&Change> {
+        self.changes
+            .iter()
+            .filter(|c| DateTime::parse_from_rfc3339(&c.at).is_ok_and(|t| t.to_utc() >= since))
+            .collect()
+    }
+
+    fn account(&self, username: &str) -> Result<&Account> {
+        self.accounts.get(username).with_context(|| {
+            format!(
+                "'{username}' is not in the local inventory (synced {}); run `md sync`",
+                self.synced_at
+            )
+        })
+    }
+}
+
+fn change(
+    at: &str,
+    kind: ChangeKind,
+    username: &str,
+    subject: &str,
+    detail: Option<String>,
+) -> Change {
+    Change {
+        at: at.to_string(),
+        kind,
+        username: username.to_string(),
+        subject: subject.to_string(),
+        detail,
+    }
+}
+
+/// Token and instance differences. Account list entries are not compared: duckling
+/// status changes on every wake and sleep.
+fn diff_account(at: &str, username: &str, old: &Account, new: &Account, out: &mut Vec<Change>) {
+    let by_id = |tokens: &[Value]| -> BTreeMap<String, Value> {
+        tokens
+            .iter()
+            .filter_map(|t| Some((t["id"].as_str()?.to_string(), t.clone())))
+            .collect()
+    };
+    let (old_tokens, new_tokens) = (by_id(&old.tokens), by_id(&new.tokens));
+    let name = |t: &Value| t["name"].as_str().map(str::to_string);
+    for (id, token) in &old_tokens {
+        if !new_tokens.contains_key(id) {
+            let subject = format!("token {id}");
+            out.push(change(
+                at,
+                ChangeKind::Disappeared,
+                username,
+                &subject,
+                name(token),
+            ));
+        }
+    }
+    for (id, token) in &new_tokens {
+        let subject = format!("token {id}");
+        match old_tokens.get(id) {
+            None => out.push(change(
+                at,
+                ChangeKind::Appeared,
+                username,
+                &subject,
+                name(token),
+            )),
+            Some(old) => {
+                let diffs = leaf_diffs(old, token);
+                if !diffs.is_empty() {
+                    let detail = Some(diffs.join(", "));
+                    out.push(change(at, ChangeKind::Changed, username, &subject, detail));
+                }
+            }
+        }
+    }
+    let diffs = leaf_diffs(&old.instances, &new.instances);
+    if !diffs.is_empty() {
+        let detail = Some(diffs.join(", "));
+        out.push(change(
+            at,
+            ChangeKind::Changed,
+            username,
+            "instances",
+            detail,
+        ));
+    }
+}
+
+/// `path: old -> new` for each differing leaf, with nested keys joined by `.`.
+fn leaf_diffs(old: &Value, new: &Value) -> Vec<String> {
+    fn walk(path: &str, old: &Value, new: &Value, out: &mut Vec<String>) {
+        match (old, new) {
+            (Value::Object(a), Value::Object(b)) => {
+                let keys: std::collections::BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+                for key in keys {
+                    let path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    let missing = Value::Null;
+                    walk(
+                        &path,
+                        a.get(key).unwrap_or(&missing),
+                        b.get(key).unwrap_or(&missing),
+                        out,
+                    );
+                }
+            }
+            (a, b) if a != b => out.push(format!("{path}: {a} -> {b}")),
+            _ => {}
+        }
+    }
+    let mut out = Vec::new();
+    walk("", old, new, &mut out);
+    out
+}
+
+/// `inventory.db` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(INVENTORY_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory() -> Inventory {
+        let account = Account {
+            synced_at: "2026-06-01T12:00:00+00:00".into(),
+            account: json!({ "username": "svc_etl", "ducklings": [] }),
+            tokens: vec![json!({ "id": "t1", "name": "ci" })],
+            instances: json!({ "read_write": { "instance_size": "pulse" } }),
+        };
+        Inventory {
+            key: "k".into(),
+            synced_at: "2026-06-01T12:00:00+00:00".into(),
+            accounts: [("svc_etl".to_string(), account)].into(),
+            changes: Vec::new(),
+            dirty: BTreeSet::new(),
+            removed: Vec::new(),
+            new_changes: 0,
+            fresh: false,
+        }
+    }
+
+    #[test]
+    fn answers_shaped_like_the_api() {
+        let inventory = inventory();
+        assert_eq!(
+            inventory.active_accounts(),
+            json!({ "accounts": [{ "username": "svc_etl", "ducklings": [] }] })
+        );
+        assert_eq!(
+            inventory.tokens("svc_etl").unwrap(),
+            json!({ "tokens": [{ "id": "t1", "name": "ci" }] })
+        );
+        let err = inventory.instances("svc_new").unwrap_err();
+        assert!(err.to_string().contains("run `md sync`"));
+    }
+
+    #[test]
+    fn diffs_tokens_and_instances() {
+        let old = &inventory().accounts["svc_etl"];
+        let new = Account {
+            synced_at: "2026-06-02T12:00:00+00:00".into(),
+            account: json!({ "username": "svc_etl", "ducklings": [{ "status": "running" }] }),
+            tokens: vec![
+                json!({ "id": "t1", "name": "ci-renamed" }),
+                json!({ "id": "t2", "name": "bi" }),
+            ],
+            instances: json!({ "read_write": { "instance_size": "jumbo" } }),
+        };
+        let mut changes = Vec::new();
+        diff_account("now", "svc_etl", old, &new, &mut changes);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.kind, c.subject.as_str(), c.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    ChangeKind::Changed,
+                    "token t1",
+                    Some(r#"name: "ci" -> "ci-renamed""#)
+                ),
+                (ChangeKind::Appeared, "token t2", Some("bi")),
+                (
+                    ChangeKind::Changed,
+                    "instances",
+                    Some(r#"read_write.instance_size: "pulse" -> "jumbo""#)
+                ),
+            ]
+        );
+    }
+}
@@ -1,7 +1,45 @@
 #![forbid(unsafe_code)]
 
-pub(crate) mod auth;
+pub(crate) mod annotations;
+pub(crate) mod approval;
+pub(crate) mod audit;
+pub mod auth;
+pub(crate) mod bundle;
+pub(crate) mod capabilities;
 mod cli;
-pub(crate) mod client;
+pub mod client;
+pub(crate) mod config;
+pub(crate) mod dates;
+pub(crate) mod defaults;
+pub(crate) mod deprecation;
+pub(crate) mod duckdb;
+pub(crate) mod executed;
+pub(crate) mod export;
+pub(crate) mod highlight;
+pub(crate) mod hints;
+pub(crate) mod inventory;
+pub(crate) mod middleware;
+#[cfg(feature = "otel")]
+pub(crate) mod otel;
+pub(crate) mod permissions;
+pub(crate) mod policy;
+pub(crate) mod psobject;
+pub(crate) mod query;
+pub(crate) mod reconcile;
+pub(crate) mod reference;
+pub(crate) mod resource;
+pub(crate) mod sandbox;
+pub(crate) mod schedule;
+pub(crate) mod search;
+pub(crate) mod sh;
+pub(crate) mod signing;
+pub(crate) mod spec;
+pub(crate) mod stream;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub(crate) mod transport;
+pub(crate) mod validate;
+pub(crate) mod wait;
 
-pub use cli::run;
+pub use cli::{ApiModeError, run, run_with};
+pub use client::{ApiError, Hooks, MotherduckClient, RetryOn};
@@ -3,7 +3,9 @@ use std::process::ExitCode;
 fn main() -> ExitCode {
     if let Err(e) = dkdc_md_cli::run(std::env::args()) {
         eprintln!("Error: {e:#}");
-        return ExitCode::FAILURE;
+        return e
+            .downcast_ref::<dkdc_md_cli::ApiModeError>()
+            .map_or(ExitCode::FAILURE, |e| ExitCode::from(e.exit_code));
     }
     ExitCode::SUCCESS
 }
@@ -0,0 +1,122 @@
+//! Programs from `[hooks]` run around mutating commands, so orgs can add guardrails
+//! (`pre`, which can veto) and notifications (`post`) without patching md.
+//!
+//! A hook gets the command's arguments as its own (`guard.sh token create svc_etl ...`,
+//! without `--token` or `--config`) and context in env vars:
+//!
+//! - `MD_HOOK`: `pre` or `post`
+//! - `MD_COMMAND`: subcommand path, e.g. `token create`
+//! - `MD_BASE_URL`, `MD_CORRELATION_ID`
+//! - `MD_STATUS` (post only): `success` or `failure`
+//! - `MD_ERROR` (post only, on failure): the error message
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use anyhow::{Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Pre,
+    Post,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pre => "pre",
+            Self::Post => "post",
+        }
+    }
+}
+
+/// The command a hook runs around.
+pub struct Invocation<'a> {
+    pub command: &'a str,
+    pub args: &'a [String],
+    pub base_url: &'a str,
+    pub correlation_id: &'a str,
+}
+
+/// `program` as configured: a path with a directory part (`scripts/guard.sh`) is relative to
+/// the config file's directory; a bare name is looked up on `PATH`.
+pub fn resolve(program: &Path, config_dir: Option<&Path>) -> PathBuf {
+    match config_dir {
+        Some(dir) if program.is_relative() && program.components().count() > 1 => dir.join(program),
+        _ => program.to_path_buf(),
+    }
+}
+
+/// Env vars for `stage`; `outcome` is the command's result for post hooks.
+pub fn env(
+    stage: Stage,
+    inv: &Invocation,
+    outcome: Option<Result<(), String>>,
+) -> Vec<(&'static str, String)> {
+    let mut vars = vec![
+        ("MD_HOOK", stage.name().to_string()),
+        ("MD_COMMAND", inv.command.to_string()),
+        ("MD_BASE_URL", inv.base_url.to_string()),
+        ("MD_CORRELATION_ID", inv.correlation_id.to_string()),
+    ];
+    match outcome {
+        Some(Ok(())) => vars.push(("MD_STATUS", "success".to_string())),
+        Some(Err(e)) => {
+            vars.push(("MD_STATUS", "failure".to_string()));
+            vars.push(("MD_ERROR", e));
+        }
+        None => {}
+    }
+    vars
+}
+
+/// Run `program` with the command's arguments and `vars`, capturing its output so callers
+/// can keep it off stdout.
+pub fn run(program: &Path, inv: &Invocation, vars: &[(&str, String)]) -> Result<Output> {
+    Command::new(program)
+        .args(inv.args)
+        .envs(vars.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to run hook {}", program.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_paths_against_config_dir() {
+        let dir = Path::new("/etc/md");
+        assert_eq!(
+            resolve(Path::new("scripts/guard.sh"), Some(dir)),
+            Path::new("/etc/md/scripts/guard.sh")
+        );
+        assert_eq!(resolve(Path::new("notify"), Some(dir)), Path::new("notify"));
+        assert_eq!(
+            resolve(Path::new("/opt/guard.sh"), Some(dir)),
+            Path::new("/opt/guard.sh")
+        );
+        assert_eq!(
+            resolve(Path::new("scripts/guard.sh"), None),
+            Path::new("scripts/guard.sh")
+        );
+    }
+
+    #[test]
+    fn post_env_carries_outcome() {
+        let inv = Invocation {
+            command: "token create",
+            args: &[],
+            base_url: "https://api.example",
+            correlation_id: "abc",
+        };
+        let pre = env(Stage::Pre, &inv, None);
+        assert_eq!(pre.len(), 4);
+        assert!(pre.contains(&("MD_COMMAND", "token create".to_string())));
+        let failed = env(Stage::Post, &inv, Some(Err("boom".to_string())));
+        assert!(failed.contains(&("MD_HOOK", "post".to_string())));
+        assert!(failed.contains(&("MD_STATUS", "failure".to_string())));
+        assert!(failed.contains(&("MD_ERROR", "boom".to_string())));
+    }
+}
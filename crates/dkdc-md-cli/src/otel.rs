@@ -0,0 +1,375 @@
+//! OpenTelemetry trace export (the `otel` feature). Each run is one trace: a root span for
+//! the command and a client span per HTTP request, sent to an OTLP/HTTP collector with JSON
+//! encoding when the run ends. Configured through the standard `OTEL_*` environment
+//! variables; without an endpoint nothing is recorded.
+
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result, bail};
+use percent_encoding::percent_decode_str;
+use serde_json::{Value, json};
+
+/// How long the export may take before the run gives up on it.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_SERVICE_NAME: &str = "md";
+const SCOPE_NAME: &str = env!("CARGO_PKG_NAME");
+const SCOPE_VERSION: &str = env!("CARGO_PKG_VERSION");
+// OTLP `SpanKind` and `StatusCode` values.
+const KIND_INTERNAL: u8 = 1;
+const KIND_CLIENT: u8 = 3;
+const STATUS_OK: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+/// The trace of the current run, if exporting is configured.
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+/// Where and how to send spans, from the environment.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Exporter {
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    service_name: String,
+}
+
+impl Exporter {
+    pub fn from_env() -> Result<Option<Self>> {
+        Self::from_env_with(|k| std::env::var(k))
+    }
+
+    fn from_env_with(
+        env_var: impl Fn(&str) -> Result<String, std::env::VarError>,
+    ) -> Result<Option<Self>> {
+        let var = |k: &str| env_var(k).ok().filter(|v| !v.trim().is_empty());
+        if var("OTEL_SDK_DISABLED").is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+            || var("OTEL_TRACES_EXPORTER").is_some_and(|v| v.trim() == "none")
+        {
+            return Ok(None);
+        }
+        let endpoint = match (
+            var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"),
+            var("OTEL_EXPORTER_OTLP_ENDPOINT"),
+        ) {
+            (Some(url), _) => url.trim().to_string(),
+            (None, Some(base)) => format!("{}/v1/traces", base.trim().trim_end_matches('/')),
+            (None, None) => return Ok(None),
+        };
+        let protocol = var("OTEL_EXPORTER_OTLP_TRACES_PROTOCOL")
+            .or_else(|| var("OTEL_EXPORTER_OTLP_PROTOCOL"));
+        if let Some(protocol) = protocol.filter(|p| p.trim() != "http/json") {
+            bail!(
+                "unsupported OTLP protocol '{}' (only http/json is supported)",
+                protocol.trim()
+            );
+        }
+        let mut headers = Vec::new();
+        for list in [
+            "OTEL_EXPORTER_OTLP_HEADERS",
+            "OTEL_EXPORTER_OTLP_TRACES_HEADERS",
+        ] {
+            headers.extend(parse_headers(&var(list).unwrap_or_default(), list)?);
+        }
+        Ok(Some(Self {
+            endpoint,
+            headers,
+            service_name: var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string()),
+        }))
+    }
+
+    fn export(&self, payload: &Value) -> Result<()> {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(EXPORT_TIMEOUT))
+            .build()
+            .into();
+        let mut request = agent.post(&self.endpoint);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request
+            .header("content-type", "application/json")
+            .send(payload.to_string().as_bytes())
+            .with_context(|| format!("failed to export traces to {}", self.endpoint))?;
+        Ok(())
+    }
+}
+
+/// `key1=value1,key2=value2` with percent-encoded values, per the OTLP exporter spec.
+fn parse_headers(list: &str, var: &str) -> Result<Vec<(String, String)>> {
+    list.split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (name, value) = pair
+                .split_once('=')
+                .with_context(|| format!("invalid {var} entry '{pair}' (expected key=value)"))?;
+            let value = percent_decode_str(value.trim())
+                .decode_utf8()
+                .with_context(|| format!("invalid {var} value for '{}'", name.trim()))?;
+            Ok((name.trim().to_string(), value.into_owned()))
+        })
+        .collect()
+}
+
+struct Trace {
+    exporter: Exporter,
+    trace_id: String,
+    root_span_id: String,
+    correlation_id: String,
+    start: SystemTime,
+    spans: Vec<Value>,
+}
+
+/// Start recording this run's trace. The trace ID is the correlation ID when that is a
+/// UUID, so the two can be looked up interchangeably.
+pub fn start(exporter: Exporter, correlation_id: &str) {
+    let trace = Trace {
+        exporter,
+        trace_id: trace_id_for(correlation_id),
+        root_span_id: random_hex(1),
+        correlation_id: correlation_id.to_string(),
+        start: SystemTime::now(),
+        spans: Vec::new(),
+    };
+    *TRACE.lock().expect("trace lock poisoned") = Some(trace);
+}
+
+/// End the root span as `name` and send the trace. Does nothing if no trace was started.
+pub fn finish(name: &str, result: &Result<()>) -> Result<()> {
+    let Some(mut trace) = TRACE.lock().expect("trace lock poisoned").take() else {
+        return Ok(());
+    };
+    let root = span_json(
+        &trace,
+        &trace.root_span_id,
+        None,
+        name,
+        KIND_INTERNAL,
+        (trace.start, SystemTime::now()),
+        vec![attr("md.correlation_id", &trace.correlation_id)],
+        result.as_ref().err().map(|e| format!("{e:#}")),
+    );
+    trace.spans.insert(0, root);
+    trace.exporter.export(&payload(&trace))
+}
+
+/// A request in flight, recorded as a child of the root span when it ends.
+pub struct HttpSpan {
+    trace_id: String,
+    span_id: String,
+    start: SystemTime,
+}
+
+impl HttpSpan {
+    /// `None` when no trace is being recorded.
+    pub fn start() -> Option<Self> {
+        let guard = TRACE.lock().expect("trace lock poisoned");
+        let trace = guard.as_ref()?;
+        Some(Self {
+            trace_id: trace.trace_id.clone(),
+            span_id: random_hex(1),
+            start: SystemTime::now(),
+        })
+    }
+
+    /// W3C `traceparent` header value, so the API can join the trace.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id, self.span_id)
+    }
+
+    /// `outcome` is the response status, or the transport error.
+    pub fn end(self, method: &str, url: &str, outcome: Result<u16, String>) {
+        let mut guard = TRACE.lock().expect("trace lock poisoned");
+        let Some(trace) = guard.as_mut() else {
+            return;
+        };
+        let mut attributes = vec![attr("http.request.method", method), attr("url.full", url)];
+        let error = match outcome {
+            Ok(status) => {
+                attributes.push(json!({
+                    "key": "http.response.status_code",
+                    "value": { "intValue": status.to_string() }
+                }));
+                (status >= 400).then(|| format!("HTTP {status}"))
+            }
+            Err(e) => Some(e),
+        };
+        let span = span_json(
+            trace,
+            &self.span_id,
+            Some(&trace.root_span_id),
+            method,
+            KIND_CLIENT,
+            (self.start, SystemTime::now()),
+            attributes,
+            error,
+        );
+        trace.spans.push(span);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn span_json(
+    trace: &Trace,
+    span_id: &str,
+    parent: Option<&str>,
+    name: &str,
+    kind: u8,
+    (start, end): (SystemTime, SystemTime),
+    attributes: Vec<Value>,
+    error: Option<String>,
+) -> Value {
+    let status = match error {
+        Some(message) => json!({ "code": STATUS_ERROR, "message": message }),
+        None => json!({ "code": STATUS_OK }),
+    };
+    json!({
+        "traceId": trace.trace_id,
+        "spanId": span_id,
+        "parentSpanId": parent.unwrap_or_default(),
+        "name": name,
+        "kind": kind,
+        "startTimeUnixNano": unix_nanos(start),
+        "endTimeUnixNano": unix_nanos(end),
+        "attributes": attributes,
+        "status": status,
+    })
+}
+
+/// An `ExportTraceServiceRequest` in OTLP's JSON encoding.
+fn payload(trace: &Trace) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    attr("service.name", &trace.exporter.service_name),
+                    attr("service.version", SCOPE_VERSION),
+                ]
+            },
+            "scopeSpans": [{
+                "scope": { "name": SCOPE_NAME, "version": SCOPE_VERSION },
+                "spans": trace.spans,
+            }]
+        }]
+    })
+}
+
+fn attr(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// 64-bit integers are strings in OTLP JSON.
+fn unix_nanos(t: SystemTime) -> String {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos())
+        .to_string()
+}
+
+fn trace_id_for(correlation_id: &str) -> String {
+    let hex: String = correlation_id.chars().filter(|c| *c != '-').collect();
+    let is_id = hex.len() == 32
+        && hex.bytes().all(|b| b.is_ascii_hexdigit())
+        && hex.bytes().any(|b| b != b'0');
+    if is_id {
+        hex.to_ascii_lowercase()
+    } else {
+        random_hex(2)
+    }
+}
+
+/// `words` random 64-bit words as lowercase hex.
+fn random_hex(words: usize) -> String {
+    let state = RandomState::new();
+    (0..words)
+        .map(|i| format!("{:016x}", state.hash_one((i, SystemTime::now()))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_with<'a>(
+        vars: &'a [(&'a str, &'a str)],
+    ) -> impl Fn(&str) -> Result<String, std::env::VarError> + 'a {
+        move |key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+                .ok_or(std::env::VarError::NotPresent)
+        }
+    }
+
+    #[test]
+    fn disabled_without_endpoint() {
+        assert_eq!(Exporter::from_env_with(env_with(&[])).unwrap(), None);
+        let env = env_with(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            ("OTEL_SDK_DISABLED", "true"),
+        ]);
+        assert_eq!(Exporter::from_env_with(env).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_standard_env_vars() {
+        let env = env_with(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318/"),
+            (
+                "OTEL_EXPORTER_OTLP_HEADERS",
+                "x-team=platform, authorization=Basic%20abc",
+            ),
+            ("OTEL_SERVICE_NAME", "md-admin"),
+        ]);
+        let exporter = Exporter::from_env_with(env).unwrap().unwrap();
+        assert_eq!(exporter.endpoint, "http://collector:4318/v1/traces");
+        assert_eq!(
+            exporter.headers,
+            [
+                ("x-team".to_string(), "platform".to_string()),
+                ("authorization".to_string(), "Basic abc".to_string()),
+            ]
+        );
+        assert_eq!(exporter.service_name, "md-admin");
+
+        let env = env_with(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://ignored:4318"),
+            (
+                "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+                "http://traces:4318/custom",
+            ),
+        ]);
+        let exporter = Exporter::from_env_with(env).unwrap().unwrap();
+        assert_eq!(exporter.endpoint, "http://traces:4318/custom");
+        assert_eq!(exporter.service_name, "md");
+    }
+
+    #[test]
+    fn rejects_unsupported_protocol_and_bad_headers() {
+        let env = env_with(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "grpc"),
+        ]);
+        let err = Exporter::from_env_with(env).unwrap_err();
+        assert!(err.to_string().contains("only http/json"));
+        let env = env_with(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            ("OTEL_EXPORTER_OTLP_HEADERS", "novalue"),
+        ]);
+        assert!(Exporter::from_env_with(env).is_err());
+    }
+
+    #[test]
+    fn trace_id_reuses_uuid_correlation_ids() {
+        assert_eq!(
+            trace_id_for("0af76519-16cd-43dd-8448-eb211c80319c"),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        let random = trace_id_for("ci-run-42");
+        assert_eq!(random.len(), 32);
+        assert_ne!(random, trace_id_for("ci-run-42"));
+        assert_ne!(
+            trace_id_for("00000000-0000-0000-0000-000000000000"),
+            "00000000000000000000000000000000"
+        );
+    }
+}
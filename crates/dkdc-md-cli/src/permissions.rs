@@ -0,0 +1,359 @@
+//! What each command needs from the token: the role of the user it belongs to and the
+//! capabilities (see `md capabilities`) it must have. One registry serves `md permissions`,
+//! the command reference, and the pre-flight check that stops a command the cached probe
+//! says will be refused, before it calls the API.
+
+use serde::Serialize;
+
+use crate::capabilities::{Access, Report};
+
+/// The role a token's user needs for a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// An org Admin: the users, tokens, ducklings, and active accounts endpoints are
+    /// admin-only.
+    Admin,
+    /// Runs locally; no token needed.
+    None,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::None => "none",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Admin => "a token of an org Admin",
+            Self::None => "no token (runs locally)",
+        }
+    }
+}
+
+pub struct Permission {
+    /// Command path, e.g. `token create`.
+    pub command: &'static str,
+    pub role: Role,
+    /// Capabilities the command cannot run without. Commands taking a resource URI need
+    /// the capability of that resource instead.
+    pub capabilities: &'static [&'static str],
+    /// API calls the command makes, with conditions in parentheses.
+    pub calls: &'static [&'static str],
+}
+
+const SA: &str = "service-accounts";
+const INST: &str = "instances";
+const AA: &str = "active-accounts";
+
+const fn local(command: &'static str) -> Permission {
+    Permission {
+        command,
+        role: Role::None,
+        capabilities: &[],
+        calls: &[],
+    }
+}
+
+const fn admin(
+    command: &'static str,
+    capabilities: &'static [&'static str],
+    calls: &'static [&'static str],
+) -> Permission {
+    Permission {
+        command,
+        role: Role::Admin,
+        capabilities,
+        calls,
+    }
+}
+
+/// Every visible command, in `md --help` order. A test keeps it in step with the CLI.
+pub const PERMISSIONS: &[Permission] = &[
+    admin("service-account create", &[SA], &["POST /v1/users"]),
+    admin(
+        "service-account delete",
+        &[SA, AA],
+        &[
+            "GET /v1/users/{username}/tokens",
+            "GET /v1/active_accounts",
+            "DELETE /v1/users/{username}",
+        ],
+    ),
+    admin(
+        "service-account expiring",
+        &[],
+        &[
+            "GET /v1/users/{username}/tokens (with --deprovision, per expired account)",
+            "GET /v1/users/{username}/instances (with --deprovision, per expired account)",
+            "DELETE /v1/users/{username}/tokens/{token_id} (with --deprovision, per token)",
+            "PUT /v1/users/{username}/instances (with --deprovision, with a read-scaling flock)",
+            "DELETE /v1/users/{username} (with --deprovision, per expired account)",
+        ],
+    ),
+    admin("token list", &[SA], &["GET /v1/users/{username}/tokens"]),
+    admin("token create", &[SA], &["POST /v1/users/{username}/tokens"]),
+    admin(
+        "token delete",
+        &[SA],
+        &["DELETE /v1/users/{username}/tokens/{token_id}"],
+    ),
+    admin(
+        "token revoke-all",
+        &[SA],
+        &[
+            "GET /v1/users/{username}/tokens",
+            "DELETE /v1/users/{username}/tokens/{token_id} (per token)",
+        ],
+    ),
+    admin(
+        "token count",
+        &[SA],
+        &[
+            "GET /v1/active_accounts (with --all-users)",
+            "GET /v1/users/{username}/tokens (per user)",
+        ],
+    ),
+    admin(
+        "token reconcile",
+        &[SA],
+        &[
+            "GET /v1/active_accounts (with --all-users)",
+            "GET /v1/users/{username}/tokens (per user)",
+        ],
+    ),
+    admin(
+        "instance get",
+        &[INST],
+        &["GET /v1/users/{username}/instances"],
+    ),
+    admin(
+        "instance set",
+        &[INST],
+        &[
+            "GET /v1/users/{username}/instances",
+            "PUT /v1/users/{username}/instances",
+        ],
+    ),
+    admin(
+        "instance validate",
+        &[],
+        &["GET /v1/users/{username}/instances (with a username)"],
+    ),
+    admin(
+        "instance scale-down",
+        &[INST],
+        &[
+            "GET /v1/active_accounts (with --all-users)",
+            "GET /v1/users/{username}/instances (per user)",
+            "PUT /v1/users/{username}/instances (per changed user)",
+        ],
+    ),
+    admin("account list-active", &[AA], &["GET /v1/active_accounts"]),
+    admin("account tree", &[AA], &["GET /v1/active_accounts"]),
+    admin("account count", &[AA], &["GET /v1/active_accounts"]),
+    admin(
+        "account export",
+        &[AA, SA, INST],
+        &[
+            "GET /v1/active_accounts",
+            "GET /v1/users/{username}/tokens (per account)",
+            "GET /v1/users/{username}/instances (per account)",
+        ],
+    ),
+    admin(
+        "audit orphans",
+        &[AA, SA],
+        &[
+            "GET /v1/active_accounts",
+            "GET /v1/users/{username}/tokens (per account)",
+        ],
+    ),
+    admin("get", &[], &["GET on the resource's API path"]),
+    admin("delete", &[], &["DELETE on the resource's API path"]),
+    local("describe"),
+    local("schedule add"),
+    local("schedule list"),
+    local("schedule remove"),
+    local("schedule run"),
+    admin(
+        "provision",
+        &[SA],
+        &[
+            "POST /v1/users",
+            "POST /v1/users/{username}/tokens",
+            "GET /v1/users/{username}/instances (with sizes)",
+            "PUT /v1/users/{username}/instances (with sizes)",
+        ],
+    ),
+    admin(
+        "deprovision",
+        &[SA, INST],
+        &[
+            "GET /v1/users/{username}/tokens",
+            "GET /v1/users/{username}/instances",
+            "DELETE /v1/users/{username}/tokens/{token_id} (per token)",
+            "PUT /v1/users/{username}/instances (with a read-scaling flock)",
+            "DELETE /v1/users/{username}",
+        ],
+    ),
+    admin(
+        "connect-string",
+        &[],
+        &[
+            "GET /v1/users/{username}/instances (with --read-scaling)",
+            "GET /v1/users/{username}/tokens (with --read-scaling)",
+        ],
+    ),
+    admin(
+        "test-connection",
+        &[SA],
+        &[
+            "POST /v1/users/{username}/tokens (without --secret)",
+            "DELETE /v1/users/{username}/tokens/{token_id} (without --secret)",
+        ],
+    ),
+    admin(
+        "search",
+        &[AA, SA],
+        &[
+            "GET /v1/active_accounts",
+            "GET /v1/users/{username}/tokens (per account, when the index is stale)",
+        ],
+    ),
+    admin(
+        "sync",
+        &[AA, SA, INST],
+        &[
+            "GET /v1/active_accounts",
+            "GET /v1/users/{username}/tokens (per account)",
+            "GET /v1/users/{username}/instances (per account)",
+        ],
+    ),
+    local("changes"),
+    local("annotate user"),
+    local("annotate list"),
+    admin(
+        "wait duckling",
+        &[INST],
+        &[
+            "GET /v1/active_accounts (per poll)",
+            "GET /v1/users/{username}/instances (per poll, for config conditions)",
+        ],
+    ),
+    admin(
+        "wait account",
+        &[SA],
+        &["GET /v1/users/{username}/tokens (per poll)"],
+    ),
+    admin(
+        "capabilities",
+        &[],
+        &[
+            "GET /v1/users/{username}/tokens",
+            "GET /v1/users/{username}/instances",
+            "GET /v1/active_accounts",
+        ],
+    ),
+    local("permissions"),
+    local("signing-key generate"),
+    local("signing-key show"),
+    local("approve"),
+    local("execute"),
+    local("bundle create"),
+    local("bundle show"),
+    local("bundle execute"),
+    local("auth login"),
+    local("auth logout"),
+    local("auth status"),
+    admin("auth whoami", &[SA], &["GET /v1/users/{username}/tokens"]),
+    local("config get"),
+    local("config set"),
+    local("config list"),
+    local("profile list"),
+    local("profile use"),
+    local("dev check-spec"),
+    local("completions"),
+];
+
+/// The registry entry for command path `command`, e.g. `token create`.
+pub fn lookup(command: &str) -> Option<&'static Permission> {
+    PERMISSIONS.iter().find(|p| p.command == command)
+}
+
+/// Why `command` cannot run, if `report` (a fresh `md capabilities` probe of this token)
+/// shows one of its `required` capabilities is not enabled.
+pub fn preflight(command: &str, required: &[&str], report: Option<&Report>) -> Option<String> {
+    let report = report?;
+    let missing: Vec<&str> = required
+        .iter()
+        .copied()
+        .filter(|c| report.capabilities.get(*c) == Some(&Access::NotEnabled))
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+    let role = lookup(command).map_or(Role::Admin, |p| p.role);
+    Some(format!(
+        "`md {command}` needs {} ({}), which your token lacks (per `md capabilities`, \
+         checked {}); use a token with access, or run `md capabilities --refresh` if access \
+         has changed",
+        missing
+            .iter()
+            .map(|c| format!("`{c}`"))
+            .collect::<Vec<_>>()
+            .join(" and "),
+        role.describe(),
+        report.checked_at
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(capabilities: &[(&str, Access)]) -> Report {
+        serde_json::from_value(serde_json::json!({
+            "key": "k",
+            "checked_at": "2026-01-01T00:00:00Z",
+            "capabilities": capabilities.iter().cloned().collect::<std::collections::BTreeMap<_, _>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn registry_names_known_capabilities_once_per_command() {
+        let known: Vec<&str> = crate::capabilities::CAPABILITIES
+            .iter()
+            .map(|c| c.name)
+            .collect();
+        for (i, p) in PERMISSIONS.iter().enumerate() {
+            assert!(
+                p.capabilities.iter().all(|c| known.contains(c)),
+                "{}",
+                p.command
+            );
+            assert!(
+                PERMISSIONS[..i].iter().all(|q| q.command != p.command),
+                "duplicate entry for {}",
+                p.command
+            );
+            assert_eq!(p.role == Role::None, p.calls.is_empty(), "{}", p.command);
+        }
+    }
+
+    #[test]
+    fn preflight_stops_commands_the_probe_refused() {
+        let report = report(&[(SA, Access::Available), (AA, Access::NotEnabled)]);
+        let msg = preflight("audit orphans", &[AA, SA], Some(&report)).unwrap();
+        assert!(msg.starts_with(
+            "`md audit orphans` needs `active-accounts` (a token of an org Admin), which your \
+             token lacks (per `md capabilities`, checked 2026-01-01T00:00:00Z)"
+        ));
+        assert!(preflight("token list", &[SA], Some(&report)).is_none());
+        assert!(preflight("audit orphans", &[AA], None).is_none());
+    }
+}
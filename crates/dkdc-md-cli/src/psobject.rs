@@ -0,0 +1,94 @@
+//! `-o psobject`: JSON shaped for PowerShell's `ConvertFrom-Json`.
+//!
+//! - A response that only wraps a list (`{"tokens": [...]}`) becomes the list itself, so
+//!   each item reaches the pipeline as its own object.
+//! - Nested objects are flattened into `parent_child` properties, so fields are one
+//!   `.Property` away and `Format-Table` / `Export-Csv` show them.
+//! - The document is printed on one line, which every PowerShell version parses from
+//!   native command output without `-Raw` or `Out-String`.
+
+use serde_json::{Map, Value};
+
+/// Reshape a result for `ConvertFrom-Json`.
+pub fn shape(value: &Value) -> Value {
+    match value {
+        Value::Object(map) if map.len() == 1 => match map.values().next() {
+            Some(Value::Array(items)) => Value::Array(items.iter().map(record).collect()),
+            _ => record(value),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(record).collect()),
+        other => record(other),
+    }
+}
+
+/// One object with nested objects flattened; lists keep their items as (flattened) objects.
+fn record(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut flat = Map::new();
+            flatten(&mut flat, "", map);
+            Value::Object(flat)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(record).collect()),
+        scalar => scalar.clone(),
+    }
+}
+
+fn flatten(flat: &mut Map<String, Value>, prefix: &str, map: &Map<String, Value>) {
+    for (key, value) in map {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}_{key}")
+        };
+        match value {
+            Value::Object(inner) if !inner.is_empty() => flatten(flat, &key, inner),
+            other => {
+                flat.insert(key, record(other));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn unwraps_single_list_responses() {
+        let value = json!({ "tokens": [{ "id": "a" }, { "id": "b" }] });
+        assert_eq!(shape(&value), json!([{ "id": "a" }, { "id": "b" }]));
+        assert_eq!(shape(&json!({ "tokens": [] })), json!([]));
+    }
+
+    #[test]
+    fn flattens_nested_objects() {
+        let value = json!({
+            "username": "svc_x",
+            "token": { "id": "a", "expire_at": null },
+            "instances": { "read_write": { "instance_size": "jumbo" } },
+            "failed": [{ "username": "svc_y", "error": { "status": 404 } }],
+        });
+        assert_eq!(
+            shape(&value),
+            json!({
+                "username": "svc_x",
+                "token_id": "a",
+                "token_expire_at": null,
+                "instances_read_write_instance_size": "jumbo",
+                "failed": [{ "username": "svc_y", "error_status": 404 }],
+            })
+        );
+    }
+
+    #[test]
+    fn single_key_objects_flatten() {
+        let value = json!({ "error": { "status": 401, "message": "no" } });
+        assert_eq!(
+            shape(&value),
+            json!({ "error_status": 401, "error_message": "no" })
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! `--query`: jq filters over a command's JSON output, run in-process with jaq so scripts
+//! need no `jq` binary (minimal containers rarely have one).
+
+use anyhow::{Result, anyhow, bail};
+use jaq_core::load::{Arena, File, Loader};
+use jaq_core::{Compiler, Ctx, Native, RcIter};
+use jaq_json::Val;
+use serde_json::Value;
+
+pub struct Query(jaq_core::Filter<Native<Val>>);
+
+impl Query {
+    /// Compile `code`, with jq's standard library.
+    pub fn parse(code: &str) -> Result<Self> {
+        let invalid = |why: String| anyhow!("invalid --query '{code}': {why}");
+        let arena = Arena::default();
+        let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+        let modules = loader
+            .load(&arena, File { code, path: () })
+            .map_err(|errors| {
+                let why = errors.into_iter().map(|(_, e)| match e {
+                    jaq_core::load::Error::Io(e) => e.into_iter().map(|(_, e)| e).collect(),
+                    jaq_core::load::Error::Lex(e) => e
+                        .into_iter()
+                        .map(|(expect, at)| expected(expect.as_str(), at))
+                        .collect(),
+                    jaq_core::load::Error::Parse(e) => e
+                        .into_iter()
+                        .map(|(expect, at)| expected(expect.as_str(), at))
+                        .collect::<Vec<_>>(),
+                });
+                invalid(why.flatten().collect::<Vec<_>>().join("; "))
+            })?;
+        let filter = Compiler::default()
+            .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+            .compile(modules)
+            .map_err(|errors| {
+                let undefined: Vec<String> = errors
+                    .into_iter()
+                    .flat_map(|(_, e)| e)
+                    .map(|(name, _)| format!("undefined '{name}'"))
+                    .collect();
+                invalid(undefined.join("; "))
+            })?;
+        Ok(Self(filter))
+    }
+
+    /// Run the filter on `input`, returning every value it yields.
+    pub fn run(&self, input: Value) -> Result<Vec<Value>> {
+        let inputs = RcIter::new(core::iter::empty());
+        self.0
+            .run((Ctx::new([], &inputs), Val::from(input)))
+            .map(|v| {
+                v.map(Value::from)
+                    .map_err(|e| anyhow!("--query failed: {e}"))
+            })
+            .collect()
+    }
+
+    /// Run the filter on each JSON document in a command's output (one pretty-printed
+    /// document, or one per line from streaming commands).
+    pub fn apply(&self, output: &[u8]) -> Result<Vec<Value>> {
+        let mut results = Vec::new();
+        for document in serde_json::Deserializer::from_slice(output).into_iter::<Value>() {
+            let Ok(document) = document else {
+                bail!("--query needs JSON output, and this command printed something else");
+            };
+            results.extend(self.run(document)?);
+        }
+        Ok(results)
+    }
+}
+
+/// `expected X at 'rest'`, with the rest of the filter cut short.
+fn expected(what: &str, at: &str) -> String {
+    match at.char_indices().nth(20) {
+        Some((end, _)) => format!("expected {what} at '{}...'", &at[..end]),
+        None if at.is_empty() => format!("expected {what} at the end"),
+        None => format!("expected {what} at '{at}'"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn yields_every_result_of_each_document() {
+        let query = Query::parse(".tokens[] | select(.expire_at == null) | .id").unwrap();
+        let output = b"{\"tokens\": [{\"id\": \"a\", \"expire_at\": null}, {\"id\": \"b\", \"expire_at\": \"x\"}]}\n\
+            {\"tokens\": [{\"id\": \"c\"}]}\n";
+        assert_eq!(query.apply(output).unwrap(), [json!("a"), json!("c")]);
+        assert_eq!(
+            Query::parse("[.[] | length] | add")
+                .unwrap()
+                .run(json!([[1], [2, 3]]))
+                .unwrap(),
+            [json!(3)]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_filters_and_inputs() {
+        let err = Query::parse(".tokens[").err().unwrap().to_string();
+        assert!(
+            err.starts_with("invalid --query '.tokens[': expected"),
+            "{err}"
+        );
+        let err = Query::parse("nosuchfn").err().unwrap().to_string();
+        assert_eq!(err, "invalid --query 'nosuchfn': undefined 'nosuchfn'");
+        let query = Query::parse(".a").unwrap();
+        assert!(query.run(json!([1])).is_err());
+        assert!(query.apply(b"ID NAME\n").is_err());
+    }
+}
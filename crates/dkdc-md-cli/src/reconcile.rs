@@ -0,0 +1,203 @@
+//! `md token reconcile`: compare an externally kept token inventory (a spreadsheet, a
+//! vault listing) against the tokens the API reports, for migrating manually tracked
+//! credentials to md-managed ones.
+
+use std::collections::HashSet;
+use std::io::Read;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One row of an inventory file: `username,id,name`. A row names its token by ID, by
+/// name, or both; the ID wins when both are given.
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    pub username: String,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Tracked tokens, in file order.
+#[derive(Debug, Default)]
+pub struct Inventory(Vec<Entry>);
+
+impl Inventory {
+    /// Read an inventory CSV with a header row. `id` and `name` are each optional, but
+    /// every row needs one of them.
+    pub fn from_csv(reader: impl Read) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (i, record) in csv::Reader::from_reader(reader).deserialize().enumerate() {
+            let entry: Entry =
+                record.with_context(|| format!("invalid inventory file row {}", i + 1))?;
+            if entry.id.is_none() && entry.name.is_none() {
+                bail!(
+                    "invalid inventory file row {}: needs an id or a name",
+                    i + 1
+                );
+            }
+            entries.push(entry);
+        }
+        Ok(Self(entries))
+    }
+
+    /// Usernames in the inventory, in first-seen order.
+    pub fn usernames(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.0
+            .iter()
+            .filter(|e| seen.insert(e.username.as_str()))
+            .map(|e| e.username.clone())
+            .collect()
+    }
+}
+
+/// A token on only one side.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    pub username: String,
+    pub id: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// Tokens found on both sides.
+    pub matched: usize,
+    /// Live tokens the inventory does not track.
+    pub untracked: Vec<Difference>,
+    /// Tracked tokens the API does not have (deleted, expired and cleaned up, or never made).
+    pub missing: Vec<Difference>,
+}
+
+/// Match `inventory` against `live`, each user's `list_tokens` response. Rows are matched
+/// by ID first; rows with only a name then take the first unmatched live token of that
+/// name, so two rows for two same-named tokens match both.
+pub fn reconcile(inventory: &Inventory, live: &[(String, Value)]) -> Report {
+    let mut report = Report::default();
+    for (username, tokens) in live {
+        let tokens: Vec<&Value> = tokens["tokens"].as_array().into_iter().flatten().collect();
+        let mut taken = vec![false; tokens.len()];
+        let rows: Vec<&Entry> = inventory
+            .0
+            .iter()
+            .filter(|e| &e.username == username)
+            .collect();
+        let mut unmatched = Vec::new();
+        for row in rows.iter().filter(|r| r.id.is_some()) {
+            match tokens
+                .iter()
+                .position(|t| t["id"].as_str() == row.id.as_deref())
+            {
+                Some(i) if !taken[i] => taken[i] = true,
+                _ => unmatched.push(*row),
+            }
+        }
+        for row in rows.iter().filter(|r| r.id.is_none()) {
+            let found = tokens
+                .iter()
+                .enumerate()
+                .position(|(i, t)| !taken[i] && t["name"].as_str() == row.name.as_deref());
+            match found {
+                Some(i) => taken[i] = true,
+                None => unmatched.push(*row),
+            }
+        }
+        report.matched += taken.iter().filter(|t| **t).count();
+        report
+            .missing
+            .extend(unmatched.into_iter().map(|row| Difference {
+                username: username.clone(),
+                id: row.id.clone(),
+                name: row.name.clone(),
+            }));
+        report
+            .untracked
+            .extend(
+                tokens
+                    .iter()
+                    .zip(&taken)
+                    .filter(|(_, taken)| !**taken)
+                    .map(|(token, _)| Difference {
+                        username: username.clone(),
+                        id: token["id"].as_str().map(str::to_string),
+                        name: token["name"].as_str().map(str::to_string),
+                    }),
+            );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const INVENTORY: &str = "\
+username,id,name
+svc_etl,tok_1,nightly
+svc_etl,,backfill
+svc_etl,tok_gone,
+svc_bi,,dashboards
+";
+
+    fn live() -> Vec<(String, Value)> {
+        vec![
+            (
+                "svc_etl".into(),
+                json!({ "tokens": [
+                    { "id": "tok_1", "name": "renamed" },
+                    { "id": "tok_2", "name": "backfill" },
+                    { "id": "tok_3", "name": "adhoc" },
+                ]}),
+            ),
+            ("svc_bi".into(), json!({ "tokens": [] })),
+        ]
+    }
+
+    fn diff(username: &str, id: Option<&str>, name: Option<&str>) -> Difference {
+        Difference {
+            username: username.into(),
+            id: id.map(Into::into),
+            name: name.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn reports_tokens_missing_from_either_side() {
+        let inventory = Inventory::from_csv(INVENTORY.as_bytes()).unwrap();
+        assert_eq!(inventory.usernames(), ["svc_etl", "svc_bi"]);
+        assert_eq!(
+            reconcile(&inventory, &live()),
+            Report {
+                matched: 2,
+                untracked: vec![diff("svc_etl", Some("tok_3"), Some("adhoc"))],
+                missing: vec![
+                    diff("svc_etl", Some("tok_gone"), None),
+                    diff("svc_bi", None, Some("dashboards")),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn same_named_tokens_match_one_row_each() {
+        let inventory =
+            Inventory::from_csv("username,name\nsvc,ci\nsvc,ci\nsvc,ci\n".as_bytes()).unwrap();
+        let live = vec![(
+            "svc".to_string(),
+            json!({ "tokens": [{ "id": "a", "name": "ci" }, { "id": "b", "name": "ci" }] }),
+        )];
+        let report = reconcile(&inventory, &live);
+        assert_eq!(report.matched, 2);
+        assert_eq!(report.missing, [diff("svc", None, Some("ci"))]);
+    }
+
+    #[test]
+    fn rows_need_an_id_or_a_name() {
+        let err = Inventory::from_csv("username,id,name\nsvc_etl,,\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("row 1: needs an id or a name"));
+    }
+}
@@ -0,0 +1,218 @@
+//! `md generate docs`: a Markdown command reference built from the clap command tree, so
+//! published runbooks stay in sync with the binary.
+
+use std::fmt::Write;
+
+use clap::{Arg, Command};
+
+use crate::permissions::{self, Role};
+
+/// Args and subcommands clap adds to every command; their meaning is the same everywhere.
+const GENERATED: [&str; 2] = ["help", "version"];
+
+/// Markdown reference for `root` and every visible subcommand: usage, arguments, options
+/// (with defaults, env vars, and accepted values), and the examples in each command's long
+/// help.
+pub fn markdown(root: &Command) -> String {
+    let mut root = root.clone();
+    root.build();
+    let mut doc = String::new();
+    let name = root.get_name();
+    writeln!(doc, "# `{name}` command reference\n").unwrap();
+    if let Some(about) = root.get_long_about().or(root.get_about()) {
+        writeln!(doc, "{about}\n").unwrap();
+    }
+    writeln!(
+        doc,
+        "Generated by `{name} generate docs`{}; do not edit by hand.\n",
+        root.get_version()
+            .map(|v| format!(" from version {v}"))
+            .unwrap_or_default()
+    )
+    .unwrap();
+    let globals: Vec<&Arg> = root.get_arguments().filter(|a| documented(a)).collect();
+    if !globals.is_empty() {
+        doc.push_str("## Global options\n\nAccepted by every command.\n\n");
+        options_table(&mut doc, &globals);
+    }
+    for sub in root.get_subcommands().filter(|c| visible(c)) {
+        command(&mut doc, sub);
+    }
+    doc
+}
+
+fn command(doc: &mut String, cmd: &Command) {
+    let path = cmd.get_bin_name().unwrap_or(cmd.get_name());
+    writeln!(doc, "## `{path}`\n").unwrap();
+    if let Some(about) = cmd.get_long_about().or(cmd.get_about()) {
+        writeln!(doc, "{about}\n").unwrap();
+    }
+    let usage = cmd.clone().render_usage().to_string();
+    writeln!(doc, "```text\n{usage}\n```\n").unwrap();
+    if let Some(p) = path
+        .split_once(' ')
+        .and_then(|(_, c)| permissions::lookup(c))
+    {
+        let capabilities: Vec<String> = p.capabilities.iter().map(|c| format!("`{c}`")).collect();
+        match (p.role, capabilities.is_empty()) {
+            (Role::None, _) => writeln!(doc, "Needs no token.\n").unwrap(),
+            (role, true) => writeln!(doc, "Needs {}.\n", role.describe()).unwrap(),
+            (role, false) => writeln!(
+                doc,
+                "Needs {} with {}.\n",
+                role.describe(),
+                capabilities.join(" and ")
+            )
+            .unwrap(),
+        }
+    }
+
+    let args: Vec<&Arg> = cmd
+        .get_arguments()
+        .filter(|a| documented(a) && !a.is_global_set())
+        .collect();
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) =
+        args.into_iter().partition(|a| a.is_positional());
+    if !positionals.is_empty() {
+        doc.push_str("| Argument | Description |\n| --- | --- |\n");
+        for arg in positionals {
+            let name = value_names(arg).join(" ");
+            writeln!(doc, "| `{name}` | {} |", description(arg)).unwrap();
+        }
+        doc.push('\n');
+    }
+    if !options.is_empty() {
+        options_table(doc, &options);
+    }
+    if let Some(examples) = cmd.get_after_long_help().or(cmd.get_after_help()) {
+        writeln!(doc, "```text\n{}\n```\n", examples.to_string().trim_end()).unwrap();
+    }
+    for sub in cmd.get_subcommands().filter(|c| visible(c)) {
+        command(doc, sub);
+    }
+}
+
+fn visible(cmd: &Command) -> bool {
+    !cmd.is_hide_set() && !GENERATED.contains(&cmd.get_name())
+}
+
+fn documented(arg: &Arg) -> bool {
+    !arg.is_hide_set() && !GENERATED.contains(&arg.get_id().as_str())
+}
+
+/// Value names as `--help` shows them: the arg id if none are set.
+fn value_names(arg: &Arg) -> Vec<String> {
+    match arg.get_value_names() {
+        Some(names) => names.iter().map(|n| n.to_string()).collect(),
+        None => vec![arg.get_id().to_string()],
+    }
+}
+
+fn options_table(doc: &mut String, options: &[&Arg]) {
+    doc.push_str("| Option | Description |\n| --- | --- |\n");
+    for arg in options {
+        let mut flag = match (arg.get_short(), arg.get_long()) {
+            (Some(s), Some(l)) => format!("-{s}, --{l}"),
+            (Some(s), None) => format!("-{s}"),
+            (None, Some(l)) => format!("--{l}"),
+            (None, None) => arg.get_id().to_string(),
+        };
+        if arg.get_action().takes_values() {
+            for name in value_names(arg) {
+                write!(flag, " <{name}>").unwrap();
+            }
+        }
+        writeln!(doc, "| `{flag}` | {} |", description(arg)).unwrap();
+    }
+    doc.push('\n');
+}
+
+/// Help text on one line, then default, env var, and accepted values in parentheses.
+fn description(arg: &Arg) -> String {
+    let help = arg
+        .get_long_help()
+        .or(arg.get_help())
+        .map(|h| h.to_string())
+        .unwrap_or_default();
+    let mut details = Vec::new();
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|v| v.to_string_lossy().into_owned())
+        .collect();
+    if !defaults.is_empty() && !arg.is_hide_default_value_set() && arg.get_action().takes_values() {
+        details.push(format!("default: `{}`", defaults.join(",")));
+    }
+    if let Some(env) = arg.get_env().filter(|_| !arg.is_hide_env_set()) {
+        details.push(format!("env: `{}`", env.to_string_lossy()));
+    }
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| format!("`{}`", v.get_name()))
+        .collect();
+    if !values.is_empty() && arg.get_action().takes_values() {
+        details.push(format!("values: {}", values.join(", ")));
+    }
+    let mut text = help.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !details.is_empty() {
+        write!(text, " ({})", details.join("; ")).unwrap();
+    }
+    text.trim_start().replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ArgAction;
+
+    #[test]
+    fn documents_commands_options_and_examples() {
+        let root = Command::new("md")
+            .about("CLI")
+            .arg(
+                Arg::new("verbose")
+                    .short('v')
+                    .long("verbose")
+                    .global(true)
+                    .action(ArgAction::SetTrue)
+                    .help("Log requests"),
+            )
+            .subcommand(
+                Command::new("token")
+                    .about("Manage tokens")
+                    .subcommand(
+                        Command::new("create")
+                            .about("Create a token")
+                            .after_long_help("Examples:\n  md token create svc -n ci")
+                            .arg(Arg::new("username").required(true).help("Username"))
+                            .arg(
+                                Arg::new("token_type")
+                                    .long("token-type")
+                                    .value_parser(["read-write", "read-scaling"])
+                                    .default_value("read-write")
+                                    .env("MD_TOKEN_TYPE")
+                                    .help("Token type | kind"),
+                            ),
+                    )
+                    .subcommand(Command::new("secret").hide(true)),
+            );
+
+        let doc = markdown(&root);
+        assert!(doc.starts_with("# `md` command reference\n\nCLI\n"));
+        assert!(doc.contains("| `-v, --verbose` | Log requests |"));
+        assert!(doc.contains("## `md token create`\n\nCreate a token\n"));
+        assert!(doc.contains("Usage: md token create [OPTIONS] <username>"));
+        assert!(doc.contains("| `username` | Username |"));
+        assert!(doc.contains(
+            "| `--token-type <token_type>` | Token type \\| kind (default: `read-write`; \
+             env: `MD_TOKEN_TYPE`; values: `read-write`, `read-scaling`) |"
+        ));
+        assert!(doc.contains("Examples:\n  md token create svc -n ci"));
+        // Globals are documented once, not per command; hidden commands are left out.
+        assert_eq!(doc.matches("--verbose").count(), 1);
+        assert!(!doc.contains("secret"));
+        assert!(!doc.contains("md token help"));
+    }
+}
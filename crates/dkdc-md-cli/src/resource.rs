@@ -0,0 +1,199 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use percent_encoding::percent_decode_str;
+
+use crate::client::{
+    ACTIVE_ACCOUNTS, INSTANCES, TOKENS, USERS, active_accounts_path, encode_path,
+    user_instances_path, user_path, user_token_path, user_tokens_path,
+};
+
+/// URI scheme for addressing resources, e.g. `md://users/svc_x/tokens/abc123`.
+pub const SCHEME: &str = "md://";
+
+/// A resource addressed by an `md://` URI. Segments are percent-decoded, so
+/// `md://users/a%2Fb` names the user `a/b`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    User { username: String },
+    Tokens { username: String },
+    Token { username: String, token_id: String },
+    Instances { username: String },
+    ActiveAccounts,
+}
+
+impl Resource {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::User { .. } => "service-account",
+            Self::Tokens { .. } => "token-list",
+            Self::Token { .. } => "token",
+            Self::Instances { .. } => "instance-config",
+            Self::ActiveAccounts => "active-accounts",
+        }
+    }
+
+    /// The REST path this resource maps to.
+    pub fn api_path(&self) -> String {
+        match self {
+            Self::User { username } => user_path(username),
+            Self::Tokens { username } => user_tokens_path(username),
+            Self::Token { username, token_id } => user_token_path(username, token_id),
+            Self::Instances { username } => user_instances_path(username),
+            Self::ActiveAccounts => active_accounts_path(),
+        }
+    }
+
+    /// Verbs that apply to this resource.
+    pub fn verbs(&self) -> &'static [&'static str] {
+        match self {
+            Self::User { .. } | Self::Token { .. } => &["get", "delete", "describe"],
+            Self::Tokens { .. } | Self::Instances { .. } | Self::ActiveAccounts => {
+                &["get", "describe"]
+            }
+        }
+    }
+}
+
+impl FromStr for Resource {
+    type Err = anyhow::Error;
+
+    fn from_str(uri: &str) -> Result<Self> {
+        let rest = uri
+            .strip_prefix(SCHEME)
+            .with_context(|| format!("resource URI must start with '{SCHEME}': {uri}"))?;
+        let segments = rest
+            .trim_end_matches('/')
+            .split('/')
+            .map(|s| {
+                percent_decode_str(s)
+                    .decode_utf8()
+                    .map(|s| s.into_owned())
+                    .with_context(|| format!("invalid percent-encoding in {uri}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if segments.iter().any(String::is_empty) {
+            bail!("empty path segment in {uri}");
+        }
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let resource = match segments.as_slice() {
+            [USERS, username] => Self::User {
+                username: username.to_string(),
+            },
+            [USERS, username, TOKENS] => Self::Tokens {
+                username: username.to_string(),
+            },
+            [USERS, username, TOKENS, token_id] => Self::Token {
+                username: username.to_string(),
+                token_id: token_id.to_string(),
+            },
+            [USERS, username, INSTANCES] => Self::Instances {
+                username: username.to_string(),
+            },
+            [ACTIVE_ACCOUNTS] => Self::ActiveAccounts,
+            _ => bail!(
+                "unknown resource URI {uri}; expected {SCHEME}{USERS}/<username>[/{TOKENS}[/<id>]|/{INSTANCES}] \
+                 or {SCHEME}{ACTIVE_ACCOUNTS}"
+            ),
+        };
+        Ok(resource)
+    }
+}
+
+impl fmt::Display for Resource {
+    /// The canonical URI, with segments percent-encoded.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::User { username } => write!(f, "{SCHEME}{USERS}/{}", encode_path(username)),
+            Self::Tokens { username } => {
+                write!(f, "{SCHEME}{USERS}/{}/{TOKENS}", encode_path(username))
+            }
+            Self::Token { username, token_id } => write!(
+                f,
+                "{SCHEME}{USERS}/{}/{TOKENS}/{}",
+                encode_path(username),
+                encode_path(token_id)
+            ),
+            Self::Instances { username } => {
+                write!(f, "{SCHEME}{USERS}/{}/{INSTANCES}", encode_path(username))
+            }
+            Self::ActiveAccounts => write!(f, "{SCHEME}{ACTIVE_ACCOUNTS}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(uri: &str) -> Resource {
+        uri.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_every_kind() {
+        assert_eq!(
+            parse("md://users/svc_x"),
+            Resource::User {
+                username: "svc_x".into()
+            }
+        );
+        assert_eq!(
+            parse("md://users/svc_x/tokens/"),
+            Resource::Tokens {
+                username: "svc_x".into()
+            }
+        );
+        assert_eq!(
+            parse("md://users/svc_x/tokens/abc123"),
+            Resource::Token {
+                username: "svc_x".into(),
+                token_id: "abc123".into()
+            }
+        );
+        assert_eq!(
+            parse("md://users/svc_x/instances"),
+            Resource::Instances {
+                username: "svc_x".into()
+            }
+        );
+        assert_eq!(parse("md://active_accounts"), Resource::ActiveAccounts);
+    }
+
+    #[test]
+    fn decodes_and_reencodes_segments() {
+        let resource = parse("md://users/a%2Fb/tokens/x%20y");
+        assert_eq!(
+            resource,
+            Resource::Token {
+                username: "a/b".into(),
+                token_id: "x y".into()
+            }
+        );
+        assert_eq!(resource.to_string(), "md://users/a%2Fb/tokens/x%20y");
+        assert_eq!(resource.api_path(), "/v1/users/a%2Fb/tokens/x%20y");
+    }
+
+    #[test]
+    fn rejects_bad_uris() {
+        for uri in [
+            "users/svc_x",
+            "https://users/svc_x",
+            "md://users",
+            "md://users//tokens",
+            "md://users/svc_x/secrets",
+            "md://users/svc_x/tokens/a/b",
+            "md://users/%FF",
+        ] {
+            assert!(uri.parse::<Resource>().is_err(), "{uri}");
+        }
+    }
+
+    #[test]
+    fn only_users_and_tokens_are_deletable() {
+        assert!(parse("md://users/x").verbs().contains(&"delete"));
+        assert!(parse("md://users/x/tokens/t").verbs().contains(&"delete"));
+        assert!(!parse("md://users/x/instances").verbs().contains(&"delete"));
+    }
+}
@@ -0,0 +1,386 @@
+//! `md --sandbox`: an in-memory stand-in for the API, seeded with a small demo org, so
+//! commands can be tried and workflows demonstrated without touching a real org. The
+//! client hands it each request instead of sending it; with a config directory its state
+//! is kept in `sandbox.json` next to `config.toml` between runs.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use ureq::http;
+
+use crate::client::{ACTIVE_ACCOUNTS, INSTANCES, TOKENS, USERS};
+use crate::transport::Transport;
+
+const SANDBOX_FILE: &str = "sandbox.json";
+
+/// Base URL shown for sandboxed requests, e.g. in `--verbose` logs; nothing is sent there.
+pub const BASE_URL: &str = "sandbox://demo";
+
+/// Stands in for the API token, which the sandbox does not check.
+pub const TOKEN: &str = "sandbox";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct State {
+    users: BTreeMap<String, User>,
+    /// Numbers the next created token's ID.
+    next_token: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct User {
+    /// Token metadata as listed (never the secret).
+    tokens: Vec<Value>,
+    /// Instance config as returned by `GET .../instances`.
+    instances: Value,
+}
+
+impl User {
+    fn new() -> Self {
+        Self {
+            tokens: Vec::new(),
+            instances: instances("pulse", "pulse", 0),
+        }
+    }
+
+    /// The user's entry in `GET /v1/active_accounts`: a running read-write duckling, and
+    /// an idle read-scaling one when the flock is not empty.
+    fn account(&self, username: &str) -> Value {
+        let mut ducklings = vec![json!({ "type": "read_write", "status": "running" })];
+        if self.instances["read_scaling"]["flock_size"].as_u64() > Some(0) {
+            ducklings.push(json!({ "type": "read_scaling", "status": "idle" }));
+        }
+        json!({ "username": username, "ducklings": ducklings })
+    }
+}
+
+fn instances(rw_size: &str, rs_size: &str, flock_size: u32) -> Value {
+    json!({
+        "read_write": { "instance_size": rw_size },
+        "read_scaling": { "instance_size": rs_size, "flock_size": flock_size }
+    })
+}
+
+impl State {
+    /// The demo org a new sandbox starts with.
+    fn seed(now: DateTime<Utc>) -> Self {
+        let mut state = Self::default();
+        let at = |days: i64| (now + TimeDelta::days(days)).to_rfc3339();
+        for (username, config, tokens) in [
+            (
+                "svc_etl",
+                instances("standard", "standard", 2),
+                vec![
+                    ("nightly", "read_write", Some(at(-30)), Some(at(60))),
+                    ("old-ci", "read_write", Some(at(-400)), Some(at(-35))),
+                ],
+            ),
+            (
+                "svc_bi",
+                instances("pulse", "pulse", 4),
+                vec![("dashboards", "read_scaling", Some(at(-90)), None)],
+            ),
+            ("svc_ml", instances("jumbo", "pulse", 0), vec![]),
+        ] {
+            let mut user = User::new();
+            user.instances = config;
+            for (name, token_type, created_at, expire_at) in tokens {
+                let id = state.token_id();
+                user.tokens.push(json!({
+                    "id": id,
+                    "name": name,
+                    "token_type": token_type,
+                    "created_at": created_at,
+                    "expire_at": expire_at,
+                }));
+            }
+            state.users.insert(username.to_string(), user);
+        }
+        state
+    }
+
+    fn token_id(&mut self) -> String {
+        self.next_token += 1;
+        format!("tok_sbx_{:04}", self.next_token)
+    }
+
+    /// Status and JSON body for `method` on `path` (without the API version prefix).
+    fn handle(
+        &mut self,
+        method: &http::Method,
+        segments: &[String],
+        body: Option<Value>,
+        now: DateTime<Utc>,
+    ) -> (u16, Value) {
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        let not_found = |what: &str| (404, json!({ "message": format!("{what} not found") }));
+        let body = body.unwrap_or(Value::Null);
+        match (method.as_str(), segments.as_slice()) {
+            ("GET", [ACTIVE_ACCOUNTS]) => {
+                let accounts: Vec<Value> =
+                    self.users.iter().map(|(u, user)| user.account(u)).collect();
+                (200, json!({ "accounts": accounts }))
+            }
+            ("POST", [USERS]) => {
+                let Some(username) = body["username"].as_str().filter(|u| !u.is_empty()) else {
+                    return (400, json!({ "message": "username is required" }));
+                };
+                if self.users.contains_key(username) {
+                    return (
+                        409,
+                        json!({ "message": format!("user '{username}' already exists") }),
+                    );
+                }
+                self.users.insert(username.to_string(), User::new());
+                (200, json!({ "username": username }))
+            }
+            ("DELETE", [USERS, username]) => match self.users.remove(*username) {
+                Some(_) => (200, json!({ "username": username })),
+                None => not_found("user"),
+            },
+            (_, [USERS, username, ..]) if !self.users.contains_key(*username) => not_found("user"),
+            ("GET", [USERS, username, TOKENS]) => {
+                (200, json!({ "tokens": self.users[*username].tokens }))
+            }
+            ("POST", [USERS, username, TOKENS]) => {
+                let Some(name) = body["name"].as_str() else {
+                    return (400, json!({ "message": "name is required" }));
+                };
+                let id = self.token_id();
+                let expire_at = body["ttl"]
+                    .as_i64()
+                    .map(|ttl| (now + TimeDelta::seconds(ttl)).to_rfc3339());
+                let token = json!({
+                    "id": id,
+                    "name": name,
+                    "token_type": body["token_type"].as_str().unwrap_or("read_write"),
+                    "created_at": now.to_rfc3339(),
+                    "expire_at": expire_at,
+                });
+                let user = self.users.get_mut(*username).expect("checked above");
+                user.tokens.push(token.clone());
+                let mut created = token;
+                created["token"] = json!(format!("md_sandbox_{}", id.trim_start_matches("tok_")));
+                (200, created)
+            }
+            ("DELETE", [USERS, username, TOKENS, id]) => {
+                let user = self.users.get_mut(*username).expect("checked above");
+                let before = user.tokens.len();
+                user.tokens.retain(|t| t["id"] != *id);
+                if user.tokens.len() == before {
+                    return not_found("token");
+                }
+                (200, json!({}))
+            }
+            ("GET", [USERS, username, INSTANCES]) => (200, self.users[*username].instances.clone()),
+            ("PUT", [USERS, username, INSTANCES]) => {
+                let config = &body["config"];
+                if !config["read_write"].is_object() || !config["read_scaling"].is_object() {
+                    return (
+                        400,
+                        json!({ "message": "config.read_write and config.read_scaling are required" }),
+                    );
+                }
+                let user = self.users.get_mut(*username).expect("checked above");
+                user.instances = config.clone();
+                (200, config.clone())
+            }
+            _ => (
+                404,
+                json!({ "message": format!("{method} {} is not available in the sandbox", segments.join("/")) }),
+            ),
+        }
+    }
+}
+
+/// The sandboxed API. Requests are served under a lock, so bulk commands that send them
+/// from several threads see a consistent org.
+#[derive(Debug)]
+pub struct Sandbox {
+    state: Mutex<State>,
+    path: Option<PathBuf>,
+}
+
+impl Sandbox {
+    /// The sandbox saved at `path`, or a freshly seeded one if there is none yet. Without
+    /// a path, changes last for this run only.
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let state = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str(&text).with_context(|| {
+                    format!(
+                        "invalid {}; delete it to start a new sandbox",
+                        path.display()
+                    )
+                })?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::seed(Utc::now()),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to read {}", path.display()));
+                }
+            },
+            None => State::seed(Utc::now()),
+        };
+        Ok(Self {
+            state: Mutex::new(state),
+            path,
+        })
+    }
+
+    /// Where the sandbox is kept between runs, if anywhere.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Serve a request for `path`, e.g. `/v1/users/svc_etl/tokens` (any API version), the
+    /// way the API would. Changes are saved before responding.
+    fn serve(&self, method: &http::Method, path: &str, body: Option<&[u8]>) -> (u16, Value) {
+        let body = match body.map(serde_json::from_slice::<Value>).transpose() {
+            Ok(body) => body,
+            Err(e) => return (400, json!({ "message": format!("invalid JSON body: {e}") })),
+        };
+        let path = path.split('?').next().unwrap_or_default();
+        // Drop the leading API version, e.g. "v1".
+        let segments: Vec<String> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .skip(1)
+            .map(|s| {
+                percent_encoding::percent_decode_str(s)
+                    .decode_utf8_lossy()
+                    .into_owned()
+            })
+            .collect();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (status, value) = state.handle(method, &segments, body, Utc::now());
+        if *method != http::Method::GET
+            && (200..300).contains(&status)
+            && let Err(e) = self.save(&state)
+        {
+            return (500, json!({ "message": format!("{e:#}") }));
+        }
+        (status, value)
+    }
+
+    fn save(&self, state: &State) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(state).expect("State serialization is infallible");
+        std::fs::write(path, text + "\n")
+            .with_context(|| format!("failed to save the sandbox to {}", path.display()))
+    }
+}
+
+/// `sandbox.json` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(SANDBOX_FILE)
+}
+
+/// Requests are answered in process; the scheme and host of the base URL are ignored.
+impl Transport for Sandbox {
+    fn run(
+        &self,
+        request: http::Request<()>,
+        body: Option<&[u8]>,
+    ) -> Result<http::Response<ureq::Body>, ureq::Error> {
+        let path = request.uri().path_and_query().map_or("/", |p| p.as_str());
+        let (status, value) = self.serve(request.method(), path, body);
+        let bytes = serde_json::to_vec(&value).expect("Value serialization is infallible");
+        Ok(http::Response::builder()
+            .status(status)
+            .body(
+                ureq::Body::builder()
+                    .mime_type("application/json")
+                    .data(bytes),
+            )
+            .expect("status and body are valid"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(
+        sandbox: &Sandbox,
+        method: http::Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (u16, Value) {
+        let body = body.map(|b| serde_json::to_vec(&b).unwrap());
+        sandbox.serve(&method, path, body.as_deref())
+    }
+
+    #[test]
+    fn serves_the_api_endpoints() {
+        let sandbox = Sandbox::open(None).unwrap();
+        let (status, accounts) = call(&sandbox, http::Method::GET, "/v1/active_accounts", None);
+        assert_eq!(status, 200);
+        assert_eq!(accounts["accounts"].as_array().unwrap().len(), 3);
+
+        let user = json!({ "username": "svc_new" });
+        assert_eq!(
+            call(
+                &sandbox,
+                http::Method::POST,
+                "/v1/users",
+                Some(user.clone())
+            )
+            .0,
+            200
+        );
+        assert_eq!(
+            call(&sandbox, http::Method::POST, "/v1/users", Some(user)).0,
+            409
+        );
+
+        let (status, created) = call(
+            &sandbox,
+            http::Method::POST,
+            "/v2/users/svc_new/tokens",
+            Some(json!({ "name": "ci", "ttl": 3600 })),
+        );
+        assert_eq!(status, 200);
+        assert!(
+            created["token"]
+                .as_str()
+                .unwrap()
+                .starts_with("md_sandbox_")
+        );
+        let (_, list) = call(
+            &sandbox,
+            http::Method::GET,
+            "/v1/users/svc_new/tokens",
+            None,
+        );
+        assert_eq!(list["tokens"][0]["name"], "ci");
+        assert!(list["tokens"][0].get("token").is_none());
+
+        let path = format!(
+            "/v1/users/svc_new/tokens/{}",
+            created["id"].as_str().unwrap()
+        );
+        assert_eq!(call(&sandbox, http::Method::DELETE, &path, None).0, 200);
+        assert_eq!(call(&sandbox, http::Method::DELETE, &path, None).0, 404);
+        assert_eq!(
+            call(
+                &sandbox,
+                http::Method::GET,
+                "/v1/users/svc_gone/instances",
+                None
+            )
+            .0,
+            404
+        );
+        assert_eq!(
+            call(&sandbox, http::Method::DELETE, "/v1/users/svc_new", None).0,
+            200
+        );
+    }
+}
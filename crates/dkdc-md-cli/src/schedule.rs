@@ -0,0 +1,199 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+
+/// File holding schedules, kept next to `config.toml`.
+const SCHEDULES_FILE: &str = "schedules.toml";
+
+/// A command to run on a cron schedule, e.g. `instance set u --flock-size 8` at `0 9 * * 1-5`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub id: u32,
+    /// Standard five-field cron expression, evaluated in local time.
+    pub cron: String,
+    /// `md` arguments, shell-quoted.
+    pub command: String,
+}
+
+impl Schedule {
+    /// `md` arguments for this schedule (without the program name).
+    pub fn args(&self) -> Result<Vec<String>> {
+        split_command(&self.command)
+    }
+
+    /// First run strictly after `after`.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Result<DateTime<Tz>> {
+        parse_cron(&self.cron)?
+            .find_next_occurrence(after, false)
+            .with_context(|| format!("no upcoming run for cron '{}'", self.cron))
+    }
+}
+
+/// All schedules, as stored in `schedules.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Schedules {
+    #[serde(rename = "schedule")]
+    pub entries: Vec<Schedule>,
+}
+
+impl Schedules {
+    /// Load schedules from `path`, or none if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str(&text).with_context(|| format!("invalid {}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let text = toml::to_string(self).context("failed to serialize schedules")?;
+        std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Validate and append a schedule, returning its id.
+    pub fn add(&mut self, cron: &str, command: &str) -> Result<u32> {
+        parse_cron(cron)?;
+        anyhow::ensure!(
+            !split_command(command)?.is_empty(),
+            "scheduled command must not be empty"
+        );
+        let id = self.entries.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+        self.entries.push(Schedule {
+            id,
+            cron: cron.trim().to_string(),
+            command: command.trim().to_string(),
+        });
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: u32) -> Result<Schedule> {
+        let index = self
+            .entries
+            .iter()
+            .position(|s| s.id == id)
+            .with_context(|| format!("no schedule with id {id}"))?;
+        Ok(self.entries.remove(index))
+    }
+
+    /// The earliest upcoming run after `after` and every schedule due at that moment.
+    pub fn next_due<Tz: TimeZone>(
+        &self,
+        after: &DateTime<Tz>,
+    ) -> Result<Option<(DateTime<Tz>, Vec<&Schedule>)>> {
+        let mut next: Option<(DateTime<Tz>, Vec<&Schedule>)> = None;
+        for schedule in &self.entries {
+            let at = schedule.next_after(after)?;
+            match &mut next {
+                Some((t, due)) if at == *t => due.push(schedule),
+                Some((t, _)) if at > *t => {}
+                _ => next = Some((at, vec![schedule])),
+            }
+        }
+        Ok(next)
+    }
+}
+
+/// `schedules.toml` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(SCHEDULES_FILE)
+}
+
+fn parse_cron(expr: &str) -> Result<Cron> {
+    Cron::new(expr.trim())
+        .parse()
+        .with_context(|| format!("invalid cron expression '{expr}'"))
+}
+
+/// Split a shell-quoted `md` command line into arguments.
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    shlex::split(command).with_context(|| format!("invalid quoting in command '{command}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn add_assigns_increasing_ids() {
+        let mut schedules = Schedules::default();
+        assert_eq!(schedules.add("0 9 * * 1-5", "instance get u").unwrap(), 1);
+        assert_eq!(schedules.add("0 18 * * *", "instance get v").unwrap(), 2);
+        schedules.remove(1).unwrap();
+        assert_eq!(schedules.add("0 7 * * *", "instance get w").unwrap(), 3);
+    }
+
+    #[test]
+    fn add_rejects_invalid_input() {
+        let mut schedules = Schedules::default();
+        assert!(schedules.add("every monday", "instance get u").is_err());
+        assert!(schedules.add("0 9 * * *", "   ").is_err());
+        assert!(schedules.add("0 9 * * *", "instance get 'u").is_err());
+        assert!(schedules.entries.is_empty());
+    }
+
+    #[test]
+    fn weekday_range_is_monday_to_friday() {
+        let mut schedules = Schedules::default();
+        schedules.add("0 9 * * 1-5", "instance get u").unwrap();
+        // 2026-10-16 is a Friday; the next weekday 09:00 after Friday noon is Monday.
+        let next = schedules.entries[0]
+            .next_after(&at("2026-10-16T12:00:00Z"))
+            .unwrap();
+        assert_eq!(next, at("2026-10-19T09:00:00Z"));
+    }
+
+    #[test]
+    fn next_due_groups_simultaneous_schedules() {
+        let mut schedules = Schedules::default();
+        schedules.add("0 9 * * *", "instance get a").unwrap();
+        schedules.add("30 8 * * *", "instance get b").unwrap();
+        schedules.add("0 9 * * *", "instance get c").unwrap();
+        let (when, due) = schedules
+            .next_due(&at("2026-10-16T08:45:00Z"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(when, at("2026-10-16T09:00:00Z"));
+        let ids: Vec<u32> = due.iter().map(|s| s.id).collect();
+        assert_eq!(ids, [1, 3]);
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut schedules = Schedules::default();
+        schedules
+            .add("0 9 * * 1-5", "instance set u --flock-size 8")
+            .unwrap();
+        let text = toml::to_string(&schedules).unwrap();
+        let loaded: Schedules = toml::from_str(&text).unwrap();
+        assert_eq!(loaded.entries, schedules.entries);
+        assert_eq!(
+            loaded.entries[0].args().unwrap(),
+            ["instance", "set", "u", "--flock-size", "8"]
+        );
+    }
+
+    #[test]
+    fn schedules_live_next_to_config() {
+        assert_eq!(
+            path_for(Path::new("/home/u/.config/md/config.toml")),
+            Path::new("/home/u/.config/md/schedules.toml")
+        );
+    }
+}
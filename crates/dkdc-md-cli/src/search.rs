@@ -0,0 +1,237 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::client::MotherduckClient;
+
+/// Index of accounts and token metadata, cached next to `config.toml`.
+const INDEX_FILE: &str = "search-index.json";
+/// How long a cached index is reused before `md search` fetches again.
+const MAX_AGE: TimeDelta = TimeDelta::minutes(10);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexedToken {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexedAccount {
+    pub username: String,
+    pub tokens: Vec<IndexedToken>,
+}
+
+/// Usernames, token names, and token IDs of every active account (no secrets).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Index {
+    /// [`cache_key`](crate::capabilities::cache_key) of the base URL and token used.
+    key: String,
+    /// RFC 3339 time the index was built.
+    pub built_at: String,
+    pub accounts: Vec<IndexedAccount>,
+}
+
+/// Which field of an account or token matched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Username,
+    TokenName,
+    TokenId,
+}
+
+impl Field {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Username => "username",
+            Self::TokenName => "token_name",
+            Self::TokenId => "token_id",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Match<'a> {
+    pub field: Field,
+    pub username: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_name: Option<&'a str>,
+}
+
+impl Index {
+    /// Fetch token listings for `usernames`, several at a time.
+    pub fn build(
+        client: &MotherduckClient,
+        usernames: Vec<String>,
+        key: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Self> {
+        let lists = client
+            .list_tokens_bulk(&usernames)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::from_listings(
+            key,
+            now,
+            usernames.iter().map(String::as_str).zip(&lists),
+        ))
+    }
+
+    /// An index of token listings already at hand, e.g. from the local inventory.
+    pub fn from_listings<'a>(
+        key: &str,
+        now: DateTime<Utc>,
+        listings: impl IntoIterator<Item = (&'a str, &'a serde_json::Value)>,
+    ) -> Self {
+        Self {
+            key: key.to_string(),
+            built_at: now.to_rfc3339(),
+            accounts: listings
+                .into_iter()
+                .map(|(username, list)| account(username, list))
+                .collect(),
+        }
+    }
+
+    /// The cached index for `key`, if it is younger than ten minutes.
+    pub fn load_fresh(path: &Path, key: &str, now: DateTime<Utc>) -> Option<Self> {
+        let text = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str::<Self>(&text).ok().filter(|i| {
+            i.key == key
+                && DateTime::parse_from_rfc3339(&i.built_at)
+                    .is_ok_and(|t| now - t.to_utc() < MAX_AGE)
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let text = serde_json::to_string(self).expect("Index serialization is infallible");
+        std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Everything containing `pattern`, ignoring case.
+    pub fn search(&self, pattern: &str) -> Vec<Match<'_>> {
+        let pattern = pattern.to_lowercase();
+        let hit = |s: &str| s.to_lowercase().contains(&pattern);
+        let mut matches = Vec::new();
+        for acct in &self.accounts {
+            if hit(&acct.username) {
+                matches.push(Match {
+                    field: Field::Username,
+                    username: &acct.username,
+                    token_id: None,
+                    token_name: None,
+                });
+            }
+            for token in &acct.tokens {
+                let field = if hit(&token.id) {
+                    Field::TokenId
+                } else if token.name.as_deref().is_some_and(hit) {
+                    Field::TokenName
+                } else {
+                    continue;
+                };
+                matches.push(Match {
+                    field,
+                    username: &acct.username,
+                    token_id: Some(&token.id),
+                    token_name: token.name.as_deref(),
+                });
+            }
+        }
+        matches
+    }
+}
+
+fn account(username: &str, list: &serde_json::Value) -> IndexedAccount {
+    let tokens = list["tokens"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| {
+            Some(IndexedToken {
+                id: t["id"].as_str()?.to_string(),
+                name: t["name"].as_str().map(str::to_string),
+            })
+        })
+        .collect();
+    IndexedAccount {
+        username: username.to_string(),
+        tokens,
+    }
+}
+
+/// `search-index.json` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(INDEX_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> Index {
+        Index {
+            key: "k".into(),
+            built_at: "2026-01-01T00:00:00+00:00".into(),
+            accounts: vec![
+                IndexedAccount {
+                    username: "svc_etl".into(),
+                    tokens: vec![
+                        IndexedToken {
+                            id: "abc123".into(),
+                            name: Some("nightly".into()),
+                        },
+                        IndexedToken {
+                            id: "def456".into(),
+                            name: None,
+                        },
+                    ],
+                },
+                IndexedAccount {
+                    username: "svc_bi".into(),
+                    tokens: vec![IndexedToken {
+                        id: "ghi789".into(),
+                        name: Some("ETL-backfill".into()),
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn finds_token_owner_by_id() {
+        let index = index();
+        assert_eq!(
+            index.search("abc123"),
+            [Match {
+                field: Field::TokenId,
+                username: "svc_etl",
+                token_id: Some("abc123"),
+                token_name: Some("nightly"),
+            }]
+        );
+    }
+
+    #[test]
+    fn matches_usernames_and_names_ignoring_case() {
+        let index = index();
+        let fields: Vec<(Field, &str)> = index
+            .search("etl")
+            .iter()
+            .map(|m| (m.field, m.username))
+            .collect();
+        assert_eq!(
+            fields,
+            [(Field::Username, "svc_etl"), (Field::TokenName, "svc_bi")]
+        );
+        assert!(index.search("nomatch").is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! `-o sh`: results as shell variable assignments, safe to `eval`.
+//!
+//! Objects flatten to `PARENT_KEY` names and arrays to `PARENT_0_KEY`, `PARENT_1_KEY`, ...
+//! plus `PARENT_COUNT`. Every value is single-quoted, so nothing in it is expanded.
+
+use std::io::{self, Write};
+
+use serde_json::Value;
+
+/// Write `value` as `NAME='value'` lines, with names under `prefix` (none if empty).
+pub fn write(out: &mut dyn Write, prefix: &str, value: &Value) -> io::Result<()> {
+    for (name, value) in assignments(prefix, value) {
+        writeln!(out, "{name}={}", quote(&value))?;
+    }
+    Ok(())
+}
+
+/// Flatten `value` into `(name, value)` pairs. A scalar at the top level without a
+/// prefix is named `RESULT`.
+pub fn assignments(prefix: &str, value: &Value) -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    flatten(&mut vars, &name(prefix), value);
+    vars
+}
+
+fn flatten(vars: &mut Vec<(String, String)>, prefix: &str, value: &Value) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            name(key)
+        } else {
+            format!("{prefix}_{}", name(key))
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(vars, &join(key), value);
+            }
+        }
+        Value::Array(items) => {
+            let prefix = if prefix.is_empty() { "RESULT" } else { prefix };
+            vars.push((format!("{prefix}_COUNT"), items.len().to_string()));
+            for (i, item) in items.iter().enumerate() {
+                flatten(vars, &format!("{prefix}_{i}"), item);
+            }
+        }
+        scalar => {
+            let prefix = if prefix.is_empty() { "RESULT" } else { prefix };
+            let text = match scalar {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            vars.push((prefix.to_string(), text));
+        }
+    }
+}
+
+/// Uppercase `key` and replace anything but letters, digits, and `_` with `_`; prefix
+/// `_` if it would start with a digit.
+fn name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Single-quote `s` for POSIX shells: `'` becomes `'\''`.
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(prefix: &str, value: &Value) -> String {
+        let mut out = Vec::new();
+        write(&mut out, prefix, value).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn quotes_everything_the_shell_would_expand() {
+        assert_eq!(quote("plain"), "'plain'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(
+            quote("$(rm -rf ~) `x` \"y\"\n"),
+            "'$(rm -rf ~) `x` \"y\"\n'"
+        );
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn flattens_objects_and_arrays() {
+        let value = json!({
+            "id": "tok_1",
+            "expire_at": null,
+            "tokens": [{ "name": "ci" }, { "name": "o'brien" }],
+            "ok": true,
+        });
+        assert_eq!(
+            render("token", &value),
+            "TOKEN_EXPIRE_AT=''\n\
+             TOKEN_ID='tok_1'\n\
+             TOKEN_OK='true'\n\
+             TOKEN_TOKENS_COUNT='2'\n\
+             TOKEN_TOKENS_0_NAME='ci'\n\
+             TOKEN_TOKENS_1_NAME='o'\\''brien'\n"
+        );
+    }
+
+    #[test]
+    fn names_are_valid_identifiers() {
+        let value = json!({ "by-user": { "svc.etl": 3, "9lives": 1 } });
+        assert_eq!(
+            render("", &value),
+            "BY_USER__9LIVES='1'\nBY_USER_SVC_ETL='3'\n"
+        );
+        assert_eq!(render("", &json!("x")), "RESULT='x'\n");
+        assert_eq!(
+            render("", &json!(["a"])),
+            "RESULT_COUNT='1'\nRESULT_0='a'\n"
+        );
+    }
+}
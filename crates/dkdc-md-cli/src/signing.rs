@@ -0,0 +1,141 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use ed25519_dalek::{Signature, Signer, VerifyingKey};
+
+/// Operator signing key (PKCS#8 Ed25519), kept next to `config.toml`.
+const KEY_FILE: &str = "signing-key.pk8";
+
+/// An operator's Ed25519 key. Public keys and signatures are exchanged as lowercase hex.
+/// ed25519-dalek rather than ring, so builds with the native-tls backend carry no ring.
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    /// Create a key at `path`, readable only by the current user. Refuses to replace an
+    /// existing key, since anything it signed could no longer be attributed.
+    pub fn generate(path: &Path) -> Result<Self> {
+        let key = Self::random()?;
+        let pkcs8 = key
+            .0
+            .to_pkcs8_der()
+            .map_err(|e| anyhow!("failed to encode signing key: {e}"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path).with_context(|| {
+            format!(
+                "failed to create {} (delete it first to replace the key)",
+                path.display()
+            )
+        })?;
+        file.write_all(pkcs8.as_bytes())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(key)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| {
+            format!(
+                "no signing key at {} (create one with `md signing-key generate`)",
+                path.display()
+            )
+        })?;
+        Self::from_pkcs8(&bytes).with_context(|| format!("invalid signing key {}", path.display()))
+    }
+
+    /// A key that is never written to disk.
+    #[cfg(test)]
+    pub fn ephemeral() -> Self {
+        Self::random().unwrap()
+    }
+
+    fn random() -> Result<Self> {
+        let mut seed = [0; ed25519_dalek::SECRET_KEY_LENGTH];
+        getrandom::getrandom(&mut seed).map_err(|_| anyhow!("failed to generate signing key"))?;
+        Ok(Self(ed25519_dalek::SigningKey::from_bytes(&seed)))
+    }
+
+    /// Keys written by earlier versions (PKCS#8 v2, from ring) load unchanged.
+    fn from_pkcs8(bytes: &[u8]) -> Result<Self> {
+        ed25519_dalek::SigningKey::from_pkcs8_der(bytes)
+            .map(Self)
+            .map_err(|e| anyhow!("{e}"))
+    }
+
+    pub fn public_key(&self) -> String {
+        to_hex(self.0.verifying_key().as_bytes())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> String {
+        to_hex(&self.0.sign(message).to_bytes())
+    }
+}
+
+/// Check that `signature` is `public_key`'s signature of `message`.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let public_key = from_hex(public_key).context("invalid public key")?;
+    let signature = from_hex(signature).context("invalid signature")?;
+    let public_key = <[u8; 32]>::try_from(public_key.as_slice())
+        .ok()
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .context("invalid public key")?;
+    let signature = Signature::from_slice(&signature).context("invalid signature")?;
+    public_key
+        .verify_strict(message, &signature)
+        .map_err(|_| anyhow!("signature does not match"))
+}
+
+/// `signing-key.pk8` in the same directory as the config file.
+pub fn path_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(KEY_FILE)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>> {
+    anyhow::ensure!(
+        s.len().is_multiple_of(2) && s.is_ascii(),
+        "expected an even number of hex digits"
+    );
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).context("expected hex digits"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_only_for_the_signed_message_and_key() {
+        let (key, other) = (SigningKey::ephemeral(), SigningKey::ephemeral());
+        let signature = key.sign(b"delete svc_x");
+        assert_eq!(key.public_key().len(), 64);
+        assert!(verify(&key.public_key(), b"delete svc_x", &signature).is_ok());
+        assert!(verify(&key.public_key(), b"delete svc_y", &signature).is_err());
+        assert!(verify(&other.public_key(), b"delete svc_x", &signature).is_err());
+        assert!(verify("zz", b"delete svc_x", &signature).is_err());
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(to_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(from_hex("000fff").unwrap(), [0, 15, 255]);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("éé").is_err());
+    }
+}
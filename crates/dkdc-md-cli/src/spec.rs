@@ -0,0 +1,337 @@
+//! `md dev check-spec`: compare the endpoints md calls, and the body fields it sends and
+//! reads, against an OpenAPI spec, so API changes show up before a command breaks on them.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// An API operation md uses, with the body fields it depends on. Fields are dotted paths;
+/// `[]` steps into array items, e.g. `tokens[].id`.
+pub struct Endpoint {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub request: &'static [&'static str],
+    pub response: &'static [&'static str],
+}
+
+const fn endpoint(
+    method: &'static str,
+    path: &'static str,
+    request: &'static [&'static str],
+    response: &'static [&'static str],
+) -> Endpoint {
+    Endpoint {
+        method,
+        path,
+        request,
+        response,
+    }
+}
+
+const INSTANCE_CONFIG: &[&str] = &[
+    "read_write.instance_size",
+    "read_scaling.instance_size",
+    "read_scaling.flock_size",
+];
+
+/// Every operation the client calls. A field belongs here when a command reads or sends it,
+/// not merely because the API returns it.
+pub const ENDPOINTS: &[Endpoint] = &[
+    endpoint("POST", "/v1/users", &["username"], &[]),
+    endpoint("DELETE", "/v1/users/{username}", &[], &[]),
+    endpoint(
+        "GET",
+        "/v1/users/{username}/tokens",
+        &[],
+        &[
+            "tokens[].id",
+            "tokens[].name",
+            "tokens[].token_type",
+            "tokens[].expire_at",
+            "tokens[].created_at",
+        ],
+    ),
+    endpoint(
+        "POST",
+        "/v1/users/{username}/tokens",
+        &["name", "ttl", "token_type"],
+        &["id", "token"],
+    ),
+    endpoint("DELETE", "/v1/users/{username}/tokens/{token_id}", &[], &[]),
+    endpoint(
+        "GET",
+        "/v1/users/{username}/instances",
+        &[],
+        INSTANCE_CONFIG,
+    ),
+    endpoint(
+        "PUT",
+        "/v1/users/{username}/instances",
+        &[
+            "config.read_write.instance_size",
+            "config.read_scaling.instance_size",
+            "config.read_scaling.flock_size",
+        ],
+        INSTANCE_CONFIG,
+    ),
+    endpoint(
+        "GET",
+        "/v1/active_accounts",
+        &[],
+        &[
+            "accounts[].username",
+            "accounts[].ducklings[].type",
+            "accounts[].ducklings[].status",
+        ],
+    ),
+];
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+/// A field md uses that the spec's schema for its body no longer has.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RemovedField {
+    pub endpoint: String,
+    /// `request` or `response`.
+    pub body: &'static str,
+    pub field: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Drift {
+    /// Operations in the spec that md does not call.
+    pub unimplemented: Vec<String>,
+    /// Operations md calls that the spec does not have.
+    pub removed_endpoints: Vec<String>,
+    pub removed_fields: Vec<RemovedField>,
+    /// Bodies md uses that the spec gives no schema for, so their fields went unchecked.
+    pub unchecked: Vec<String>,
+}
+
+impl Drift {
+    /// Whether md uses something the spec no longer has.
+    pub fn breaking(&self) -> bool {
+        !self.removed_endpoints.is_empty() || !self.removed_fields.is_empty()
+    }
+}
+
+/// Read an OpenAPI spec, as YAML or JSON.
+pub fn load(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let yaml: serde_yaml_ng::Value = serde_yaml_ng::from_str(&text)
+        .with_context(|| format!("{} is not valid YAML or JSON", path.display()))?;
+    // Through serde so that integer keys (unquoted status codes) become strings.
+    let spec: Value = serde_json::to_value(yaml)
+        .with_context(|| format!("{} has keys that are not strings", path.display()))?;
+    anyhow::ensure!(
+        spec["paths"].is_object(),
+        "{} is not an OpenAPI spec: no `paths`",
+        path.display()
+    );
+    Ok(spec)
+}
+
+/// Compare `endpoints` against `spec`. Path parameters match whatever their names.
+pub fn check(spec: &Value, endpoints: &[Endpoint]) -> Drift {
+    let mut drift = Drift::default();
+    let operations: Vec<(String, &str, &Value)> = spec["paths"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .flat_map(|(path, item)| {
+            METHODS.iter().filter_map(move |method| {
+                Some((method.to_uppercase(), path.as_str(), item.get(*method)?))
+            })
+        })
+        .collect();
+
+    for (method, path, _) in &operations {
+        if !endpoints
+            .iter()
+            .any(|e| e.method == method && same_path(e.path, path))
+        {
+            drift.unimplemented.push(format!("{method} {path}"));
+        }
+    }
+    for endpoint in endpoints {
+        let name = format!("{} {}", endpoint.method, endpoint.path);
+        let Some((_, _, op)) = operations
+            .iter()
+            .find(|(method, path, _)| method == endpoint.method && same_path(endpoint.path, path))
+        else {
+            drift.removed_endpoints.push(name);
+            continue;
+        };
+        let request = resolve(spec, &op["requestBody"]);
+        let response = op["responses"]
+            .as_object()
+            .and_then(|r| r.iter().find(|(code, _)| code.starts_with('2')))
+            .map(|(_, r)| resolve(spec, r));
+        let bodies = [
+            ("request", Some(request), endpoint.request),
+            ("response", response, endpoint.response),
+        ];
+        for (body, described, fields) in bodies {
+            if fields.is_empty() {
+                continue;
+            }
+            let schema = described
+                .and_then(|d| d["content"]["application/json"].get("schema"))
+                .map(|s| resolve(spec, s));
+            let Some(schema) = schema else {
+                drift.unchecked.push(format!("{name} {body}"));
+                continue;
+            };
+            for field in fields {
+                if lookup(spec, schema, field) == Some(false) {
+                    drift.removed_fields.push(RemovedField {
+                        endpoint: name.clone(),
+                        body,
+                        field: field.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    drift
+}
+
+/// Whether two path templates are the same apart from parameter names.
+fn same_path(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<_>, Vec<_>) = (a.split('/').collect(), b.split('/').collect());
+    a.len() == b.len()
+        && a.iter()
+            .zip(&b)
+            .all(|(a, b)| a == b || (a.starts_with('{') && b.starts_with('{')))
+}
+
+/// Follow a local `$ref` (`#/components/...`), if any.
+fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+    match value["$ref"]
+        .as_str()
+        .and_then(|r| spec.pointer(r.trim_start_matches('#')))
+    {
+        Some(target) => resolve(spec, target),
+        None => value,
+    }
+}
+
+/// Whether `schema` has `field`, or `None` where it does not say (no `properties`).
+fn lookup(spec: &Value, schema: &Value, field: &str) -> Option<bool> {
+    let (step, rest) = match field.split_once('.') {
+        Some((step, rest)) => (step, Some(rest)),
+        None => (field, None),
+    };
+    let (name, array) = match step.strip_suffix("[]") {
+        Some(name) => (name, true),
+        None => (step, false),
+    };
+    let schema = resolve(spec, schema);
+    // Composed schemas have the field if any part does.
+    for key in ["allOf", "oneOf", "anyOf"] {
+        if let Some(parts) = schema[key].as_array() {
+            let found: Vec<Option<bool>> = parts.iter().map(|p| lookup(spec, p, field)).collect();
+            return if found.contains(&Some(true)) {
+                Some(true)
+            } else if found.contains(&None) {
+                None
+            } else {
+                Some(false)
+            };
+        }
+    }
+    let properties = schema["properties"].as_object()?;
+    let Some(property) = properties.get(name) else {
+        return Some(false);
+    };
+    let property = match array {
+        true => resolve(spec, property).get("items")?,
+        false => property,
+    };
+    match rest {
+        Some(rest) => lookup(spec, property, rest),
+        None => Some(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec() -> Value {
+        json!({
+            "paths": {
+                "/v1/users/{user}/tokens": {
+                    "get": { "responses": { "200": { "content": { "application/json": {
+                        "schema": { "type": "object", "properties": {
+                            "tokens": { "type": "array", "items": { "$ref": "#/components/schemas/Token" } }
+                        }}
+                    }}}}},
+                    "post": {
+                        "requestBody": { "content": { "application/json": {
+                            "schema": { "allOf": [{ "properties": { "name": {} } }, { "properties": { "ttl": {} } }] }
+                        }}},
+                        "responses": { "201": { "description": "created" } }
+                    }
+                },
+                "/v1/users/{user}/tokens/{id}/rotate": { "post": { "responses": {} } }
+            },
+            "components": { "schemas": { "Token": { "type": "object", "properties": { "id": {}, "name": {} } } } }
+        })
+    }
+
+    const ENDPOINTS: &[Endpoint] = &[
+        endpoint(
+            "GET",
+            "/v1/users/{username}/tokens",
+            &[],
+            &["tokens[].id", "tokens[].expire_at"],
+        ),
+        endpoint(
+            "POST",
+            "/v1/users/{username}/tokens",
+            &["name", "ttl", "token_type"],
+            &["id"],
+        ),
+        endpoint("DELETE", "/v1/users/{username}", &[], &[]),
+    ];
+
+    #[test]
+    fn reports_unimplemented_and_removed_endpoints_and_fields() {
+        let removed = |endpoint: &str, body, field: &str| RemovedField {
+            endpoint: endpoint.into(),
+            body,
+            field: field.into(),
+        };
+        let drift = check(&spec(), ENDPOINTS);
+        assert_eq!(
+            drift,
+            Drift {
+                unimplemented: vec!["POST /v1/users/{user}/tokens/{id}/rotate".into()],
+                removed_endpoints: vec!["DELETE /v1/users/{username}".into()],
+                removed_fields: vec![
+                    removed(
+                        "GET /v1/users/{username}/tokens",
+                        "response",
+                        "tokens[].expire_at"
+                    ),
+                    removed("POST /v1/users/{username}/tokens", "request", "token_type"),
+                ],
+                unchecked: vec!["POST /v1/users/{username}/tokens response".into()],
+            }
+        );
+        assert!(drift.breaking());
+    }
+
+    #[test]
+    fn free_form_schemas_leave_fields_unknown() {
+        let spec = json!({ "properties": { "config": { "type": "object" } } });
+        assert_eq!(lookup(&spec, &spec, "config.flock_size"), None);
+        assert_eq!(lookup(&spec, &spec, "config"), Some(true));
+        assert_eq!(lookup(&spec, &spec, "size"), Some(false));
+    }
+}
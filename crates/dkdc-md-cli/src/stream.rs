@@ -0,0 +1,139 @@
+//! Incremental parsing of list responses, so very large listings are handled one element
+//! at a time instead of being buffered into a single `Value`.
+
+use std::fmt;
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+/// Parse a JSON object from `reader`, calling `f` with each element of the array under
+/// `key` as soon as it is parsed. Other keys are skipped; a missing key yields nothing.
+pub fn for_each_in_array(
+    reader: impl Read,
+    key: &str,
+    mut f: impl FnMut(Value) -> Result<()>,
+) -> Result<()> {
+    let mut failed = None;
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let parsed = de.deserialize_map(ObjectVisitor {
+        key,
+        f: &mut f,
+        failed: &mut failed,
+    });
+    // An error from `f` surfaces as a serde error; report the original instead.
+    if let Some(err) = failed {
+        return Err(err);
+    }
+    parsed.context("invalid JSON in response")?;
+    de.end().context("invalid JSON in response")
+}
+
+struct ObjectVisitor<'a, F> {
+    key: &'a str,
+    f: &'a mut F,
+    failed: &'a mut Option<anyhow::Error>,
+}
+
+impl<'de, F: FnMut(Value) -> Result<()>> Visitor<'de> for ObjectVisitor<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an object with a '{}' array", self.key)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(k) = map.next_key::<String>()? {
+            if k == self.key {
+                map.next_value_seed(ArrayVisitor {
+                    f: &mut *self.f,
+                    failed: &mut *self.failed,
+                })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ArrayVisitor<'a, F> {
+    f: &'a mut F,
+    failed: &'a mut Option<anyhow::Error>,
+}
+
+impl<'de, F: FnMut(Value) -> Result<()>> DeserializeSeed<'de> for ArrayVisitor<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+        de.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(Value) -> Result<()>> Visitor<'de> for ArrayVisitor<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(item) = seq.next_element::<Value>()? {
+            if let Err(err) = (self.f)(item) {
+                *self.failed = Some(err);
+                return Err(serde::de::Error::custom("aborted"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn collect(body: &str) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        for_each_in_array(body.as_bytes(), "accounts", |v| {
+            items.push(v);
+            Ok(())
+        })?;
+        Ok(items)
+    }
+
+    #[test]
+    fn yields_each_element_and_skips_other_keys() {
+        let body = r#"{"next": {"a": [1]}, "accounts": [{"username": "a"}, {"username": "b"}], "total": 2}"#;
+        assert_eq!(
+            collect(body).unwrap(),
+            [json!({"username": "a"}), json!({"username": "b"})]
+        );
+    }
+
+    #[test]
+    fn missing_key_yields_nothing() {
+        assert!(collect(r#"{"other": []}"#).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(collect(r#"{"accounts": [{"username": "a"},"#).is_err());
+        assert!(collect(r#"{"accounts": {}}"#).is_err());
+        assert!(collect(r#"{"accounts": []} trailing"#).is_err());
+    }
+
+    #[test]
+    fn callback_error_stops_parsing() {
+        let mut seen = 0;
+        let err = for_each_in_array(r#"{"accounts": [1, 2, 3]}"#.as_bytes(), "accounts", |_| {
+            seen += 1;
+            anyhow::ensure!(seen < 2, "stop");
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "stop");
+        assert_eq!(seen, 2);
+    }
+}
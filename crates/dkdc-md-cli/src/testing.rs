@@ -0,0 +1,264 @@
+//! Test support for code built on this crate: an in-process mock of the MotherDuck API
+//! and canned responses shaped like the real ones. Enabled by the `testing` feature.
+//!
+//! ```no_run
+//! use dkdc_md_cli::MotherduckClient;
+//! use dkdc_md_cli::testing::{MockServer, fixtures};
+//!
+//! let server = MockServer::start();
+//! server.mock("GET", "/v1/users/svc_test/tokens", 200, fixtures::token_list());
+//! let client = MotherduckClient::builder("test-token")
+//!     .base_url(&server.url())
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(client.list_tokens("svc_test").unwrap(), fixtures::token_list());
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use serde_json::{Value, json};
+
+/// A request received by [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    /// Value of the first header named `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Request body parsed as JSON (`Value::Null` if empty or invalid).
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+struct Route {
+    method: String,
+    path: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<Route>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// HTTP server on an ephemeral localhost port answering registered routes with canned
+/// responses. Unmatched requests get a 404 with a MotherDuck-style error body.
+pub struct MockServer {
+    server: Arc<tiny_http::Server>,
+    state: Arc<Mutex<State>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").expect("bind mock server"));
+        let state = Arc::new(Mutex::new(State::default()));
+        let handle = {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || serve(&server, &state))
+        };
+        Self {
+            server,
+            state,
+            handle: Some(handle),
+        }
+    }
+
+    /// Base URL to pass to [`MotherduckClientBuilder::base_url`](crate::client::MotherduckClientBuilder::base_url).
+    pub fn url(&self) -> String {
+        let addr = self.server.server_addr().to_ip().expect("TCP listener");
+        format!("http://{addr}")
+    }
+
+    /// Answer `method path` with `status` and a JSON `body`. Later registrations for the
+    /// same route take precedence, so a test can change a response mid-flow.
+    pub fn mock(&self, method: &str, path: &str, status: u16, body: Value) {
+        self.mock_raw(method, path, status, &body.to_string());
+    }
+
+    /// Like [`mock`](Self::mock) but with a verbatim (possibly non-JSON) body.
+    pub fn mock_raw(&self, method: &str, path: &str, status: u16, body: &str) {
+        self.mock_with_headers(method, path, status, &[], body);
+    }
+
+    /// Like [`mock_raw`](Self::mock_raw) with a body that need not be UTF-8.
+    pub fn mock_bytes(&self, method: &str, path: &str, status: u16, body: &[u8]) {
+        self.route(method, path, status, &[], body);
+    }
+
+    /// Like [`mock_raw`](Self::mock_raw), also sending `headers`, e.g. `Retry-After`.
+    pub fn mock_with_headers(
+        &self,
+        method: &str,
+        path: &str,
+        status: u16,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) {
+        self.route(method, path, status, headers, body.as_bytes());
+    }
+
+    fn route(&self, method: &str, path: &str, status: u16, headers: &[(&str, &str)], body: &[u8]) {
+        self.state.lock().unwrap().routes.push(Route {
+            method: method.to_ascii_uppercase(),
+            path: path.to_string(),
+            status,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.to_vec(),
+        });
+    }
+
+    /// All requests received so far, in arrival order.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn serve(server: &tiny_http::Server, state: &Mutex<State>) {
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let _ = request.as_reader().read_to_string(&mut body);
+        let recorded = RecordedRequest {
+            method: request.method().as_str().to_string(),
+            path: request.url().to_string(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|h| (h.field.as_str().to_string(), h.value.as_str().to_string()))
+                .collect(),
+            body,
+        };
+
+        let (status, headers, body) = {
+            let mut state = state.lock().unwrap();
+            let found = state
+                .routes
+                .iter()
+                .rev()
+                .find(|r| r.method == recorded.method && r.path == recorded.path)
+                .map(|r| (r.status, r.headers.clone(), r.body.clone()));
+            let message = format!("no mock for {} {}", recorded.method, recorded.path);
+            state.requests.push(recorded);
+            found.unwrap_or_else(|| {
+                let body = json!({ "message": message }).to_string();
+                (404, Vec::new(), body.into_bytes())
+            })
+        };
+
+        let content_type =
+            tiny_http::Header::from_bytes("Content-Type", "application/json").expect("valid");
+        let mut response = tiny_http::Response::from_data(body)
+            .with_status_code(status)
+            .with_header(content_type);
+        for (name, value) in headers {
+            response.add_header(
+                tiny_http::Header::from_bytes(name.as_bytes(), value.as_bytes())
+                    .expect("valid header"),
+            );
+        }
+        let _ = request.respond(response);
+    }
+}
+
+/// Canned API responses matching the shapes the CLI relies on.
+pub mod fixtures {
+    use serde_json::{Value, json};
+
+    pub const USERNAME: &str = "svc_test";
+    pub const TOKEN_ID: &str = "tok_123";
+
+    /// Response to `POST /v1/users`.
+    pub fn user() -> Value {
+        json!({ "username": USERNAME })
+    }
+
+    /// A single read-write token as returned in listings.
+    pub fn token() -> Value {
+        json!({
+            "id": TOKEN_ID,
+            "name": "ci",
+            "token_type": "read_write",
+            "expire_at": "2030-01-01T00:00:00Z"
+        })
+    }
+
+    /// Response to `POST /v1/users/{username}/tokens` (includes the secret).
+    pub fn created_token() -> Value {
+        let mut token = token();
+        token["token"] = json!("md_secret_value");
+        token
+    }
+
+    /// Response to `GET /v1/users/{username}/tokens`.
+    pub fn token_list() -> Value {
+        json!({
+            "tokens": [
+                token(),
+                {
+                    "id": "tok_456",
+                    "name": "reader",
+                    "token_type": "read_scaling",
+                    "expire_at": null
+                }
+            ]
+        })
+    }
+
+    /// Response to `GET`/`PUT /v1/users/{username}/instances`.
+    pub fn duckling_config() -> Value {
+        json!({
+            "read_write": { "instance_size": "standard" },
+            "read_scaling": { "instance_size": "standard", "flock_size": 4 }
+        })
+    }
+
+    /// Response to `GET /v1/active_accounts`.
+    pub fn active_accounts() -> Value {
+        json!({
+            "accounts": [
+                {
+                    "username": USERNAME,
+                    "ducklings": [
+                        { "type": "read_write", "status": "running" },
+                        { "type": "read_scaling", "status": "idle" }
+                    ]
+                },
+                { "username": "svc_other", "ducklings": [] }
+            ]
+        })
+    }
+
+    /// Error body in the API's `{"message": ...}` shape.
+    pub fn error(message: &str) -> Value {
+        json!({ "message": message })
+    }
+}
@@ -0,0 +1,112 @@
+//! How requests reach the API. [`MotherduckClient`](crate::MotherduckClient) builds each
+//! request and hands it to a [`Transport`]: HTTP through ureq, with the TLS backend chosen
+//! by cargo features, or the in-process sandbox (`md --sandbox`).
+//!
+//! TLS features (at least one is required; `native-tls` wins when both are on):
+//!
+//! - `rustls` (default): pure-Rust TLS on ring with bundled webpki roots.
+//! - `native-tls`: the platform's TLS library (SChannel on Windows, Secure Transport on
+//!   macOS, OpenSSL elsewhere), for targets where ring's rustls backend does not build or
+//!   where the system certificate store must be used.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ureq::tls::{TlsConfig, TlsProvider};
+use ureq::{Agent, Body, http};
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("dkdc-md-cli needs a TLS backend: enable the `rustls` or `native-tls` feature");
+
+/// Sends one request and returns the response, whatever its status. An `Err` is a
+/// transport failure (DNS, connect, TLS, timeout), which the client may retry.
+pub(crate) trait Transport: Send + Sync {
+    fn run(
+        &self,
+        request: http::Request<()>,
+        body: Option<&[u8]>,
+    ) -> Result<http::Response<Body>, ureq::Error>;
+}
+
+/// HTTP(S) through a ureq agent.
+pub(crate) struct Http {
+    agent: Agent,
+}
+
+impl Http {
+    pub fn new(timeout: Duration, proxy: Option<&str>) -> Result<Self> {
+        let proxy = proxy
+            .map(|p| ureq::Proxy::new(p).with_context(|| format!("invalid proxy '{p}'")))
+            .transpose()?;
+        let mut config = Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .tls_config(tls_config());
+        if proxy.is_some() {
+            config = config.proxy(proxy);
+        }
+        Ok(Self {
+            agent: config.build().into(),
+        })
+    }
+}
+
+impl Transport for Http {
+    fn run(
+        &self,
+        request: http::Request<()>,
+        body: Option<&[u8]>,
+    ) -> Result<http::Response<Body>, ureq::Error> {
+        match body {
+            Some(bytes) => {
+                let (parts, ()) = request.into_parts();
+                self.agent
+                    .run(http::Request::from_parts(parts, bytes.to_vec()))
+            }
+            None => self.agent.run(request),
+        }
+    }
+}
+
+fn tls_config() -> TlsConfig {
+    let provider = if cfg!(feature = "native-tls") {
+        TlsProvider::NativeTls
+    } else {
+        TlsProvider::Rustls
+    };
+    TlsConfig::builder().provider(provider).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn native_tls_takes_precedence() {
+        assert_eq!(tls_config().provider(), TlsProvider::NativeTls);
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    #[test]
+    fn rustls_is_the_default() {
+        assert_eq!(tls_config().provider(), TlsProvider::Rustls);
+    }
+
+    #[test]
+    fn transport_failures_are_errors_not_responses() {
+        // Nothing listens on port 9 (discard) on a test machine; the refusal must surface
+        // as a transport error so the client can retry it.
+        let http = Http::new(Duration::from_secs(5), None).unwrap();
+        let request = http::Request::get("http://127.0.0.1:9/").body(()).unwrap();
+        assert!(http.run(request, None).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_proxy() {
+        let err = Http::new(Duration::from_secs(1), Some("not a proxy"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("invalid proxy"));
+    }
+}
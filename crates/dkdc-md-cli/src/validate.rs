@@ -0,0 +1,130 @@
+//! `md instance validate`: check a proposed duckling config before sending it, reporting
+//! every problem at once instead of one API 400 per attempt.
+
+use std::ops::RangeInclusive;
+
+use serde_json::Value;
+
+use crate::capabilities::{Access, Report};
+
+/// Instance sizes the API accepts.
+pub const INSTANCE_SIZES: [&str; 5] = ["pulse", "standard", "jumbo", "mega", "giga"];
+/// Read-scaling flock sizes the API accepts.
+pub const FLOCK_RANGE: RangeInclusive<u64> = 0..=64;
+
+/// Every way `config` (the `config` object of a `PUT .../instances` body) would be
+/// rejected. `report`, a cached `md capabilities` report, adds whether the plan allows it.
+pub fn violations(config: &Value, report: Option<&Report>) -> Vec<String> {
+    let mut errors = Vec::new();
+    if !config.is_object() {
+        errors.push(format!("config: expected an object, got {config}"));
+        return errors;
+    }
+    if let Some(report) = report
+        && report.capabilities.get("instances") == Some(&Access::NotEnabled)
+    {
+        errors.push("`instances` is not enabled for your plan or token".to_string());
+    }
+
+    for section in ["read_write", "read_scaling"] {
+        match &config[section] {
+            Value::Null => errors.push(format!("{section}: missing")),
+            Value::Object(_) => {}
+            other => errors.push(format!("{section}: expected an object, got {other}")),
+        }
+    }
+
+    for section in ["read_write", "read_scaling"] {
+        let at = format!("{section}.instance_size");
+        match &config[section]["instance_size"] {
+            Value::Null if config[section].is_object() => errors.push(format!("{at}: missing")),
+            Value::Null => {}
+            Value::String(s) if !INSTANCE_SIZES.contains(&s.as_str()) => errors.push(format!(
+                "{at}: {s:?} is not one of {}",
+                INSTANCE_SIZES.join(", ")
+            )),
+            Value::String(_) => {}
+            other => errors.push(format!("{at}: expected a string, got {other}")),
+        }
+    }
+
+    let at = "read_scaling.flock_size";
+    match &config["read_scaling"]["flock_size"] {
+        Value::Null if config["read_scaling"].is_object() => errors.push(format!("{at}: missing")),
+        Value::Null => {}
+        value => match value.as_u64() {
+            Some(n) if !FLOCK_RANGE.contains(&n) => errors.push(format!(
+                "{at}: {n} is outside {}-{}",
+                FLOCK_RANGE.start(),
+                FLOCK_RANGE.end()
+            )),
+            Some(_) => {}
+            None => errors.push(format!("{at}: expected a whole number, got {value}")),
+        },
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn report(access: Access) -> Report {
+        serde_json::from_value(json!({
+            "key": "k",
+            "checked_at": "2026-01-01T00:00:00Z",
+            "capabilities": { "instances": access },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_a_valid_config() {
+        let config = json!({
+            "read_write": { "instance_size": "standard" },
+            "read_scaling": { "instance_size": "pulse", "flock_size": 4, "min_flock_size": 1 }
+        });
+        assert!(violations(&config, None).is_empty());
+    }
+
+    #[test]
+    fn reports_every_violation_at_once() {
+        let config = json!({
+            "read_write": { "instance_size": "huge" },
+            "read_scaling": { "flock_size": 80 }
+        });
+        assert_eq!(
+            violations(&config, None),
+            [
+                "read_write.instance_size: \"huge\" is not one of pulse, standard, jumbo, mega, giga",
+                "read_scaling.instance_size: missing",
+                "read_scaling.flock_size: 80 is outside 0-64",
+            ]
+        );
+        assert_eq!(
+            violations(&json!({ "read_write": "pulse" }), None),
+            [
+                "read_write: expected an object, got \"pulse\"",
+                "read_scaling: missing",
+            ]
+        );
+        assert_eq!(
+            violations(&json!([]), None),
+            ["config: expected an object, got []"]
+        );
+    }
+
+    #[test]
+    fn reports_instances_not_enabled_from_the_capabilities_report() {
+        let config = json!({
+            "read_write": { "instance_size": "jumbo" },
+            "read_scaling": { "instance_size": "standard", "flock_size": 32 }
+        });
+        assert_eq!(
+            violations(&config, Some(&report(Access::NotEnabled))),
+            ["`instances` is not enabled for your plan or token"]
+        );
+        assert!(violations(&config, Some(&report(Access::Available))).is_empty());
+    }
+}
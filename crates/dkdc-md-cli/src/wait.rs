@@ -0,0 +1,159 @@
+//! `md wait`: poll the API until a condition holds, so deployment scripts do not move on
+//! before a duckling is running or an account deletion has propagated.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde_json::Value;
+
+/// A duckling property `md wait duckling --until` can check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// `status` in the active accounts listing, e.g. running.
+    Status,
+    /// `instance_size` in the instance config.
+    Size,
+    /// `flock_size` in the instance config.
+    FlockSize,
+}
+
+impl Field {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Size => "size",
+            Self::FlockSize => "flock_size",
+        }
+    }
+
+    /// Whether the field comes from the instance config rather than the account listing.
+    pub fn in_config(self) -> bool {
+        !matches!(self, Self::Status)
+    }
+}
+
+/// `KEY=VALUE`, e.g. `status=running`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub field: Field,
+    pub value: String,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got '{s}'"))?;
+        let field = match key {
+            "status" => Field::Status,
+            "size" => Field::Size,
+            "flock_size" => Field::FlockSize,
+            _ => {
+                return Err(format!(
+                    "unknown key '{key}' (expected status, size, or flock_size)"
+                ));
+            }
+        };
+        Ok(Self {
+            field,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.field.name(), self.value)
+    }
+}
+
+impl Condition {
+    /// The field's current value for the `kind` duckling (`read_write` or `read_scaling`),
+    /// or "-" if absent. `account` is the user's entry in the active accounts listing and
+    /// `config` their instance config.
+    pub fn observe(&self, kind: &str, account: Option<&Value>, config: &Value) -> String {
+        let value = match self.field {
+            Field::Status => account
+                .and_then(|a| a["ducklings"].as_array())
+                .into_iter()
+                .flatten()
+                .find(|d| d["type"] == kind)
+                .map(|d| d["status"].clone()),
+            Field::Size => Some(config[kind]["instance_size"].clone()),
+            Field::FlockSize => Some(config[kind]["flock_size"].clone()),
+        };
+        match value {
+            Some(Value::String(s)) => s,
+            Some(Value::Number(n)) => n.to_string(),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+/// Call `check` every `interval` until it returns true (then `Ok(true)`) or `timeout`
+/// has passed (then `Ok(false)`). The first check is immediate.
+pub fn poll(
+    timeout: Duration,
+    interval: Duration,
+    mut check: impl FnMut() -> Result<bool>,
+) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if check()? {
+            return Ok(true);
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(false);
+        }
+        std::thread::sleep(interval.min(left));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_and_observes_conditions() {
+        let status: Condition = "status=running".parse().unwrap();
+        let flock: Condition = "flock_size=4".parse().unwrap();
+        assert!("colour=blue".parse::<Condition>().is_err());
+        assert!("status".parse::<Condition>().is_err());
+        assert_eq!(flock.to_string(), "flock_size=4");
+
+        let account = json!({ "ducklings": [
+            { "type": "read_write", "status": "running" },
+            { "type": "read_scaling", "status": "idle" }
+        ] });
+        let config = json!({ "read_scaling": { "instance_size": "pulse", "flock_size": 4 } });
+        assert_eq!(
+            status.observe("read_write", Some(&account), &config),
+            "running"
+        );
+        assert_eq!(
+            status.observe("read_scaling", Some(&account), &config),
+            "idle"
+        );
+        assert_eq!(status.observe("read_write", None, &config), "-");
+        assert_eq!(flock.observe("read_scaling", None, &config), "4");
+    }
+
+    #[test]
+    fn poll_stops_at_timeout() {
+        let mut checks = 0;
+        let met = poll(Duration::from_millis(30), Duration::from_millis(10), || {
+            checks += 1;
+            Ok(false)
+        })
+        .unwrap();
+        assert!(!met);
+        assert!(checks >= 2);
+        assert!(poll(Duration::ZERO, Duration::ZERO, || Ok(true)).unwrap());
+    }
+}
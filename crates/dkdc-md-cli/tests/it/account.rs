@@ -0,0 +1,356 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+
+use crate::{TempDir, md, md_in, stderr, stdout};
+
+#[test]
+fn list_active_renders_ducklings() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+
+    let out = stdout(&md(&server, &["account", "list-active"]));
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("svc_test"));
+    assert!(lines[1].ends_with("read_write (running), read_scaling (idle)"));
+    assert_eq!(lines[2].trim_end(), "svc_other");
+}
+
+#[test]
+fn list_active_stream_prints_json_lines() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+
+    let out = stdout(&md(&server, &["account", "list-active", "--stream"]));
+    let accounts: Vec<serde_json::Value> = out
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        accounts,
+        fixtures::active_accounts()["accounts"].as_array().unwrap()[..]
+    );
+}
+
+#[test]
+fn list_active_ndjson_prints_one_account_per_line() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+
+    let out = stdout(&md(&server, &["-o", "ndjson", "account", "list-active"]));
+    let accounts: Vec<serde_json::Value> = out
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        accounts,
+        fixtures::active_accounts()["accounts"].as_array().unwrap()[..]
+    );
+}
+
+#[test]
+fn rejected_token_error_includes_hint() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        401,
+        fixtures::error("Unauthorized"),
+    );
+
+    let output = md(
+        &server,
+        &["account", "list-active", "--correlation-id", "run-42"],
+    );
+    assert!(!output.status.success());
+    assert_eq!(
+        stderr(&output).trim_end(),
+        "Error: the token was rejected (expired or revoked?); pass a current one with --token \
+         or MOTHERDUCK_TOKEN (correlation id run-42): GET /v1/active_accounts: API error (401): Unauthorized"
+    );
+}
+
+#[test]
+fn json_mode_reports_errors_as_json() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_gone/instances",
+        404,
+        fixtures::error("user not found"),
+    );
+
+    let output = md(&server, &["-o", "json", "instance", "get", "svc_gone"]);
+    assert!(!output.status.success());
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(body["error"]["status"], 404);
+    assert_eq!(
+        body["error"]["message"],
+        "GET /v1/users/svc_gone/instances: API error (404): user not found"
+    );
+    assert!(stderr(&output).starts_with("Error: GET /v1/users/svc_gone/instances"));
+
+    let output = md(
+        &server,
+        &["-o", "json", "instance", "get", "svc_gone", "--token", ""],
+    );
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(body["error"]["status"].is_null());
+}
+
+#[test]
+fn every_request_carries_one_correlation_id() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let output = md(&server, &["token", "count", "--all-users", "--verbose"]);
+    assert!(output.status.success());
+    let requests = server.requests();
+    let id = requests[0].header("x-correlation-id").unwrap();
+    assert_eq!(id.len(), 36);
+    assert!(
+        requests
+            .iter()
+            .all(|r| r.header("x-correlation-id") == Some(id))
+    );
+    let log = stderr(&output);
+    assert_eq!(log.lines().count(), requests.len());
+    assert!(log.starts_with(&format!(
+        "md: GET {}/v1/active_accounts -> 200 OK in ",
+        server.url()
+    )));
+    assert!(log.contains(&format!("[correlation id {id}]")));
+
+    server.mock(
+        "GET",
+        "/v1/users/svc_gone/tokens",
+        404,
+        fixtures::error("no"),
+    );
+    let output = md(
+        &server,
+        &[
+            "-o",
+            "json",
+            "token",
+            "list",
+            "svc_gone",
+            "--correlation-id",
+            "trace-7",
+        ],
+    );
+    let body: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(body["error"]["correlation_id"], "trace-7");
+    assert_eq!(
+        server.requests().last().unwrap().header("x-correlation-id"),
+        Some("trace-7")
+    );
+}
+
+#[test]
+fn user_agent_suffix_identifies_the_pipeline() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    let args = [
+        "account",
+        "list-active",
+        "--user-agent-suffix",
+        "airflow-dag=provision",
+    ];
+    stdout(&md(&server, &args));
+    let agent = server.requests()[0]
+        .header("user-agent")
+        .unwrap()
+        .to_string();
+    assert!(agent.starts_with("dkdc-md-cli/"));
+    assert!(agent.ends_with(" airflow-dag=provision"));
+
+    let output = md(
+        &server,
+        &["account", "list-active", "--user-agent-suffix", ""],
+    );
+    assert!(stderr(&output).contains("invalid user agent suffix"));
+}
+
+#[test]
+fn count_filters_by_duckling_status() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+
+    assert_eq!(stdout(&md(&server, &["account", "count"])), "2\n");
+    assert_eq!(
+        stdout(&md(&server, &["account", "count", "--status", "running"])),
+        "1\n"
+    );
+    let out = stdout(&md(&server, &["-o", "json", "account", "count"]));
+    let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        value,
+        serde_json::json!({ "count": 2, "by_status": { "idle": 1, "running": 1 } })
+    );
+}
+
+/// Active accounts with token listings and instance configs for each.
+fn mock_org(server: &MockServer) {
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/tokens",
+        200,
+        serde_json::json!({ "tokens": [] }),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+}
+
+#[test]
+fn tree_nests_ducklings_with_sizes() {
+    let server = MockServer::start();
+    mock_org(&server);
+
+    let out = stdout(&md(&server, &["account", "tree"]));
+    assert_eq!(
+        out,
+        "svc_test (2 tokens)\n\
+         ├─ read_write (running, standard)\n\
+         └─ read_scaling (idle, standard, flock 4)\n\
+         svc_other (0 tokens)\n"
+    );
+}
+
+#[test]
+fn export_writes_archive_without_secrets() {
+    let server = MockServer::start();
+    mock_org(&server);
+    let mut token = fixtures::token_list();
+    token["tokens"][0]["token"] = "md_secret_value".into();
+    server.mock("GET", "/v1/users/svc_test/tokens", 200, token);
+    let dir = TempDir::new();
+
+    let out = stdout(&md_in(&dir, &server, &["account", "export", "backup.json"]));
+    assert_eq!(out, "exported 2 accounts to backup.json\n");
+    let text = std::fs::read_to_string(dir.0.join("backup.json")).unwrap();
+    assert!(!text.contains("md_secret_value"));
+    let archive: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(archive["format"], "md-export");
+    assert_eq!(archive["base_url"], server.url());
+    let account = &archive["accounts"][0];
+    assert_eq!(account["username"], "svc_test");
+    assert_eq!(account["instances"], fixtures::duckling_config());
+    assert_eq!(account["tokens"][1]["id"], "tok_456");
+    assert_eq!(account["account"]["ducklings"][0]["status"], "running");
+
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/instances",
+        500,
+        fixtures::error("down"),
+    );
+    let output = md_in(&dir, &server, &["account", "export", "partial.json"]);
+    assert!(stderr(&output).contains("failed to export 'svc_other'"));
+    assert!(!dir.0.join("partial.json").exists());
+}
+
+#[test]
+fn fail_if_empty_exits_nonzero_when_nothing_matches() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    let count = |status: &str| {
+        md(
+            &server,
+            &["account", "count", "--status", status, "--fail-if-empty"],
+        )
+    };
+
+    assert_eq!(stdout(&count("running")), "1\n");
+    let output = count("stopped");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+    assert!(stderr(&output).contains("no results (--fail-if-empty)"));
+
+    for username in ["svc_test", "svc_other"] {
+        server.mock(
+            "GET",
+            &format!("/v1/users/{username}/tokens"),
+            200,
+            fixtures::token_list(),
+        );
+    }
+    let output = md(
+        &server,
+        &["--api-mode", "--fail-if-empty", "search", "nothing-matches"],
+    );
+    assert_eq!(output.status.code(), Some(11));
+    let value: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(value["error"]["code"], "empty");
+
+    let output = md(&server, &["--fail-if-empty", "instance", "get", "svc_test"]);
+    assert!(stderr(&output).contains("--fail-if-empty is not supported by `md instance get`"));
+}
@@ -0,0 +1,61 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::Value;
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+#[test]
+fn annotations_merge_into_listings_and_describe() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    let dir = TempDir::new();
+
+    let output = md_in(&dir, &server, &["annotate", "user", "svc_test"]);
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("no annotations for 'svc_test'"));
+    let args = [
+        "annotate",
+        "user",
+        "svc_test",
+        "--owner",
+        "data-platform",
+        "--ticket",
+        "JIRA-123",
+    ];
+    stdout(&md_in(&dir, &server, &args));
+    assert!(dir.0.join("annotations.toml").exists());
+
+    let out = stdout(&md_in(&dir, &server, &["account", "list-active"]));
+    let svc_test = out.lines().find(|l| l.starts_with("svc_test")).unwrap();
+    assert!(svc_test.ends_with("data-platform  JIRA-123"), "{out}");
+    assert!(out.lines().next().unwrap().contains("OWNER"));
+    let svc_other = out.lines().find(|l| l.starts_with("svc_other")).unwrap();
+    assert!(
+        svc_other.split_whitespace().eq(["svc_other", "-", "-"]),
+        "{out}"
+    );
+
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "json", "describe", "md://users/svc_test"],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value["annotations"]["owner"], "data-platform");
+    let out = stdout(&md_in(&dir, &server, &["describe", "md://users/svc_test"]));
+    assert!(out.contains("ticket:   JIRA-123"), "{out}");
+
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["annotate", "user", "svc_test", "--clear"],
+    ));
+    let out = stdout(&md_in(&dir, &server, &["account", "list-active"]));
+    assert!(!out.contains("OWNER"), "{out}");
+    let out = stdout(&md_in(&dir, &server, &["annotate", "list"]));
+    assert_eq!(out, "", "clearing every field removes the entry");
+}
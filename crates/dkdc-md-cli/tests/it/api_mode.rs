@@ -0,0 +1,78 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::md;
+
+fn envelope(output: &std::process::Output) -> Value {
+    serde_json::from_slice(&output.stdout).expect("stdout is one JSON document")
+}
+
+#[test]
+fn success_wraps_result_in_envelope() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let output = md(&server, &["--api-mode", "token", "list", "svc_test"]);
+    assert_eq!(output.status.code(), Some(0));
+    let value = envelope(&output);
+    assert_eq!(value["version"], 1);
+    assert_eq!(value["ok"], true);
+    assert_eq!(value["result"], fixtures::token_list());
+    assert_eq!(value["error"], Value::Null);
+}
+
+#[test]
+fn failures_have_distinct_exit_codes() {
+    let server = MockServer::start();
+    server.mock("GET", "/v1/users/svc_test/tokens", 401, json!({}));
+
+    let cases: [(&[&str], i32, &str); 4] = [
+        (&["token", "list", "svc_test"], 4, "unauthorized"),
+        (&["instance", "get", "svc_test"], 5, "not_found"),
+        (
+            &["token", "delete", "svc_test", "tok_123"],
+            3,
+            "confirmation_required",
+        ),
+        (&["-o", "text", "token", "list", "svc_test"], 1, "error"),
+    ];
+    for (args, exit_code, code) in cases {
+        let output = md(&server, &[&["--api-mode"], args].concat());
+        assert_eq!(output.status.code(), Some(exit_code), "{args:?}");
+        let value = envelope(&output);
+        assert_eq!(value["ok"], false);
+        assert_eq!(value["error"]["code"], code, "{args:?}");
+        assert_eq!(value["error"]["exit_code"], exit_code);
+    }
+}
+
+#[test]
+fn partial_failure_keeps_report() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_456",
+        200,
+        json!({}),
+    );
+
+    let output = md(
+        &server,
+        &["--api-mode", "-y", "token", "revoke-all", "svc_test"],
+    );
+    assert_eq!(output.status.code(), Some(10));
+    let value = envelope(&output);
+    assert_eq!(value["error"]["code"], "partial_failure");
+    assert_eq!(value["result"]["failed"], json!(["tok_123"]));
+}
@@ -0,0 +1,95 @@
+use dkdc_md_cli::testing::MockServer;
+use serde_json::json;
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+/// A config directory with its own signing key; returns the public key.
+fn operator(server: &MockServer) -> (TempDir, String) {
+    let dir = TempDir::new();
+    let public_key = stdout(&md_in(&dir, server, &["signing-key", "generate"]));
+    (dir, public_key.trim().to_string())
+}
+
+#[test]
+fn destructive_command_runs_only_after_second_operator_approves() {
+    let server = MockServer::start();
+    server.mock("GET", "/v1/active_accounts", 200, json!({ "accounts": [] }));
+    server.mock("DELETE", "/v1/users/svc_test", 200, json!({}));
+    let (requester, requester_key) = operator(&server);
+    let (approver, approver_key) = operator(&server);
+    let config = format!("[approval]\nrequired = true\napprovers = [\"{approver_key}\"]");
+    for dir in [&requester, &approver] {
+        std::fs::write(dir.0.join("config.toml"), &config).unwrap();
+    }
+
+    let output = md_in(
+        &requester,
+        &server,
+        &["-y", "service-account", "delete", "svc_test"],
+    );
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("approval required"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(server.requests().iter().all(|r| r.method != "DELETE"));
+    let file = std::fs::read_dir(&requester.0)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.to_string_lossy().contains("md-change-"))
+        .expect("change request written to the working directory");
+    let file = file.to_str().unwrap();
+    let request: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+    assert_eq!(
+        request["args"],
+        json!(["-y", "service-account", "delete", "svc_test"])
+    );
+    assert_eq!(request["requested_by"], requester_key);
+
+    let output = md_in(&requester, &server, &["execute", file]);
+    assert!(
+        stderr(&output).contains("has not been approved"),
+        "{}",
+        stderr(&output)
+    );
+    let output = md_in(&requester, &server, &["-y", "approve", file]);
+    assert!(
+        stderr(&output).contains("requester's own key"),
+        "{}",
+        stderr(&output)
+    );
+
+    let approved = stdout(&md_in(&approver, &server, &["-y", "approve", file]));
+    assert!(approved.starts_with("approved "));
+    assert!(server.requests().iter().all(|r| r.method != "DELETE"));
+
+    stdout(&md_in(&requester, &server, &["execute", file]));
+    assert_eq!(server.requests().last().unwrap().method, "DELETE");
+    let output = md_in(&requester, &server, &["execute", file]);
+    assert!(
+        stderr(&output).contains("already executed"),
+        "{}",
+        stderr(&output)
+    );
+
+    // `executed_at` is not signed; removing it must not allow a second run.
+    let mut request: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+    assert!(request["executed_at"].is_string());
+    request.as_object_mut().unwrap().remove("executed_at");
+    std::fs::write(file, request.to_string()).unwrap();
+    let output = md_in(&requester, &server, &["execute", file]);
+    assert!(
+        stderr(&output).contains("already executed"),
+        "{}",
+        stderr(&output)
+    );
+    let deletes = server
+        .requests()
+        .iter()
+        .filter(|r| r.method == "DELETE")
+        .count();
+    assert_eq!(deletes, 1);
+}
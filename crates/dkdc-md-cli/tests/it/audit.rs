@@ -0,0 +1,64 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::{TempDir, md, stdout};
+
+#[test]
+fn orphans_reports_unowned_accounts_and_exports_csv() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+
+    let dir = TempDir::new();
+    let owners = dir.0.join("owners.csv");
+    let report = dir.0.join("report.csv");
+    std::fs::write(
+        &owners,
+        "username,owner,owner_active\nsvc_test,alice,false\n",
+    )
+    .unwrap();
+
+    let out = stdout(&md(
+        &server,
+        &[
+            "-o",
+            "json",
+            "audit",
+            "orphans",
+            "--owners",
+            owners.to_str().unwrap(),
+            "--csv",
+            report.to_str().unwrap(),
+        ],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "orphans": [
+                { "username": "svc_test", "owner": "alice", "tokens": 2, "reason": "owner departed" },
+                { "username": "svc_other", "owner": null, "tokens": 0, "reason": "no owner" }
+            ]
+        })
+    );
+    assert_eq!(
+        std::fs::read_to_string(&report).unwrap(),
+        "username,owner,tokens,reason\nsvc_test,alice,2,owner departed\nsvc_other,,0,no owner\n"
+    );
+}
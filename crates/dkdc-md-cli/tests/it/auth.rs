@@ -0,0 +1,113 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+
+use crate::{TempDir, md, stderr, stdout};
+
+/// A JWT with `username`, `token_type`, `iat`, and `exp` claims; the API checks the
+/// signature, so a fake one does here.
+const JWT: &str = "eyJhbGciOiJIUzI1NiJ9.\
+    eyJ1c2VybmFtZSI6InN2Y19ldGwiLCJ0b2tlbl90eXBlIjoicmVhZF9zY2FsaW5nIiwiaWF0IjoxNzkwMDAwMDAwLCJleHAiOjE4MDAwMDAwMDB9.\
+    c2ln";
+
+#[test]
+fn whoami_checks_the_token_and_reads_its_claims() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/md_capabilities_probe/tokens",
+        404,
+        fixtures::error("user not found"),
+    );
+
+    let out = stdout(&md(&server, &["--token", JWT, "auth", "whoami"]));
+    assert_eq!(
+        out,
+        format!(
+            "valid:   yes (accepted by {})\nuser:    svc_etl\norg:     -\ntype:    read_scaling\n\
+             issued:  2026-09-21T14:13:20Z\nexpires: 2027-01-15T08:00:00Z\n",
+            server.url()
+        )
+    );
+    assert_eq!(
+        server.requests()[0].header("authorization"),
+        Some(format!("Bearer {JWT}").as_str())
+    );
+
+    let output = md(&server, &["-o", "json", "auth", "whoami"]);
+    let value: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(value["valid"], true);
+    assert_eq!(value["user"], serde_json::Value::Null);
+    assert!(stderr(&output).contains("not a JWT"));
+}
+
+#[test]
+fn whoami_fails_when_the_api_rejects_the_token() {
+    for status in [401, 403] {
+        let server = MockServer::start();
+        server.mock(
+            "GET",
+            "/v1/users/md_capabilities_probe/tokens",
+            status,
+            fixtures::error("invalid token"),
+        );
+
+        let output = md(&server, &["--token", JWT, "auth", "whoami"]);
+        assert!(!output.status.success());
+        assert!(stderr(&output).contains("the API did not accept the token"));
+    }
+}
+
+// Without a Secret Service (as on most test runners and servers), status reports the
+// keyring as unavailable and logout fails, rather than pretending nothing is stored.
+#[cfg(target_os = "linux")]
+#[test]
+fn status_reports_the_env_var_ahead_of_an_unavailable_keyring() {
+    use std::process::{Command, Stdio};
+
+    let server = MockServer::start();
+    let dir = TempDir::new();
+    let profile = format!("it-auth-{}", std::process::id());
+    std::fs::write(dir.0.join("config.toml"), format!("[profiles.{profile}]\n")).unwrap();
+    let md_without_bus = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_md"))
+            .args(["--profile", &profile])
+            .args(args)
+            .current_dir(&dir.0)
+            .env("MOTHERDUCK_API_URL", server.url())
+            .env("MOTHERDUCK_TOKEN", "test-token")
+            .env("MD_CONFIG", dir.0.join("config.toml"))
+            .env(
+                "DBUS_SESSION_BUS_ADDRESS",
+                "unix:path=/nonexistent/md-test-bus",
+            )
+            .stdin(Stdio::null())
+            .output()
+            .expect("failed to run md")
+    };
+
+    let out = stdout(&md_without_bus(&["auth", "status"]));
+    assert!(
+        out.starts_with("token: env MOTHERDUCK_TOKEN\nkeyring: unavailable ("),
+        "{out}"
+    );
+    let value: serde_json::Value =
+        serde_json::from_str(&stdout(&md_without_bus(&["-o", "json", "auth", "status"]))).unwrap();
+    assert_eq!(value["source"], "env MOTHERDUCK_TOKEN");
+    assert_eq!(value["stored"], serde_json::Value::Null);
+    assert!(value["keyring_error"].is_string());
+
+    let output = md_without_bus(&["auth", "logout"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("OS keyring"),
+        "{}",
+        stderr(&output)
+    );
+    let output = md_without_bus(&["--token", "tok", "auth", "login"]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("cannot write to the OS keyring"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(server.requests().is_empty());
+}
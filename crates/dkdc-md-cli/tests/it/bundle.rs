@@ -0,0 +1,160 @@
+use std::process::{Command, Stdio};
+
+use dkdc_md_cli::testing::MockServer;
+use serde_json::json;
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+/// A config directory with a signing key; returns the public key.
+fn signer(server: &MockServer) -> (TempDir, String) {
+    let dir = TempDir::new();
+    let public_key = stdout(&md_in(&dir, server, &["signing-key", "generate"]));
+    (dir, public_key.trim().to_string())
+}
+
+/// Like `md_in`, on a machine without a token.
+fn md_offline(dir: &TempDir, server: &MockServer, args: &[&str]) -> std::process::Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_md"));
+    for var in [
+        "motherduck_token",
+        "MOTHERDUCK_TOKEN",
+        "motherduck_api_key",
+        "MOTHERDUCK_API_KEY",
+    ] {
+        command.env_remove(var);
+    }
+    command
+        .args(args)
+        .current_dir(&dir.0)
+        .env("MOTHERDUCK_API_URL", server.url())
+        .env("MD_CONFIG", dir.0.join("config.toml"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run md")
+}
+
+#[test]
+fn bundle_signed_offline_runs_once_on_a_trusting_host() {
+    let server = MockServer::start();
+    server.mock("GET", "/v1/active_accounts", 200, json!({ "accounts": [] }));
+    server.mock("DELETE", "/v1/users/svc_test", 200, json!({}));
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+    let (workstation, signer_key) = signer(&server);
+    std::fs::write(
+        workstation.0.join("commands.txt"),
+        "# decommission svc_test\ntoken list svc_test\nservice-account delete svc_test\n",
+    )
+    .unwrap();
+    let file = workstation.0.join("bundle.json");
+    let file = file.to_str().unwrap();
+    let output = md_offline(
+        &workstation,
+        &server,
+        &["bundle", "create", file, "--from", "commands.txt"],
+    );
+    assert_eq!(stdout(&output).trim(), file);
+    assert!(server.requests().is_empty());
+
+    let bastion = TempDir::new();
+    let output = md_in(&bastion, &server, &["-y", "bundle", "execute", file]);
+    assert!(
+        stderr(&output).contains("no trusted signers"),
+        "{}",
+        stderr(&output)
+    );
+    std::fs::write(
+        bastion.0.join("config.toml"),
+        format!("[bundles]\ntrusted_signers = [\"{signer_key}\"]"),
+    )
+    .unwrap();
+    let shown = stdout(&md_in(&bastion, &server, &["bundle", "show", file]));
+    assert!(
+        shown.contains("2. md service-account delete svc_test"),
+        "{shown}"
+    );
+
+    stdout(&md_in(
+        &bastion,
+        &server,
+        &["-y", "bundle", "execute", file],
+    ));
+    let methods: Vec<_> = server.requests().iter().map(|r| r.method.clone()).collect();
+    assert_eq!(methods.last().unwrap(), "DELETE");
+    let output = md_in(&bastion, &server, &["-y", "bundle", "execute", file]);
+    assert!(
+        stderr(&output).contains("already executed"),
+        "{}",
+        stderr(&output)
+    );
+
+    // `executed_at` is not signed; removing it must not allow a replay.
+    let mut bundle: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+    bundle.as_object_mut().unwrap().remove("executed_at");
+    std::fs::write(file, bundle.to_string()).unwrap();
+    let output = md_in(&bastion, &server, &["-y", "bundle", "execute", file]);
+    assert!(
+        stderr(&output).contains("already executed"),
+        "{}",
+        stderr(&output)
+    );
+    let deletes = server
+        .requests()
+        .iter()
+        .filter(|r| r.method == "DELETE")
+        .count();
+    assert_eq!(deletes, 1);
+}
+
+#[test]
+fn altered_bundle_is_refused() {
+    let server = MockServer::start();
+    let (dir, signer_key) = signer(&server);
+    std::fs::write(
+        dir.0.join("config.toml"),
+        format!("[bundles]\ntrusted_signers = [\"{signer_key}\"]"),
+    )
+    .unwrap();
+    stdout(&md_in(
+        &dir,
+        &server,
+        &[
+            "bundle",
+            "create",
+            "b.json",
+            "--command",
+            "token list svc_test",
+        ],
+    ));
+    let path = dir.0.join("b.json");
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, text.replace("svc_test", "svc_prod")).unwrap();
+
+    let output = md_in(&dir, &server, &["-y", "bundle", "execute", "b.json"]);
+    assert!(stderr(&output).contains("altered"), "{}", stderr(&output));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn create_rejects_operator_flags_and_nested_commands() {
+    let server = MockServer::start();
+    let (dir, _) = signer(&server);
+    for command in [
+        "token list svc_test --token abc",
+        "bundle show x.json",
+        "token lst",
+    ] {
+        let output = md_in(
+            &dir,
+            &server,
+            &["bundle", "create", "b.json", "--command", command],
+        );
+        assert!(!output.status.success(), "{command}");
+    }
+    assert!(!dir.0.join("b.json").exists());
+}
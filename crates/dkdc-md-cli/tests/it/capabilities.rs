@@ -0,0 +1,121 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+fn mock_probes(server: &MockServer) {
+    // Unknown user on a usable endpoint: the default 404 means "available".
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        403,
+        fixtures::error("forbidden"),
+    );
+}
+
+#[test]
+fn probes_and_caches_capabilities() {
+    let server = MockServer::start();
+    mock_probes(&server);
+    let dir = TempDir::new();
+
+    let out = stdout(&md_in(&dir, &server, &["capabilities"]));
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].starts_with("CAPABILITY"));
+    assert!(lines[1].starts_with("service-accounts  available"));
+    assert!(lines[2].starts_with("instances         available"));
+    assert!(lines[3].starts_with("active-accounts   not-enabled"));
+    assert_eq!(server.requests().len(), 3);
+
+    let output = md_in(&dir, &server, &["-o", "json", "capabilities"]);
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["capabilities"]["active-accounts"], "not-enabled");
+    assert!(stderr(&output).contains("pass --refresh"));
+    assert_eq!(server.requests().len(), 3);
+
+    stdout(&md_in(&dir, &server, &["capabilities", "--refresh"]));
+    assert_eq!(server.requests().len(), 6);
+}
+
+#[test]
+fn forbidden_command_explains_cached_capability() {
+    let server = MockServer::start();
+    mock_probes(&server);
+    let dir = TempDir::new();
+
+    let output = md_in(&dir, &server, &["account", "list-active"]);
+    assert!(!output.status.success());
+    assert!(!stderr(&output).contains("not enabled"));
+
+    stdout(&md_in(&dir, &server, &["capabilities"]));
+    let requests = server.requests().len();
+    let output = md_in(&dir, &server, &["account", "list-active"]);
+    assert!(!output.status.success());
+    let err = stderr(&output);
+    assert!(err.contains(
+        "`md account list-active` needs `active-accounts` (a token of an org Admin), which \
+         your token lacks"
+    ));
+    assert_eq!(server.requests().len(), requests, "stopped before the call");
+
+    // A stale probe no longer stops the command, but still explains the 403.
+    let path = dir.0.join("capabilities.json");
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut report: serde_json::Value = serde_json::from_str(&text).unwrap();
+    report["checked_at"] = "2020-01-01T00:00:00+00:00".into();
+    std::fs::write(&path, report.to_string()).unwrap();
+    let output = md_in(&dir, &server, &["account", "list-active"]);
+    let err = stderr(&output);
+    assert!(err.contains("`active-accounts` is not enabled for your plan or token"));
+    assert!(err.contains("API error (403): forbidden"));
+}
+
+#[test]
+fn permissions_lists_requirements_and_cached_access() {
+    let server = MockServer::start();
+    mock_probes(&server);
+    let dir = TempDir::new();
+
+    let out = stdout(&md_in(&dir, &server, &["permissions"]));
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].starts_with("COMMAND"));
+    assert!(lines.iter().any(|l| l.starts_with("audit orphans")
+        && l.contains("admin")
+        && l.ends_with("active-accounts, service-accounts")));
+    assert!(
+        lines
+            .iter()
+            .any(|l| l.starts_with("config set") && l.contains("none"))
+    );
+
+    let out = stdout(&md_in(&dir, &server, &["permissions", "audit", "orphans"]));
+    assert_eq!(
+        out,
+        "role: admin (a token of an org Admin)\n\
+         capabilities:\n  \
+         active-accounts (not checked; run `md capabilities`)\n  \
+         service-accounts (not checked; run `md capabilities`)\n\
+         calls:\n  \
+         GET /v1/active_accounts\n  \
+         GET /v1/users/{username}/tokens (per account)\n"
+    );
+
+    stdout(&md_in(&dir, &server, &["capabilities"]));
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "json", "permissions", "audit", "orphans"],
+    ));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        json["permissions"][0]["capabilities"],
+        serde_json::json!([
+            { "name": "active-accounts", "access": "not-enabled" },
+            { "name": "service-accounts", "access": "available" }
+        ])
+    );
+
+    let unknown = md_in(&dir, &server, &["permissions", "token", "make"]);
+    assert!(!unknown.status.success());
+    assert!(stderr(&unknown).contains("unknown command `token make`"));
+    assert_eq!(server.requests().len(), 3, "only the probes reach the API");
+}
@@ -0,0 +1,26 @@
+use dkdc_md_cli::testing::MockServer;
+
+use crate::{md, md_with_config, stdout};
+
+#[test]
+fn emits_a_script_for_each_shell() {
+    let server = MockServer::start();
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let script = stdout(&md(&server, &["completions", shell]));
+        for word in ["service-account", "revoke-all", "scale-down", "rw-size"] {
+            assert!(script.contains(word), "{shell} script lacks {word}");
+        }
+    }
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn ignores_a_broken_config() {
+    let server = MockServer::start();
+    let script = stdout(&md_with_config(
+        &server,
+        "[output\n",
+        &["completions", "bash"],
+    ));
+    assert!(script.starts_with("_md() {"));
+}
@@ -0,0 +1,112 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::json;
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+#[test]
+fn set_get_and_list_round_trip() {
+    let server = MockServer::start();
+    let dir = TempDir::new();
+    std::fs::write(dir.0.join("config.toml"), "# ours\nconfirm = \"always\"\n").unwrap();
+
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["config", "set", "defaults.output", "json"],
+    ));
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["config", "set", "defaults.ttl.read_write", "90d"],
+    ));
+    let text = std::fs::read_to_string(dir.0.join("config.toml")).unwrap();
+    assert!(text.starts_with("# ours\nconfirm = \"always\"\n"));
+
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "text", "config", "get", "defaults.output"],
+    ));
+    assert_eq!(out, "json\n");
+    let out = stdout(&md_in(&dir, &server, &["-o", "text", "config", "list"]));
+    assert_eq!(
+        out,
+        "confirm = \"always\"\ndefaults.output = \"json\"\ndefaults.ttl.read_write = \"90d\"\n"
+    );
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "json", "config", "get", "defaults"],
+    ));
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&out).unwrap(),
+        json!({ "defaults.output": "json", "defaults.ttl.read_write": "90d" })
+    );
+
+    let missing = md_in(&dir, &server, &["config", "get", "defaults.yes"]);
+    assert!(!missing.status.success());
+    assert!(stderr(&missing).contains("defaults.yes is not set"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn set_refuses_invalid_settings_and_can_repair_a_broken_file() {
+    let server = MockServer::start();
+    let dir = TempDir::new();
+    std::fs::write(dir.0.join("config.toml"), "[defaults]\noutput = \"jsn\"\n").unwrap();
+
+    let broken = md_in(&dir, &server, &["token", "list", "svc_test"]);
+    assert!(!broken.status.success());
+    assert!(stderr(&broken).contains("invalid output 'jsn' in [defaults]"));
+
+    let typo = md_in(&dir, &server, &["config", "set", "defaults.outptu", "json"]);
+    assert!(!typo.status.success());
+    assert!(stderr(&typo).contains("unknown field `outptu`"));
+
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["config", "set", "defaults.output", "json"],
+    ));
+    let text = std::fs::read_to_string(dir.0.join("config.toml")).unwrap();
+    assert_eq!(text, "[defaults]\noutput = \"json\"\n");
+}
+
+#[test]
+fn defaults_apply_and_flags_win() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_123",
+        200,
+        json!({}),
+    );
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.0.join("config.toml"),
+        "[defaults]\noutput = \"json\"\nyes = true\nrequest_timeout = \"30s\"\n",
+    )
+    .unwrap();
+
+    let out = stdout(&md_in(&dir, &server, &["token", "list", "svc_test"]));
+    assert!(out.starts_with('{'));
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "text", "token", "list", "svc_test"],
+    ));
+    assert!(out.starts_with("ID"));
+
+    // `yes = true` answers the confirmation that would otherwise fail without a terminal.
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["token", "delete", "svc_test", "tok_123"],
+    ));
+}
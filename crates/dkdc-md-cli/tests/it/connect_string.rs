@@ -0,0 +1,86 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::json;
+
+use crate::{md, stderr, stdout};
+
+const INSTANCES: &str = "/v1/users/svc_test/instances";
+const TOKENS: &str = "/v1/users/svc_test/tokens";
+
+#[test]
+fn prints_read_write_connection_string() {
+    let server = MockServer::start();
+    server.mock("GET", INSTANCES, 200, fixtures::duckling_config());
+
+    let output = md(
+        &server,
+        &["connect-string", "svc_test", "--database", "analytics"],
+    );
+    assert_eq!(stdout(&output), "md:analytics\n");
+    assert!(stderr(&output).contains("reads the token from the motherduck_token"));
+
+    let output = md(
+        &server,
+        &["connect-string", "svc_test", "--secret", "tok.abc"],
+    );
+    assert_eq!(stdout(&output), "md:?motherduck_token=tok.abc\n");
+}
+
+#[test]
+fn read_scaling_needs_a_flock_and_warns_without_a_token() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        INSTANCES,
+        200,
+        json!({
+            "read_write": { "instance_size": "standard" },
+            "read_scaling": { "instance_size": "pulse", "flock_size": 0 }
+        }),
+    );
+    let output = md(&server, &["connect-string", "svc_test", "--read-scaling"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no read-scaling ducklings (flock_size 0)"));
+
+    server.mock("GET", INSTANCES, 200, fixtures::duckling_config());
+    server.mock(
+        "GET",
+        TOKENS,
+        200,
+        json!({ "tokens": [{ "id": "tok_1", "name": "ci", "token_type": "read_write" }] }),
+    );
+    let output = md(
+        &server,
+        &[
+            "connect-string",
+            "svc_test",
+            "--read-scaling",
+            "--session-hint",
+            "alice",
+        ],
+    );
+    assert_eq!(stdout(&output), "md:?session_hint=alice\n");
+    assert!(stderr(&output).contains("warning: 'svc_test' has no read-scaling token"));
+}
+
+#[test]
+fn json_output_redacts_the_secret() {
+    let server = MockServer::start();
+    server.mock("GET", INSTANCES, 200, fixtures::duckling_config());
+
+    let args = [
+        "-o",
+        "json",
+        "connect-string",
+        "svc_test",
+        "--secret",
+        "tok",
+    ];
+    let output = md(&server, &args);
+    let value: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(value["connection_string"], "md:?motherduck_token=***");
+    assert!(stderr(&output).contains("pass --show-secret"));
+
+    let output = md(&server, &[&args[..], &["--show-secret"]].concat());
+    let value: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(value["connection_string"], "md:?motherduck_token=tok");
+}
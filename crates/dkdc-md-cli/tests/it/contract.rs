@@ -0,0 +1,374 @@
+//! Contract tests: serve the operations in `openapi.json` from the mock server and check
+//! every request the library client sends against the spec (method, path, auth and
+//! content headers, and body schema), so the hand-written client cannot drift from the API.
+
+use dkdc_md_cli::MotherduckClient;
+use dkdc_md_cli::testing::{MockServer, RecordedRequest};
+use serde_json::{Value, json};
+
+use crate::{TempDir, md, stderr, stdout};
+
+const SPEC: &str = include_str!("openapi.json");
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+fn spec() -> Value {
+    serde_json::from_str(SPEC).expect("openapi.json is valid JSON")
+}
+
+/// Follow a local `$ref` (`#/components/...`), if any.
+fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+    match value["$ref"].as_str() {
+        Some(pointer) => {
+            let target = spec
+                .pointer(pointer.trim_start_matches('#'))
+                .unwrap_or_else(|| panic!("dangling $ref {pointer}"));
+            resolve(spec, target)
+        }
+        None => value,
+    }
+}
+
+/// `template` with each `{param}` replaced by that parameter's example.
+fn example_path(spec: &Value, template: &str, item: &Value) -> String {
+    let mut path = template.to_string();
+    for param in item["parameters"].as_array().into_iter().flatten() {
+        let param = resolve(spec, param);
+        let name = param["name"].as_str().unwrap();
+        let example = param["example"]
+            .as_str()
+            .unwrap_or_else(|| panic!("{template}: parameter {name} has no example"));
+        path = path.replace(&format!("{{{name}}}"), example);
+    }
+    path
+}
+
+/// A mock server answering every operation in the spec with its example response.
+fn spec_server(spec: &Value) -> MockServer {
+    let server = MockServer::start();
+    for (template, item) in spec["paths"].as_object().unwrap() {
+        let path = example_path(spec, template, item);
+        for method in METHODS {
+            let Some(op) = item.get(method) else { continue };
+            let (status, response) = op["responses"]
+                .as_object()
+                .and_then(|r| r.iter().find(|(code, _)| code.starts_with('2')))
+                .unwrap_or_else(|| panic!("{method} {template}: no success response"));
+            let example = response["content"]["application/json"]["example"].clone();
+            server.mock(
+                &method.to_uppercase(),
+                &path,
+                status.parse().unwrap(),
+                example,
+            );
+        }
+    }
+    server
+}
+
+fn matches_template(template: &str, path: &str) -> bool {
+    let (t, p): (Vec<_>, Vec<_>) = (template.split('/').collect(), path.split('/').collect());
+    t.len() == p.len()
+        && t.iter()
+            .zip(&p)
+            .all(|(t, p)| (t.starts_with('{') && !p.is_empty()) || t == p)
+}
+
+/// Every way `req` departs from the spec; empty if it conforms.
+fn violations(spec: &Value, req: &RecordedRequest) -> Vec<String> {
+    let method = req.method.to_lowercase();
+    let path = req.path.split('?').next().unwrap();
+    let Some((template, op)) = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter(|(template, _)| matches_template(template, path))
+        .find_map(|(template, item)| item.get(&method).map(|op| (template, op)))
+    else {
+        return vec![format!("{} {path}: no such operation", req.method)];
+    };
+
+    let mut errors = Vec::new();
+    let at = |msg: String| format!("{} {template}: {msg}", req.method);
+    if !req
+        .header("authorization")
+        .is_some_and(|v| v.starts_with("Bearer ") && v.len() > "Bearer ".len())
+    {
+        errors.push(at("missing bearer Authorization header".into()));
+    }
+    match op["requestBody"]["content"]["application/json"].get("schema") {
+        Some(schema) => {
+            if req.header("content-type") != Some("application/json") {
+                errors.push(at(format!(
+                    "Content-Type is {:?}, expected application/json",
+                    req.header("content-type")
+                )));
+            }
+            match serde_json::from_str(&req.body) {
+                Ok(body) => {
+                    let mut body_errors = Vec::new();
+                    check(spec, schema, &body, "body", &mut body_errors);
+                    errors.extend(body_errors.into_iter().map(at));
+                }
+                Err(e) => errors.push(at(format!("body is not JSON: {e}"))),
+            }
+        }
+        None if !req.body.is_empty() => errors.push(at("unexpected request body".into())),
+        None => {}
+    }
+    errors
+}
+
+/// Check `value` against the subset of JSON Schema the spec uses.
+fn check(spec: &Value, schema: &Value, value: &Value, at: &str, errors: &mut Vec<String>) {
+    let schema = resolve(spec, schema);
+    if value.is_null() && schema["nullable"] == true {
+        return;
+    }
+    let type_ok = match schema["type"].as_str() {
+        Some("object") => value.is_object(),
+        Some("array") => value.is_array(),
+        Some("string") => value.is_string(),
+        Some("integer") => value.is_u64() || value.is_i64(),
+        Some("number") => value.is_number(),
+        Some("boolean") => value.is_boolean(),
+        _ => true,
+    };
+    if !type_ok {
+        errors.push(format!("{at}: expected {}, got {value}", schema["type"]));
+        return;
+    }
+    if let Some(allowed) = schema["enum"].as_array()
+        && !allowed.contains(value)
+    {
+        errors.push(format!("{at}: {value} is not one of {}", schema["enum"]));
+    }
+    if let Some(n) = value.as_f64() {
+        if schema["minimum"].as_f64().is_some_and(|min| n < min) {
+            errors.push(format!("{at}: {n} is below minimum {}", schema["minimum"]));
+        }
+        if schema["maximum"].as_f64().is_some_and(|max| n > max) {
+            errors.push(format!("{at}: {n} is above maximum {}", schema["maximum"]));
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for name in schema["required"].as_array().into_iter().flatten() {
+            let name = name.as_str().unwrap();
+            if !object.contains_key(name) {
+                errors.push(format!("{at}: missing required property {name}"));
+            }
+        }
+        for (name, v) in object {
+            match schema["properties"].get(name) {
+                Some(property) => check(spec, property, v, &format!("{at}.{name}"), errors),
+                None if schema["additionalProperties"] == false => {
+                    errors.push(format!("{at}: unknown property {name}"));
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (i, v) in array.iter().enumerate() {
+            check(spec, items, v, &format!("{at}[{i}]"), errors);
+        }
+    }
+}
+
+#[test]
+fn spec_examples_fill_every_path() {
+    let spec = spec();
+    for (template, item) in spec["paths"].as_object().unwrap() {
+        let path = example_path(&spec, template, item);
+        assert!(!path.contains('{'), "{template} left unfilled: {path}");
+        assert!(matches_template(template, &path));
+    }
+}
+
+#[test]
+fn client_requests_conform_to_spec() {
+    let spec = spec();
+    let server = spec_server(&spec);
+    let client = MotherduckClient::builder("test-token")
+        .base_url(&server.url())
+        .build()
+        .unwrap();
+    let users = vec!["svc_test".to_string()];
+
+    client.create_user("svc_test").unwrap();
+    client.delete_user("svc_test").unwrap();
+    assert!(client.user_exists("svc_test").unwrap());
+    client.list_tokens("svc_test").unwrap();
+    client.create_token("svc_test", "ci", None, None).unwrap();
+    client
+        .create_token("svc_test", "ci", Some(3600), Some("read_scaling"))
+        .unwrap();
+    client.delete_token("svc_test", "tok_123").unwrap();
+    assert!(client.token_exists("svc_test", "tok_123").unwrap());
+    client.get_duckling_config("svc_test").unwrap();
+    client
+        .set_duckling_config("svc_test", "standard", "pulse", 4)
+        .unwrap();
+    let config = client.get_duckling_config("svc_test").unwrap();
+    client.put_duckling_config("svc_test", &config).unwrap();
+    client.list_active_accounts().unwrap();
+    for result in client.list_tokens_bulk(&users) {
+        result.unwrap();
+    }
+    for result in client.get_duckling_configs(&users) {
+        result.unwrap();
+    }
+    let mut accounts = 0;
+    client
+        .for_each_active_account(|_| {
+            accounts += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(accounts, 1);
+
+    let requests = server.requests();
+    let errors: Vec<_> = requests
+        .iter()
+        .flat_map(|req| violations(&spec, req))
+        .collect();
+    assert!(errors.is_empty(), "{}", errors.join("\n"));
+
+    // Every operation in the spec is exercised by some client method.
+    for (template, item) in spec["paths"].as_object().unwrap() {
+        for method in METHODS.iter().filter(|m| item.get(**m).is_some()) {
+            assert!(
+                requests
+                    .iter()
+                    .any(|r| r.method.eq_ignore_ascii_case(method)
+                        && matches_template(template, r.path.split('?').next().unwrap())),
+                "no client method calls {} {template}",
+                method.to_uppercase()
+            );
+        }
+    }
+}
+
+#[test]
+fn validator_reports_drift() {
+    let spec = spec();
+    let request = |method: &str, path: &str, body: Value| RecordedRequest {
+        method: method.into(),
+        path: path.into(),
+        headers: vec![
+            ("Authorization".into(), "Bearer t".into()),
+            ("Content-Type".into(), "application/json".into()),
+        ],
+        body: if body.is_null() {
+            String::new()
+        } else {
+            body.to_string()
+        },
+    };
+
+    let ok = request(
+        "POST",
+        "/v1/users/svc_a/tokens",
+        json!({ "name": "ci", "ttl": 3600 }),
+    );
+    assert!(violations(&spec, &ok).is_empty());
+
+    let errors = violations(
+        &spec,
+        &request(
+            "POST",
+            "/v1/users/svc_a/tokens",
+            json!({ "ttl": 10, "token_type": "admin", "scope": "all" }),
+        ),
+    );
+    for expected in [
+        "missing required property name",
+        "body.ttl: 10 is below minimum 300",
+        "body.token_type: \"admin\" is not one of",
+        "unknown property scope",
+    ] {
+        assert!(
+            errors.iter().any(|e| e.contains(expected)),
+            "{expected:?} not in {errors:?}"
+        );
+    }
+
+    let errors = violations(&spec, &request("PATCH", "/v1/users/svc_a", Value::Null));
+    assert_eq!(errors, ["PATCH /v1/users/svc_a: no such operation"]);
+
+    let mut unauthenticated = request("GET", "/v1/active_accounts", Value::Null);
+    unauthenticated.headers.clear();
+    assert_eq!(
+        violations(&spec, &unauthenticated),
+        ["GET /v1/active_accounts: missing bearer Authorization header"]
+    );
+}
+
+#[test]
+fn check_spec_reports_what_md_uses_that_the_spec_lacks() {
+    let server = MockServer::start();
+    let current = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/it/openapi.json");
+    let output = md(
+        &server,
+        &["-o", "json", "dev", "check-spec", "--spec", current],
+    );
+    let drift: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(drift["removed_endpoints"], json!([]));
+    assert_eq!(drift["removed_fields"], json!([]));
+    assert_eq!(drift["unimplemented"], json!([]));
+
+    let dir = TempDir::new();
+    let path = dir.0.join("openapi.yaml");
+    std::fs::write(
+        &path,
+        "paths:
+  /v1/users:
+    post:
+      requestBody:
+        content:
+          application/json:
+            schema: { $ref: '#/components/schemas/CreateUser' }
+      responses:
+        201: { description: created }
+  /v1/users/{user}/rename:
+    post:
+      responses:
+        200: { description: renamed }
+components:
+  schemas:
+    CreateUser:
+      type: object
+      properties:
+        name: { type: string }
+",
+    )
+    .unwrap();
+    let output = md(
+        &server,
+        &[
+            "-o",
+            "json",
+            "dev",
+            "check-spec",
+            "--spec",
+            path.to_str().unwrap(),
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("md uses endpoints or fields that"));
+    let drift: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        drift["removed_fields"],
+        json!([{ "endpoint": "POST /v1/users", "body": "request", "field": "username" }])
+    );
+    assert_eq!(
+        drift["unimplemented"],
+        json!(["POST /v1/users/{user}/rename"])
+    );
+    assert!(
+        drift["removed_endpoints"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("GET /v1/active_accounts"))
+    );
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,34 @@
+use dkdc_md_cli::testing::MockServer;
+
+use crate::{md, stdout};
+
+#[test]
+fn docs_cover_visible_commands_without_a_token() {
+    let server = MockServer::start();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_md"))
+        .args(["generate", "docs"])
+        .env_remove("MOTHERDUCK_TOKEN")
+        .env_remove("motherduck_token")
+        .env("MD_CONFIG", "/nonexistent/config.toml")
+        .output()
+        .unwrap();
+    let doc = stdout(&output);
+
+    assert!(doc.starts_with("# `md` command reference\n"));
+    assert!(doc.contains("## `md token create`\n\nCreate a new access token\n"));
+    assert!(doc.contains(
+        "| `--token-type <TOKEN_TYPE>` | Token type (default: `read-write`; values: \
+         `read-write`, `read-scaling`) |"
+    ));
+    assert!(doc.contains("  md wait duckling svc_etl --until status=running\n"));
+    assert!(
+        !doc.contains("## `md generate"),
+        "hidden commands are left out"
+    );
+    assert!(!doc.contains("## `md token help"));
+    assert!(server.requests().is_empty());
+
+    // Hidden from the command list, but runnable the usual way too.
+    let help = stdout(&md(&server, &["--help"]));
+    assert!(!help.contains("generate"));
+}
@@ -0,0 +1,130 @@
+use std::os::unix::fs::PermissionsExt;
+
+use dkdc_md_cli::testing::{MockServer, fixtures};
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+const GUARD: &str = r#"#!/bin/sh
+echo "$MD_HOOK [$MD_COMMAND] $*" >> hooks.log
+case " $* " in *" svc_prod "*) echo "svc_prod is protected" >&2; exit 1;; esac
+"#;
+
+const NOTIFY: &str = r#"#!/bin/sh
+echo "$MD_HOOK [$MD_COMMAND] $MD_STATUS $MD_ERROR" >> hooks.log
+"#;
+
+/// A config dir with `[hooks]` pointing at the scripts above, relative to it.
+fn hooked_dir() -> TempDir {
+    let dir = TempDir::new();
+    let scripts = dir.0.join("scripts");
+    std::fs::create_dir_all(&scripts).unwrap();
+    for (name, body) in [("guard.sh", GUARD), ("notify.sh", NOTIFY)] {
+        let path = scripts.join(name);
+        std::fs::write(&path, body).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    std::fs::write(
+        dir.0.join("config.toml"),
+        "[hooks]\npre = \"scripts/guard.sh\"\npost = \"scripts/notify.sh\"\n",
+    )
+    .unwrap();
+    dir
+}
+
+fn log(dir: &TempDir) -> String {
+    std::fs::read_to_string(dir.0.join("hooks.log")).unwrap_or_default()
+}
+
+#[test]
+fn hooks_run_around_mutating_commands_only() {
+    let server = MockServer::start();
+    server.mock("POST", "/v1/users", 200, fixtures::user());
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    let dir = hooked_dir();
+
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["service-account", "create", fixtures::USERNAME],
+    ));
+    assert_eq!(out.trim(), fixtures::USERNAME);
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["token", "list", fixtures::USERNAME],
+    ));
+
+    assert_eq!(
+        log(&dir),
+        "pre [service-account create] service-account create svc_test\n\
+         post [service-account create] success \n"
+    );
+}
+
+#[test]
+fn failing_pre_hook_stops_the_command() {
+    let server = MockServer::start();
+    let dir = hooked_dir();
+
+    let output = md_in(
+        &dir,
+        &server,
+        &[
+            "--api-mode",
+            "--yes",
+            "service-account",
+            "delete",
+            "svc_prod",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(13));
+    let envelope: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(envelope["error"]["code"], "refused");
+    assert!(stderr(&output).contains("svc_prod is protected"));
+    assert!(server.requests().is_empty());
+    assert_eq!(
+        log(&dir),
+        "pre [service-account delete] --api-mode --yes service-account delete svc_prod\n"
+    );
+}
+
+#[test]
+fn pre_hook_guards_test_connection_token_creation() {
+    let server = MockServer::start();
+    let dir = hooked_dir();
+
+    let output = md_in(&dir, &server, &["test-connection", "svc_prod"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("svc_prod is protected"));
+    assert!(server.requests().is_empty(), "no temporary token created");
+    assert_eq!(
+        log(&dir),
+        "pre [test-connection] test-connection svc_prod\n"
+    );
+}
+
+#[test]
+fn post_hook_sees_failures() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users",
+        409,
+        fixtures::error("user already exists"),
+    );
+    let dir = hooked_dir();
+
+    let output = md_in(&dir, &server, &["service-account", "create", "svc_dup"]);
+    assert!(!output.status.success());
+    let log = log(&dir);
+    assert!(
+        log.contains("post [service-account create] failure ")
+            && log.contains("user already exists"),
+        "{log}"
+    );
+}
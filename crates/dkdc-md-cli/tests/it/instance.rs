@@ -0,0 +1,285 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::json;
+
+use crate::{TempDir, md, md_in, md_with_stdin, stderr, stdout};
+
+const PATH: &str = "/v1/users/svc_test/instances";
+
+#[test]
+fn get_renders_config() {
+    let server = MockServer::start();
+    server.mock("GET", PATH, 200, fixtures::duckling_config());
+
+    let out = stdout(&md(&server, &["instance", "get", "svc_test"]));
+    assert_eq!(
+        out,
+        "read_write:   standard\nread_scaling: standard (flock_size: 4)\n"
+    );
+}
+
+#[test]
+fn api_version_selects_endpoint_prefix() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v2/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+
+    stdout(&md(
+        &server,
+        &["--api-version", "v2", "instance", "get", "svc_test"],
+    ));
+    assert_eq!(server.requests()[0].path, "/v2/users/svc_test/instances");
+}
+
+#[test]
+fn set_merges_overrides_into_current_config() {
+    let server = MockServer::start();
+    server.mock("GET", PATH, 200, fixtures::duckling_config());
+    server.mock("PUT", PATH, 200, fixtures::duckling_config());
+
+    stdout(&md(
+        &server,
+        &["instance", "set", "svc_test", "--rw-size", "pulse"],
+    ));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].method, "PUT");
+    assert_eq!(
+        requests[1].json(),
+        json!({
+            "config": {
+                "read_write": { "instance_size": "pulse" },
+                "read_scaling": { "instance_size": "standard", "flock_size": 4 }
+            }
+        })
+    );
+}
+
+#[test]
+fn set_keeps_fields_md_does_not_know() {
+    let server = MockServer::start();
+    let current = json!({
+        "read_write": { "instance_size": "standard", "cooldown_seconds": 300 },
+        "read_scaling": { "instance_size": "standard", "flock_size": 4, "min_flock_size": 1 },
+        "region": "us-east-1"
+    });
+    server.mock("GET", PATH, 200, current);
+    server.mock("PUT", PATH, 200, fixtures::duckling_config());
+
+    stdout(&md(
+        &server,
+        &["instance", "set", "svc_test", "--flock-size", "2"],
+    ));
+
+    assert_eq!(
+        server.requests()[1].json(),
+        json!({
+            "config": {
+                "read_write": { "instance_size": "standard", "cooldown_seconds": 300 },
+                "read_scaling": { "instance_size": "standard", "flock_size": 2, "min_flock_size": 1 },
+                "region": "us-east-1"
+            }
+        })
+    );
+}
+
+#[test]
+fn set_fails_when_current_config_incomplete() {
+    let server = MockServer::start();
+    server.mock("GET", PATH, 200, json!({ "read_write": {} }));
+
+    let output = md(
+        &server,
+        &["instance", "set", "svc_test", "--flock-size", "2"],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("missing read_write.instance_size"));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn validate_reports_every_problem_without_sending() {
+    let server = MockServer::start();
+    let dir = TempDir::new();
+    std::fs::write(
+        dir.0.join("instances.json"),
+        json!({ "config": { "read_write": { "instance_size": "huge" }, "read_scaling": {} } })
+            .to_string(),
+    )
+    .unwrap();
+
+    let output = md_in(
+        &dir,
+        &server,
+        &[
+            "instance",
+            "validate",
+            "--file",
+            "instances.json",
+            "--flock-size",
+            "80",
+        ],
+    );
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "read_write.instance_size: \"huge\" is not one of pulse, standard, jumbo, mega, giga\n\
+         read_scaling.instance_size: missing\n\
+         read_scaling.flock_size: 80 is outside 0-64\n"
+    );
+    assert!(stderr(&output).contains("3 problems in the proposed config"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn validate_checks_the_cached_instances_capability() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/md_capabilities_probe/instances",
+        403,
+        fixtures::error("forbidden"),
+    );
+    server.mock("GET", PATH, 200, fixtures::duckling_config());
+    let dir = TempDir::new();
+    std::fs::write(dir.0.join("config.toml"), "").unwrap();
+
+    let output = md_in(&dir, &server, &["instance", "validate", "svc_test"]);
+    assert_eq!(stdout(&output), "config is valid\n");
+    assert!(stderr(&output).contains("plan access not checked"));
+
+    stdout(&md_in(&dir, &server, &["capabilities"]));
+    let output = md_in(
+        &dir,
+        &server,
+        &["-o", "json", "instance", "validate", "svc_test"],
+    );
+    assert!(!output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["valid"], false);
+    assert_eq!(
+        report["violations"],
+        json!(["`instances` is not enabled for your plan or token"])
+    );
+    assert!(!server.requests().iter().any(|r| r.method == "PUT"));
+}
+
+#[test]
+fn set_reads_a_complete_config_from_stdin() {
+    let server = MockServer::start();
+    server.mock("PUT", PATH, 200, fixtures::duckling_config());
+
+    let config = json!({
+        "read_write": { "instance_size": "jumbo" },
+        "read_scaling": { "instance_size": "standard", "flock_size": 8 }
+    });
+    stdout(&md_with_stdin(
+        &server,
+        &config.to_string(),
+        &["duckling", "set", "svc_test", "-f", "-", "-y"],
+    ));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "PUT");
+    assert_eq!(requests[0].json(), json!({ "config": config }));
+
+    let output = md_with_stdin(
+        &server,
+        r#"{"read_write": {"instance_size": "huge"}, "read_scaling": {"instance_size": "standard", "flock_size": 4}}"#,
+        &["instance", "set", "svc_test", "-f", "-", "-y"],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid config in stdin"));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn duckling_is_an_alias() {
+    let server = MockServer::start();
+    server.mock("GET", PATH, 200, fixtures::duckling_config());
+
+    let output = md(
+        &server,
+        &["--warnings-as-errors", "duckling", "get", "svc_test"],
+    );
+    assert!(stdout(&output).starts_with("read_write:   standard"));
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn scale_down_all_users_skips_small_flocks() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock("GET", PATH, 200, fixtures::duckling_config());
+    server.mock("PUT", PATH, 200, fixtures::duckling_config());
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/instances",
+        200,
+        json!({
+            "read_write": { "instance_size": "pulse" },
+            "read_scaling": { "instance_size": "pulse", "flock_size": 0 }
+        }),
+    );
+
+    let output = md(
+        &server,
+        &[
+            "--force",
+            "duckling",
+            "scale-down",
+            "--all-users",
+            "--flock-size",
+            "0",
+        ],
+    );
+    assert_eq!(stdout(&output), "scaled down 1 user\n");
+    let err = stderr(&output);
+    assert!(
+        err.contains("svc_test") && !err.contains("svc_other"),
+        "{err}"
+    );
+
+    let puts: Vec<_> = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "PUT")
+        .collect();
+    assert_eq!(puts.len(), 1);
+    assert_eq!(
+        puts[0].json()["config"],
+        json!({
+            "read_write": { "instance_size": "standard" },
+            "read_scaling": { "instance_size": "standard", "flock_size": 0 }
+        })
+    );
+}
+
+#[test]
+fn scale_down_needs_force_without_a_terminal() {
+    let server = MockServer::start();
+    server.mock("GET", PATH, 200, fixtures::duckling_config());
+
+    let output = md(&server, &["-y", "instance", "scale-down", "svc_test"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("pass --force"));
+    assert!(server.requests().iter().all(|r| r.method == "GET"));
+}
+
+#[test]
+fn scale_down_requires_targets() {
+    let server = MockServer::start();
+    let output = md(&server, &["instance", "scale-down"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,302 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+
+#[test]
+fn run_with_captures_output() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+
+    let url = server.url();
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    dkdc_md_cli::run_with(
+        [
+            "md",
+            "--base-url",
+            &url,
+            "--token",
+            "t",
+            "instance",
+            "get",
+            "svc_test",
+        ],
+        &mut out,
+        &mut err,
+    )
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "read_write:   standard\nread_scaling: standard (flock_size: 4)\n"
+    );
+    assert!(err.is_empty());
+}
+
+#[test]
+fn call_sends_patch_with_json_body() {
+    let server = MockServer::start();
+    server.mock(
+        "PATCH",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .build()
+        .unwrap();
+
+    let body = serde_json::json!({ "read_scaling": { "flock_size": 4 } });
+    let resp = client
+        .call("PATCH", "/v1/users/svc_test/instances", Some(&body))
+        .unwrap();
+
+    assert_eq!(resp, fixtures::duckling_config());
+    let req = &server.requests()[0];
+    assert_eq!(req.method, "PATCH");
+    assert_eq!(req.header("content-type"), Some("application/json"));
+    assert_eq!(req.json(), body);
+}
+
+#[test]
+fn verbose_and_curl_lines_are_left_to_the_caller() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .verbose(true)
+        .curl(true)
+        .build()
+        .unwrap();
+
+    client.list_active_accounts().unwrap();
+    let log = client.take_log();
+    assert_eq!(log.len(), 2, "{log:?}");
+    assert!(log[0].starts_with("curl http"), "{}", log[0]);
+    assert!(log[1].starts_with("md: GET "), "{}", log[1]);
+    assert!(client.take_log().is_empty());
+
+    let url = server.url();
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    dkdc_md_cli::run_with(
+        [
+            "md",
+            "--base-url",
+            &url,
+            "--token",
+            "t",
+            "--verbose",
+            "account",
+            "list-active",
+        ],
+        &mut out,
+        &mut err,
+    )
+    .unwrap();
+    let err = String::from_utf8(err).unwrap();
+    assert!(
+        err.starts_with(&format!("md: GET {url}/v1/active_accounts -> 200 OK in ")),
+        "{err}"
+    );
+}
+
+#[test]
+fn oversized_response_fails_unless_streamed() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .max_response_size(32)
+        .build()
+        .unwrap();
+
+    let err = client.list_active_accounts().unwrap_err();
+    assert!(format!("{err:#}").contains("exceeds the 32-byte limit"));
+
+    let mut count = 0;
+    client
+        .for_each_active_account(|_| {
+            count += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn clear_clipboard_requires_clipboard_token() {
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let result = dkdc_md_cli::run_with(
+        [
+            "md",
+            "--token",
+            "t",
+            "--clear-clipboard",
+            "account",
+            "list-active",
+        ],
+        &mut out,
+        &mut err,
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "--clear-clipboard requires --token clipboard"
+    );
+}
+
+#[test]
+fn hooks_see_every_request_and_add_headers() {
+    use std::sync::{Arc, Mutex};
+
+    use dkdc_md_cli::client::{RequestInfo, ResponseInfo};
+
+    #[derive(Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl dkdc_md_cli::Hooks for Recorder {
+        fn on_request(&self, request: &RequestInfo<'_>, headers: &mut Vec<(String, String)>) {
+            headers.push(("x-tenant".into(), "acme".into()));
+            let line = format!("{} {}", request.method, request.url);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_response(&self, _: &RequestInfo<'_>, response: &ResponseInfo<'_>) {
+            let line = format!("-> {:?}", response.status);
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    let recorder = Recorder::default();
+    let log = Arc::clone(&recorder.0);
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .hooks(recorder)
+        .build()
+        .unwrap();
+
+    client.list_tokens("svc_test").unwrap();
+    client.list_tokens("svc_missing").unwrap_err();
+
+    let url = server.url();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            format!("GET {url}/v1/users/svc_test/tokens"),
+            "-> Ok(200)".into(),
+            format!("GET {url}/v1/users/svc_missing/tokens"),
+            "-> Ok(404)".into(),
+        ]
+    );
+    assert_eq!(server.requests()[0].header("x-tenant"), Some("acme"));
+}
+
+#[test]
+fn hooks_cannot_replace_client_headers() {
+    struct Impostor;
+
+    impl dkdc_md_cli::Hooks for Impostor {
+        fn on_request(
+            &self,
+            _: &dkdc_md_cli::client::RequestInfo<'_>,
+            headers: &mut Vec<(String, String)>,
+        ) {
+            headers.push(("Authorization".into(), "Bearer other".into()));
+        }
+    }
+
+    let server = MockServer::start();
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .hooks(Impostor)
+        .build()
+        .unwrap();
+    let err = client.list_tokens("svc_test").unwrap_err();
+    assert!(format!("{err:#}").contains("the Authorization header is set by the client"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn builder_sends_extra_headers_and_retries_idempotent_requests() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        503,
+        fixtures::error("unavailable"),
+    );
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        503,
+        fixtures::error("unavailable"),
+    );
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .header("x-tenant", "acme")
+        .user_agent_suffix("airflow-dag=provision")
+        .retries(2)
+        .build()
+        .unwrap();
+
+    let err = client.list_tokens("svc_test").unwrap_err();
+    assert!(format!("{err:#}").contains("API error (503)"));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].header("x-tenant"), Some("acme"));
+    assert!(
+        requests[0]
+            .header("user-agent")
+            .unwrap()
+            .ends_with(" airflow-dag=provision")
+    );
+
+    client
+        .create_token("svc_test", "ci", None, None)
+        .unwrap_err();
+    assert_eq!(server.requests().len(), 4, "POST is not retried");
+}
+
+#[test]
+fn bulk_reads_keep_input_order() {
+    let server = MockServer::start();
+    let usernames: Vec<String> = (0..20).map(|i| format!("svc_{i}")).collect();
+    for username in usernames.iter().step_by(2) {
+        server.mock(
+            "GET",
+            &format!("/v1/users/{username}/instances"),
+            200,
+            fixtures::duckling_config(),
+        );
+    }
+    let client = dkdc_md_cli::MotherduckClient::builder("t")
+        .base_url(&server.url())
+        .build()
+        .unwrap();
+
+    let configs = client.get_duckling_configs(&usernames);
+    assert_eq!(configs.len(), 20);
+    for (i, config) in configs.iter().enumerate() {
+        assert_eq!(config.is_ok(), i % 2 == 0, "svc_{i}");
+    }
+    assert_eq!(server.requests().len(), 20);
+}
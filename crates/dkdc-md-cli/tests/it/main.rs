@@ -0,0 +1,121 @@
+//! End-to-end tests running the `md` binary against the in-process mock API.
+
+mod account;
+mod annotate;
+mod api_mode;
+mod approval;
+mod audit;
+mod auth;
+mod bundle;
+mod capabilities;
+mod completions;
+mod config;
+mod connect_string;
+mod contract;
+mod generate;
+#[cfg(unix)]
+mod hooks;
+mod instance;
+mod library;
+#[cfg(feature = "otel")]
+mod otel;
+mod profile;
+mod provision;
+mod resource;
+mod sandbox;
+mod schedule;
+mod search;
+mod service_account;
+mod sync;
+#[cfg(unix)]
+mod test_connection;
+mod token;
+mod wait;
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dkdc_md_cli::testing::MockServer;
+
+/// Run `md` against `server` with a dummy token, no config file, and no TTY on stdin.
+fn md(server: &MockServer, args: &[&str]) -> Output {
+    md_with_config(server, "", args)
+}
+
+/// Like [`md`], with `config` as the contents of the config file.
+fn md_with_config(server: &MockServer, config: &str, args: &[&str]) -> Output {
+    let dir = TempDir::new();
+    std::fs::write(dir.0.join("config.toml"), config).unwrap();
+    md_in(&dir, server, args)
+}
+
+/// Like [`md`], with `dir` as the config and working directory so state persists across
+/// runs.
+fn md_in(dir: &TempDir, server: &MockServer, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_md"))
+        .args(args)
+        .current_dir(&dir.0)
+        .env("MOTHERDUCK_API_URL", server.url())
+        .env("MOTHERDUCK_TOKEN", "test-token")
+        .env("MD_CONFIG", dir.0.join("config.toml"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run md")
+}
+
+/// Like [`md`], with `input` piped to stdin.
+fn md_with_stdin(server: &MockServer, input: &str, args: &[&str]) -> Output {
+    let dir = TempDir::new();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_md"))
+        .args(args)
+        .current_dir(&dir.0)
+        .env("MOTHERDUCK_API_URL", server.url())
+        .env("MOTHERDUCK_TOKEN", "test-token")
+        .env("MD_CONFIG", dir.0.join("config.toml"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run md");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("failed to run md")
+}
+
+/// Uniquely named directory under the system temp dir, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("md-it-{}-{n}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "md failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
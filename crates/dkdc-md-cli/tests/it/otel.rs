@@ -0,0 +1,57 @@
+use std::process::{Command, Stdio};
+
+use dkdc_md_cli::testing::{MockServer, fixtures};
+
+use crate::{TempDir, stdout};
+
+#[test]
+fn exports_command_and_request_spans() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock("POST", "/v1/traces", 200, serde_json::json!({}));
+
+    let dir = TempDir::new();
+    let output = Command::new(env!("CARGO_BIN_EXE_md"))
+        .args(["token", "list", "svc_test"])
+        .env("MOTHERDUCK_API_URL", server.url())
+        .env("MOTHERDUCK_TOKEN", "test-token")
+        .env("MD_CONFIG", dir.0.join("config.toml"))
+        .env("OTEL_EXPORTER_OTLP_ENDPOINT", server.url())
+        .env("OTEL_SERVICE_NAME", "md-it")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    stdout(&output);
+
+    let requests = server.requests();
+    let [api, export] = &requests[..] else {
+        panic!("expected an API call and an export, got {requests:?}");
+    };
+    let payload = export.json();
+    let resource = &payload["resourceSpans"][0];
+    assert_eq!(
+        resource["resource"]["attributes"][0]["value"]["stringValue"],
+        "md-it"
+    );
+    let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+    let [root, http] = &spans[..] else {
+        panic!("expected two spans, got {spans:?}");
+    };
+    assert_eq!(root["name"], "md token list");
+    assert_eq!(http["name"], "GET");
+    assert_eq!(http["parentSpanId"], root["spanId"]);
+    assert_eq!(http["status"]["code"], 1);
+
+    let correlation_id = api.header("x-correlation-id").unwrap().replace('-', "");
+    assert_eq!(root["traceId"], correlation_id);
+    let traceparent = format!(
+        "00-{correlation_id}-{}-01",
+        http["spanId"].as_str().unwrap()
+    );
+    assert_eq!(api.header("traceparent"), Some(traceparent.as_str()));
+}
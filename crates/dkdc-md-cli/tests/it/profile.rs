@@ -0,0 +1,178 @@
+use std::process::{Command, Output, Stdio};
+
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::{TempDir, stderr, stdout};
+
+/// Run `md` with `dir`'s config and no base URL or token in the environment, so the
+/// profile supplies them.
+fn md_profile(dir: &TempDir, env: &[(&str, &str)], args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_md"))
+        .args(args)
+        .current_dir(&dir.0)
+        .env_remove("MOTHERDUCK_API_URL")
+        .env_remove("MOTHERDUCK_TOKEN")
+        .env_remove("motherduck_token")
+        .env_remove("MD_PROFILE")
+        .env("MD_CONFIG", dir.0.join("config.toml"))
+        .envs(env.iter().copied())
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run md")
+}
+
+fn two_orgs(acme: &MockServer, labs: &MockServer) -> TempDir {
+    let dir = TempDir::new();
+    let config = format!(
+        "# orgs\n\n[profiles.acme]\ntoken_env = \"MD_TOKEN_ACME\"\nbase_url = \"{}\"\n\n\
+         [profiles.labs]\ntoken_env = \"MD_TOKEN_LABS\"\nbase_url = \"{}\"\noutput = \"json\"\n",
+        acme.url(),
+        labs.url()
+    );
+    std::fs::write(dir.0.join("config.toml"), config).unwrap();
+    dir
+}
+
+const TOKENS: [(&str, &str); 2] = [
+    ("MD_TOKEN_ACME", "acme-token"),
+    ("MD_TOKEN_LABS", "labs-token"),
+];
+
+#[test]
+fn profile_selects_org_token_and_output() {
+    let acme = MockServer::start();
+    let labs = MockServer::start();
+    for server in [&acme, &labs] {
+        server.mock(
+            "GET",
+            "/v1/users/svc_test/tokens",
+            200,
+            fixtures::token_list(),
+        );
+    }
+    let dir = two_orgs(&acme, &labs);
+
+    let out = stdout(&md_profile(
+        &dir,
+        &TOKENS,
+        &["--profile", "labs", "token", "list", "svc_test"],
+    ));
+    assert!(out.starts_with('{'));
+    assert!(acme.requests().is_empty());
+    assert_eq!(
+        labs.requests()[0].header("authorization"),
+        Some("Bearer labs-token")
+    );
+
+    let out = stdout(&md_profile(
+        &dir,
+        &[TOKENS[0], ("MD_PROFILE", "acme")],
+        &["token", "list", "svc_test"],
+    ));
+    assert!(out.starts_with("ID"));
+    assert_eq!(
+        acme.requests()[0].header("authorization"),
+        Some("Bearer acme-token")
+    );
+
+    // --token wins over the profile's token source.
+    stdout(&md_profile(
+        &dir,
+        &[],
+        &[
+            "--profile",
+            "acme",
+            "--token",
+            "flag-token",
+            "token",
+            "list",
+            "svc_test",
+        ],
+    ));
+    assert_eq!(
+        acme.requests()[1].header("authorization"),
+        Some("Bearer flag-token")
+    );
+
+    let missing = md_profile(
+        &dir,
+        &[],
+        &["--profile", "labs", "token", "list", "svc_test"],
+    );
+    assert!(!missing.status.success());
+    assert!(stderr(&missing).contains("no token for profile 'labs'"));
+    let unknown = md_profile(
+        &dir,
+        &TOKENS,
+        &["--profile", "lab", "token", "list", "svc_test"],
+    );
+    assert!(!unknown.status.success());
+    assert!(stderr(&unknown).contains("unknown profile 'lab' (expected acme, labs)"));
+}
+
+#[test]
+fn use_switches_the_active_profile() {
+    let acme = MockServer::start();
+    let labs = MockServer::start();
+    labs.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+    let dir = two_orgs(&acme, &labs);
+
+    let out = stdout(&md_profile(&dir, &[], &["profile", "use", "labs"]));
+    assert_eq!(out, "now using profile 'labs'\n");
+    let text = std::fs::read_to_string(dir.0.join("config.toml")).unwrap();
+    assert!(text.starts_with("profile = \"labs\"\n# orgs\n"));
+
+    stdout(&md_profile(&dir, &TOKENS, &["token", "list", "svc_test"]));
+    assert_eq!(labs.requests().len(), 1);
+    assert!(acme.requests().is_empty());
+
+    let out = stdout(&md_profile(&dir, &[], &["profile", "list"]));
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].starts_with("ACTIVE"));
+    assert!(lines[1].contains("acme") && lines[1].contains("env MD_TOKEN_ACME"));
+    assert!(lines[2].starts_with('*') && lines[2].contains("labs"));
+
+    let out = stdout(&md_profile(
+        &dir,
+        &[],
+        &["-o", "json", "--profile", "acme", "profile", "list"],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value["active"], "acme");
+    assert_eq!(value["profiles"][1]["output"], "json");
+
+    let unknown = md_profile(&dir, &[], &["profile", "use", "nope"]);
+    assert!(!unknown.status.success());
+    assert!(stderr(&unknown).contains("unknown profile 'nope'"));
+}
+
+#[cfg(unix)]
+#[test]
+fn token_command_supplies_the_token() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    let dir = TempDir::new();
+    let config = format!(
+        "profile = \"vault\"\n\n[profiles.vault]\ntoken_command = \"echo 'vault-token'\"\n\
+         base_url = \"{}\"\n",
+        server.url()
+    );
+    std::fs::write(dir.0.join("config.toml"), config).unwrap();
+
+    stdout(&md_profile(&dir, &[], &["token", "list", "svc_test"]));
+    assert_eq!(
+        server.requests()[0].header("authorization"),
+        Some("Bearer vault-token")
+    );
+}
@@ -0,0 +1,289 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::{md, md_with_config, stderr, stdout};
+
+fn mock_account(server: &MockServer) {
+    server.mock("POST", "/v1/users", 200, fixtures::user());
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+}
+
+fn calls(server: &MockServer) -> Vec<String> {
+    server
+        .requests()
+        .iter()
+        .map(|r| format!("{} {}", r.method, r.path))
+        .collect()
+}
+
+#[test]
+fn creates_account_token_and_config() {
+    let server = MockServer::start();
+    mock_account(&server);
+    server.mock(
+        "PUT",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+
+    let out = stdout(&md(
+        &server,
+        &[
+            "provision",
+            "svc_test",
+            "--ttl",
+            "90d",
+            "--rw-size",
+            "pulse",
+            "--flock-size",
+            "2",
+        ],
+    ));
+    assert_eq!(out, "md_secret_value\n");
+    assert_eq!(
+        calls(&server),
+        [
+            "POST /v1/users",
+            "POST /v1/users/svc_test/tokens",
+            "GET /v1/users/svc_test/instances",
+            "PUT /v1/users/svc_test/instances",
+        ]
+    );
+    let requests = server.requests();
+    assert_eq!(
+        requests[1].json(),
+        json!({ "name": "default", "ttl": 90 * 86400, "token_type": "read_write" })
+    );
+    assert_eq!(
+        requests[3].json(),
+        json!({ "config": {
+            "read_write": { "instance_size": "pulse" },
+            "read_scaling": { "instance_size": "standard", "flock_size": 2 }
+        } })
+    );
+}
+
+const TEMPLATES: &str = r#"
+[templates.etl-worker]
+token_name = "etl"
+ttl = "30d"
+token_type = "read-scaling"
+rs_size = "jumbo"
+flock_size = 3
+"#;
+
+#[test]
+fn template_fills_options_and_flags_win() {
+    let server = MockServer::start();
+    mock_account(&server);
+    server.mock(
+        "PUT",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+
+    stdout(&md_with_config(
+        &server,
+        TEMPLATES,
+        &[
+            "provision",
+            "svc_test",
+            "--template",
+            "etl-worker",
+            "--flock-size",
+            "1",
+        ],
+    ));
+    let requests = server.requests();
+    assert_eq!(
+        requests[1].json(),
+        json!({ "name": "etl", "ttl": 30 * 86400, "token_type": "read_scaling" })
+    );
+    assert_eq!(
+        requests[3].json()["config"]["read_scaling"],
+        json!({ "instance_size": "jumbo", "flock_size": 1 })
+    );
+}
+
+#[test]
+fn unknown_template_fails_before_any_call() {
+    let server = MockServer::start();
+
+    let output = md_with_config(
+        &server,
+        TEMPLATES,
+        &["provision", "svc_test", "--template", "nope"],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no template 'nope' in the config"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn json_output_redacts_secret() {
+    let server = MockServer::start();
+    mock_account(&server);
+
+    let out = stdout(&md(
+        &server,
+        &["-o", "json", "provision", "svc_test", "--ttl", "3600"],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value["username"], "svc_test");
+    assert_eq!(value["token"]["token"], "***");
+    assert!(value["instances"].is_null());
+    assert_eq!(calls(&server).len(), 2, "no sizing means no config calls");
+}
+
+#[test]
+fn rolls_back_when_config_fails() {
+    let server = MockServer::start();
+    mock_account(&server);
+    server.mock(
+        "PUT",
+        "/v1/users/svc_test/instances",
+        500,
+        fixtures::error("boom"),
+    );
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_123",
+        200,
+        json!({}),
+    );
+    server.mock("DELETE", "/v1/users/svc_test", 200, json!({}));
+
+    let output = md(
+        &server,
+        &["provision", "svc_test", "--ttl", "1h", "--flock-size", "2"],
+    );
+    assert!(!output.status.success());
+    assert_eq!(
+        calls(&server)[4..],
+        [
+            "DELETE /v1/users/svc_test/tokens/tok_123",
+            "DELETE /v1/users/svc_test",
+        ]
+    );
+    let err = stderr(&output);
+    assert!(
+        err.contains("provisioning 'svc_test' failed; the service account was deleted again"),
+        "{err}"
+    );
+    assert!(err.contains("API error (500): boom"), "{err}");
+}
+
+#[test]
+fn reports_failed_rollback() {
+    let server = MockServer::start();
+    server.mock("POST", "/v1/users", 200, fixtures::user());
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        400,
+        fixtures::error("bad ttl"),
+    );
+
+    let output = md(&server, &["provision", "svc_test", "--ttl", "1h"]);
+    assert!(!output.status.success());
+    let err = stderr(&output);
+    assert!(err.contains("rollback failed"), "{err}");
+    assert!(err.contains("delete the service account by hand"), "{err}");
+}
+
+fn mock_deprovision(server: &MockServer) {
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+    for id in ["tok_123", "tok_456"] {
+        server.mock(
+            "DELETE",
+            &format!("/v1/users/svc_test/tokens/{id}"),
+            200,
+            json!({}),
+        );
+    }
+}
+
+#[test]
+fn deprovision_runs_steps_in_order() {
+    let server = MockServer::start();
+    mock_deprovision(&server);
+    server.mock(
+        "PUT",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+    server.mock("DELETE", "/v1/users/svc_test", 200, json!({}));
+
+    let output = md(&server, &["--force", "deprovision", "svc_test"]);
+    assert_eq!(
+        stdout(&output),
+        "deprovisioned 'svc_test' (revoked 2 tokens)\n"
+    );
+    assert!(stderr(&output).contains("scale read-scaling flock 4 -> 0"));
+    assert_eq!(
+        calls(&server)[2..],
+        [
+            "DELETE /v1/users/svc_test/tokens/tok_123",
+            "DELETE /v1/users/svc_test/tokens/tok_456",
+            "PUT /v1/users/svc_test/instances",
+            "DELETE /v1/users/svc_test",
+        ]
+    );
+    assert_eq!(
+        server.requests()[4].json()["config"]["read_scaling"],
+        json!({ "instance_size": "standard", "flock_size": 0 })
+    );
+}
+
+#[test]
+fn deprovision_needs_typed_confirmation() {
+    let server = MockServer::start();
+    mock_deprovision(&server);
+
+    let output = md(&server, &["-y", "deprovision", "svc_test"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("needs a typed confirmation"));
+    assert_eq!(calls(&server).len(), 2, "nothing changed");
+}
+
+#[test]
+fn deprovision_stops_at_first_failure() {
+    let server = MockServer::start();
+    mock_deprovision(&server);
+    server.mock(
+        "PUT",
+        "/v1/users/svc_test/instances",
+        500,
+        fixtures::error("boom"),
+    );
+
+    let output = md(&server, &["--force", "deprovision", "svc_test"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("failed to scale down; tokens were already revoked"));
+    assert!(!calls(&server).contains(&"DELETE /v1/users/svc_test".to_string()));
+}
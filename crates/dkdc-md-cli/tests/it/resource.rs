@@ -0,0 +1,133 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::json;
+
+use crate::{md, stderr, stdout};
+
+#[test]
+fn get_single_token_by_uri() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let out = stdout(&md(
+        &server,
+        &["-o", "json", "get", "md://users/svc_test/tokens/tok_456"],
+    ));
+    let token: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(token["name"], "reader");
+}
+
+#[test]
+fn get_highlights_json_with_color() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    let args = ["-o", "json", "get", "md://users/svc_test/tokens/tok_456"];
+
+    let out = stdout(&md(&server, &[&["--color", "always"][..], &args].concat()));
+    assert!(
+        out.contains("\x1b[1;34m\"name\"\x1b[0m: \x1b[32m\"reader\"\x1b[0m"),
+        "{out:?}"
+    );
+    let out = stdout(&md(
+        &server,
+        &[&["--color", "always", "--no-color"][..], &args].concat(),
+    ));
+    assert!(!out.contains('\x1b'), "{out:?}");
+}
+
+#[test]
+fn get_missing_token_fails() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let output = md(&server, &["get", "md://users/svc_test/tokens/nope"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("token 'nope' not found"));
+}
+
+#[test]
+fn get_instances_renders_like_instance_get() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+
+    let out = stdout(&md(&server, &["get", "md://users/svc_test/instances"]));
+    assert_eq!(
+        out,
+        "read_write:   standard\nread_scaling: standard (flock_size: 4)\n"
+    );
+}
+
+#[test]
+fn delete_token_by_uri() {
+    let server = MockServer::start();
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_123",
+        200,
+        json!({}),
+    );
+
+    stdout(&md(
+        &server,
+        &["-y", "delete", "md://users/svc_test/tokens/tok_123"],
+    ));
+    let req = &server.requests()[0];
+    assert_eq!(req.method, "DELETE");
+    assert_eq!(req.path, "/v1/users/svc_test/tokens/tok_123");
+}
+
+#[test]
+fn delete_read_only_resource_fails() {
+    let server = MockServer::start();
+    let output = md(&server, &["-y", "delete", "md://users/svc_test/instances"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("cannot be deleted"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn describe_makes_no_request() {
+    let server = MockServer::start();
+    let out = stdout(&md(
+        &server,
+        &["-o", "json", "describe", "md://users/a%2Fb/tokens"],
+    ));
+    let described: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        described,
+        json!({
+            "uri": "md://users/a%2Fb/tokens",
+            "kind": "token-list",
+            "api_path": "/v1/users/a%2Fb/tokens",
+            "verbs": ["get", "describe"],
+        })
+    );
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn invalid_uri_is_a_usage_error() {
+    let server = MockServer::start();
+    let output = md(&server, &["get", "users/svc_test"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("must start with 'md://'"));
+}
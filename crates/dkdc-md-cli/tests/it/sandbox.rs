@@ -0,0 +1,52 @@
+use dkdc_md_cli::testing::MockServer;
+use serde_json::Value;
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+#[test]
+fn sandbox_serves_a_demo_org_without_calling_the_api() {
+    let server = MockServer::start();
+    let dir = TempDir::new();
+
+    let output = md_in(&dir, &server, &["--sandbox", "account", "list-active"]);
+    assert!(stdout(&output).contains("svc_etl"));
+    assert!(stderr(&output).contains("sandbox: using the demo org in"));
+
+    stdout(&md_in(
+        &dir,
+        &server,
+        &["--sandbox", "service-account", "create", "svc_demo"],
+    ));
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &[
+            "--sandbox",
+            "-o",
+            "json",
+            "token",
+            "create",
+            "svc_demo",
+            "--name",
+            "ci",
+            "--ttl",
+            "3600",
+        ],
+    ));
+    let token: Value = serde_json::from_str(&out).unwrap();
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["--sandbox", "token", "list", "svc_demo"],
+    ));
+    assert!(out.contains(token["id"].as_str().unwrap()), "{out}");
+    assert!(dir.0.join("sandbox.json").exists());
+
+    let output = md_in(
+        &dir,
+        &server,
+        &["--sandbox", "token", "list", "svc_missing"],
+    );
+    assert!(stderr(&output).contains("user not found"));
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,83 @@
+use dkdc_md_cli::testing::MockServer;
+use serde_json::Value;
+
+use crate::{TempDir, md, md_in, stderr, stdout};
+
+#[test]
+fn add_list_remove() {
+    let server = MockServer::start();
+    let dir = TempDir::new();
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &[
+            "schedule",
+            "add",
+            "instance set svc_test --flock-size 8",
+            "--cron",
+            "0 9 * * 1-5",
+        ],
+    ));
+    assert_eq!(out.trim(), "1");
+
+    let out = stdout(&md_in(&dir, &server, &["-o", "json", "schedule", "list"]));
+    let listed: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(listed["schedules"][0]["cron"], "0 9 * * 1-5");
+    assert_eq!(
+        listed["schedules"][0]["command"],
+        "instance set svc_test --flock-size 8"
+    );
+    assert!(
+        listed["schedules"][0]["next"]
+            .as_str()
+            .unwrap()
+            .ends_with("09:00")
+    );
+
+    stdout(&md_in(&dir, &server, &["schedule", "remove", "1"]));
+    let out = stdout(&md_in(&dir, &server, &["schedule", "list"]));
+    assert!(out.is_empty());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn add_rejects_invalid_commands() {
+    let server = MockServer::start();
+    for (command, expected) in [
+        (
+            "instance set svc_test --flock-size 99",
+            "invalid scheduled command",
+        ),
+        ("schedule list", "cannot manage schedules"),
+    ] {
+        let output = md(
+            &server,
+            &["schedule", "add", command, "--cron", "0 9 * * *"],
+        );
+        assert!(!output.status.success());
+        assert!(stderr(&output).contains(expected), "{command}");
+    }
+    let output = md(
+        &server,
+        &["schedule", "add", "account list-active", "--cron", "9am"],
+    );
+    assert!(stderr(&output).contains("invalid cron expression"));
+}
+
+#[test]
+fn add_accepts_schedule_as_an_argument_value() {
+    let server = MockServer::start();
+    let dir = TempDir::new();
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &[
+            "schedule",
+            "add",
+            "token create svc_test --name schedule",
+            "--cron",
+            "0 9 * * *",
+        ],
+    ));
+    assert_eq!(out.trim(), "1");
+}
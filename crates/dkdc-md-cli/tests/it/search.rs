@@ -0,0 +1,45 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::{TempDir, md_in, stdout};
+
+#[test]
+fn finds_token_owner_and_caches_index() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+    let dir = TempDir::new();
+
+    let out = stdout(&md_in(&dir, &server, &["search", "tok_456"]));
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("svc_test  token_id  tok_456"));
+    assert_eq!(server.requests().len(), 3);
+
+    let out = stdout(&md_in(&dir, &server, &["-o", "json", "search", "OTHER"]));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        value,
+        json!([{ "field": "username", "username": "svc_other" }])
+    );
+    assert_eq!(server.requests().len(), 3, "second search uses the cache");
+
+    stdout(&md_in(&dir, &server, &["search", "x", "--refresh"]));
+    assert_eq!(server.requests().len(), 6);
+}
@@ -0,0 +1,204 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+fn mock_org(server: &MockServer) {
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    for username in ["svc_test", "svc_other"] {
+        server.mock(
+            "GET",
+            &format!("/v1/users/{username}/tokens"),
+            200,
+            fixtures::token_list(),
+        );
+        server.mock(
+            "GET",
+            &format!("/v1/users/{username}/instances"),
+            200,
+            fixtures::duckling_config(),
+        );
+    }
+}
+
+#[test]
+fn local_commands_read_the_synced_inventory() {
+    let server = MockServer::start();
+    mock_org(&server);
+    let dir = TempDir::new();
+
+    let out = stdout(&md_in(&dir, &server, &["sync"]));
+    assert_eq!(out, "synced 2 accounts (2 added, 0 updated, 0 removed)\n");
+    let output = md_in(&dir, &server, &["changes"]);
+    assert_eq!(stdout(&output), "", "the first sync is the baseline");
+    assert!(stderr(&output).contains("no changes since"));
+    let synced = server.requests().len();
+    assert_eq!(synced, 5);
+
+    let api = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "json", "token", "list", "svc_test"],
+    ));
+    let output = md_in(
+        &dir,
+        &server,
+        &["-o", "json", "--local", "token", "list", "svc_test"],
+    );
+    assert_eq!(stdout(&output), api);
+    assert!(stderr(&output).contains("from local inventory synced"));
+    for args in [
+        &["--local", "account", "tree"][..],
+        &["--local", "account", "count"],
+        &["--local", "token", "count", "--all-users"],
+        &["--local", "search", "tok_456"],
+    ] {
+        stdout(&md_in(&dir, &server, args));
+    }
+    assert_eq!(
+        server.requests().len(),
+        synced + 1,
+        "only the non-local list"
+    );
+
+    let output = md_in(&dir, &server, &["--local", "token", "list", "svc_new"]);
+    assert!(stderr(&output).contains("'svc_new' is not in the local inventory"));
+    let output = md_in(&dir, &server, &["--local", "instance", "get", "svc_test"]);
+    assert!(stderr(&output).contains("--local is not supported by `md instance get`"));
+}
+
+#[test]
+fn sync_updates_named_accounts_and_drops_inactive_ones() {
+    let server = MockServer::start();
+    mock_org(&server);
+    let dir = TempDir::new();
+
+    let output = md_in(&dir, &server, &["sync", "svc_test"]);
+    assert!(stderr(&output).contains("run `md sync` without usernames first"));
+    stdout(&md_in(&dir, &server, &["sync"]));
+
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        json!({ "accounts": [{ "username": "svc_test", "ducklings": [] }] }),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+    let before = server.requests().len();
+    let out = stdout(&md_in(&dir, &server, &["-o", "json", "sync", "svc_test"]));
+    let summary: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        summary,
+        json!({ "accounts": 1, "added": 0, "updated": 1, "removed": 1, "changes": 3 })
+    );
+    assert_eq!(server.requests().len(), before + 3);
+
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["--local", "token", "count", "svc_test"],
+    ));
+    assert_eq!(out, "0\n");
+    let output = md_in(&dir, &server, &["sync", "svc_gone"]);
+    assert!(stderr(&output).contains("'svc_gone' is not an active account"));
+}
+
+#[test]
+fn changes_report_what_differs_between_syncs() {
+    let server = MockServer::start();
+    mock_org(&server);
+    let dir = TempDir::new();
+    stdout(&md_in(&dir, &server, &["sync"]));
+
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        json!({ "accounts": [{ "username": "svc_test" }, { "username": "svc_new" }] }),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        json!({
+            "read_write": { "instance_size": "standard" },
+            "read_scaling": { "instance_size": "standard", "flock_size": 0 }
+        }),
+    );
+    for path in ["/v1/users/svc_new/tokens", "/v1/users/svc_test/tokens"] {
+        server.mock("GET", path, 200, json!({ "tokens": [fixtures::token()] }));
+    }
+    server.mock(
+        "GET",
+        "/v1/users/svc_new/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+    stdout(&md_in(&dir, &server, &["sync"]));
+
+    let out = stdout(&md_in(
+        &dir,
+        &server,
+        &["-o", "json", "changes", "--since", "today"],
+    ));
+    let changes: Value = serde_json::from_str(&out).unwrap();
+    let summary: Vec<String> = changes["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            format!(
+                "{} {} {} {}",
+                c["kind"], c["username"], c["subject"], c["detail"]
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            r#""disappeared" "svc_other" "account" null"#,
+            r#""appeared" "svc_new" "account" null"#,
+            r#""disappeared" "svc_test" "token tok_456" "reader""#,
+            r#""changed" "svc_test" "instances" "read_scaling.flock_size: 4 -> 0""#,
+        ]
+    );
+
+    let out = stdout(&md_in(&dir, &server, &["changes", "--since", "yesterday"]));
+    assert!(out.starts_with("SYNCED_AT"));
+    assert_eq!(out.lines().count(), 5);
+    let output = md_in(&dir, &server, &["changes", "--since", "whenever"]);
+    assert!(stderr(&output).contains("invalid --since 'whenever'"));
+}
+
+#[test]
+fn sync_writes_only_the_rows_it_changed() {
+    let server = MockServer::start();
+    mock_org(&server);
+    let dir = TempDir::new();
+    stdout(&md_in(&dir, &server, &["sync"]));
+
+    let db = rusqlite::Connection::open(dir.0.join("inventory.db")).unwrap();
+    db.execute("UPDATE accounts SET synced_at = 'untouched'", [])
+        .unwrap();
+    stdout(&md_in(&dir, &server, &["sync", "svc_test"]));
+    let synced_at = |username: &str| -> String {
+        db.query_row(
+            "SELECT synced_at FROM accounts WHERE username = ?1",
+            [username],
+            |r| r.get(0),
+        )
+        .unwrap()
+    };
+    assert_eq!(synced_at("svc_other"), "untouched");
+    assert_ne!(synced_at("svc_test"), "untouched");
+}
@@ -0,0 +1,135 @@
+use std::os::unix::fs::PermissionsExt;
+
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::json;
+
+use crate::{TempDir, md_in, stderr, stdout};
+
+const TOKENS: &str = "/v1/users/svc_test/tokens";
+
+/// Stands in for the DuckDB CLI: logs its arguments and token, and answers `SELECT 1`
+/// unless the token is `bad`.
+const DUCKDB: &str = r#"#!/bin/sh
+echo "$* token=$motherduck_token" >> duckdb.log
+if [ "$motherduck_token" = bad ]; then echo "Invalid token" >&2; exit 1; fi
+echo 1
+"#;
+
+fn dir_with_duckdb() -> TempDir {
+    let dir = TempDir::new();
+    let path = dir.0.join("duckdb");
+    std::fs::write(&path, DUCKDB).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(dir.0.join("config.toml"), "").unwrap();
+    dir
+}
+
+fn log(dir: &TempDir) -> String {
+    std::fs::read_to_string(dir.0.join("duckdb.log")).unwrap_or_default()
+}
+
+#[test]
+fn creates_and_deletes_a_temporary_token() {
+    let server = MockServer::start();
+    server.mock("POST", TOKENS, 200, fixtures::created_token());
+    server.mock(
+        "DELETE",
+        &format!("{TOKENS}/{}", fixtures::TOKEN_ID),
+        200,
+        json!({}),
+    );
+    let dir = dir_with_duckdb();
+
+    let output = md_in(
+        &dir,
+        &server,
+        &[
+            "test-connection",
+            "svc_test",
+            "--duckdb",
+            "./duckdb",
+            "--database",
+            "analytics",
+        ],
+    );
+    let out = stdout(&output);
+    assert!(out.starts_with("ok: 'svc_test' connected and ran SELECT 1 in "));
+    assert_eq!(
+        log(&dir),
+        "-noheader -list md:analytics SELECT 1 token=md_secret_value\n"
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    let created = requests[0].json();
+    assert_eq!(created["ttl"], 300);
+    assert_eq!(created["token_type"], "read_write");
+    assert!(
+        created["name"]
+            .as_str()
+            .unwrap()
+            .starts_with("md-test-connection-")
+    );
+    assert_eq!(requests[1].method, "DELETE");
+    assert!(stderr(&output).contains("deleted temporary token"));
+}
+
+#[test]
+fn reports_failure_after_cleaning_up() {
+    let server = MockServer::start();
+    let mut token = fixtures::created_token();
+    token["token"] = json!("bad");
+    server.mock("POST", TOKENS, 200, token);
+    server.mock(
+        "DELETE",
+        &format!("{TOKENS}/{}", fixtures::TOKEN_ID),
+        200,
+        json!({}),
+    );
+    let dir = dir_with_duckdb();
+
+    let output = md_in(
+        &dir,
+        &server,
+        &[
+            "test-connection",
+            "svc_test",
+            "--read-scaling",
+            "--duckdb",
+            "./duckdb",
+        ],
+    );
+    assert!(!output.status.success());
+    let err = stderr(&output);
+    assert!(err.contains("connection test failed for 'svc_test'"));
+    assert!(err.contains("Invalid token"));
+    assert_eq!(server.requests()[0].json()["token_type"], "read_scaling");
+    assert_eq!(server.requests()[1].method, "DELETE");
+}
+
+#[test]
+fn uses_a_provided_token_without_touching_the_api() {
+    let server = MockServer::start();
+    let dir = dir_with_duckdb();
+
+    let output = md_in(
+        &dir,
+        &server,
+        &[
+            "-o",
+            "json",
+            "test-connection",
+            "svc_test",
+            "--secret",
+            "given",
+            "--duckdb",
+            "./duckdb",
+        ],
+    );
+    let report: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(report["ok"], true);
+    assert_eq!(report["temporary_token"], false);
+    assert!(report["latency_ms"].is_u64());
+    assert_eq!(log(&dir), "-noheader -list md: SELECT 1 token=given\n");
+    assert!(server.requests().is_empty());
+}
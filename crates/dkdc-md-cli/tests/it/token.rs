@@ -0,0 +1,954 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::{Value, json};
+
+use crate::{TempDir, md, md_with_config, stderr, stdout};
+
+#[test]
+fn list_renders_table() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let out = stdout(&md(&server, &["token", "list", "svc_test"]));
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("ID"));
+    assert!(lines[1].contains("tok_123") && lines[1].contains("2030-01-01T00:00:00Z"));
+    assert!(lines[2].contains("read_scaling") && lines[2].ends_with("never"));
+}
+
+#[test]
+fn list_json_passes_response_through() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let out = stdout(&md(&server, &["-o", "json", "token", "list", "svc_test"]));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value, fixtures::token_list());
+}
+
+#[test]
+fn list_psobject_prints_items_on_one_line() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let out = stdout(&md(
+        &server,
+        &["-o", "psobject", "token", "list", "svc_test"],
+    ));
+    assert_eq!(out.lines().count(), 1);
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value, fixtures::token_list()["tokens"]);
+}
+
+#[test]
+fn list_ndjson_prints_one_token_per_line() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let out = stdout(&md(&server, &["-o", "ndjson", "token", "list", "svc_test"]));
+    let tokens: Vec<Value> = out
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        tokens,
+        fixtures::token_list()["tokens"].as_array().unwrap()[..]
+    );
+
+    // Other commands print their one document on one line.
+    let out = stdout(&md(
+        &server,
+        &["-o", "ndjson", "token", "count", "svc_test"],
+    ));
+    assert_eq!(out, "{\"by_user\":{\"svc_test\":2},\"count\":2}\n");
+}
+
+#[test]
+fn query_filters_the_json_result() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+
+    let out = stdout(&md(
+        &server,
+        &["token", "list", "svc_test", "--query", ".tokens[].id"],
+    ));
+    assert_eq!(out, "tok_123\ntok_456\n");
+    let out = stdout(&md(
+        &server,
+        &[
+            "-o",
+            "json",
+            "token",
+            "list",
+            "svc_test",
+            "--query",
+            ".tokens | length",
+        ],
+    ));
+    assert_eq!(out, "2\n");
+
+    let output = md(
+        &server,
+        &["token", "list", "svc_test", "--query", ".tokens["],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("invalid --query '.tokens[': expected"));
+    assert_eq!(
+        server.requests().len(),
+        2,
+        "an invalid query makes no request"
+    );
+}
+
+#[test]
+fn output_default_from_config_applies_per_command() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+    let config = "[output]\ntoken.list = \"json\"";
+
+    let out = stdout(&md_with_config(
+        &server,
+        config,
+        &["token", "list", "svc_test"],
+    ));
+    assert_eq!(
+        serde_json::from_str::<Value>(&out).unwrap(),
+        fixtures::token_list()
+    );
+    let out = stdout(&md_with_config(
+        &server,
+        config,
+        &["-o", "text", "token", "list", "svc_test"],
+    ));
+    assert!(out.starts_with("ID"));
+    let args = [
+        "token", "create", "svc_test", "--name", "ci", "--ttl", "3600",
+    ];
+    let out = stdout(&md_with_config(&server, config, &args));
+    assert_eq!(out.trim(), "md_secret_value");
+
+    let output = md_with_config(&server, "[output]\ntoken.lst = \"json\"", &args);
+    assert!(stderr(&output).contains("unknown command `token lst`"));
+    let output = md_with_config(&server, "[output]\ntoken.list = \"jsn\"", &args);
+    assert!(stderr(&output).contains(
+        "invalid output 'jsn' for `token list` in [output] \
+         (expected text, json, sh, ndjson, or psobject)"
+    ));
+}
+
+#[test]
+fn empty_list_says_so_on_stderr() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+    let list = |extra: &[&str]| {
+        let mut args = extra.to_vec();
+        args.extend(["token", "list", "svc_test"]);
+        md(&server, &args)
+    };
+
+    let output = list(&[]);
+    assert_eq!(stdout(&output), "");
+    assert_eq!(
+        stderr(&output),
+        "no tokens for 'svc_test'; create one with `md token create svc_test --name <name>`\n"
+    );
+    let output = md(&server, &["token", "list", "svc_test", "--hide-expired"]);
+    assert_eq!(
+        stderr(&output),
+        "no tokens for 'svc_test' match the filters\n"
+    );
+    assert_eq!(stderr(&list(&["--quiet"])), "");
+    assert_eq!(stderr(&list(&["-o", "json"])), "");
+}
+
+fn mixed_expiry_tokens() -> Value {
+    json!({
+        "tokens": [
+            { "id": "tok_old", "name": "old", "token_type": "read_write", "expire_at": "2020-01-01T00:00:00Z" },
+            { "id": "tok_new", "name": "new", "token_type": "read_write", "expire_at": null }
+        ]
+    })
+}
+
+#[test]
+fn list_marks_and_filters_expired() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        mixed_expiry_tokens(),
+    );
+
+    let out = stdout(&md(&server, &["token", "list", "svc_test"]));
+    assert!(
+        out.lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("2020-01-01T00:00:00Z EXPIRED")
+    );
+
+    let out = stdout(&md(
+        &server,
+        &["token", "list", "svc_test", "--hide-expired"],
+    ));
+    assert!(!out.contains("tok_old") && out.contains("tok_new"));
+
+    let out = stdout(&md(
+        &server,
+        &["-o", "json", "token", "list", "svc_test", "--only-expired"],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value["tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(value["tokens"][0]["id"], "tok_old");
+
+    let output = md(
+        &server,
+        &[
+            "token",
+            "list",
+            "svc_test",
+            "--hide-expired",
+            "--only-expired",
+        ],
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn list_filters_by_creation_and_expiry_time() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        json!({
+            "tokens": [
+                { "id": "tok_2024", "created_at": "2024-03-01T00:00:00Z", "expire_at": "2099-01-01T00:00:00Z" },
+                { "id": "tok_2025", "created_at": "2025-03-01T00:00:00Z", "expire_at": null },
+                { "id": "tok_undated", "expire_at": "2020-01-01T00:00:00Z" }
+            ]
+        }),
+    );
+    let ids = |args: &[&str]| -> Vec<String> {
+        let mut full = vec!["-o", "json", "token", "list", "svc_test"];
+        full.extend(args);
+        let value: Value = serde_json::from_str(&stdout(&md(&server, &full))).unwrap();
+        value["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    assert_eq!(ids(&["--created-after", "2025-01-01"]), ["tok_2025"]);
+    assert_eq!(
+        ids(&["--created-before", "2025-01-01T00:00:00Z"]),
+        ["tok_2024"]
+    );
+    assert_eq!(ids(&["--expires-before", "2030-01-01"]), ["tok_undated"]);
+    assert_eq!(
+        ids(&["--expires-before", "36500d"]),
+        ["tok_2024", "tok_undated"]
+    );
+
+    let output = md(
+        &server,
+        &["token", "list", "svc_test", "--created-after", "30d"],
+    );
+    assert!(stderr(&output).contains("left out 1 token "));
+    let output = md(
+        &server,
+        &["token", "list", "svc_test", "--created-after", "soon"],
+    );
+    assert!(stderr(&output).contains("invalid --created-after 'soon'"));
+}
+
+#[test]
+fn count_totals_tokens_across_users() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/tokens",
+        200,
+        json!({ "tokens": [] }),
+    );
+
+    assert_eq!(stdout(&md(&server, &["token", "count", "svc_test"])), "2\n");
+    let out = stdout(&md(
+        &server,
+        &["-o", "json", "token", "count", "--all-users"],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        value,
+        json!({ "count": 2, "by_user": { "svc_test": 2, "svc_other": 0 } })
+    );
+    assert!(!md(&server, &["token", "count"]).status.success());
+}
+
+#[test]
+fn create_sends_options_and_prints_secret() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+
+    let out = stdout(&md(
+        &server,
+        &[
+            "token",
+            "create",
+            "svc_test",
+            "--name",
+            "ci",
+            "--ttl",
+            "3600",
+            "--token-type",
+            "read-scaling",
+        ],
+    ));
+    assert_eq!(out.trim(), "md_secret_value");
+    assert_eq!(
+        server.requests()[0].json(),
+        json!({ "name": "ci", "ttl": 3600, "token_type": "read_scaling" })
+    );
+}
+
+#[test]
+fn create_takes_the_ttl_as_a_duration() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+    let create = |ttl: &str| {
+        md(
+            &server,
+            &["token", "create", "svc_test", "--name", "ci", "--ttl", ttl],
+        )
+    };
+
+    stdout(&create("90d"));
+    assert_eq!(server.requests()[0].json()["ttl"], 90 * 86400);
+    for ttl in ["1m", "400d"] {
+        let output = create(ttl);
+        assert!(!output.status.success());
+        assert!(
+            stderr(&output).contains("is not between 5m and 365d"),
+            "{ttl}"
+        );
+    }
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn create_sh_output_evals_to_variables() {
+    let server = MockServer::start();
+    let mut token = fixtures::created_token();
+    token["name"] = json!("it's $(touch pwned)");
+    server.mock("POST", "/v1/users/svc_test/tokens", 200, token);
+
+    let out = stdout(&md(
+        &server,
+        &["-o", "sh", "token", "create", "svc_test", "--name", "ci"],
+    ));
+    assert!(out.contains("TOKEN_SECRET='md_secret_value'\n"), "{out}");
+    let script =
+        format!("{out}\nprintf '%s|%s|%s' \"$TOKEN_ID\" \"$TOKEN_SECRET\" \"$TOKEN_NAME\"");
+    let evaluated = std::process::Command::new("sh")
+        .args(["-c", &script])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(evaluated.stdout).unwrap(),
+        format!("{}|md_secret_value|it's $(touch pwned)", fixtures::TOKEN_ID)
+    );
+}
+
+#[test]
+fn create_prints_the_field_asked_for() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+    let create = |field: &str| {
+        md(
+            &server,
+            &["token", "create", "svc_test", "-n", "ci", "--print", field],
+        )
+    };
+
+    assert_eq!(stdout(&create("id")), format!("{}\n", fixtures::TOKEN_ID));
+    let output = create("secret");
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains(
+            "--print: no field 'secret' in the result (fields: expire_at, id, name, token, \
+             token_type)"
+        ),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn curl_prints_each_request_without_the_token() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+
+    let url = server.url();
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    dkdc_md_cli::run_with(
+        [
+            "md",
+            "--base-url",
+            &url,
+            "--token",
+            "t",
+            "--curl",
+            "token",
+            "create",
+            "svc_test",
+            "-n",
+            "ci",
+            "--ttl",
+            "300",
+        ],
+        &mut out,
+        &mut err,
+    )
+    .unwrap();
+    let correlation_id = server.requests()[0]
+        .header("x-correlation-id")
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        String::from_utf8(err).unwrap(),
+        format!(
+            "curl -X POST {}/v1/users/svc_test/tokens -H \"Authorization: Bearer \
+             $MOTHERDUCK_TOKEN\" -H 'x-correlation-id: {correlation_id}' -H \
+             'content-type: application/json' --data-raw \
+             '{{\"name\":\"ci\",\"ttl\":300,\"token_type\":\"read_write\"}}'\n",
+            server.url()
+        )
+    );
+    assert_eq!(String::from_utf8(out).unwrap(), "md_secret_value\n");
+}
+
+#[test]
+fn delete_hits_token_path() {
+    let server = MockServer::start();
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_123",
+        200,
+        json!({}),
+    );
+
+    stdout(&md(
+        &server,
+        &["-y", "token", "delete", "svc_test", "tok_123"],
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn create_without_ttl_uses_default_for_token_type() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+    let config = "[defaults.ttl]\nread_write = \"90d\"\nread_scaling = \"7d\"";
+
+    let args = ["token", "create", "svc_test", "--name", "ci"];
+    let output = md_with_config(
+        &server,
+        config,
+        &[&args[..], &["--token-type", "read-scaling"]].concat(),
+    );
+    stdout(&output);
+    assert!(!stderr(&output).contains("never expire"));
+    stdout(&md_with_config(&server, config, &args));
+    stdout(&md_with_config(
+        &server,
+        config,
+        &[&args[..], &["--ttl", "3600"]].concat(),
+    ));
+    let ttls: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|r| r.json()["ttl"].clone())
+        .collect();
+    assert_eq!(ttls, [json!(7 * 86400), json!(90 * 86400), json!(3600)]);
+}
+
+#[test]
+fn create_without_ttl_warns() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+
+    let output = md(&server, &["token", "create", "svc_test", "--name", "ci"]);
+    stdout(&output);
+    assert!(stderr(&output).contains("warning: token 'ci' will never expire"));
+}
+
+#[test]
+fn warnings_as_errors_blocks_create() {
+    let server = MockServer::start();
+
+    let output = md(
+        &server,
+        &[
+            "--warnings-as-errors",
+            "token",
+            "create",
+            "svc_test",
+            "--name",
+            "ci",
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("warning treated as error"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn garbled_response_body_warns_through_warnings_as_errors() {
+    let server = MockServer::start();
+    let body = b"{\"tokens\": [], \"note\": \"caf\xe9\"}";
+    server.mock_bytes("GET", "/v1/users/svc_test/tokens", 200, body);
+
+    let output = md(&server, &["-o", "json", "token", "list", "svc_test"]);
+    assert!(stdout(&output).contains("caf\u{fffd}"));
+    assert_eq!(
+        stderr(&output),
+        "warning: HTTP 200 response body is not valid UTF-8; invalid bytes replaced\n"
+    );
+    let output = md(
+        &server,
+        &["--warnings-as-errors", "token", "list", "svc_test"],
+    );
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output)
+            .contains("not valid UTF-8; invalid bytes replaced (warning treated as error)")
+    );
+}
+
+const TTL_POLICY: &str = r#"
+[policy]
+require_ttl = true
+max_ttl = "1d"
+"#;
+
+#[test]
+fn policy_requires_ttl() {
+    let server = MockServer::start();
+
+    let output = md_with_config(
+        &server,
+        TTL_POLICY,
+        &["token", "create", "svc_test", "--name", "ci"],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("policy requires a token TTL"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn policy_rejects_ttl_over_max() {
+    let server = MockServer::start();
+
+    let output = md_with_config(
+        &server,
+        TTL_POLICY,
+        &[
+            "token", "create", "svc_test", "--name", "ci", "--ttl", "86401",
+        ],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("exceeds policy max_ttl 1d"));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn policy_clamps_ttl_when_configured() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+
+    let config = format!("{TTL_POLICY}clamp_ttl = true\n");
+    let output = md_with_config(
+        &server,
+        &config,
+        &[
+            "token", "create", "svc_test", "--name", "ci", "--ttl", "172800",
+        ],
+    );
+    stdout(&output);
+    assert!(stderr(&output).contains("clamping to 1d"));
+    assert_eq!(server.requests()[0].json()["ttl"], 86400);
+}
+
+#[test]
+fn revoke_all_deletes_every_token() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_123",
+        200,
+        json!({}),
+    );
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_456",
+        200,
+        json!({}),
+    );
+
+    let out = stdout(&md(
+        &server,
+        &[
+            "-y",
+            "token",
+            "revoke-all",
+            "svc_test",
+            "--reason",
+            "incident-123",
+        ],
+    ));
+    assert_eq!(
+        out.trim(),
+        "revoked 2 tokens for 'svc_test' (reason: incident-123)"
+    );
+    let deleted: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "DELETE")
+        .map(|r| r.path)
+        .collect();
+    assert_eq!(
+        deleted,
+        [
+            "/v1/users/svc_test/tokens/tok_123",
+            "/v1/users/svc_test/tokens/tok_456"
+        ]
+    );
+}
+
+#[test]
+fn revoke_all_continues_past_failures() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    // tok_123 has no DELETE mock, so it gets the mock server's 404.
+    server.mock(
+        "DELETE",
+        "/v1/users/svc_test/tokens/tok_456",
+        200,
+        json!({}),
+    );
+
+    let output = md(
+        &server,
+        &["-y", "-o", "json", "token", "revoke-all", "svc_test"],
+    );
+    assert!(!output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["revoked"], json!(["tok_456"]));
+    assert_eq!(report["failed"], json!(["tok_123"]));
+    let err = stderr(&output);
+    assert!(err.contains("failed to revoke 'tok_123'"), "{err}");
+    assert!(err.contains("failed to revoke 1 of 2 tokens"), "{err}");
+}
+
+#[test]
+fn create_json_redacts_secret_by_default() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::created_token(),
+    );
+    let args = [
+        "-o", "json", "token", "create", "svc_test", "--name", "ci", "--ttl", "3600",
+    ];
+
+    let output = md(&server, &args);
+    let created: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(created["token"], "***");
+    assert_eq!(created["id"], fixtures::TOKEN_ID);
+    assert!(stderr(&output).contains("--show-secret"));
+
+    let output = md(&server, &[&args[..], &["--show-secret"]].concat());
+    let created: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(created["token"], "md_secret_value");
+}
+
+#[test]
+fn reconcile_reports_untracked_and_missing_tokens() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        200,
+        fixtures::token_list(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_other/tokens",
+        200,
+        json!({ "tokens": [{ "id": "tok_789", "name": "manual" }] }),
+    );
+    let dir = TempDir::new();
+    let inventory = dir.0.join("tokens.csv");
+    std::fs::write(
+        &inventory,
+        "username,id,name\nsvc_test,tok_123,ci\nsvc_test,,nightly\nsvc_gone,tok_999,\n",
+    )
+    .unwrap();
+    let inventory = inventory.to_str().unwrap();
+
+    let out = stdout(&md(
+        &server,
+        &["-o", "json", "token", "reconcile", "--inventory", inventory],
+    ));
+    let value: Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "matched": 1,
+            "untracked": [{ "username": "svc_test", "id": "tok_456", "name": "reader" }],
+            "missing": [
+                { "username": "svc_test", "id": null, "name": "nightly" },
+                { "username": "svc_gone", "id": "tok_999", "name": null }
+            ]
+        })
+    );
+
+    let out = stdout(&md(
+        &server,
+        &[
+            "token",
+            "reconcile",
+            "--inventory",
+            inventory,
+            "--all-users",
+        ],
+    ));
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(lines[0].starts_with("USERNAME"));
+    assert!(lines[2].contains("svc_other") && lines[2].contains("untracked"));
+    assert!(lines[4].contains("svc_gone") && lines[4].ends_with("missing"));
+    assert_eq!(lines[5], "1 matched, 2 untracked, 2 missing");
+}
+
+#[test]
+fn create_retries_only_when_the_token_cannot_have_been_created() {
+    let server = MockServer::start();
+    server.mock(
+        "POST",
+        "/v1/users/svc_rate_limited/tokens",
+        429,
+        fixtures::error("slow down"),
+    );
+    server.mock(
+        "POST",
+        "/v1/users/svc_test/tokens",
+        503,
+        fixtures::error("unavailable"),
+    );
+    let retry = ["--retries", "2", "--retry-delay", "1ms"];
+    let create = |username| {
+        let mut args = retry.to_vec();
+        args.extend(["token", "create", username, "-n", "ci"]);
+        md(&server, &args)
+    };
+
+    assert!(!create("svc_rate_limited").status.success());
+    assert_eq!(
+        server.requests().len(),
+        4,
+        "a 429 is retried, per --rate-limit-retries"
+    );
+    assert!(!create("svc_test").status.success());
+    assert_eq!(server.requests().len(), 5, "a 503 may have created it");
+
+    // Nothing listens on port 1, so the request never reached the API.
+    let mut args = retry.to_vec();
+    args.extend(["--verbose", "--base-url", "http://127.0.0.1:1"]);
+    args.extend(["token", "create", "svc_test", "-n", "ci"]);
+    let output = md(&server, &args);
+    assert!(!output.status.success());
+    assert_eq!(
+        stderr(&output)
+            .matches("md: connection failed; retrying in")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn retry_counts_per_kind_of_failure_come_from_the_config() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/tokens",
+        503,
+        fixtures::error("unavailable"),
+    );
+    let config = "[retries]\nconnect = 2\nserver_error = 1\n";
+    let list = |extra: &[&str]| {
+        let mut args = vec!["--verbose", "--retry-delay", "1ms"];
+        args.extend(extra);
+        args.extend(["token", "list", "svc_test"]);
+        md_with_config(&server, config, &args)
+    };
+
+    let output = list(&[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("md: server error; retrying in"));
+    assert!(stderr(&output).contains("(retry 1 of 1)"));
+    assert_eq!(server.requests().len(), 2);
+
+    assert!(!list(&["--retries", "0"]).status.success());
+    assert_eq!(server.requests().len(), 3, "--retries wins over the config");
+
+    let output = list(&["--base-url", "http://127.0.0.1:1"]);
+    assert_eq!(
+        stderr(&output)
+            .matches("md: connection failed; retrying in")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn rate_limited_requests_wait_as_long_as_retry_after_asks() {
+    let server = MockServer::start();
+    let limit = |retry_after: &str| {
+        server.mock_with_headers(
+            "GET",
+            "/v1/users/svc_test/tokens",
+            429,
+            &[("Retry-After", retry_after)],
+            &fixtures::error("slow down").to_string(),
+        );
+    };
+    let list = |extra: &[&str]| {
+        let mut args = extra.to_vec();
+        args.extend(["token", "list", "svc_test"]);
+        md(&server, &args)
+    };
+
+    limit("0");
+    let output = list(&["--verbose"]);
+    assert!(stderr(&output).contains("API error (429): slow down"));
+    assert!(stderr(&output).contains("md: rate limited; retrying in 0 ms (retry 3 of 3)"));
+    assert_eq!(server.requests().len(), 4);
+
+    assert!(!list(&["--rate-limit-retries", "0"]).status.success());
+    assert_eq!(server.requests().len(), 5);
+
+    // Longer than md waits: the 429 comes back at once.
+    limit("120");
+    assert!(!list(&[]).status.success());
+    assert_eq!(server.requests().len(), 6);
+}
@@ -0,0 +1,99 @@
+use dkdc_md_cli::testing::{MockServer, fixtures};
+use serde_json::Value;
+
+use crate::{md, stderr, stdout};
+
+#[test]
+fn duckling_wait_returns_once_conditions_hold() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/active_accounts",
+        200,
+        fixtures::active_accounts(),
+    );
+    server.mock(
+        "GET",
+        "/v1/users/svc_test/instances",
+        200,
+        fixtures::duckling_config(),
+    );
+
+    let output = md(
+        &server,
+        &[
+            "-o",
+            "json",
+            "wait",
+            "duckling",
+            "svc_test",
+            "--until",
+            "status=running",
+            "--until",
+            "size=standard",
+        ],
+    );
+    let value: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(value["reached"], "status=running size=standard");
+    assert_eq!(server.requests().len(), 2);
+
+    let output = md(
+        &server,
+        &[
+            "--api-mode",
+            "wait",
+            "duckling",
+            "svc_test",
+            "--type",
+            "read-scaling",
+            "--until",
+            "status=running",
+            "--timeout",
+            "300ms",
+            "--interval",
+            "100ms",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(12));
+    let err = stderr(&output);
+    assert!(
+        err.contains("'svc_test' read_scaling: status=idle"),
+        "{err}"
+    );
+    assert_eq!(
+        err.matches("status=idle").count(),
+        2,
+        "progress only on change: {err}"
+    );
+    assert!(err.contains("(last seen: status=idle)"), "{err}");
+}
+
+#[test]
+fn account_wait_sees_deletion() {
+    let server = MockServer::start();
+    server.mock(
+        "GET",
+        "/v1/users/svc_gone/tokens",
+        404,
+        fixtures::error("user not found"),
+    );
+
+    let output = md(
+        &server,
+        &["wait", "account", "svc_gone", "--until", "deleted"],
+    );
+    assert!(stderr(&output).contains("reached deleted after 0s"));
+    let output = md(
+        &server,
+        &[
+            "wait",
+            "account",
+            "svc_gone",
+            "--until",
+            "exists",
+            "--timeout",
+            "0s",
+        ],
+    );
+    assert!(stderr(&output).contains("timed out after 0s waiting for 'svc_gone' to reach exists"));
+}
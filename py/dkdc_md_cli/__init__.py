@@ -1,8 +1,9 @@
 import sys
 
+from dkdc_md_cli.core import Client
 from dkdc_md_cli.core import run as _run
 
-__all__ = ["run", "main"]
+__all__ = ["Client", "run", "main"]
 
 
 def run(argv: list[str] | None = None) -> None:
@@ -1,3 +1,28 @@
+from typing import Any
+
 def run(argv: list[str]) -> None:
     """Run the dkdc-md-cli CLI with the given arguments."""
     ...
+
+class Client:
+    """Typed MotherDuck API client sharing the CLI's request and error handling."""
+
+    def __init__(self, token: str | None = None) -> None:
+        """Create a client. Without `token`, resolves it from env vars like the CLI."""
+        ...
+    def create_user(self, username: str) -> Any: ...
+    def delete_user(self, username: str) -> Any: ...
+    def list_tokens(self, username: str) -> Any: ...
+    def create_token(
+        self,
+        username: str,
+        name: str,
+        ttl: int | None = None,
+        token_type: str | None = None,
+    ) -> Any: ...
+    def delete_token(self, username: str, token_id: str) -> Any: ...
+    def get_duckling_config(self, username: str) -> Any: ...
+    def set_duckling_config(
+        self, username: str, rw_size: str, rs_size: str, flock_size: int
+    ) -> Any: ...
+    def list_active_accounts(self) -> Any: ...
@@ -1,6 +1,8 @@
 """Tests for the dkdc-md-cli Python bindings."""
 
-from dkdc_md_cli import run, main
+import pytest
+
+from dkdc_md_cli import Client, run, main
 
 
 class TestImports:
@@ -15,3 +17,16 @@ class TestImports:
 
     def test_main_has_docstring(self):
         assert main.__doc__ is not None
+
+    def test_import_client(self):
+        assert callable(Client)
+
+
+class TestClient:
+    def test_repr_redacts_token(self):
+        client = Client("secret-token")
+        assert "secret-token" not in repr(client)
+
+    def test_empty_token_raises(self):
+        with pytest.raises(RuntimeError, match="must not be empty"):
+            Client("   ")